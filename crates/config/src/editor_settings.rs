@@ -0,0 +1,87 @@
+use toml_edit::Value;
+use vfs::{AbsPath, VfsHandler};
+
+use crate::{DiagnosticConfig, IniOrTomlValue, ProjectOptions, apply_from_base_config};
+
+/// Settings that are passed by an editor, e.g. with the LSP initialization options. They use the
+/// same keys as a mypy config and are layered under the config files of a project, which means
+/// that a config file overwrites them.
+#[derive(Clone, Debug, Default)]
+pub struct EditorSettings {
+    mypy_compatible: Option<bool>,
+    completion_snippets: bool,
+    items: Vec<(String, Value)>,
+}
+
+impl EditorSettings {
+    pub fn new(items: impl IntoIterator<Item = (String, Value)>) -> anyhow::Result<Self> {
+        let mut mypy_compatible = None;
+        let mut completion_snippets = false;
+        let mut result_items = vec![];
+        for (key, value) in items {
+            let key = key.replace('-', "_");
+            if key == "mypy_compatible" {
+                let Some(b) = value.as_bool() else {
+                    anyhow::bail!("Expected bool for mypy_compatible, got {value}")
+                };
+                mypy_compatible = Some(b);
+            } else if key == "completion_snippets" {
+                let Some(b) = value.as_bool() else {
+                    anyhow::bail!("Expected bool for completion_snippets, got {value}")
+                };
+                completion_snippets = b;
+            } else {
+                result_items.push((key, value))
+            }
+        }
+        Ok(Self {
+            mypy_compatible,
+            completion_snippets,
+            items: result_items,
+        })
+    }
+
+    /// The options that are used before a config file is applied.
+    pub(crate) fn initial_options(
+        &self,
+        vfs: &dyn VfsHandler,
+        current_dir: &AbsPath,
+        mypy_compatible: bool,
+    ) -> anyhow::Result<ProjectOptions> {
+        let mut options = if mypy_compatible {
+            ProjectOptions::mypy_default()
+        } else {
+            ProjectOptions::default()
+        };
+        for (key, value) in &self.items {
+            apply_from_base_config(
+                vfs,
+                current_dir,
+                None,
+                &mut options.settings,
+                &mut options.flags,
+                &mut DiagnosticConfig::default(),
+                key,
+                IniOrTomlValue::Toml(value),
+            )
+            .map_err(|err| anyhow::anyhow!("Invalid editor setting {key}: {err}"))?;
+        }
+        Ok(options)
+    }
+
+    /// Checks that the settings are valid before they are used for a project.
+    pub fn validate(&self, vfs: &dyn VfsHandler, current_dir: &AbsPath) -> anyhow::Result<()> {
+        self.initial_options(vfs, current_dir, false).map(|_| ())
+    }
+
+    /// Whether the options without a config file should be compatible with Mypy.
+    pub(crate) fn mypy_compatible_or(&self, default: bool) -> bool {
+        self.mypy_compatible.unwrap_or(default)
+    }
+
+    /// Whether completions of functions should insert a call with placeholders for the
+    /// arguments.
+    pub fn completion_snippets(&self) -> bool {
+        self.completion_snippets
+    }
+}
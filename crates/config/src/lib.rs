@@ -1,3 +1,4 @@
+mod editor_settings;
 mod searcher;
 mod venv;
 
@@ -9,7 +10,8 @@ use regex::Regex;
 use toml_edit::{DocumentMut, Item, Table, Value};
 use vfs::{AbsPath, Directory, GlobAbsPath, LocalFS, NormalizedPath, VfsHandler};
 
-pub use searcher::{find_cli_config, find_workspace_config};
+pub use editor_settings::EditorSettings;
+pub use searcher::{find_cli_config, find_nested_workspace_configs, find_workspace_config};
 
 type ConfigResult = anyhow::Result<bool>;
 
@@ -41,6 +43,15 @@ pub struct ProjectOptions {
     pub settings: Settings,
     pub flags: TypeCheckerFlags,
     pub overrides: Vec<OverrideConfig>,
+    /// Parts of the project that are configured separately, e.g. additional workspace folders
+    /// of a language server that have their own config files.
+    pub sub_projects: Vec<SubProjectOptions>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SubProjectOptions {
+    pub root: Arc<NormalizedPath>,
+    pub options: ProjectOptions,
 }
 
 #[derive(Clone, Hash, PartialEq, Eq, Debug)]
@@ -169,6 +180,7 @@ impl ProjectOptions {
             settings,
             flags,
             overrides: vec![],
+            sub_projects: vec![],
         }
     }
 
@@ -180,6 +192,7 @@ impl ProjectOptions {
             },
             flags: TypeCheckerFlags::mypy_default(),
             overrides: Default::default(),
+            sub_projects: Default::default(),
         }
     }
 
@@ -189,9 +202,10 @@ impl ProjectOptions {
         config_file_path: &AbsPath,
         code: &str,
         diagnostic_config: &mut DiagnosticConfig,
+        editor_settings: &EditorSettings,
     ) -> anyhow::Result<Option<Self>> {
         let ini = parse_python_ini(code)?;
-        let mut result = Self::mypy_default();
+        let mut result = editor_settings.initial_options(vfs, current_dir, true)?;
         let mut had_relevant_section = false;
         for (name, section) in ini.iter() {
             let Some(name) = name else { continue };
@@ -232,6 +246,7 @@ impl ProjectOptions {
         config_file_path: &AbsPath,
         code: &str,
         diagnostic_config: &mut DiagnosticConfig,
+        editor_settings: &EditorSettings,
         mypy_compatible_default: bool,
     ) -> anyhow::Result<Option<Self>> {
         let document = code.parse()?;
@@ -241,16 +256,18 @@ impl ProjectOptions {
             config_file_path,
             &document,
             diagnostic_config,
+            editor_settings,
         )?;
         Ok(
             if let Some(config) = document.get("tool").and_then(|item| item.get("zuban")) {
-                let mut result = result.unwrap_or_else(|| {
-                    if mypy_compatible_default {
-                        Self::mypy_default()
-                    } else {
-                        Self::default()
-                    }
-                });
+                let mut result = match result {
+                    Some(result) => result,
+                    None => editor_settings.initial_options(
+                        vfs,
+                        current_dir,
+                        editor_settings.mypy_compatible_or(mypy_compatible_default),
+                    )?,
+                };
                 result.apply_pyproject_table(
                     vfs,
                     current_dir,
@@ -271,9 +288,10 @@ impl ProjectOptions {
         config_file_path: &AbsPath,
         document: &DocumentMut,
         diagnostic_config: &mut DiagnosticConfig,
+        editor_settings: &EditorSettings,
     ) -> anyhow::Result<Option<Self>> {
         if let Some(config) = document.get("tool").and_then(|item| item.get("mypy")) {
-            let mut result = ProjectOptions::mypy_default();
+            let mut result = editor_settings.initial_options(vfs, current_dir, true)?;
             result.apply_pyproject_table(
                 vfs,
                 current_dir,
@@ -1031,6 +1049,7 @@ mod tests {
                 &current_dir,
                 code,
                 &mut DiagnosticConfig::default(),
+                &EditorSettings::default(),
             )
         } else {
             ProjectOptions::from_pyproject_toml_only(
@@ -1039,6 +1058,7 @@ mod tests {
                 &current_dir,
                 code,
                 &mut DiagnosticConfig::default(),
+                &EditorSettings::default(),
                 false,
             )
         }
@@ -1,8 +1,12 @@
-use std::{io::Read, path::Path, sync::Arc};
+use std::{
+    io::Read,
+    path::Path,
+    sync::{Arc, Weak},
+};
 
-use crate::{DiagnosticConfig, ProjectOptions};
+use crate::{DiagnosticConfig, EditorSettings, ExcludeRegex, ProjectOptions};
 use toml_edit::DocumentMut;
-use vfs::{AbsPath, VfsHandler};
+use vfs::{AbsPath, DirectoryEntry, Parent, VfsHandler};
 
 const PYPROJECT_TOML_NAME: &str = "pyproject.toml";
 const CONFIG_PATHS: [&str; 4] = [
@@ -27,9 +31,95 @@ pub struct FoundConfig {
 pub fn find_workspace_config(
     vfs: &dyn VfsHandler,
     workspace_dir: &AbsPath,
+    editor_settings: &EditorSettings,
     on_check_path: impl FnMut(&AbsPath),
 ) -> anyhow::Result<ProjectOptions> {
-    Ok(find_mypy_config_file_in_dir(vfs, workspace_dir, false, on_check_path)?.project_options)
+    Ok(
+        find_mypy_config_file_in_dir(vfs, workspace_dir, editor_settings, false, on_check_path)?
+            .project_options,
+    )
+}
+
+/// Finds the configs in the subdirectories of a workspace. Files in these directories are checked
+/// with the options of the most nested config. Directories that are excluded by the workspace
+/// config, hidden directories (like `.git`) and virtual environments are not searched.
+pub fn find_nested_workspace_configs(
+    vfs: &dyn VfsHandler,
+    workspace_dir: &AbsPath,
+    editor_settings: &EditorSettings,
+    excludes: &[ExcludeRegex],
+    mut on_found_config: impl FnMut(&AbsPath),
+) -> anyhow::Result<Vec<(Arc<AbsPath>, ProjectOptions)>> {
+    let _p = tracing::info_span!("nested_config_finder").entered();
+    let mut finder = NestedConfigFinder {
+        vfs,
+        editor_settings,
+        excludes,
+        on_found_config: &mut on_found_config,
+        found: vec![],
+    };
+    finder.search_sub_dirs(workspace_dir)?;
+    Ok(finder.found)
+}
+
+struct NestedConfigFinder<'a, F> {
+    vfs: &'a dyn VfsHandler,
+    editor_settings: &'a EditorSettings,
+    excludes: &'a [ExcludeRegex],
+    on_found_config: &'a mut F,
+    found: Vec<(Arc<AbsPath>, ProjectOptions)>,
+}
+
+impl<F: FnMut(&AbsPath)> NestedConfigFinder<'_, F> {
+    fn search_sub_dirs(&mut self, dir: &AbsPath) -> anyhow::Result<()> {
+        // The entries are only used to find the names in the directory, they are not part of a
+        // workspace. Hidden directories, `node_modules`, `site-packages`, etc. and symlink cycles
+        // are already filtered out by the VFS.
+        let entries = self
+            .vfs
+            .read_and_watch_dir(dir, Parent::Workspace(Weak::new()));
+        for entry in &entries.iter() {
+            let DirectoryEntry::Directory(sub_dir) = entry else {
+                continue;
+            };
+            let sub_dir = self.vfs.join(dir, &sub_dir.name);
+            let is_excluded = || {
+                // Mypy matches directories with a trailing slash
+                let with_slash = format!("{sub_dir}{}", self.vfs.separator());
+                self.excludes.iter().any(|e| e.regex.is_match(&with_slash))
+            };
+            if is_excluded() {
+                continue;
+            }
+            self.search_dir(&sub_dir)?;
+        }
+        Ok(())
+    }
+
+    fn search_dir(&mut self, dir: &Arc<AbsPath>) -> anyhow::Result<()> {
+        let entries = self
+            .vfs
+            .read_and_watch_dir(dir, Parent::Workspace(Weak::new()));
+        let entries = entries.iter();
+        let has_file = |name: &str| {
+            (&entries)
+                .into_iter()
+                .any(|entry| matches!(entry, DirectoryEntry::File(f) if &*f.name == name))
+        };
+        // Virtual environments are not part of the project
+        if has_file("pyvenv.cfg") {
+            return Ok(());
+        }
+        if CONFIG_PATHS.iter().any(|config_name| has_file(config_name)) {
+            let config =
+                find_mypy_config_file_in_dir(self.vfs, dir, self.editor_settings, false, |_| ())?;
+            if let Some(config_path) = &config.config_path {
+                (self.on_found_config)(config_path);
+                self.found.push((dir.clone(), config.project_options));
+            }
+        }
+        self.search_sub_dirs(dir)
+    }
 }
 
 pub fn find_cli_config(
@@ -46,7 +136,14 @@ pub fn find_cli_config(
         let s = std::fs::read_to_string(config_path.as_ref())
             .map_err(|err| anyhow::anyhow!("Issue while reading {config_path}: {err}"))?;
 
-        let result = initialize_config(vfs, current_dir, config_path, s, mypy_compatible_default)?;
+        let result = initialize_config(
+            vfs,
+            current_dir,
+            config_path,
+            s,
+            &EditorSettings::default(),
+            mypy_compatible_default,
+        )?;
         let project_options = result.0.unwrap_or_else(ProjectOptions::mypy_default);
         Ok(FoundConfig {
             project_options,
@@ -54,7 +151,13 @@ pub fn find_cli_config(
             config_path: Some(result.2),
         })
     } else {
-        find_mypy_config_file_in_dir(vfs, current_dir, mypy_compatible_default, |_| ())
+        find_mypy_config_file_in_dir(
+            vfs,
+            current_dir,
+            &EditorSettings::default(),
+            mypy_compatible_default,
+            |_| (),
+        )
     }
 }
 
@@ -63,6 +166,7 @@ fn initialize_config(
     current_dir: &AbsPath,
     config_path: Arc<AbsPath>,
     content: String,
+    editor_settings: &EditorSettings,
     mypy_compatible_default: bool,
 ) -> anyhow::Result<(Option<ProjectOptions>, DiagnosticConfig, Arc<AbsPath>)> {
     let _p = tracing::info_span!("config_finder").entered();
@@ -74,6 +178,7 @@ fn initialize_config(
             &config_path,
             &content,
             &mut diagnostic_config,
+            editor_settings,
             mypy_compatible_default,
         )?
     } else {
@@ -83,6 +188,7 @@ fn initialize_config(
             &config_path,
             &content,
             &mut diagnostic_config,
+            editor_settings,
         )?
     };
     Ok((options, diagnostic_config, config_path))
@@ -91,6 +197,7 @@ fn initialize_config(
 fn find_mypy_config_file_in_dir(
     vfs: &dyn VfsHandler,
     dir: &AbsPath,
+    editor_settings: &EditorSettings,
     mypy_compatible_default: bool,
     mut on_check_path: impl FnMut(&AbsPath),
 ) -> anyhow::Result<FoundConfig> {
@@ -115,6 +222,7 @@ fn find_mypy_config_file_in_dir(
                     &config_path,
                     pyproject_toml.as_ref().unwrap(),
                     &mut diagnostic_config,
+                    editor_settings,
                 )?;
                 if let Some(project_options) = project_options {
                     end_result = Some(FoundConfig {
@@ -125,15 +233,24 @@ fn find_mypy_config_file_in_dir(
                     break;
                 }
             } else {
-                let result =
-                    initialize_config(vfs, dir, config_path, content, mypy_compatible_default)?;
-                if let Some(project_options) = result.0.or_else(|| {
-                    ["mypy.ini", ".mypy.ini"].contains(config_name).then(|| {
-                        // Both mypy.ini and .mypy.ini always take precedent, even if there is no [mypy]
-                        // section. See also https://mypy.readthedocs.io/en/stable/config_file.html
-                        ProjectOptions::mypy_default()
-                    })
-                }) {
+                let result = initialize_config(
+                    vfs,
+                    dir,
+                    config_path,
+                    content,
+                    editor_settings,
+                    mypy_compatible_default,
+                )?;
+                let project_options = match result.0 {
+                    Some(project_options) => Some(project_options),
+                    // Both mypy.ini and .mypy.ini always take precedent, even if there is no [mypy]
+                    // section. See also https://mypy.readthedocs.io/en/stable/config_file.html
+                    None if ["mypy.ini", ".mypy.ini"].contains(config_name) => {
+                        Some(editor_settings.initial_options(vfs, dir, true)?)
+                    }
+                    None => None,
+                };
+                if let Some(project_options) = project_options {
                     end_result = Some(FoundConfig {
                         project_options,
                         diagnostic_config: result.1,
@@ -144,14 +261,16 @@ fn find_mypy_config_file_in_dir(
             };
         }
     }
-    let default_config = |config_path| FoundConfig {
-        project_options: if mypy_compatible_default {
-            ProjectOptions::mypy_default()
-        } else {
-            ProjectOptions::default()
-        },
-        diagnostic_config: DiagnosticConfig::default(),
-        config_path,
+    let default_config = |config_path| -> anyhow::Result<_> {
+        Ok(FoundConfig {
+            project_options: editor_settings.initial_options(
+                vfs,
+                dir,
+                editor_settings.mypy_compatible_or(mypy_compatible_default),
+            )?,
+            diagnostic_config: DiagnosticConfig::default(),
+            config_path,
+        })
     };
     if let Some(pyproject_toml) = pyproject_toml {
         if let Some(config) = pyproject_toml
@@ -161,7 +280,7 @@ fn find_mypy_config_file_in_dir(
             if end_result.is_none() {
                 end_result = Some(default_config(Some(
                     vfs.absolute_path(dir, PYPROJECT_TOML_NAME),
-                )));
+                ))?);
             }
             let found = end_result.as_mut().unwrap();
             found.project_options.apply_pyproject_table(
@@ -173,8 +292,11 @@ fn find_mypy_config_file_in_dir(
             )?
         }
     }
-    Ok(end_result.unwrap_or_else(|| {
-        tracing::info!("No relevant config found");
-        default_config(None)
-    }))
+    match end_result {
+        Some(end_result) => Ok(end_result),
+        None => {
+            tracing::info!("No relevant config found");
+            default_config(None)
+        }
+    }
 }
@@ -76,6 +76,137 @@ impl InternalTree {
     pub fn new(code: Box<str>, nodes: Vec<InternalNode>) -> Self {
         Self { code, nodes }
     }
+
+    /// Creates the tree for `code` by reusing all children of the root node that are not touched
+    /// by the difference between the old and the new code. Only the touched children and one
+    /// neighbour on each side are reparsed with `parse_fragment`; `is_child_boundary` decides
+    /// whether a reparsed region may start or end at a given position.
+    ///
+    /// The code is given back if the old tree cannot be reused (e.g. because of error recovery),
+    /// in which case the caller is expected to parse everything.
+    pub fn reparse_changed_root_children(
+        &self,
+        code: Box<str>,
+        is_child_boundary: impl Fn(&str, usize) -> bool,
+        parse_fragment: impl FnOnce(&str) -> Vec<InternalNode>,
+    ) -> Result<Self, Box<str>> {
+        let Some(root) = self.nodes.first() else {
+            return Err(code);
+        };
+        if root.type_.is_leaf() || self.nodes.iter().any(|n| n.type_.is_error_recovery()) {
+            return Err(code);
+        }
+        let old = self.code.as_bytes();
+        let new = code.as_bytes();
+        let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        let suffix = old
+            .iter()
+            .rev()
+            .zip(new.iter().rev())
+            .take(old.len().min(new.len()) - prefix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let old_change_end = old.len() - suffix;
+        let delta = new.len() as i64 - old.len() as i64;
+
+        let old_children = root_children(&self.nodes);
+        let Some(last_index) = old_children.len().checked_sub(1) else {
+            return Err(code);
+        };
+        let first_touched = old_children
+            .iter()
+            .position(|&(_, _, end)| end >= prefix)
+            .unwrap_or(last_index);
+        let last_touched = old_children
+            .iter()
+            .rposition(|&(_, start, _)| start <= old_change_end)
+            .unwrap_or(0);
+        // Also reparse the neighbours, because they might be influenced by the change (e.g. an
+        // `else` that is added after an `if`).
+        let first = first_touched.min(last_touched).saturating_sub(1);
+        let last = (first_touched.max(last_touched) + 1).min(last_index);
+        let reaches_end = last == last_index;
+
+        let region_start = match first {
+            0 => 0,
+            _ => old_children[first - 1].2,
+        };
+        let region_end = match reaches_end {
+            true => new.len(),
+            false => (old_children[last].2 as i64 + delta) as usize,
+        };
+        if !is_child_boundary(&code, region_start)
+            || !reaches_end && !is_child_boundary(&code, region_end)
+        {
+            return Err(code);
+        }
+
+        let fragment_nodes = parse_fragment(&code[region_start..region_end]);
+        if fragment_nodes.first().map(|n| n.type_) != Some(root.type_)
+            || fragment_nodes.iter().any(|n| n.type_.is_error_recovery())
+        {
+            return Err(code);
+        }
+        let mut fragment_children = root_children(&fragment_nodes);
+        if !reaches_end {
+            // The fragment ends with the same node the whole tree ends with (e.g. an end
+            // marker), which is not part of the region.
+            let fragment_len = region_end - region_start;
+            let end_node = &self.nodes[old_children[last_index].0];
+            let fragment_end = fragment_children.pop().map(|(i, ..)| fragment_nodes[i]);
+            if fragment_end.map(|n| (n.type_, n.start_index as usize))
+                != Some((end_node.type_, fragment_len))
+                || fragment_children.last().map(|&(_, _, end)| end) != Some(fragment_len)
+            {
+                return Err(code);
+            }
+        }
+
+        let mut nodes = Vec::with_capacity((self.nodes.len() as i64 + delta.max(0)) as usize);
+        nodes.push(InternalNode {
+            length: (root.length as i64 + delta) as CodeLength,
+            ..*root
+        });
+        let mut child_indexes = vec![];
+        let mut add_children =
+            |from: &[InternalNode], children: &[(usize, usize, usize)], shift: i64| {
+                for &(index, ..) in children {
+                    child_indexes.push(nodes.len());
+                    let end = index + from[index].next_node_offset as usize;
+                    let end = if end == index { from.len() } else { end };
+                    nodes.extend(from[index..end].iter().map(|n| InternalNode {
+                        start_index: (n.start_index as i64 + shift) as CodeIndex,
+                        ..*n
+                    }));
+                }
+            };
+        add_children(&self.nodes, &old_children[..first], 0);
+        add_children(&fragment_nodes, &fragment_children, region_start as i64);
+        if !reaches_end {
+            add_children(&self.nodes, &old_children[last + 1..], delta);
+        }
+        for window in child_indexes.windows(2) {
+            nodes[window[0]].next_node_offset = (window[1] - window[0]) as NodeIndex;
+        }
+        if let Some(&last_child) = child_indexes.last() {
+            nodes[last_child].next_node_offset = 0;
+        }
+        Ok(Self { code, nodes })
+    }
+}
+
+/// Returns the index, the start and the end of all children of the root node.
+fn root_children(nodes: &[InternalNode]) -> Vec<(usize, usize, usize)> {
+    let mut children = vec![];
+    let mut index = 1;
+    while let Some(n) = nodes.get(index) {
+        children.push((index, n.start_index as usize, n.end_index() as usize));
+        if n.next_node_offset == 0 {
+            break;
+        }
+        index += n.next_node_offset as usize;
+    }
+    children
 }
 
 #[derive(Debug)]
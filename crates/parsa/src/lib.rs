@@ -753,6 +753,25 @@ macro_rules! create_grammar {
                     internal_tree: $crate::InternalTree::new(code, nodes)
                 }
             }
+
+            pub fn parse_incrementally(
+                &self,
+                previous: &$Tree,
+                code: Box<str>,
+                is_child_boundary: impl Fn(&str, usize) -> bool,
+            ) -> $Tree {
+                use $crate::Tokenizer;
+                let start = $NonterminalType::map()[stringify!($first_node)];
+                let reparsed = previous.internal_tree.reparse_changed_root_children(
+                    code,
+                    is_child_boundary,
+                    |fragment| self.internal_grammar.parse(fragment, $Tokenizer::new(fragment), start),
+                );
+                match reparsed {
+                    Ok(internal_tree) => $Tree { internal_tree },
+                    Err(code) => self.parse(code),
+                }
+            }
         }
 
         #[derive(Clone)]
@@ -1,5 +1,5 @@
 // Heavily inspired by https://docs.python.org/3.10/reference/grammar.html
-// and adapted to Python 3.13
+// and adapted to Python 3.14
 
 #![recursion_limit = "2048"]
 mod tokenizer;
@@ -181,7 +181,7 @@ create_grammar!(
 
     except_block: "except" [except_expression] ":" block
     except_star_block: "except" "*" except_expression ":" block
-    except_expression: expression ["as" name_def]
+    except_expression: expression ["as" name_def | "," [",".expression+ [","]]]
     finally_block: "finally" ":" block
 
     // Match statement
@@ -447,6 +447,18 @@ pub fn parse(code: Box<str>) -> PyTree {
     PYTHON_GRAMMAR.parse(code.into())
 }
 
+/// Parses the code again, but reuses all top-level statements of the previous tree that are not
+/// touched by the change.
+pub fn parse_incrementally(previous: &PyTree, code: Box<str>) -> PyTree {
+    if !code.ends_with('\n') {
+        return parse(code);
+    }
+    PYTHON_GRAMMAR.parse_incrementally(previous, code, |code, index| {
+        // Top-level statements always start at the beginning of a line.
+        index == 0 || code.as_bytes()[index - 1] == b'\n'
+    })
+}
+
 pub fn keywords_contain(keyword: &str) -> bool {
     PYTHON_GRAMMAR.keywords_contain(keyword)
 }
@@ -463,4 +475,51 @@ mod tests {
             PyNodeType::Nonterminal(NonterminalType::file)
         );
     }
+
+    fn tree_summary(tree: &PyTree) -> Vec<(PyNodeType, u32, u32, Option<NodeIndex>)> {
+        tree.nodes()
+            .map(|n| {
+                (
+                    n.type_(),
+                    n.start(),
+                    n.end(),
+                    n.next_sibling().map(|s| s.index),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_incremental_parse() {
+        let code = "import os\n\ndef foo():\n    return 1\n\n# comment\nx = foo()\n\nclass C:\n    y = 2\nz = 3\n";
+        let edits = [
+            ("return 1", "return 100"),
+            ("x = foo()", "x = foo(); y = 1"),
+            ("x = foo()\n", ""),
+            ("import os", "import os, sys"),
+            ("import os\n", ""),
+            ("z = 3", "z = 3\nw = 4"),
+            ("z = 3\n", ""),
+            ("# comment\n", "# other\n\n"),
+            ("class C:\n    y = 2\n", "if z:\n    pass\n"),
+            ("x = foo()\n", "else:\n    pass\n"),
+            ("x = foo()", "x = (foo()"),
+            ("x = foo()", "x = '''foo()"),
+            ("    y = 2\n", "    y = 2\n  z = 4\n"),
+            ("def foo():", "def foo(a, b):"),
+            ("\n\nclass", "\n\n@decorator\nclass"),
+        ];
+        let previous = parse(code.into());
+        for (before, after) in edits {
+            let new_code = code.replacen(before, after, 1);
+            let incremental = parse_incrementally(&previous, new_code.clone().into());
+            let full = parse(new_code.into());
+            assert_eq!(incremental.as_code(), full.as_code());
+            assert_eq!(
+                tree_summary(&incremental),
+                tree_summary(&full),
+                "Replacing {before:?} with {after:?}"
+            );
+        }
+    }
 }
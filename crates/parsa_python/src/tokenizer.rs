@@ -38,7 +38,7 @@ lazy_static::lazy_static! {
 
     static ref NAME: Regex = r(r"^[A-Za-z_0-9\u0080-\uffff]+");
     static ref NEWLINE: Regex = r(r"^(\r\n?|\n)");
-    static ref F_STRING_START: Regex = r(r#"^([Ff][Rr]?|[Rr][Ff])("""|'''|'|")"#);
+    static ref F_STRING_START: Regex = r(r#"^([FfTt][Rr]?|[Rr][FfTt])("""|'''|'|")"#);
     static ref UNICODE_CHARACTER_NAME: Regex = r(r"\{[A-Za-z0-9\-]+( [A-Za-z0-9\-]+)*\}");
 
     static ref ALWAYS_BREAK_NAMES: HashSet<&'static str> = [
@@ -232,7 +232,7 @@ impl PythonTokenizer<'_> {
                     }
                 }
             } else if character == '"' {
-                for (j, node) in self.f_string_stack.iter().enumerate() {
+                for (j, node) in self.f_string_stack.iter().enumerate().rev() {
                     let quote = node.quote;
                     match quote {
                         QuoteType::Double => return self.end_f_string(i, j, quote),
@@ -717,6 +717,14 @@ mod tests {
                                  (6, 1, Op), (7, 1, FStringEnd)];
         f_string8 "f'{ f''}'" => [(0, 2, FStringStart), (2, 1, Op), (4, 2, FStringStart),
                                   (6, 1, FStringEnd), (7, 1, Op), (8, 1, FStringEnd)];
+        f_string9 r#"f"{f""}""# => [(0, 2, FStringStart), (2, 1, Op), (3, 2, FStringStart),
+                                    (5, 1, FStringEnd), (6, 1, Op), (7, 1, FStringEnd)];
+
+        t_string1 "t'{x}'" => [(0, 2, FStringStart), (2, 1, Op), (3, 1, Name),
+                               (4, 1, Op), (5, 1, FStringEnd)];
+        t_string2 "Rt'a' tR''" => [(0, 3, FStringStart), (3, 1, FStringString),
+                                   (4, 1, FStringEnd), (6, 3, FStringStart), (9, 1, FStringEnd)];
+        t_string3 "tf''" => [(0, 2, Name), (2, 2, String)];
 
         f_string_format_spec1 "f'Some {x:.2f}{y}'" => [
             (0, 2, FStringStart), (2, 5, FStringString), (7, 1, Operator),
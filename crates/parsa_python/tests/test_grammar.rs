@@ -308,6 +308,20 @@ parametrize_snapshots!(
         except* (BarError, BazError) as e:
             ...
         "#);
+    except_without_parentheses: dedent(r#"
+        try:
+            ...
+        except A, B:
+            ...
+        try:
+            ...
+        except* C, D,:
+            ...
+        "#);
+    template_strings: dedent(r#"
+        t'{x} y'
+        Rt"{x!r:>{width}}" f''
+        "#);
     invalid_syntax: dedent(r#"
         def f():
             (x,) += 1
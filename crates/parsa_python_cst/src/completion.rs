@@ -2,7 +2,8 @@ use parsa_python::{
     CodeIndex,
     NonterminalType::*,
     PyNode,
-    PyNodeType::{ErrorNonterminal, Nonterminal},
+    PyNodeType::{ErrorNonterminal, Nonterminal, Terminal},
+    TerminalType,
 };
 
 use crate::{
@@ -27,7 +28,7 @@ impl Tree {
                 leaf = n;
             }
         }
-        if leaf.end() == position && leaf.as_code() == "." {
+        if leaf.end() == position && matches!(leaf.as_code(), "." | "(" | "[" | "," | "=") {
             leaf = leaf.next_leaf().unwrap();
         }
         let scope = scope_for_node(leaf);
@@ -139,7 +140,15 @@ impl Tree {
                     return (scope, CompletionNode::AsNewName, rest);
                 }
                 "def" => {
-                    return (scope, CompletionNode::AfterDefKeyword, rest);
+                    let keyword_start = match previous.previous_leaf() {
+                        Some(leaf) if leaf.as_code() == "async" => leaf.start(),
+                        _ => previous.start(),
+                    };
+                    return (
+                        scope,
+                        CompletionNode::AfterDefKeyword { keyword_start },
+                        rest,
+                    );
                 }
                 "class" => {
                     return (scope, CompletionNode::AfterClassKeyword, rest);
@@ -284,7 +293,10 @@ pub enum CompletionNode<'db> {
     },
     AsNewName,
     NecessaryKeyword(&'static str),
-    AfterDefKeyword,
+    AfterDefKeyword {
+        /// The start of `def` or of `async` for `async def`
+        keyword_start: CodeIndex,
+    },
     AfterClassKeyword,
     Global,
 }
@@ -330,6 +342,227 @@ impl<'db> RestNode<'db> {
     pub fn ensure_no_rest(&mut self) {
         self.position = 0;
     }
+
+    /// Whether a completed name could be followed by a call, which is not the case in decorators
+    /// like `@foo|` or if there are already brackets like in `foo|()`.
+    pub fn allows_call_snippet(&self) -> bool {
+        let after = &self.tree.code()[self.position as usize..];
+        if after
+            .trim_start_matches(|c: char| c.is_alphanumeric() || c == '_')
+            .starts_with('(')
+        {
+            return false;
+        }
+        let mut leaf = self.node;
+        if self.position <= leaf.start() {
+            let Some(previous) = leaf.previous_leaf() else {
+                return true;
+            };
+            leaf = previous;
+        }
+        // Find the start of a dotted name like `foo.bar`
+        while leaf.is_type(Terminal(TerminalType::Name)) || leaf.as_code() == "." {
+            let Some(previous) = leaf.previous_leaf() else {
+                return true;
+            };
+            leaf = previous;
+        }
+        // `@` is only a decorator at the start of a line, otherwise it's a matrix multiplication
+        leaf.as_code() != "@"
+            || leaf.previous_leaf().is_some_and(|l| {
+                !matches!(
+                    l.type_(),
+                    Terminal(TerminalType::Newline | TerminalType::Indent | TerminalType::Dedent)
+                )
+            })
+    }
+
+    /// Returns the string literal the cursor is in, e.g. `d["ti|` or `f('a|')`.
+    pub fn string_start(&self) -> Option<StringStart> {
+        self.string_start_and_leaf_before().0
+    }
+
+    fn string_start_and_leaf_before(&self) -> (Option<StringStart>, Option<PyNode<'db>>) {
+        let before = self.node.previous_leaf();
+        if self.position > self.node.start() && self.is_string() {
+            let code = self.node.as_code();
+            let quote_index = code.find(['"', '\'']).unwrap();
+            let start = StringStart {
+                start: self.node.start() + quote_index as CodeIndex,
+                quote: code[quote_index..].chars().next().unwrap(),
+                is_closed: self.node.end() > self.position,
+            };
+            return (Some(start), before);
+        }
+        // Unfinished strings like `"ti` are tokenized as an error token for the quote and a
+        // name.
+        if let Some(quote) = before
+            && quote.end() == self.node.start().min(self.position)
+            && let Some(last) = quote.as_code().chars().last()
+            && matches!(last, '"' | '\'')
+            && quote.as_code().len() <= 3
+            && quote
+                .as_code()
+                .chars()
+                .rev()
+                .skip(1)
+                .all(|c| c.is_alphabetic())
+        {
+            let start = StringStart {
+                start: quote.end() - 1,
+                quote: last,
+                is_closed: false,
+            };
+            return (Some(start), quote.previous_leaf());
+        }
+        (None, before)
+    }
+
+    /// Returns the call or subscript whose brackets contain the completion position, e.g.
+    /// `foo(1, na|` or `d["|`.
+    pub fn bracket_context(&self) -> Option<BracketContext<'db>> {
+        let before = self.string_start_and_leaf_before().1?;
+        let (position, last_of_previous_args) = match before.as_code() {
+            "(" | "[" | "," => (ArgumentPosition::NewArgument, before),
+            "=" => {
+                let name = before.previous_leaf()?;
+                let before_name = name.previous_leaf()?;
+                if !name.is_type(Terminal(TerminalType::Name))
+                    || !matches!(before_name.as_code(), "(" | ",")
+                {
+                    return None;
+                }
+                (ArgumentPosition::KeywordValue(name.as_code()), before_name)
+            }
+            _ => return None,
+        };
+        // Search the opening bracket
+        let mut depth = 0;
+        let mut open = before;
+        loop {
+            match open.as_code() {
+                ")" | "]" | "}" => depth += 1,
+                "(" | "[" | "{" if depth > 0 => depth -= 1,
+                "(" | "[" => break,
+                "{" | ";" => return None,
+                _ if open.is_type(Terminal(TerminalType::Newline)) => return None,
+                _ => (),
+            }
+            open = open.previous_leaf()?;
+        }
+        let base = open.previous_sibling()?;
+        let base = if base.is_type(Nonterminal(atom)) {
+            PrimaryOrAtom::Atom(Atom::new(base))
+        } else if base.is_type(Nonterminal(primary)) {
+            PrimaryOrAtom::Primary(Primary::new(base))
+        } else {
+            return None;
+        };
+        if open.as_code() == "[" {
+            // Only the first element of a subscript is interesting.
+            return (before.index == open.index
+                && matches!(position, ArgumentPosition::NewArgument))
+            .then_some(BracketContext::Subscript { base });
+        }
+        let mut call = CallContext {
+            callee: base,
+            positional_args: vec![],
+            keyword_args: vec![],
+            has_star_args: false,
+            position,
+        };
+        if last_of_previous_args.index == open.index {
+            return Some(BracketContext::Call(call));
+        }
+        // Split the previous arguments at the commas that are not nested in brackets.
+        let mut first: Option<PyNode> = None;
+        let mut is_keyword = false;
+        let mut leaf = open;
+        loop {
+            leaf = leaf.next_leaf()?;
+            match leaf.as_code() {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => depth -= 1,
+                "=" if depth == 0 => is_keyword = true,
+                "," if depth == 0 => {
+                    let first = first.take()?;
+                    let prev = leaf.previous_leaf()?;
+                    if is_keyword {
+                        call.keyword_args.push(first.as_code());
+                    } else if matches!(first.as_code(), "*" | "**") {
+                        call.has_star_args = true;
+                    } else {
+                        call.positional_args.push(argument_node(first, prev));
+                    }
+                    is_keyword = false;
+                }
+                _ => (),
+            }
+            if first.is_none() && leaf.as_code() != "," {
+                first = Some(leaf);
+            }
+            if leaf.index == last_of_previous_args.index {
+                return Some(BracketContext::Call(call));
+            }
+        }
+    }
+}
+
+/// Finds the name or attribute access that spans exactly from `first` to `last`.
+fn argument_node<'db>(first: PyNode<'db>, last: PyNode<'db>) -> Option<PrimaryOrAtom<'db>> {
+    let mut node = first.parent()?;
+    while node.start() == first.start() && node.end() <= last.end() {
+        if node.end() == last.end() {
+            if node.is_type(Nonterminal(atom)) {
+                return Some(PrimaryOrAtom::Atom(Atom::new(node)));
+            } else if node.is_type(Nonterminal(primary)) {
+                return Some(PrimaryOrAtom::Primary(Primary::new(node)));
+            }
+        }
+        node = node.parent()?;
+    }
+    None
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct StringStart {
+    /// The position of the opening quote, string prefixes are not included.
+    pub start: CodeIndex,
+    pub quote: char,
+    pub is_closed: bool,
+}
+
+#[derive(Debug)]
+pub enum BracketContext<'db> {
+    Call(CallContext<'db>),
+    Subscript { base: PrimaryOrAtom<'db> },
+}
+
+#[derive(Debug)]
+pub struct CallContext<'db> {
+    pub callee: PrimaryOrAtom<'db>,
+    /// The positional arguments before the completion, `None` for anything that is not a simple
+    /// name, attribute or call.
+    pub positional_args: Vec<Option<PrimaryOrAtom<'db>>>,
+    pub keyword_args: Vec<&'db str>,
+    pub has_star_args: bool,
+    pub position: ArgumentPosition<'db>,
+}
+
+impl CallContext<'_> {
+    /// The index of the positional argument that is being completed, if it is known.
+    pub fn positional_index(&self) -> Option<usize> {
+        (matches!(self.position, ArgumentPosition::NewArgument)
+            && self.keyword_args.is_empty()
+            && !self.has_star_args)
+            .then_some(self.positional_args.len())
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum ArgumentPosition<'db> {
+    NewArgument,
+    KeywordValue(&'db str),
 }
 
 impl std::fmt::Debug for RestNode<'_> {
@@ -1,7 +1,10 @@
 mod bytes;
 mod completion;
 mod match_stmt;
+mod ranges;
+mod refactoring;
 mod strings;
+mod syntax_errors;
 
 use std::{
     borrow::Cow,
@@ -12,19 +15,27 @@ use std::{
 
 pub use bytes::parse_python_bytes_literal;
 use completion::scope_for_node;
-pub use completion::{CompletionNode, RestNode, Scope};
+pub use completion::{
+    ArgumentPosition, BracketContext, CallContext, CompletionNode, RestNode, Scope, StringStart,
+};
 pub use match_stmt::{
-    CasePattern, KeyEntryInPattern, MappingPatternItem, ParamPattern, PatternKind,
-    SequencePatternItem, StarPatternContent, SubjectExprContent,
+    CasePattern, KeyEntryInPattern, LiteralPatternContent, MappingPatternItem, ParamPattern,
+    PatternKind, SequencePatternItem, StarPatternContent, SubjectExprContent,
 };
 pub use parsa_python::{CodeIndex, NodeIndex, keywords_contain};
 use parsa_python::{
     NonterminalType::*,
     PyNode,
     PyNodeType::{self, ErrorNonterminal, ErrorTerminal, Nonterminal, Terminal},
-    PyTree, SearchIterator, SiblingIterator, TerminalType, parse,
+    PyTree, SearchIterator, SiblingIterator, TerminalType, parse, parse_incrementally,
+};
+pub use ranges::{FoldingRange, FoldingRangeKind};
+pub use refactoring::{
+    ControlFlow, SelectedExpression, SelectedExpressionContent, SelectedStatements,
+    StandaloneStatement, VariableUsage,
 };
 pub use strings::PythonString;
+pub use syntax_errors::{SyntaxError, SyntaxErrorKind};
 
 pub const NAME_DEF_TO_NAME_DIFFERENCE: u32 = 1;
 
@@ -36,6 +47,10 @@ impl Tree {
         Self(parse(code))
     }
 
+    pub fn parse_incrementally(previous: &Tree, code: Box<str>) -> Self {
+        Self(parse_incrementally(&previous.0, code))
+    }
+
     pub fn invalid_empty() -> Self {
         Self(PyTree::empty())
     }
@@ -161,6 +176,56 @@ impl Tree {
         self.0.node_by_index(index).as_code()
     }
 
+    /// Returns the name that starts at the position.
+    pub fn name_by_start(&self, position: CodeIndex) -> Option<Name<'_>> {
+        let leaf = self.0.leaf_by_position(position);
+        (leaf.start() == position && leaf.is_type(Terminal(TerminalType::Name)))
+            .then(|| Name::new(leaf))
+    }
+
+    /// Returns the string literals with the given content that are used as keys, either in
+    /// subscripts like `x["key"]` or in dict literals like `{"key": 1}`.
+    pub fn string_keys<'a>(&'a self, key: &str) -> Vec<(StringLiteral<'a>, StringKeyUsage<'a>)> {
+        let mut result = vec![];
+        let mut add = |expr: Expression<'a>, usage: StringKeyUsage<'a>| {
+            if let Some(literal) = expr.maybe_single_string_literal()
+                && literal.content() == key
+            {
+                result.push((literal, usage))
+            }
+        };
+        for node in self.0.nodes() {
+            match node.type_() {
+                Nonterminal(primary) => {
+                    let primary_ = Primary::new(node);
+                    if let PrimaryContent::GetItem(SliceType::NamedExpression(n)) =
+                        primary_.second()
+                    {
+                        add(n.expression(), StringKeyUsage::Subscript(primary_))
+                    }
+                }
+                Nonterminal(t_primary) => {
+                    let target = PrimaryTarget::new(node);
+                    if let PrimaryContent::GetItem(SliceType::NamedExpression(n)) = target.second()
+                    {
+                        add(n.expression(), StringKeyUsage::SubscriptTarget(target))
+                    }
+                }
+                Nonterminal(atom) => {
+                    if let AtomContent::Dict(dict) = Atom::new(node).unpack() {
+                        for element in dict.iter_elements() {
+                            if let DictElement::KeyValue(key_value) = element {
+                                add(key_value.key(), StringKeyUsage::Dict(Atom::new(node)))
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        result
+    }
+
     pub fn short_debug_of_index(&self, index: NodeIndex) -> &str {
         let node = self.0.node_by_index(index);
         node.as_code().get(..40).unwrap_or_else(|| node.as_code())
@@ -292,6 +357,30 @@ impl Tree {
             .filter(|&n| n.is_type(Terminal(TerminalType::Name)))
             .map(Name::new)
     }
+
+    pub fn iter_imports(&self) -> impl Iterator<Item = ImportStatement<'_>> {
+        self.0.nodes().filter_map(|n| {
+            if n.is_type(Nonterminal(import_name)) {
+                Some(ImportStatement::ImportName(ImportName::new(n)))
+            } else if n.is_type(Nonterminal(import_from)) {
+                Some(ImportStatement::ImportFrom(ImportFrom::new(n)))
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn iter_string_literals(&self) -> impl Iterator<Item = StringLiteral<'_>> {
+        self.0
+            .nodes()
+            .filter(|&n| n.is_type(Terminal(TerminalType::String)))
+            .map(StringLiteral::new)
+    }
+}
+
+pub enum ImportStatement<'db> {
+    ImportName(ImportName<'db>),
+    ImportFrom(ImportFrom<'db>),
 }
 
 pub fn maybe_type_ignore(text: &str) -> Option<Option<&str>> {
@@ -696,6 +785,65 @@ impl<'db> Name<'db> {
         }
     }
 
+    /// Returns the class if the name is used as a base class, e.g. `C` in `class D(C)`,
+    /// `class D(mod.C)` or `class D(C[int])`.
+    pub fn maybe_base_class_of(&self) -> Option<ClassDef<'db>> {
+        let mut node = self.node;
+        loop {
+            let parent = node.parent()?;
+            match parent.type_() {
+                Nonterminal(named_expression | expression) => (),
+                Nonterminal(atom) => {
+                    if matches!(parent.nth_child(0).as_code(), "[" | "{") {
+                        return None;
+                    }
+                }
+                Nonterminal(primary) => {
+                    let second = parent.nth_child(1);
+                    let expected = if node.index < second.index { "[" } else { "." };
+                    if second.as_code() != expected {
+                        return None;
+                    }
+                }
+                Nonterminal(arguments) => {
+                    let class = parent.parent()?;
+                    return class
+                        .is_type(Nonterminal(class_def))
+                        .then(|| ClassDef::new(class));
+                }
+                _ => return None,
+            }
+            node = parent;
+        }
+    }
+
+    /// Returns the call if the name is called directly, e.g. `f` in `f(1)` or `m` in
+    /// `obj.m(1)`.
+    pub fn maybe_called(&self) -> Option<Primary<'db>> {
+        let mut node = self.node;
+        let mut parent = node.parent()?;
+        if parent.is_type(Nonterminal(atom)) {
+            node = parent;
+            parent = node.parent()?;
+        }
+        if !parent.is_type(Nonterminal(primary)) {
+            return None;
+        }
+        let call = if parent.nth_child(0).index == node.index {
+            parent
+        } else if parent.nth_child(1).as_code() == "." {
+            let par_par = parent.parent()?;
+            if !par_par.is_type(Nonterminal(primary)) || par_par.nth_child(0).index != parent.index
+            {
+                return None;
+            }
+            par_par
+        } else {
+            return None;
+        };
+        (call.nth_child(1).as_code() == "(").then(|| Primary::new(call))
+    }
+
     pub fn maybe_assignment_definition_name(&self) -> Option<Assignment<'db>> {
         let node = self
             .node
@@ -1529,6 +1677,11 @@ impl<'db> WithStmt<'db> {
 }
 
 impl<'db> WithItems<'db> {
+    pub fn is_parenthesized(&self) -> bool {
+        // with_items: ",".with_item+ | "(" ",".with_item+ ","? ")"
+        self.node.nth_child(0).is_leaf()
+    }
+
     pub fn iter(&self) -> WithItemsIterator<'db> {
         WithItemsIterator(self.node.iter_children())
     }
@@ -1685,18 +1838,29 @@ impl<'db> ExceptStarBlock<'db> {
 }
 
 impl<'db> ExceptExpression<'db> {
-    pub fn unpack(&self) -> (Expression<'db>, Option<NameDef<'db>>) {
-        // except_expression: [expression ["as" name_def]]
+    pub fn unpack(&self) -> (ExceptTypes<'db>, Option<NameDef<'db>>) {
+        // except_expression: expression ["as" name_def | "," [",".expression+ [","]]]
         let mut clause_iterator = self.node.iter_children();
         let expr = clause_iterator.next().unwrap();
-        clause_iterator.next();
-        let as_name = clause_iterator.next().map(NameDef::new);
-        (Expression::new(expr), as_name)
+        match clause_iterator.next() {
+            Some(keyword) if keyword.as_code() == "," => (
+                ExceptTypes::Tuple(StarExpressionsIterator(
+                    self.node.iter_children().step_by(2),
+                )),
+                None,
+            ),
+            _ => (
+                ExceptTypes::Expression(Expression::new(expr)),
+                clause_iterator.next().map(NameDef::new),
+            ),
+        }
     }
+}
 
-    pub fn expression(&self) -> Expression<'db> {
-        Expression::new(self.node.nth_child(0))
-    }
+pub enum ExceptTypes<'db> {
+    Expression(Expression<'db>),
+    // Unparenthesized like `except A, B:`, which is allowed since Python 3.14
+    Tuple(StarExpressionsIterator<'db>),
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -2123,6 +2287,10 @@ impl<'db> ClassDef<'db> {
         self.name_def().name()
     }
 
+    pub fn parent_scope(&self) -> Scope<'db> {
+        scope_for_node(self.node)
+    }
+
     pub fn arguments(&self) -> Option<Arguments<'db>> {
         let mut args = self.node.nth_child(3);
         if args.is_leaf() {
@@ -2956,6 +3124,30 @@ impl<'db> ImportFrom<'db> {
         (level, None)
     }
 
+    /// The range of e.g. `..foo.bar` in `from ..foo.bar import baz`
+    pub fn module_range(&self) -> (CodeIndex, CodeIndex) {
+        let mut start = None;
+        let mut end = 0;
+        for node in self.node.iter_children().skip(1) {
+            if node.as_code() == "import" {
+                break;
+            }
+            start.get_or_insert(node.start());
+            end = node.end();
+        }
+        (start.unwrap_or(end), end)
+    }
+
+    /// The range of e.g. `(baz, qux)` in `from foo import (baz, qux)`
+    pub fn targets_range(&self) -> (CodeIndex, CodeIndex) {
+        let targets = self
+            .node
+            .iter_children()
+            .find(|n| n.is_type(Nonterminal(import_from_targets)))
+            .unwrap();
+        (targets.start(), targets.end())
+    }
+
     pub fn unpack_targets(&self) -> ImportFromTargets<'db> {
         // import_from_targets:
         //     "*" | "(" ",".import_from_as_name+ ","? ")" | ",".import_from_as_name+
@@ -3841,6 +4033,18 @@ impl Argument<'_> {
 }
 
 impl<'db> Kwarg<'db> {
+    /// Returns the call of the keyword argument, e.g. `f(x=1)`, but not for class definitions
+    /// like `class C(metaclass=M)`.
+    pub fn maybe_call(&self) -> Option<Primary<'db>> {
+        let parent = self
+            .node
+            .parent_until(&[Nonterminal(arguments)])?
+            .parent()?;
+        parent
+            .is_type(Nonterminal(primary))
+            .then(|| Primary::new(parent))
+    }
+
     pub fn unpack(&self) -> (Name<'db>, Expression<'db>) {
         // kwarg: Name "=" expression
         let mut kwarg_iterator = self.node.iter_children();
@@ -4639,9 +4843,67 @@ pub enum StringType<'db> {
 }
 
 impl<'db> FString<'db> {
+    pub fn is_template_string(&self) -> bool {
+        // The prefix of the FStringStart is something like `f`, `rf`, `t` or `Rt`
+        self.node.nth_child(0).as_code().contains(['t', 'T'])
+    }
+
     pub fn iter_content(&self) -> impl Iterator<Item = FStringContent<'db>> {
         FStringContentIterator(self.node.iter_children().skip(1))
     }
+
+    /// Finds syntax in replacement fields that is only allowed since Python 3.12 (PEP 701) and
+    /// returns the node together with a description of the feature.
+    pub fn find_pep_701_syntax(&self) -> Option<(NodeIndex, &'static str)> {
+        let start = self.node.nth_child(0).as_code();
+        let quote = start.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+        find_pep_701_syntax_in_content(self.iter_content(), quote)
+    }
+}
+
+fn find_pep_701_syntax_in_content<'db>(
+    content: impl Iterator<Item = FStringContent<'db>>,
+    quote: &str,
+) -> Option<(NodeIndex, &'static str)> {
+    for c in content {
+        let FStringContent::FStringExpr(e) = c else {
+            continue;
+        };
+        let (exprs, format_spec) = e.unpack();
+        for leaf in exprs.node.search(
+            &[
+                Terminal(TerminalType::String),
+                Terminal(TerminalType::FStringStart),
+                Terminal(TerminalType::FStringString),
+            ],
+            false,
+        ) {
+            let code = leaf.as_code();
+            if !leaf.is_type(Terminal(TerminalType::FStringString))
+                && code
+                    .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+                    .starts_with(quote)
+            {
+                return Some((
+                    leaf.index,
+                    "f-string expressions containing the enclosing quote are",
+                ));
+            }
+            if code.contains('\\') {
+                return Some((
+                    leaf.index,
+                    "f-string expressions containing backslashes are",
+                ));
+            }
+        }
+        if let Some(format_spec) = format_spec {
+            let result = find_pep_701_syntax_in_content(format_spec.iter_content(), quote);
+            if result.is_some() {
+                return result;
+            }
+        }
+    }
+    None
 }
 
 pub struct FStringContentIterator<'db>(Skip<SiblingIterator<'db>>);
@@ -4691,6 +4953,23 @@ impl<'db> Expressions<'db> {
     }
 }
 
+#[derive(Debug, Copy, Clone)]
+pub enum StringKeyUsage<'db> {
+    Subscript(Primary<'db>),
+    SubscriptTarget(PrimaryTarget<'db>),
+    Dict(Atom<'db>),
+}
+
+impl<'db> StringKeyUsage<'db> {
+    pub fn parent_scope(&self) -> Scope<'db> {
+        scope_for_node(match self {
+            Self::Subscript(p) => p.node,
+            Self::SubscriptTarget(t) => t.node,
+            Self::Dict(a) => a.node,
+        })
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum GotoNode<'db> {
     Name(Name<'db>),
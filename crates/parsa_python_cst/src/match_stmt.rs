@@ -1,10 +1,16 @@
 use crate::{
-    Block, CaseBlock, ClassPattern, DottedPatternName, DoubleStarPattern, GroupPattern, Guard,
-    KeyValuePattern, KeywordPattern, LiteralPattern, MappingPattern, MatchStmt, Name, NameDef,
-    NamedExpression, OpenSequencePattern, OrPattern, Pattern, SequencePattern,
-    StarLikeExpressionIterator, StarPattern, SubjectExpr, WildcardPattern,
+    Block, Bytes, CaseBlock, ClassPattern, ComplexNumber, DottedPatternName, DoubleStarPattern,
+    GroupPattern, Guard, Int, KeyValuePattern, Keyword, KeywordPattern, LiteralPattern,
+    MappingPattern, MatchStmt, Name, NameDef, NamedExpression, OpenSequencePattern, OrPattern,
+    Pattern, SequencePattern, SignedNumber, StarLikeExpressionIterator, StarPattern, Strings,
+    SubjectExpr, WildcardPattern,
+};
+use parsa_python::{
+    NonterminalType::*,
+    PyNode,
+    PyNodeType::{self, Nonterminal},
+    SiblingIterator,
 };
-use parsa_python::{NonterminalType::*, PyNode, PyNodeType::Nonterminal, SiblingIterator};
 
 impl<'db> MatchStmt<'db> {
     pub fn unpack(&self) -> (SubjectExpr<'db>, impl Iterator<Item = CaseBlock<'db>>) {
@@ -110,6 +116,48 @@ fn pattern_node_to_kind(node: PyNode) -> PatternKind {
     }
 }
 
+pub enum LiteralPatternContent<'db> {
+    Strings(Strings<'db>),
+    Bytes(Bytes<'db>),
+    SignedNumber(SignedNumber<'db>),
+    ComplexNumber(ComplexNumber<'db>),
+    NoneLiteral,
+    Bool(Keyword<'db>),
+}
+
+impl<'db> LiteralPattern<'db> {
+    pub fn unpack(&self) -> LiteralPatternContent<'db> {
+        let first = self.node.nth_child(0);
+        match first.type_() {
+            Nonterminal(strings) => LiteralPatternContent::Strings(Strings::new(first)),
+            Nonterminal(bytes) => LiteralPatternContent::Bytes(Bytes::new(first)),
+            Nonterminal(signed_number) => {
+                LiteralPatternContent::SignedNumber(SignedNumber::new(first))
+            }
+            Nonterminal(complex_number) => {
+                LiteralPatternContent::ComplexNumber(ComplexNumber::new(first))
+            }
+            PyNodeType::Keyword => match first.as_code() {
+                "None" => LiteralPatternContent::NoneLiteral,
+                _ => LiteralPatternContent::Bool(Keyword::new(first)),
+            },
+            _ => unreachable!("{first:?}"),
+        }
+    }
+}
+
+impl<'db> SignedNumber<'db> {
+    pub fn is_negative(&self) -> bool {
+        self.node.nth_child(0).as_code() == "-"
+    }
+
+    pub fn maybe_int(&self) -> Option<Int<'db>> {
+        let number = self.node.iter_children().last().unwrap();
+        let code = number.as_code();
+        (!code.contains(['j', 'J', '.'])).then(|| Int::new(number))
+    }
+}
+
 impl<'db> ClassPattern<'db> {
     pub fn unpack(
         &self,
@@ -144,6 +192,11 @@ pub enum ParamPattern<'db> {
 }
 
 impl<'db> KeywordPattern<'db> {
+    pub fn class_pattern(&self) -> ClassPattern<'db> {
+        // class_pattern: dotted_pattern_name "(" param_patterns? ")"
+        ClassPattern::new(self.node.parent().unwrap().parent().unwrap())
+    }
+
     pub fn unpack(&self) -> (Name<'db>, Pattern<'db>) {
         let mut iterator = self.node.iter_children();
         let name = Name::new(iterator.next().unwrap());
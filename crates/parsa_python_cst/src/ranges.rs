@@ -0,0 +1,224 @@
+use parsa_python::{
+    CodeIndex, NodeIndex,
+    NonterminalType::*,
+    PyNode,
+    PyNodeType::{Nonterminal, Terminal},
+    TerminalType,
+};
+
+use crate::{ClassDef, FunctionDef, Tree, refactoring::end_without_newlines};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldingRangeKind {
+    Comment,
+    Imports,
+    Region,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FoldingRange {
+    pub start: CodeIndex,
+    pub end: CodeIndex,
+    pub kind: FoldingRangeKind,
+}
+
+impl Tree {
+    /// Returns the ranges of the nodes around the position, from the innermost to the outermost.
+    pub fn selection_ranges(&self, position: CodeIndex) -> Vec<(CodeIndex, CodeIndex)> {
+        let mut leaf = self.0.leaf_by_position(position);
+        if let Some(previous) = leaf.previous_leaf()
+            && previous.end() == position
+            && (leaf.start() > position
+                || is_whitespace_leaf(leaf)
+                || !leaf.is_type(Terminal(TerminalType::Name))
+                    && previous.is_type(Terminal(TerminalType::Name)))
+        {
+            leaf = previous
+        }
+        let mut result: Vec<(CodeIndex, CodeIndex)> = vec![];
+        let mut node = Some(leaf);
+        while let Some(n) = node {
+            let start = if n.is_type(Nonterminal(block)) {
+                // Indented blocks start with a newline, which should not be selected
+                n.iter_children()
+                    .find(|child| child.is_type(Nonterminal(stmt)))
+                    .map(|first| first.start())
+                    .unwrap_or_else(|| n.start())
+            } else {
+                n.start()
+            };
+            let end = if n.is_leaf() {
+                n.end()
+            } else {
+                end_without_newlines(n)
+            };
+            if start < end && result.last() != Some(&(start, end)) {
+                result.push((start, end))
+            }
+            node = n.parent();
+        }
+        result
+    }
+
+    /// Returns the ranges that can be folded, sorted by their start.
+    pub fn folding_ranges(&self) -> Vec<FoldingRange> {
+        let mut result = vec![];
+        let mut add = |start, end, kind| {
+            if self.code()[start as usize..end as usize].contains('\n') {
+                result.push(FoldingRange { start, end, kind })
+            }
+        };
+        let mut docstrings: Vec<NodeIndex> = self
+            .root()
+            .docstring()
+            .map(|d| d.index())
+            .into_iter()
+            .collect();
+        for node in self.0.nodes() {
+            match node.type_() {
+                Nonterminal(block) => {
+                    if node.nth_child(0).is_type(Terminal(TerminalType::Newline)) {
+                        add(
+                            header_start(node),
+                            end_without_newlines(node),
+                            FoldingRangeKind::Other,
+                        )
+                    }
+                    add_import_groups(node, &mut add)
+                }
+                Nonterminal(file) => add_import_groups(node, &mut add),
+                Nonterminal(function_def) => {
+                    docstrings.extend(FunctionDef::new(node).docstring().map(|d| d.index()))
+                }
+                Nonterminal(class_def) => {
+                    docstrings.extend(ClassDef::new(node).docstring().map(|d| d.index()))
+                }
+                Nonterminal(match_stmt) => add(
+                    node.start(),
+                    end_without_newlines(node),
+                    FoldingRangeKind::Other,
+                ),
+                Nonterminal(strings) => {
+                    let kind = if docstrings.contains(&node.index) {
+                        FoldingRangeKind::Comment
+                    } else {
+                        FoldingRangeKind::Other
+                    };
+                    add(node.start(), node.end(), kind)
+                }
+                Nonterminal(atom | primary | function_def_parameters) => {
+                    // The contents of brackets, the closing bracket stays visible.
+                    if let Some(opening) = node
+                        .iter_children()
+                        .find(|child| child.is_leaf() && matches!(child.as_code(), "(" | "[" | "{"))
+                    {
+                        let closing = node.last_leaf_in_subtree();
+                        if let Some(last_inner) = closing.previous_leaf() {
+                            add(opening.start(), last_inner.end(), FoldingRangeKind::Other)
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        self.add_region_folds(&mut add);
+        result.sort_by_key(|range| range.start);
+        result
+    }
+
+    /// Adds folds between `# region` and `# endregion` comments
+    fn add_region_folds(&self, add: &mut impl FnMut(CodeIndex, CodeIndex, FoldingRangeKind)) {
+        let code = self.code();
+        let mut region_starts = vec![];
+        let mut offset = 0;
+        for line in code.split_inclusive('\n') {
+            let trimmed = line.trim_start();
+            let comment_start = (offset + line.len() - trimmed.len()) as CodeIndex;
+            offset += line.len();
+            let Some(comment) = trimmed.strip_prefix('#') else {
+                continue;
+            };
+            let leaf = self.0.leaf_by_position(comment_start);
+            if leaf.start() <= comment_start && comment_start < leaf.end() {
+                // The `#` is part of a string
+                continue;
+            }
+            match comment.split_whitespace().next() {
+                Some("region") => region_starts.push(comment_start),
+                Some("endregion") => {
+                    if let Some(start) = region_starts.pop() {
+                        let end = comment_start + trimmed.trim_end().len() as CodeIndex;
+                        add(start, end, FoldingRangeKind::Region)
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Returns the start of the clause of a block, e.g. the `else` in `for ...: ... else: ...`.
+fn header_start(block_node: PyNode) -> CodeIndex {
+    let parent = block_node.parent().unwrap();
+    let mut start = parent.start();
+    let mut after_block = false;
+    for child in parent.iter_children() {
+        if child.index == block_node.index {
+            break;
+        }
+        if after_block {
+            start = child.start();
+            after_block = false;
+        }
+        if child.is_type(Nonterminal(block)) {
+            after_block = true;
+        }
+    }
+    start
+}
+
+/// Folds consecutive import statements
+fn add_import_groups(node: PyNode, add: &mut impl FnMut(CodeIndex, CodeIndex, FoldingRangeKind)) {
+    let mut group: Option<(CodeIndex, CodeIndex)> = None;
+    for child in node.iter_children() {
+        if is_import_stmt(child) {
+            let end = end_without_newlines(child);
+            group = Some(match group {
+                Some((start, _)) => (start, end),
+                None => (child.start(), end),
+            });
+        } else if let Some((start, end)) = group.take() {
+            add(start, end, FoldingRangeKind::Imports)
+        }
+    }
+    if let Some((start, end)) = group {
+        add(start, end, FoldingRangeKind::Imports)
+    }
+}
+
+fn is_import_stmt(node: PyNode) -> bool {
+    if !node.is_type(Nonterminal(stmt)) {
+        return false;
+    }
+    let simple_stmts_ = node.nth_child(0);
+    simple_stmts_.is_type(Nonterminal(simple_stmts))
+        && simple_stmts_
+            .iter_children()
+            .filter(|n| n.is_type(Nonterminal(simple_stmt)))
+            .all(|n| {
+                let inner = n.nth_child(0);
+                inner.is_type(Nonterminal(import_name)) || inner.is_type(Nonterminal(import_from))
+            })
+}
+
+fn is_whitespace_leaf(leaf: PyNode) -> bool {
+    [
+        TerminalType::Newline,
+        TerminalType::Indent,
+        TerminalType::Dedent,
+        TerminalType::Endmarker,
+    ]
+    .iter()
+    .any(|&t| leaf.is_type(Terminal(t)))
+}
@@ -0,0 +1,593 @@
+use parsa_python::{
+    CodeIndex, NodeIndex,
+    NonterminalType::*,
+    PyNode, PyNodeType,
+    PyNodeType::{Nonterminal, Terminal},
+    TerminalType,
+};
+
+use crate::{
+    Assignment, Expression, ExpressionPart, Name, Scope, StringLiteral, Tree,
+    completion::scope_for_node,
+};
+
+impl Tree {
+    /// Returns the expression that is exactly covered by the range, whitespace around it is
+    /// ignored.
+    pub fn expression_in_range(
+        &self,
+        start: CodeIndex,
+        end: CodeIndex,
+    ) -> Option<SelectedExpression<'_>> {
+        let (start, end) = self.strip_whitespace(start, end)?;
+        let mut node = self.0.leaf_by_position(start);
+        if node.start() != start {
+            return None;
+        }
+        let mut result = None;
+        while let Some(parent) = node.parent() {
+            if parent.start() != start || parent.end() > end {
+                break;
+            }
+            node = parent;
+            if node.end() == end
+                && (node.is_type(Nonterminal(expression))
+                    || is_expression_part(node) && result.is_none())
+            {
+                result = Some(SelectedExpression { node })
+            }
+        }
+        result
+    }
+
+    /// Returns the statements that are covered by the range. The range needs to start at the
+    /// beginning of a statement and end at the end of a statement in the same block.
+    pub fn statements_in_range(
+        &self,
+        start: CodeIndex,
+        end: CodeIndex,
+    ) -> Option<SelectedStatements<'_>> {
+        let (start, end) = self.strip_whitespace(start, end)?;
+        let mut node = self.0.leaf_by_position(start);
+        if node.start() != start {
+            return None;
+        }
+        let mut first = None;
+        while let Some(parent) = node.parent() {
+            if parent.start() != start {
+                break;
+            }
+            node = parent;
+            if node.is_type(Nonterminal(stmt)) {
+                first = Some(node);
+            }
+        }
+        let first = first?;
+        let mut last = first;
+        let mut current = first;
+        while let Some(next) = current.next_sibling() {
+            if !next.is_type(Nonterminal(stmt)) || next.start() >= end {
+                break;
+            }
+            last = next;
+            current = next;
+        }
+        let statements_end = end_without_newlines(last);
+        (statements_end <= end).then_some(SelectedStatements {
+            first,
+            last,
+            end: statements_end,
+        })
+    }
+
+    /// All reads and definitions of the variables of a scope in the range. Reads within nested
+    /// scopes are included if the nested scopes don't define the name themselves.
+    pub fn variable_usages(
+        &self,
+        scope: Scope,
+        start: CodeIndex,
+        end: CodeIndex,
+    ) -> Vec<VariableUsage<'_>> {
+        let scope_index = scope_node_index(scope);
+        let names: Vec<_> = self
+            .0
+            .nodes()
+            .skip_while(|n| n.start() < start)
+            .take_while(|n| n.start() < end)
+            .filter(|n| n.is_type(Terminal(TerminalType::Name)))
+            .collect();
+        let mut nested_definitions = vec![];
+        let mut result = vec![];
+        for &leaf in &names {
+            let parent = leaf.parent().unwrap();
+            if !parent.is_type(Nonterminal(name_def)) {
+                continue;
+            }
+            let name_def_parent = parent.parent().unwrap();
+            if name_def_parent.is_type(Nonterminal(global_stmt))
+                || name_def_parent.is_type(Nonterminal(nonlocal_stmt))
+                || is_comprehension_target(parent)
+            {
+                continue;
+            }
+            let defining_scope = if name_def_parent.is_type(Nonterminal(function_def))
+                || name_def_parent.is_type(Nonterminal(class_def))
+            {
+                scope_for_node(name_def_parent)
+            } else {
+                scope_for_node(leaf)
+            };
+            if scope_node_index(defining_scope) == scope_index {
+                let name = Name::new(leaf);
+                if is_aug_assign_target(parent) {
+                    result.push(VariableUsage {
+                        name,
+                        is_definition: false,
+                    });
+                }
+                result.push(VariableUsage {
+                    name,
+                    is_definition: true,
+                });
+            } else {
+                nested_definitions.push((scope_node_index(defining_scope), leaf.as_code()));
+            }
+        }
+        for leaf in names {
+            if !leaf.parent().unwrap().is_type(Nonterminal(atom)) || is_bound_by_comprehension(leaf)
+            {
+                continue;
+            }
+            // Check if a nested scope defines the name
+            let mut current = scope_for_node(leaf);
+            let mut is_shadowed = false;
+            while scope_node_index(current) != scope_index {
+                let index = scope_node_index(current);
+                if nested_definitions.contains(&(index, leaf.as_code())) {
+                    is_shadowed = true;
+                    break;
+                }
+                current = match current {
+                    Scope::Module => break,
+                    Scope::Class(c) => scope_for_node(c.node),
+                    Scope::Function(f) => scope_for_node(f.node),
+                    Scope::Lambda(l) => scope_for_node(l.node),
+                };
+            }
+            if !is_shadowed {
+                result.push(VariableUsage {
+                    name: Name::new(leaf),
+                    is_definition: false,
+                })
+            }
+        }
+        result.sort_by_key(|usage| (usage.name.start(), usage.is_definition));
+        result
+    }
+
+    fn strip_whitespace(&self, start: CodeIndex, end: CodeIndex) -> Option<(CodeIndex, CodeIndex)> {
+        let code = self.code().get(start as usize..end as usize)?;
+        let trimmed_start = code.trim_start();
+        let new_start = start + (code.len() - trimmed_start.len()) as CodeIndex;
+        let new_end = new_start + trimmed_start.trim_end().len() as CodeIndex;
+        (new_start < new_end).then_some((new_start, new_end))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VariableUsage<'db> {
+    pub name: Name<'db>,
+    pub is_definition: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SelectedExpression<'db> {
+    node: PyNode<'db>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SelectedExpressionContent<'db> {
+    Expression(Expression<'db>),
+    ExpressionPart(ExpressionPart<'db>),
+}
+
+impl<'db> SelectedExpression<'db> {
+    fn node(&self) -> PyNode<'db> {
+        self.node
+    }
+
+    pub fn unpack(&self) -> SelectedExpressionContent<'db> {
+        if self.node.is_type(Nonterminal(expression)) {
+            SelectedExpressionContent::Expression(Expression::new(self.node))
+        } else {
+            SelectedExpressionContent::ExpressionPart(ExpressionPart::new(self.node))
+        }
+    }
+
+    pub fn start(&self) -> CodeIndex {
+        self.node().start()
+    }
+
+    pub fn end(&self) -> CodeIndex {
+        self.node().end()
+    }
+
+    pub fn as_code(&self) -> &'db str {
+        self.node().as_code()
+    }
+
+    pub fn scope(&self) -> Scope<'db> {
+        scope_for_node(self.node())
+    }
+
+    /// The position where the expression can be evaluated before its statement without changing
+    /// the semantics. Returns None for expressions in lambdas, comprehensions, `elif` and
+    /// `while` conditions and for expressions that are only evaluated conditionally, like the
+    /// right side of `and`/`or`, the branches of ternaries and the types of except clauses.
+    pub fn statement_start(&self) -> Option<CodeIndex> {
+        let mut node = self.node();
+        while let Some(parent) = node.parent() {
+            if parent.is_type(Nonterminal(simple_stmt)) || parent.is_type(Nonterminal(stmt)) {
+                return Some(parent.start());
+            }
+            if parent.is_type(Nonterminal(lambda))
+                || parent.is_type(Nonterminal(comprehension))
+                || parent.is_type(Nonterminal(dict_comprehension))
+                || parent.is_type(Nonterminal(while_stmt))
+                    && node.is_type(Nonterminal(named_expression))
+                || parent.is_type(Nonterminal(if_stmt))
+                    && node.is_type(Nonterminal(named_expression))
+                    && node.previous_sibling().unwrap().as_code() == "elif"
+                || (parent.is_type(Nonterminal(disjunction))
+                    || parent.is_type(Nonterminal(conjunction)))
+                    && node.previous_sibling().is_some()
+                || parent.is_type(Nonterminal(ternary))
+                    && node.previous_sibling().map(|n| n.as_code()) != Some("if")
+                || parent.is_type(Nonterminal(except_expression))
+            {
+                return None;
+            }
+            node = parent;
+        }
+        None
+    }
+
+    pub fn top_level_statement_start(&self) -> CodeIndex {
+        top_level_statement_start(self.node())
+    }
+
+    /// Whether the expression is a statement on its own, e.g. `foo()` in `foo()\n`
+    pub fn is_expression_statement(&self) -> bool {
+        let mut node = self.node();
+        while let Some(parent) = node.parent() {
+            if parent.start() != node.start() || parent.end() != node.end() {
+                return false;
+            }
+            if parent.is_type(Nonterminal(simple_stmt)) {
+                return true;
+            }
+            node = parent;
+        }
+        false
+    }
+
+    pub fn contains_await(&self) -> bool {
+        let node = self.node();
+        node.is_type(Nonterminal(await_primary))
+            || !node.is_leaf()
+                && node
+                    .search(&[Nonterminal(await_primary)], false)
+                    .any(|n| scope_node_index(scope_for_node(n)) == scope_node_index(self.scope()))
+    }
+
+    pub fn contains_yield(&self) -> bool {
+        let node = self.node();
+        !node.is_leaf()
+            && node
+                .search(&[Nonterminal(yield_expr)], false)
+                .next()
+                .is_some()
+    }
+
+    /// Whether the expression can be used in other expressions without adding parentheses.
+    pub fn is_atomic(&self) -> bool {
+        let mut node = self.node;
+        if node.is_type(Nonterminal(expression)) {
+            node = node.nth_child(0);
+        }
+        node.is_type(Nonterminal(atom)) || node.is_type(Nonterminal(primary))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SelectedStatements<'db> {
+    first: PyNode<'db>,
+    last: PyNode<'db>,
+    end: CodeIndex,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ControlFlow {
+    pub has_return: bool,
+    pub has_yield: bool,
+    pub has_await: bool,
+    pub has_loop_control_outside_of_loop: bool,
+    pub has_global_or_nonlocal: bool,
+}
+
+impl<'db> SelectedStatements<'db> {
+    pub fn start(&self) -> CodeIndex {
+        self.first.start()
+    }
+
+    pub fn end(&self) -> CodeIndex {
+        self.end
+    }
+
+    pub fn scope(&self) -> Scope<'db> {
+        scope_for_node(self.first)
+    }
+
+    pub fn top_level_statement_start(&self) -> CodeIndex {
+        top_level_statement_start(self.first)
+    }
+
+    /// Whether the statements are the last statements of the function they are in
+    pub fn is_at_end_of_function(&self) -> bool {
+        let Some(parent) = self.last.parent() else {
+            return false;
+        };
+        parent.is_type(Nonterminal(block))
+            && parent.parent().unwrap().is_type(Nonterminal(function_def))
+            && self
+                .last
+                .next_sibling()
+                .is_none_or(|n| !n.is_type(Nonterminal(stmt)))
+    }
+
+    pub fn control_flow(&self) -> ControlFlow {
+        let scope_index = scope_node_index(self.scope());
+        let mut result = ControlFlow::default();
+        let mut current = Some(self.first);
+        while let Some(statement) = current {
+            for node in statement.search(
+                &[
+                    Nonterminal(return_stmt),
+                    Nonterminal(yield_expr),
+                    Nonterminal(await_primary),
+                    Nonterminal(async_stmt),
+                    Nonterminal(break_stmt),
+                    Nonterminal(continue_stmt),
+                    Nonterminal(global_stmt),
+                    Nonterminal(nonlocal_stmt),
+                ],
+                false,
+            ) {
+                if scope_node_index(scope_for_node(node)) != scope_index {
+                    continue;
+                }
+                match node.type_() {
+                    Nonterminal(return_stmt) => result.has_return = true,
+                    Nonterminal(yield_expr) => result.has_yield = true,
+                    Nonterminal(await_primary) => result.has_await = true,
+                    Nonterminal(async_stmt) => {
+                        if !node.nth_child(1).is_type(Nonterminal(function_def)) {
+                            result.has_await = true
+                        }
+                    }
+                    Nonterminal(break_stmt | continue_stmt) => {
+                        let is_in_loop = node
+                            .parent_until(&[Nonterminal(for_stmt), Nonterminal(while_stmt)])
+                            .is_some_and(|loop_| loop_.start() >= self.start());
+                        if !is_in_loop {
+                            result.has_loop_control_outside_of_loop = true
+                        }
+                    }
+                    _ => result.has_global_or_nonlocal = true,
+                }
+            }
+            if statement.index == self.last.index {
+                break;
+            }
+            current = statement.next_sibling();
+        }
+        result
+    }
+}
+
+impl Name<'_> {
+    /// Whether the name is a complete expression, e.g. `x` in `foo(x)`, but not in `x + 1`.
+    pub fn is_complete_expression(&self) -> bool {
+        let parent = self.node.parent().unwrap();
+        parent.is_type(Nonterminal(atom))
+            && parent
+                .parent()
+                .is_some_and(|n| n.is_type(Nonterminal(expression)))
+    }
+}
+
+impl StringLiteral<'_> {
+    /// Whether the string is a forward reference, i.e. it is part of an annotation, the type of a
+    /// `cast(...)` or the value of an explicit `TypeAlias`.
+    pub fn is_forward_reference(&self) -> bool {
+        let Some(parent) = self.node.parent_until(&[
+            Nonterminal(annotation),
+            Nonterminal(return_annotation),
+            Nonterminal(arguments),
+            Nonterminal(assignment),
+            Nonterminal(stmt),
+        ]) else {
+            return false;
+        };
+        match parent.type_() {
+            Nonterminal(annotation | return_annotation) => true,
+            Nonterminal(arguments) => {
+                // Only the first argument of cast is a type
+                let strings_ = self.node.parent().unwrap();
+                let first = parent.nth_child(0);
+                first.start() == strings_.start()
+                    && first.end() == strings_.end()
+                    && parent.parent().is_some_and(|call| {
+                        let callee = call.nth_child(0).as_code();
+                        callee == "cast" || callee.ends_with(".cast")
+                    })
+            }
+            Nonterminal(assignment) => parent.iter_children().any(|child| {
+                child.is_type(Nonterminal(annotation))
+                    && child.nth_child(1).as_code().ends_with("TypeAlias")
+            }),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StandaloneStatement {
+    pub start: CodeIndex,
+    /// Includes the newline of the statement
+    pub end: CodeIndex,
+    pub is_only_statement_in_block: bool,
+}
+
+impl Assignment<'_> {
+    /// Returns the statement of the assignment if there are no other statements on the same line.
+    pub fn maybe_standalone_statement(&self) -> Option<StandaloneStatement> {
+        let simple_stmt_ = self.node.parent().unwrap();
+        let simple_stmts_ = simple_stmt_.parent().unwrap();
+        if simple_stmts_.iter_children().count() != 2 {
+            return None;
+        }
+        let stmt_ = simple_stmts_.parent().unwrap();
+        if !stmt_.is_type(Nonterminal(stmt)) {
+            return None;
+        }
+        let parent = stmt_.parent().unwrap();
+        Some(StandaloneStatement {
+            start: stmt_.start(),
+            end: stmt_.end(),
+            is_only_statement_in_block: parent.is_type(Nonterminal(block))
+                && parent
+                    .iter_children()
+                    .filter(|n| n.is_type(Nonterminal(stmt)))
+                    .count()
+                    == 1,
+        })
+    }
+}
+
+impl Expression<'_> {
+    /// Whether evaluating the expression might change something, because it contains calls,
+    /// `await`, `yield` or assignment expressions. The bodies of lambdas are not evaluated.
+    pub fn may_have_side_effects(&self) -> bool {
+        const SEARCH: &[PyNodeType] = &[
+            Nonterminal(primary),
+            Nonterminal(await_primary),
+            Nonterminal(yield_expr),
+            Nonterminal(walrus),
+        ];
+        self.node.search(SEARCH, false).any(|node| {
+            if node.is_type(Nonterminal(primary)) && node.nth_child(1).as_code() != "(" {
+                return false;
+            }
+            let mut current = node;
+            while let Some(parent) = current.parent() {
+                if parent.index == self.node.index {
+                    return true;
+                }
+                if parent.is_type(Nonterminal(lambda)) {
+                    return false;
+                }
+                current = parent;
+            }
+            true
+        })
+    }
+}
+
+fn is_expression_part(node: PyNode) -> bool {
+    [
+        atom,
+        primary,
+        await_primary,
+        power,
+        factor,
+        term,
+        sum,
+        shift_expr,
+        bitwise_and,
+        bitwise_xor,
+        bitwise_or,
+        comparison,
+        inversion,
+        conjunction,
+        disjunction,
+    ]
+    .iter()
+    .any(|&t| node.is_type(Nonterminal(t)))
+}
+
+fn scope_node_index(scope: Scope) -> Option<NodeIndex> {
+    match scope {
+        Scope::Module => None,
+        Scope::Class(c) => Some(c.index()),
+        Scope::Function(f) => Some(f.index()),
+        Scope::Lambda(l) => Some(l.index()),
+    }
+}
+
+fn top_level_statement_start(node: PyNode) -> CodeIndex {
+    let mut result = node;
+    while let Some(statement) = result.parent_until(&[Nonterminal(stmt)]) {
+        result = statement;
+    }
+    result.start()
+}
+
+pub(crate) fn end_without_newlines(node: PyNode) -> CodeIndex {
+    let mut leaf = node.last_leaf_in_subtree();
+    while leaf.is_type(Terminal(TerminalType::Newline))
+        || leaf.is_type(Terminal(TerminalType::Dedent))
+        || leaf.is_type(Terminal(TerminalType::Indent))
+        || leaf.is_type(Terminal(TerminalType::Endmarker))
+    {
+        leaf = leaf.previous_leaf().unwrap();
+    }
+    leaf.end()
+}
+
+fn is_comprehension_target(name_def_node: PyNode) -> bool {
+    name_def_node
+        .parent_until(&[Nonterminal(sync_for_if_clause), Nonterminal(stmt)])
+        .is_some_and(|n| {
+            n.is_type(Nonterminal(sync_for_if_clause))
+                && name_def_node.start() < n.nth_child(2).start()
+        })
+}
+
+fn is_aug_assign_target(name_def_node: PyNode) -> bool {
+    let mut node = name_def_node;
+    while let Some(parent) = node.parent() {
+        if !parent.is_type(Nonterminal(single_target)) {
+            break;
+        }
+        node = parent;
+    }
+    node.next_sibling()
+        .is_some_and(|n| n.is_type(Nonterminal(augassign)))
+}
+
+fn is_bound_by_comprehension(name: PyNode) -> bool {
+    let mut node = name;
+    while let Some(parent) =
+        node.parent_until(&[Nonterminal(comprehension), Nonterminal(dict_comprehension)])
+    {
+        let binds_name = parent
+            .search(&[Nonterminal(name_def)], false)
+            .any(|n| n.as_code() == name.as_code() && is_comprehension_target(n));
+        if binds_name {
+            return true;
+        }
+        node = parent;
+    }
+    false
+}
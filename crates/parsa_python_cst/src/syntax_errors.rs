@@ -0,0 +1,392 @@
+use std::fmt;
+
+use parsa_python::{
+    NodeIndex,
+    NonterminalType::*,
+    PyNode,
+    PyNodeType::{ErrorNonterminal, ErrorTerminal, Nonterminal, Terminal},
+    TerminalType,
+};
+
+use crate::Tree;
+
+/// A more specific explanation for an error recovery node. The messages are the same that
+/// CPython uses.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SyntaxErrorKind {
+    InvalidSyntax,
+    InvalidCharacter(char),
+    UnterminatedString {
+        triple_quoted: bool,
+        detected_at_line: usize,
+    },
+    InvalidNumberLiteral(&'static str),
+    UnmatchedBracket(char),
+    MismatchedBracket {
+        closing: char,
+        opening: char,
+    },
+    BracketNeverClosed(char),
+    ExpectedColon,
+    CannotAssignTo(&'static str),
+    IllegalAugmentedAssignmentTarget(&'static str),
+    UnexpectedIndent,
+    UnindentDoesNotMatch,
+    ExpectedIndentedBlock {
+        after: &'static str,
+        line: usize,
+    },
+}
+
+impl fmt::Display for SyntaxErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSyntax => write!(f, "invalid syntax"),
+            Self::InvalidCharacter(c) => {
+                write!(f, "invalid character '{c}' (U+{:04X})", *c as u32)
+            }
+            Self::UnterminatedString {
+                triple_quoted,
+                detected_at_line,
+            } => {
+                let triple = if *triple_quoted { "triple-quoted " } else { "" };
+                write!(
+                    f,
+                    "unterminated {triple}string literal (detected at line {detected_at_line})"
+                )
+            }
+            Self::InvalidNumberLiteral(kind) => write!(f, "invalid {kind} literal"),
+            Self::UnmatchedBracket(c) => write!(f, "unmatched '{c}'"),
+            Self::MismatchedBracket { closing, opening } => write!(
+                f,
+                "closing parenthesis '{closing}' does not match opening parenthesis '{opening}'"
+            ),
+            Self::BracketNeverClosed(c) => write!(f, "'{c}' was never closed"),
+            Self::ExpectedColon => write!(f, "expected ':'"),
+            Self::CannotAssignTo(target) => match *target {
+                "function call" | "literal" | "expression" | "ellipsis" => write!(
+                    f,
+                    "cannot assign to {target} here. Maybe you meant '==' instead of '='?"
+                ),
+                _ => write!(f, "cannot assign to {target}"),
+            },
+            Self::IllegalAugmentedAssignmentTarget(target) => {
+                write!(
+                    f,
+                    "'{target}' is an illegal expression for augmented assignment"
+                )
+            }
+            Self::UnexpectedIndent => write!(f, "unexpected indent"),
+            Self::UnindentDoesNotMatch => {
+                write!(f, "unindent does not match any outer indentation level")
+            }
+            Self::ExpectedIndentedBlock { after, line } => {
+                write!(f, "expected an indented block after {after} on line {line}")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SyntaxError {
+    pub index: NodeIndex,
+    pub kind: SyntaxErrorKind,
+}
+
+impl Tree {
+    /// Explains an error recovery statement (or a broken scope) that starts a sequence of
+    /// errors. Falls back to "invalid syntax" on the first node if nothing more specific is
+    /// found. `line_offset` is the number of lines in front of the code of this tree, if it is
+    /// only a fragment of a file (e.g. a type comment).
+    pub fn syntax_error(&self, index: NodeIndex, line_offset: usize) -> SyntaxError {
+        let node = self.0.node_by_index(index);
+        if node.is_type(Nonterminal(broken_scope)) {
+            // The first child is the Indent leaf.
+            return SyntaxError {
+                index: node.nth_child(1).index,
+                kind: SyntaxErrorKind::UnexpectedIndent,
+            };
+        }
+        let last = std::iter::successors(Some(node), |n| n.next_sibling())
+            .take_while(|n| n.is_error_recovery_node())
+            .last()
+            .unwrap();
+        self.syntax_error_in_leaves(node, last, line_offset)
+            .or_else(|| self.compound_stmt_syntax_error(node, line_offset))
+            .unwrap_or(SyntaxError {
+                index,
+                kind: SyntaxErrorKind::InvalidSyntax,
+            })
+    }
+
+    fn syntax_error_in_leaves(
+        &self,
+        first: PyNode,
+        last: PyNode,
+        line_offset: usize,
+    ) -> Option<SyntaxError> {
+        let last_leaf_index = last.last_leaf_in_subtree().index;
+        let mut brackets = vec![];
+        let mut leaf = first_leaf(first);
+        loop {
+            let code = leaf.as_code();
+            let error = |kind| {
+                Some(SyntaxError {
+                    index: leaf.index,
+                    kind,
+                })
+            };
+            match leaf.type_() {
+                ErrorTerminal(TerminalType::ErrorToken) => {
+                    let without_prefix = code.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+                    if without_prefix.starts_with(['"', '\'']) {
+                        let triple_quoted = without_prefix.starts_with(r#"""""#)
+                            || without_prefix.starts_with("'''");
+                        let detected_at = if triple_quoted {
+                            self.code().trim_end_matches(['\n', '\r']).len()
+                        } else {
+                            leaf.start() as usize
+                        };
+                        return error(SyntaxErrorKind::UnterminatedString {
+                            triple_quoted,
+                            detected_at_line: line_offset + self.line_of(detected_at),
+                        });
+                    }
+                    let c = code.chars().next()?;
+                    return error(if c.is_ascii() {
+                        SyntaxErrorKind::InvalidSyntax
+                    } else {
+                        SyntaxErrorKind::InvalidCharacter(c)
+                    });
+                }
+                ErrorTerminal(TerminalType::ErrorDedent) => {
+                    return Some(SyntaxError {
+                        index: leaf
+                            .next_leaf()
+                            .filter(|n| !n.is_type(Terminal(TerminalType::Endmarker)))
+                            .unwrap_or(leaf)
+                            .index,
+                        kind: SyntaxErrorKind::UnindentDoesNotMatch,
+                    });
+                }
+                Terminal(TerminalType::Number) | ErrorTerminal(TerminalType::Number) => {
+                    if let Some(next) = leaf.next_leaf()
+                        && next.start() == leaf.end()
+                        && matches!(
+                            next.type_(),
+                            Terminal(TerminalType::Name | TerminalType::Number)
+                                | ErrorTerminal(TerminalType::Name | TerminalType::Number)
+                        )
+                    {
+                        let lower = code.to_ascii_lowercase();
+                        return error(SyntaxErrorKind::InvalidNumberLiteral(
+                            if lower.starts_with("0x") {
+                                "hexadecimal"
+                            } else if lower.starts_with("0o") {
+                                "octal"
+                            } else if lower.starts_with("0b") {
+                                "binary"
+                            } else if lower.ends_with('j') {
+                                "imaginary"
+                            } else {
+                                "decimal"
+                            },
+                        ));
+                    }
+                }
+                _ => match code {
+                    "(" | "[" | "{" => brackets.push(leaf),
+                    ")" | "]" | "}" => {
+                        let closing = first_char(leaf);
+                        match brackets.pop().map(first_char) {
+                            Some(opening)
+                                if matches!(
+                                    (opening, closing),
+                                    ('(', ')') | ('[', ']') | ('{', '}')
+                                ) => {}
+                            Some(opening) => {
+                                return error(SyntaxErrorKind::MismatchedBracket {
+                                    closing,
+                                    opening,
+                                });
+                            }
+                            None => return error(SyntaxErrorKind::UnmatchedBracket(closing)),
+                        }
+                    }
+                    _ if leaf.is_error_recovery_node() && is_assignment_operator(code) => {
+                        if let Some((target, name)) = invalid_assignment_target(leaf) {
+                            return Some(SyntaxError {
+                                index: target.index,
+                                kind: if code == "=" {
+                                    SyntaxErrorKind::CannotAssignTo(name)
+                                } else {
+                                    SyntaxErrorKind::IllegalAugmentedAssignmentTarget(name)
+                                },
+                            });
+                        }
+                    }
+                    _ => (),
+                },
+            }
+            if leaf.index >= last_leaf_index {
+                break;
+            }
+            leaf = leaf.next_leaf()?;
+        }
+        // Brackets that are still open at the end of the file are the most likely cause
+        let bracket = brackets.pop()?;
+        last.next_sibling()
+            .is_some_and(|n| n.is_type(Terminal(TerminalType::Endmarker)))
+            .then(|| SyntaxError {
+                index: bracket.index,
+                kind: SyntaxErrorKind::BracketNeverClosed(first_char(bracket)),
+            })
+    }
+
+    fn compound_stmt_syntax_error(&self, first: PyNode, line_offset: usize) -> Option<SyntaxError> {
+        // Find the innermost compound statement (e.g. an `else` block in an `if` statement)
+        let mut compound = first;
+        while let Some(last_child) = compound.iter_children().last()
+            && matches!(last_child.type_(), ErrorNonterminal(t) if t != block)
+        {
+            compound = last_child;
+        }
+        let ErrorNonterminal(
+            if_stmt | else_block | while_stmt | for_stmt | with_stmt | try_stmt | except_block
+            | except_star_block | finally_block | function_def | class_def | match_stmt
+            | case_block,
+        ) = compound.type_()
+        else {
+            return None;
+        };
+        let last_child = compound.iter_children().last()?;
+        if last_child.is_type(ErrorNonterminal(block)) {
+            let keyword = compound
+                .iter_children()
+                .filter(|n| {
+                    matches!(
+                        n.as_code(),
+                        "if" | "elif"
+                            | "else"
+                            | "while"
+                            | "for"
+                            | "with"
+                            | "try"
+                            | "except"
+                            | "finally"
+                            | "def"
+                            | "class"
+                            | "match"
+                            | "case"
+                    )
+                })
+                .last()?;
+            let after = match keyword.as_code() {
+                "def" => "function definition",
+                "class" => "class definition",
+                "if" => "'if' statement",
+                "elif" => "'elif' statement",
+                "else" => "'else' statement",
+                "while" => "'while' statement",
+                "for" => "'for' statement",
+                "with" => "'with' statement",
+                "try" => "'try' statement",
+                "except" => "'except' statement",
+                "finally" => "'finally' statement",
+                "match" => "'match' statement",
+                _ => "'case' statement",
+            };
+            let next = last_child
+                .last_leaf_in_subtree()
+                .next_leaf()
+                .filter(|n| !n.is_type(Terminal(TerminalType::Endmarker)))
+                .unwrap_or(keyword);
+            return Some(SyntaxError {
+                index: next.index,
+                kind: SyntaxErrorKind::ExpectedIndentedBlock {
+                    after,
+                    line: line_offset + self.line_of(keyword.start() as usize),
+                },
+            });
+        }
+        let last_leaf = last_child.last_leaf_in_subtree();
+        (last_leaf.as_code() != ":"
+            && last_leaf
+                .next_leaf()
+                .is_some_and(|n| n.is_type(Terminal(TerminalType::Newline))))
+        .then_some(SyntaxError {
+            index: last_leaf.index,
+            kind: SyntaxErrorKind::ExpectedColon,
+        })
+    }
+
+    fn line_of(&self, position: usize) -> usize {
+        self.code()[..position].matches('\n').count() + 1
+    }
+}
+
+fn first_leaf(mut node: PyNode) -> PyNode {
+    while !node.is_leaf() {
+        node = node.nth_child(0);
+    }
+    node
+}
+
+fn first_char(leaf: PyNode) -> char {
+    leaf.as_code().chars().next().unwrap()
+}
+
+fn is_assignment_operator(code: &str) -> bool {
+    matches!(
+        code,
+        "=" | "+="
+            | "-="
+            | "*="
+            | "@="
+            | "/="
+            | "%="
+            | "&="
+            | "|="
+            | "^="
+            | "<<="
+            | ">>="
+            | "**="
+            | "//="
+    )
+}
+
+fn invalid_assignment_target(operator: PyNode) -> Option<(PyNode, &'static str)> {
+    let previous = operator.previous_leaf()?;
+    let star_exprs = previous.parent_until(&[Nonterminal(star_expressions)])?;
+    if star_exprs.end() != previous.end() {
+        return None;
+    }
+    let mut children = star_exprs.iter_children();
+    let expr = children.next()?;
+    if children.next().is_some() || !expr.is_type(Nonterminal(expression)) {
+        return None;
+    }
+    let target = expr.nth_child(0);
+    let name = match target.type_() {
+        Nonterminal(primary) if target.nth_child(1).as_code() == "(" => "function call",
+        Nonterminal(atom) => match target.nth_child(0).type_() {
+            Terminal(TerminalType::Number) | Nonterminal(strings | bytes) => "literal",
+            _ => match target.as_code() {
+                "None" => "None",
+                "True" => "True",
+                "False" => "False",
+                "..." => "ellipsis",
+                _ => return None,
+            },
+        },
+        Nonterminal(
+            sum | term | factor | power | shift_expr | bitwise_or | bitwise_xor | bitwise_and
+            | inversion | conjunction | disjunction | await_primary,
+        ) => "expression",
+        Nonterminal(comparison) => "comparison",
+        Nonterminal(ternary) => "conditional expression",
+        Nonterminal(lambda) => "lambda",
+        _ => return None,
+    };
+    Some((target, name))
+}
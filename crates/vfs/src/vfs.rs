@@ -30,6 +30,7 @@ pub trait VfsFile: Unpin {
     fn code(&self) -> &str;
     fn into_recoverable_artifacts(self) -> Self::Artifacts;
     fn invalidate_references_to(&mut self, file_index: Option<FileIndex>);
+    fn sub_files(&self) -> Vec<FileIndex>;
 }
 
 struct RecoveryFile<T> {
@@ -245,9 +246,12 @@ impl<F: VfsFile> Vfs<F> {
         original_file_index: Option<FileIndex>,
         invalid_index: FileIndex,
     ) -> InvalidationResult {
-        let file = self.file_state_mut(invalid_index);
-        let new_invalidations = file.file_entry.invalidations.take();
-        file.invalidate_references_to(original_file_index);
+        let new_invalidations = self
+            .file_state_mut(invalid_index)
+            .file_entry
+            .invalidations
+            .take();
+        self.invalidate_references_including_sub_files(invalid_index, original_file_index);
 
         if let InvalidationDetail::Some(invs) = new_invalidations.iter() {
             for invalidation in &invs {
@@ -271,6 +275,30 @@ impl<F: VfsFile> Vfs<F> {
         InvalidationResult::InvalidatedFiles
     }
 
+    fn invalidate_references_including_sub_files(
+        &mut self,
+        invalid_index: FileIndex,
+        original_file_index: Option<FileIndex>,
+    ) {
+        // Sub files share the file entry of their super file and are therefore never part of
+        // invalidations themselves.
+        let file_state = self.file_state_mut(invalid_index);
+        let sub_files = file_state.file().map(|f| f.sub_files()).unwrap_or_default();
+        file_state.invalidate_references_to(original_file_index);
+        for sub_file in sub_files {
+            self.invalidate_references_including_sub_files(sub_file, original_file_index)
+        }
+    }
+
+    fn unload_including_sub_files(&mut self, file_index: FileIndex) {
+        let file_state = self.file_state_mut(file_index);
+        let sub_files = file_state.file().map(|f| f.sub_files()).unwrap_or_default();
+        file_state.unload();
+        for sub_file in sub_files {
+            self.unload_including_sub_files(sub_file)
+        }
+    }
+
     pub fn file(&self, index: FileIndex) -> Option<&F> {
         self.files.get(index.0 as usize).unwrap().file()
     }
@@ -459,9 +487,12 @@ impl<F: VfsFile> Vfs<F> {
     }
 
     fn invalidate_and_unload_file(&mut self, file_index: FileIndex) -> InvalidationResult {
-        let file_state = &mut self.files[file_index.0 as usize];
-        file_state.unload();
-        let invalidations = file_state.file_entry.invalidations.take();
+        self.unload_including_sub_files(file_index);
+        let invalidations = self
+            .file_state_mut(file_index)
+            .file_entry
+            .invalidations
+            .take();
         self.invalidate_files(Some(file_index), invalidations)
     }
 
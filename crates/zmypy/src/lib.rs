@@ -272,6 +272,70 @@ pub fn with_diagnostics_from_cli<T>(
     Ok(callback(diagnostics?, &diagnostic_config))
 }
 
+/// Adds the inferred annotations to the functions in the files that would be checked.
+pub fn annotate(cli: Cli) -> ExitCode {
+    let current_dir = std::env::current_dir().expect("Expected a valid working directory");
+    const CWD_ERROR: &str = "Expected valid unicode in working directory";
+    let current_dir = current_dir.into_os_string().into_string().expect(CWD_ERROR);
+    match annotate_files(cli, &current_dir, None) {
+        Ok(annotated) => {
+            for path in &annotated {
+                println!("Annotated {path}")
+            }
+            println!("Annotated {} file(s)", annotated.len());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Writes the annotations to the files and returns the paths of the changed files.
+fn annotate_files(
+    cli: Cli,
+    current_dir: &str,
+    typeshed_path: Option<Arc<NormalizedPath>>,
+) -> anyhow::Result<Vec<String>> {
+    tracing::info!("Annotating in {current_dir}");
+    let (mut project, _) =
+        project_from_cli(cli, current_dir, typeshed_path, |name| std::env::var(name));
+    // All edits are calculated before writing any of them, because the call sites in other
+    // files are used to infer the param types.
+    let mut changes = vec![];
+    for path in project.paths_to_check()? {
+        let Some(document) = project.document(&path) else {
+            continue;
+        };
+        let edits: Vec<_> = document
+            .annotate_file()
+            .into_iter()
+            .map(|edit| {
+                (
+                    edit.range.0.byte_position,
+                    edit.range.1.byte_position,
+                    edit.new_text,
+                )
+            })
+            .collect();
+        if !edits.is_empty() {
+            changes.push((path, edits))
+        }
+    }
+    let mut annotated = vec![];
+    for (path, edits) in changes {
+        let file_path: &str = path.path();
+        let mut code = std::fs::read_to_string(file_path)?;
+        for (start, end, new_text) in edits.into_iter().rev() {
+            code.replace_range(start..end, &new_text)
+        }
+        std::fs::write(file_path, code)?;
+        annotated.push(file_path.to_owned())
+    }
+    Ok(annotated)
+}
+
 fn project_from_cli(
     cli: Cli,
     current_dir: &str,
@@ -1017,4 +1081,55 @@ mod tests {
             ["m.py:3: note: Revealed type is \"builtins.int\""]
         );
     }
+
+    #[test]
+    fn test_annotate() {
+        logging_config::setup_logging_for_tests();
+        let test_dir = test_utils::write_files_from_fixture(
+            r#"
+            [file shapes.py]
+            class Point:
+                def __init__(self, x, y):
+                    self.x = x
+                    self.y = y
+
+            [file m.py]
+            from shapes import Point
+
+            def origin():
+                return Point(0, 0)
+
+            def scale(point, factor=2):
+                pass
+            "#,
+            false,
+        );
+        let annotate = |cli_args: &[&str]| {
+            annotate_files(
+                Cli::parse_from(cli_args),
+                test_dir.path(),
+                Some(test_utils::typeshed_path()),
+            )
+            .unwrap()
+            .len()
+        };
+        let read = |name: &str| std::fs::read_to_string(Path::new(test_dir.path()).join(name));
+
+        assert_eq!(annotate(&["", "m.py"]), 1);
+        assert_eq!(
+            read("m.py").unwrap(),
+            "from shapes import Point\n\n\
+             def origin() -> Point:\n    return Point(0, 0)\n\n\
+             def scale(point, factor: int = 2) -> None:\n    pass\n"
+        );
+        // The calls in m.py are used for the params of `__init__`
+        assert_eq!(annotate(&["", "shapes.py"]), 1);
+        assert_eq!(
+            read("shapes.py").unwrap(),
+            "class Point:\n    def __init__(self, x: int, y: int) -> None:\n        \
+             self.x = x\n        self.y = y\n\n"
+        );
+        // Everything that can be inferred is annotated now
+        assert_eq!(annotate(&[""]), 0);
+    }
 }
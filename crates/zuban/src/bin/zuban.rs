@@ -17,15 +17,20 @@ enum Commands {
     Check(#[command(flatten)] zmypy::Cli),
     /// Type checks files like you would do when calling `mypy`
     Mypy(#[command(flatten)] zmypy::MypyCli),
+    /// Adds the annotations inferred from function bodies and call sites to untyped functions
+    Annotate(#[command(flatten)] zmypy::Cli),
     /// Starts an LSP server
     Server {},
 }
 
 fn main() -> ExitCode {
-    let run_check = |zmypy_config: zmypy::Cli| {
+    let setup_logging = || {
         if let Err(err) = logging_config::setup_logging_without_printing_errors_by_default() {
             panic!("{err}")
         };
+    };
+    let run_check = |zmypy_config: zmypy::Cli| {
+        setup_logging();
         zmypy::run(zmypy_config)
     };
     match Cli::parse().command {
@@ -35,6 +40,10 @@ fn main() -> ExitCode {
             mypy_options,
         }),
         Commands::Check(zmypy_config) => run_check(zmypy_config),
+        Commands::Annotate(zmypy_config) => {
+            setup_logging();
+            zmypy::annotate(zmypy_config)
+        }
         Commands::Server {} => match run_server() {
             Ok(()) => ExitCode::from(0),
             Err(err) => {
@@ -0,0 +1,311 @@
+//! Adds missing annotations to functions. Return types are inferred from the function bodies and
+//! param types from the arguments at the call sites in the type checked workspaces, similar to
+//! `dmypy suggest`. Classes that are used in the annotations are imported if needed.
+
+use anyhow::bail;
+use parsa_python_cst::{
+    Argument, ArgumentsDetails, CodeIndex, FunctionDef, GotoNode, Param, ParamKind, Primary,
+    PrimaryContent, Scope,
+};
+
+use crate::{
+    database::Database,
+    file::{ClassNodeRef, File, PythonFile},
+    file_moves::TextEdit,
+    goto::{PositionalDocument, batched_references, with_i_s_non_self},
+    inference_state::InferenceState,
+    inferred::Inferred,
+    name::Name,
+    node_ref::NodeRef,
+    refactoring::AnnotationWriter,
+    type_::Type,
+    type_helpers::{Function, cache_class_name},
+};
+
+pub(crate) fn annotate_function<'db>(
+    document: PositionalDocument<'db, GotoNode<'db>>,
+) -> anyhow::Result<Vec<TextEdit<'db>>> {
+    let func = function_under_cursor(&document)?;
+    let mut annotator = Annotator::new(document.db, document.file);
+    let [calls] = calls_of_functions(document.db, document.file, &[func])
+        .try_into()
+        .expect("There are calls for every function");
+    annotator.annotate(func, calls);
+    Ok(annotator.into_edits())
+}
+
+/// Checks whether the function under the cursor misses annotations. Only the syntax tree is
+/// used, because finding the call sites is expensive.
+pub(crate) fn check_annotate_function(
+    document: PositionalDocument<'_, GotoNode<'_>>,
+) -> anyhow::Result<()> {
+    let func = function_under_cursor(&document)?;
+    if func.return_annotation().is_some() && !misses_param_annotations(func) {
+        bail!("The function is already annotated");
+    }
+    Ok(())
+}
+
+fn function_under_cursor<'db>(
+    document: &PositionalDocument<'db, GotoNode<'db>>,
+) -> anyhow::Result<FunctionDef<'db>> {
+    let on_func_name = document
+        .node
+        .on_name()
+        .and_then(|name| name.name_def()?.maybe_name_of_func());
+    match (on_func_name, document.scope) {
+        (Some(func), _) | (None, Scope::Function(func)) => Ok(func),
+        _ => bail!("Could not find a function to annotate"),
+    }
+}
+
+fn is_bound(func: FunctionDef) -> bool {
+    matches!(func.parent_scope(), Scope::Class(_))
+        && !func.maybe_decorated().is_some_and(|decorated| {
+            decorated
+                .decorators()
+                .iter()
+                .any(|d| d.named_expression().as_code() == "staticmethod")
+        })
+}
+
+fn is_annotatable(is_bound: bool, (i, param): &(usize, Param)) -> bool {
+    param.annotation().is_none()
+        && !(is_bound && *i == 0)
+        && !matches!(param.kind(), ParamKind::Star | ParamKind::StarStar)
+}
+
+fn misses_param_annotations(func: FunctionDef) -> bool {
+    let is_bound = is_bound(func);
+    func.params()
+        .iter()
+        .enumerate()
+        .any(|p| is_annotatable(is_bound, &p))
+}
+
+/// Annotates all functions of the file, e.g. for `zuban annotate`.
+pub(crate) fn annotate_file<'db>(db: &'db Database, file: &'db PythonFile) -> Vec<TextEdit<'db>> {
+    let mut annotator = Annotator::new(db, file);
+    let funcs: Vec<FunctionDef> = file
+        .tree
+        .filter_all_names()
+        .filter_map(|name| name.name_def()?.maybe_name_of_func())
+        .collect();
+    let calls = calls_of_functions(db, file, &funcs);
+    for (func, calls) in funcs.into_iter().zip(calls) {
+        annotator.annotate(func, calls)
+    }
+    annotator.into_edits()
+}
+
+type Call<'db> = (&'db PythonFile, Scope<'db>, Primary<'db>);
+
+struct Annotator<'db> {
+    db: &'db Database,
+    file: &'db PythonFile,
+    i_s: InferenceState<'db, 'db>,
+    edits: Vec<(CodeIndex, CodeIndex, String)>,
+    writer: AnnotationWriter<'db>,
+}
+
+impl<'db> Annotator<'db> {
+    fn new(db: &'db Database, file: &'db PythonFile) -> Self {
+        Self {
+            db,
+            file,
+            i_s: InferenceState::new(db, file),
+            edits: vec![],
+            writer: AnnotationWriter::new(db, file),
+        }
+    }
+
+    fn annotate(&mut self, func: FunctionDef<'db>, calls: Vec<Call<'db>>) {
+        if let Scope::Class(class) = func.parent_scope() {
+            cache_class_name(NodeRef::new(self.file, class.name_def().index()), class);
+            ClassNodeRef::new(self.file, class.index()).ensure_cached_class_infos(&self.i_s);
+        }
+        let is_bound = is_bound(func);
+        let params: Vec<Param> = func.params().iter().collect();
+        if params
+            .iter()
+            .copied()
+            .enumerate()
+            .any(|p| is_annotatable(is_bound, &p))
+        {
+            let call_site_types = self.call_site_types(&params, is_bound, calls);
+            for p in params.iter().copied().enumerate() {
+                if is_annotatable(is_bound, &p) {
+                    self.annotate_param(func, &p.1, &call_site_types[p.0])
+                }
+            }
+        }
+        if func.return_annotation().is_none() {
+            let function =
+                Function::new_with_unknown_parent(self.db, NodeRef::new(self.file, func.index()));
+            let t = function
+                .ensure_cached_untyped_return(&self.i_s)
+                .avoid_implicit_literal(&self.i_s)
+                .as_type(&self.i_s);
+            if let Some(annotation) = self.writer.annotation(&t) {
+                let end = func.params().end();
+                self.edits.push((end, end, format!(" -> {annotation}")))
+            }
+        }
+    }
+
+    fn annotate_param(&mut self, func: FunctionDef, param: &Param, call_site_types: &[Type]) {
+        let default_type = param.default().map(|default| {
+            with_i_s_non_self(self.db, self.file, func.parent_scope(), |i_s| {
+                self.file
+                    .inference(i_s)
+                    .infer_expression(default)
+                    .avoid_implicit_literal(i_s)
+                    .as_type(i_s)
+            })
+        });
+        let mut types = call_site_types.iter();
+        let t = match (types.next(), default_type) {
+            // A default of None alone does not say anything about the param
+            (None, Some(Type::None) | None) => return,
+            (None, Some(default_type)) => default_type,
+            (Some(first), default_type) => {
+                let mut t = first.clone();
+                for other in types.chain(default_type.as_ref()) {
+                    t = t.simplified_union(&self.i_s, other)
+                }
+                t
+            }
+        };
+        let Some(annotation) = self.writer.annotation(&t) else {
+            return;
+        };
+        let name_end = param.name_def().end();
+        match param.default() {
+            Some(default) => {
+                self.edits
+                    .push((name_end, default.start(), format!(": {annotation} = ")))
+            }
+            None => self
+                .edits
+                .push((name_end, name_end, format!(": {annotation}"))),
+        }
+    }
+
+    /// Infers the types of the arguments that are passed to each param at the call sites.
+    fn call_site_types(
+        &self,
+        params: &[Param],
+        is_bound: bool,
+        calls: Vec<Call<'db>>,
+    ) -> Vec<Vec<Type>> {
+        let db = self.db;
+        let positional: Vec<usize> = params
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| {
+                matches!(
+                    p.kind(),
+                    ParamKind::PositionalOnly | ParamKind::PositionalOrKeyword
+                )
+            })
+            .map(|(i, _)| i)
+            .skip(is_bound as usize)
+            .collect();
+        let mut result = vec![vec![]; params.len()];
+        for (file, scope, call) in calls {
+            let PrimaryContent::Execution(ArgumentsDetails::Node(args)) = call.second() else {
+                continue;
+            };
+            with_i_s_non_self(db, file, scope, |i_s| {
+                let inference = file.inference(i_s);
+                let mut add = |param_index: usize, inf: Inferred| {
+                    let t = inf.avoid_implicit_literal(i_s).as_type(i_s);
+                    if !t.is_any() && !result[param_index].contains(&t) {
+                        result[param_index].push(t)
+                    }
+                };
+                for (i, arg) in args.iter().enumerate() {
+                    match arg {
+                        Argument::Positional(named_expr) => {
+                            if let Some(&param_index) = positional.get(i) {
+                                add(param_index, inference.infer_named_expression(named_expr))
+                            }
+                        }
+                        Argument::Keyword(kwarg) => {
+                            let (name, expr) = kwarg.unpack();
+                            let param_index = params.iter().position(|p| {
+                                p.name_def().as_code() == name.as_str()
+                                    && matches!(
+                                        p.kind(),
+                                        ParamKind::PositionalOrKeyword | ParamKind::KeywordOnly
+                                    )
+                            });
+                            if let Some(param_index) = param_index {
+                                add(param_index, inference.infer_expression(expr))
+                            }
+                        }
+                        // The positions of the following arguments are unknown
+                        Argument::Star(_) | Argument::StarStar(_) => break,
+                    }
+                }
+            })
+        }
+        result
+    }
+
+    fn into_edits(mut self) -> Vec<TextEdit<'db>> {
+        if !self.edits.is_empty() {
+            for (position, text) in self.writer.import_insertions() {
+                self.edits.push((position, position, text))
+            }
+        }
+        self.edits.sort_by_key(|(start, _, _)| *start);
+        self.edits
+            .into_iter()
+            .map(|(start, end, new_text)| TextEdit {
+                range: (
+                    self.file.byte_to_position_infos(self.db, start),
+                    self.file.byte_to_position_infos(self.db, end),
+                ),
+                new_text,
+            })
+            .collect()
+    }
+}
+
+/// Finds the calls of the functions that miss param annotations. The references of all functions
+/// are searched at once, because each search walks the type checked workspaces.
+fn calls_of_functions<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    funcs: &[FunctionDef<'db>],
+) -> Vec<Vec<Call<'db>>> {
+    let mut names = vec![];
+    let mut name_funcs = vec![];
+    for (i, func) in funcs.iter().enumerate() {
+        if !misses_param_annotations(*func) {
+            continue;
+        }
+        names.push(func.name());
+        name_funcs.push(i);
+        if func.name().as_str() == "__init__"
+            && let Scope::Class(class) = func.parent_scope()
+        {
+            // Calls of `__init__` are instantiations of the class
+            names.push(class.name());
+            name_funcs.push(i);
+        }
+    }
+    let references = batched_references(db, file, &names, |name| match name {
+        Name::TreeName(name) => name
+            .cst_name
+            .maybe_called()
+            .map(|call| (name.file, name.cst_name.parent_scope(), call)),
+        _ => None,
+    });
+    let mut calls: Vec<Vec<Call>> = funcs.iter().map(|_| vec![]).collect();
+    for (i, references) in name_funcs.into_iter().zip(references) {
+        calls[i].extend(references.into_iter().flatten())
+    }
+    calls
+}
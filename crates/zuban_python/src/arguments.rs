@@ -118,30 +118,7 @@ pub(crate) struct SimpleArgs<'db, 'a> {
 
 impl<'db: 'a, 'a> Args<'db> for SimpleArgs<'db, 'a> {
     fn iter<'x>(&'x self, mode: Mode<'x>) -> ArgIterator<'db, 'x> {
-        ArgIterator::new(match self.details {
-            ArgumentsDetails::Node(arguments) => ArgIteratorBase::Iterator {
-                i_s: self.i_s.with_mode(mode),
-                file: self.file,
-                iterator: arguments.iter().enumerate(),
-                kwargs_before_star_args: {
-                    let mut iterator = arguments.iter();
-                    if iterator.any(|arg| matches!(arg, CSTArgument::Keyword(_))) {
-                        if iterator.any(|arg| matches!(arg, CSTArgument::Star(_))) {
-                            Some(vec![])
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                },
-                ignore_metaclass_keyword: false,
-            },
-            ArgumentsDetails::Comprehension(comprehension) => {
-                ArgIteratorBase::Comprehension(self.i_s.with_mode(mode), self.file, comprehension)
-            }
-            ArgumentsDetails::None => ArgIteratorBase::Finished,
-        })
+        self.iter_with_skipped_args(mode, 0)
     }
 
     fn calculate_diagnostics_for_any_callable(&self) {
@@ -232,6 +209,43 @@ impl<'db: 'a, 'a> SimpleArgs<'db, 'a> {
             _ => unreachable!(),
         }
     }
+
+    fn iter_with_skipped_args<'x>(&'x self, mode: Mode<'x>, skip: usize) -> ArgIterator<'db, 'x> {
+        ArgIterator::new(match self.details {
+            ArgumentsDetails::Node(arguments) => ArgIteratorBase::Iterator {
+                i_s: self.i_s.with_mode(mode),
+                file: self.file,
+                iterator: {
+                    // Skipping happens before enumerating, so that positions are counted from the
+                    // first argument that is not skipped.
+                    let mut iterator = arguments.iter();
+                    for _ in 0..skip {
+                        iterator.next();
+                    }
+                    iterator.enumerate()
+                },
+                kwargs_before_star_args: {
+                    let mut iterator = arguments.iter();
+                    if iterator.any(|arg| matches!(arg, CSTArgument::Keyword(_))) {
+                        if iterator.any(|arg| matches!(arg, CSTArgument::Star(_))) {
+                            Some(vec![])
+                        } else {
+                            None
+                        }
+                    } else {
+                        None
+                    }
+                },
+                ignore_metaclass_keyword: false,
+            },
+            ArgumentsDetails::Comprehension(comprehension) if skip == 0 => {
+                ArgIteratorBase::Comprehension(self.i_s.with_mode(mode), self.file, comprehension)
+            }
+            ArgumentsDetails::Comprehension(_) | ArgumentsDetails::None => {
+                ArgIteratorBase::Finished
+            }
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -1325,6 +1339,33 @@ impl<'db: 'a, 'a> Args<'db> for InitSubclassArgs<'db, 'a> {
     }
 }
 
+/// The arguments of a `functools.partial(func, ...)` call that are bound to `func`, i.e. all
+/// arguments except for the first one.
+#[derive(Debug)]
+pub(crate) struct PartialBoundArgs<'db, 'a>(pub &'a SimpleArgs<'db, 'a>);
+
+impl<'db: 'a, 'a> Args<'db> for PartialBoundArgs<'db, 'a> {
+    fn iter<'x>(&'x self, mode: Mode<'x>) -> ArgIterator<'db, 'x> {
+        self.0.iter_with_skipped_args(mode, 1)
+    }
+
+    fn calculate_diagnostics_for_any_callable(&self) {
+        self.0.calculate_diagnostics_for_any_callable()
+    }
+
+    fn as_node_ref_internal(&self) -> Option<NodeRef<'_>> {
+        self.0.as_node_ref_internal()
+    }
+
+    fn points_backup(&self) -> Option<PointsBackup> {
+        self.0.points_backup()
+    }
+
+    fn reset_points_from_backup(&self, backup: &Option<PointsBackup>) {
+        self.0.reset_points_from_backup(backup)
+    }
+}
+
 #[derive(Clone, Copy)]
 pub(crate) struct CustomAddIssue<'a>(&'a dyn Fn(IssueKind));
 
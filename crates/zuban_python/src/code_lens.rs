@@ -0,0 +1,213 @@
+//! Code lenses above classes and functions. References and implementations are looked up when a
+//! lens is resolved. Overrides lenses are only created for methods that override something, which
+//! is checked with the MRO of the class.
+
+use parsa_python_cst::{
+    Argument, AsyncStmtContent, ClassDef, Decoratee, FunctionDef, GotoNode, Name as CSTName, Scope,
+    StmtLikeContent, StmtLikeIterator,
+};
+
+use crate::{
+    InputPosition, ReferencesGoal,
+    database::Database,
+    file::{ClassNodeRef, PythonFile},
+    goto::{PositionalDocument, ReferencesResolver},
+    inference_state::InferenceState,
+    name::{Name, TreeName},
+    node_ref::NodeRef,
+    type_helpers::{Class, Instance, InstanceLookupOptions, cache_class_name},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeLensKind {
+    References,
+    Implementations,
+    Overrides,
+}
+
+enum Definition<'db> {
+    Class(ClassDef<'db>),
+    Function(FunctionDef<'db>),
+}
+
+fn iter_definitions(stmts: StmtLikeIterator) -> impl Iterator<Item = Definition> {
+    stmts.filter_map(|stmt| match stmt.node {
+        StmtLikeContent::ClassDef(class) => Some(Definition::Class(class)),
+        StmtLikeContent::FunctionDef(func) => Some(Definition::Function(func)),
+        StmtLikeContent::Decorated(decorated) => Some(match decorated.decoratee() {
+            Decoratee::ClassDef(class) => Definition::Class(class),
+            Decoratee::FunctionDef(func) | Decoratee::AsyncFunctionDef(func) => {
+                Definition::Function(func)
+            }
+        }),
+        StmtLikeContent::AsyncStmt(async_stmt) => match async_stmt.unpack() {
+            AsyncStmtContent::FunctionDef(func) => Some(Definition::Function(func)),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// Returns the lenses for module level classes/functions and for the methods of these classes.
+pub(crate) fn code_lenses<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+) -> Vec<(CSTName<'db>, CodeLensKind)> {
+    let mut result = vec![];
+    for definition in iter_definitions(file.tree.root().iter_stmt_likes()) {
+        match definition {
+            Definition::Class(class) => {
+                result.push((class.name(), CodeLensKind::References));
+                result.push((class.name(), CodeLensKind::Implementations));
+                let (_, arguments, block) = class.unpack();
+                let has_bases = arguments.is_some_and(|arguments| {
+                    arguments
+                        .iter()
+                        .any(|arg| !matches!(arg, Argument::Keyword(_)))
+                });
+                for definition in iter_definitions(block.iter_stmt_likes()) {
+                    match definition {
+                        Definition::Class(nested) => {
+                            result.push((nested.name(), CodeLensKind::References))
+                        }
+                        Definition::Function(func) => {
+                            let name = func.name();
+                            result.push((name, CodeLensKind::References));
+                            result.push((name, CodeLensKind::Implementations));
+                            if has_bases
+                                && overridden_method(db, file, class, name.as_str()).is_some()
+                            {
+                                result.push((name, CodeLensKind::Overrides));
+                            }
+                        }
+                    }
+                }
+            }
+            Definition::Function(func) => result.push((func.name(), CodeLensKind::References)),
+        }
+    }
+    result
+}
+
+/// Returns the subclasses of the class under the cursor or the methods overriding the method
+/// under the cursor.
+pub(crate) fn implementations<'db, T>(
+    document: PositionalDocument<'db, GotoNode<'db>>,
+    mut on_name: impl FnMut(Name<'db, '_>) -> T,
+) -> Vec<T> {
+    let db = document.db;
+    let Some(name_def) = document.node.on_name().and_then(|name| name.name_def()) else {
+        return vec![];
+    };
+    let (class, method_name) = if let Some(class) = name_def.maybe_name_of_class() {
+        (class, None)
+    } else if let Some(func) = name_def.maybe_name_of_func()
+        && let Scope::Class(class) = func.parent_scope()
+    {
+        (class, Some(func.name().as_str()))
+    } else {
+        return vec![];
+    };
+    let mut found = vec![];
+    subclasses(db, document.file, class, &mut found);
+    found
+        .into_iter()
+        .filter_map(|(file, subclass)| {
+            let name = match method_name {
+                Some(method_name) => {
+                    let index = ClassNodeRef::new(file, subclass.index())
+                        .class_storage()
+                        .class_symbol_table
+                        .lookup_symbol(method_name)?;
+                    NodeRef::new(file, index).expect_name()
+                }
+                None => subclass.name(),
+            };
+            Some(on_name(Name::TreeName(
+                TreeName::with_unknown_parent_scope(db, file, name),
+            )))
+        })
+        .collect()
+}
+
+fn subclasses<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    class: ClassDef<'db>,
+    found: &mut Vec<(&'db PythonFile, ClassDef<'db>)>,
+) {
+    let Ok(document) = PositionalDocument::for_goto(
+        db,
+        file,
+        InputPosition::NthUTF8Byte(class.name().start() as usize),
+    ) else {
+        return;
+    };
+    let direct_subclasses = ReferencesResolver::new(document, |name| match name {
+        Name::TreeName(name) => name
+            .cst_name
+            .maybe_base_class_of()
+            .map(|subclass| (name.file, subclass)),
+        _ => None,
+    })
+    .references(ReferencesGoal::OnlyTypeCheckedWorkspaces, false);
+    for (file, subclass) in direct_subclasses.into_iter().flatten() {
+        let is_new = !found
+            .iter()
+            .any(|(f, c)| f.file_index == file.file_index && c.index() == subclass.index());
+        if is_new {
+            found.push((file, subclass));
+            subclasses(db, file, subclass, found);
+        }
+    }
+}
+
+/// Returns the method the method under the cursor overrides.
+pub(crate) fn overridden_method_at<'db, T>(
+    document: PositionalDocument<'db, GotoNode<'db>>,
+    mut on_name: impl FnMut(Name<'db, '_>) -> T,
+) -> Option<T> {
+    let func = document.node.on_name()?.name_def()?.maybe_name_of_func()?;
+    let Scope::Class(class) = func.parent_scope() else {
+        return None;
+    };
+    let name = overridden_method(document.db, document.file, class, func.name().as_str())?;
+    Some(on_name(name))
+}
+
+/// Looks up the method in the MRO of the class, like the checks for `@override` do, but
+/// ignores the methods every class inherits from `object`.
+fn overridden_method<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    class: ClassDef<'db>,
+    name: &str,
+) -> Option<Name<'db, 'db>> {
+    cache_class_name(NodeRef::new(file, class.name_def().index()), class);
+    let class_node_ref = ClassNodeRef::new(file, class.index());
+    let i_s = &InferenceState::new(db, file);
+    class_node_ref.ensure_cached_class_infos(i_s);
+    let c = Class::with_self_generics(db, class_node_ref);
+    let i_s = &i_s.with_class_context(&c);
+    let details = Instance::new(c, None).lookup(
+        i_s,
+        name,
+        InstanceLookupOptions::new(&|_| ())
+            .with_skip_first_of_mro(db, &c)
+            .with_avoid_inferring_return_types(),
+    );
+    if !details.lookup.is_some() {
+        return None;
+    }
+    let base = details.class.as_maybe_class()?;
+    if base.node_ref == db.python_state.object_node_ref() {
+        return None;
+    }
+    let index = base.class_storage.class_symbol_table.lookup_symbol(name)?;
+    let base_file = db.loaded_python_file(base.node_ref.file_index());
+    Some(Name::TreeName(TreeName::with_unknown_parent_scope(
+        db,
+        base_file,
+        NodeRef::new(base_file, index).expect_name(),
+    )))
+}
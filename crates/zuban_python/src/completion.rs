@@ -1,9 +1,10 @@
-use std::{borrow::Cow, collections::HashSet};
+use std::{borrow::Cow, collections::HashSet, sync::Arc};
 
 pub use lsp_types::CompletionItemKind;
 use parsa_python_cst::{
-    ClassDef, CompletionNode, FunctionDef, NAME_DEF_TO_NAME_DIFFERENCE, NameDef, NodeIndex,
-    RestNode, Scope,
+    ArgumentPosition, BracketContext, CallContext, ClassDef, CodeIndex, CompletionNode, Expression,
+    FunctionDef, FunctionParent, NAME_DEF_TO_NAME_DIFFERENCE, NameDef, NameParent, NodeIndex,
+    ParamKind, RestNode, Scope, StringStart,
 };
 use vfs::{Directory, DirectoryEntry, Entries, FileIndex, Parent};
 
@@ -17,11 +18,16 @@ use crate::{
     inference_state::InferenceState,
     inferred::Inferred,
     lines::BytePositionInfos,
-    name::Range,
+    matching::ResultContext,
+    name::{Name, Range, TreeName, ValueName},
     node_ref::NodeRef,
     recoverable_error,
-    type_::{CallableParam, Enum, EnumMemberDefinition, FunctionKind, Namespace, Type},
-    type_helpers::{Class, Function, TypeOrClass, is_private},
+    type_::{
+        CallableContent, CallableLike, CallableParam, CallableParams, Enum, EnumMemberDefinition,
+        FunctionKind, LiteralValue, Namespace, ParamSpecUsage, ParamType, StarParamType,
+        StarStarParamType, Type,
+    },
+    type_helpers::{Class, Function, TypeOrClass, cache_class_name, is_private},
 };
 
 struct CompletionInfo<'db> {
@@ -75,6 +81,7 @@ pub(crate) struct CompletionResolver<'db, C, T> {
     added_names: HashSet<Cow<'db, str>>,
     should_start_with_lowercase: Option<String>,
     replace_range: Range<'db>,
+    allow_call_snippets: bool,
 }
 
 impl<'db, C: for<'a> Fn(Range, &dyn Completion) -> T, T> CompletionResolver<'db, C, T> {
@@ -94,6 +101,10 @@ impl<'db, C: for<'a> Fn(Range, &dyn Completion) -> T, T> CompletionResolver<'db,
             file.byte_to_position_infos(db, infos.node.rest.start()),
             file.byte_to_position_infos(db, infos.node.cursor_position.byte),
         );
+        let allow_call_snippets = matches!(
+            infos.node.node,
+            CompletionNode::Global | CompletionNode::Attribute { .. }
+        ) && infos.node.rest.allows_call_snippet();
         let mut slf = Self {
             infos,
             on_result,
@@ -101,6 +112,7 @@ impl<'db, C: for<'a> Fn(Range, &dyn Completion) -> T, T> CompletionResolver<'db,
             added_names: Default::default(),
             should_start_with_lowercase: None,
             replace_range,
+            allow_call_snippets,
         };
         if filter_with_name_under_cursor {
             slf.should_start_with_lowercase = Some(slf.infos.node.rest.as_code().to_lowercase());
@@ -119,6 +131,10 @@ impl<'db, C: for<'a> Fn(Range, &dyn Completion) -> T, T> CompletionResolver<'db,
                 self.add_attribute_completions(inf)
             }
             CompletionNode::Global => {
+                if self.add_bracket_completions() {
+                    // Names are not useful within strings
+                    return;
+                }
                 let reachable_scopes = &mut ScopesIterator {
                     file,
                     only_reachable: true,
@@ -205,11 +221,235 @@ impl<'db, C: for<'a> Fn(Range, &dyn Completion) -> T, T> CompletionResolver<'db,
                 self.items
                     .push((CompletionSortPriority::Default(keyword), result))
             }
-            CompletionNode::AfterDefKeyword => (),
+            CompletionNode::AfterDefKeyword { keyword_start } => {
+                if let Scope::Class(class) = self.infos.scope {
+                    self.add_override_completions(class, *keyword_start)
+                }
+            }
             CompletionNode::AfterClassKeyword => (),
         }
     }
 
+    /// Adds completions for the call or subscript around the cursor, e.g. `foo(na|` completes
+    /// `name=` and `d["|` completes the keys of a TypedDict. Returns true if the cursor is within
+    /// a string.
+    fn add_bracket_completions(&mut self) -> bool {
+        let db = self.infos.db;
+        let file = self.infos.file;
+        let string = self.infos.node.rest.string_start();
+        let Some(context) = self.infos.node.rest.bracket_context() else {
+            return string.is_some();
+        };
+        if let Some(string) = string {
+            // The quote is part of the completion
+            self.replace_range.0 = file.byte_to_position_infos(db, string.start);
+            if self.should_start_with_lowercase.is_some() {
+                let typed = &file.tree.code()
+                    [string.start as usize..self.infos.node.cursor_position.byte as usize];
+                self.should_start_with_lowercase = Some(typed.to_lowercase());
+            }
+        }
+        with_i_s_non_self(db, file, self.infos.scope, |i_s| match context {
+            BracketContext::Call(call) => {
+                let callee = self.infos.infer_primary_or_atom(call.callee);
+                let callables = callables_for_type(i_s, &callee.as_cow_type(i_s));
+                for callable in &callables {
+                    let CallableParams::Simple(params) = &callable.params else {
+                        continue;
+                    };
+                    if let Some(expected) = expected_argument_type(db, params, &call) {
+                        ResultContext::new_known(&expected)
+                            .with_type_if_exists_and_replace_type_var_likes(i_s, |t| {
+                                self.add_expected_value_completions(t, string)
+                            });
+                    }
+                }
+                if string.is_none() && matches!(call.position, ArgumentPosition::NewArgument) {
+                    for callable in &callables {
+                        if let CallableParams::Simple(params) = &callable.params {
+                            self.add_keyword_argument_completions(
+                                i_s,
+                                params,
+                                call.positional_args.len(),
+                                &call,
+                            )
+                        }
+                    }
+                }
+            }
+            BracketContext::Subscript { base } => {
+                let base = self.infos.infer_primary_or_atom(base);
+                let base = unpack_union_types(db, base.as_cow_type(i_s));
+                for t in base.iter_with_unpacked_unions(db) {
+                    if let Type::TypedDict(td) = t {
+                        for member in td.members(db) {
+                            let name = member.name.as_str(db);
+                            if let Some((label, insert_text)) = quoted_string(name, string) {
+                                self.add_synthetic(
+                                    CompletionSortPriority::Literal,
+                                    SyntheticCompletion {
+                                        label,
+                                        insert_text,
+                                        kind: CompletionItemKind::FIELD,
+                                        type_description: Some(
+                                            member.type_.format_short(db).into_string(),
+                                        ),
+                                    },
+                                )
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        string.is_some()
+    }
+
+    fn add_keyword_argument_completions(
+        &mut self,
+        i_s: &InferenceState,
+        params: &[CallableParam],
+        mut positional_count: usize,
+        call: &CallContext,
+    ) {
+        let db = self.infos.db;
+        let add = |slf: &mut Self, name: &str, t: &Type| {
+            if call.keyword_args.contains(&name) {
+                return;
+            }
+            slf.add_synthetic(
+                CompletionSortPriority::NamedParam,
+                SyntheticCompletion {
+                    label: format!("{name}="),
+                    insert_text: None,
+                    kind: CompletionItemKind::VARIABLE,
+                    type_description: Some(t.format_short(db).into_string()),
+                },
+            )
+        };
+        let params_positional_count = params
+            .iter()
+            .filter(|p| {
+                matches!(
+                    p.type_,
+                    ParamType::PositionalOnly(_) | ParamType::PositionalOrKeyword(_)
+                )
+            })
+            .count();
+        for param in params {
+            match &param.type_ {
+                ParamType::PositionalOnly(_) => {
+                    positional_count = positional_count.saturating_sub(1)
+                }
+                ParamType::PositionalOrKeyword(_) if positional_count > 0 => positional_count -= 1,
+                ParamType::PositionalOrKeyword(t) | ParamType::KeywordOnly(t) => {
+                    if let Some(name) = &param.name {
+                        add(self, name.as_str(db), t)
+                    }
+                }
+                ParamType::StarStar(StarStarParamType::UnpackTypedDict(td)) => {
+                    for member in td.members(db) {
+                        add(self, member.name.as_str(db), &member.type_)
+                    }
+                }
+                ParamType::StarStar(StarStarParamType::ParamSpecKwargs(usage)) => {
+                    // The keyword arguments are the ones of the callable that is passed for the
+                    // ParamSpec, e.g. `run(func, |`.
+                    let Some(arg) = param_spec_callable_index(params, usage)
+                        .and_then(|index| call.positional_args.get(index).copied().flatten())
+                    else {
+                        continue;
+                    };
+                    let arg = self.infos.infer_primary_or_atom(arg);
+                    let positional_count = call
+                        .positional_args
+                        .len()
+                        .saturating_sub(params_positional_count);
+                    for callable in callables_for_type(i_s, &arg.as_cow_type(i_s)) {
+                        if let CallableParams::Simple(params) = &callable.params {
+                            self.add_keyword_argument_completions(
+                                i_s,
+                                params,
+                                positional_count,
+                                call,
+                            )
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn add_expected_value_completions(&mut self, t: &Type, string: Option<StringStart>) {
+        let db = self.infos.db;
+        for t in t.iter_with_unpacked_unions(db) {
+            let (label, insert_text, kind) = match t {
+                Type::Literal(literal) => {
+                    let label = match literal.value(db) {
+                        LiteralValue::String(s) => {
+                            if let Some((label, insert_text)) = quoted_string(s, string) {
+                                self.add_synthetic(
+                                    CompletionSortPriority::Literal,
+                                    SyntheticCompletion {
+                                        label,
+                                        insert_text,
+                                        kind: CompletionItemKind::VALUE,
+                                        type_description: None,
+                                    },
+                                )
+                            }
+                            continue;
+                        }
+                        _ if string.is_some() => continue,
+                        LiteralValue::Int(i) => i.to_string(),
+                        LiteralValue::Bool(b) => (if b { "True" } else { "False" }).into(),
+                        LiteralValue::Bytes(_) => continue,
+                    };
+                    (label, None, CompletionItemKind::VALUE)
+                }
+                _ if string.is_some() => continue,
+                Type::Enum(enum_) => {
+                    for member in enum_.members.iter() {
+                        self.add_synthetic(
+                            CompletionSortPriority::Literal,
+                            SyntheticCompletion {
+                                label: format!("{}.{}", enum_.name.as_str(db), member.name(db)),
+                                insert_text: None,
+                                kind: CompletionItemKind::ENUM_MEMBER,
+                                type_description: None,
+                            },
+                        )
+                    }
+                    continue;
+                }
+                Type::EnumMember(member) => (
+                    format!("{}.{}", member.enum_.name.as_str(db), member.name(db)),
+                    None,
+                    CompletionItemKind::ENUM_MEMBER,
+                ),
+                _ => continue,
+            };
+            self.add_synthetic(
+                CompletionSortPriority::Literal,
+                SyntheticCompletion {
+                    label,
+                    insert_text,
+                    kind,
+                    type_description: None,
+                },
+            )
+        }
+    }
+
+    fn add_synthetic(&mut self, priority: CompletionSortPriority<'db>, comp: SyntheticCompletion) {
+        if !self.maybe_add_cow(Cow::Owned(comp.label.clone())) {
+            return;
+        }
+        let result = (self.on_result)(self.replace_range, &comp);
+        self.items.push((priority, result))
+    }
+
     fn add_import_result_completions(&mut self, import_result: Option<ImportResult>) {
         match import_result {
             Some(ImportResult::File(file_index)) => {
@@ -484,7 +724,9 @@ impl<'db, C: for<'a> Fn(Range, &dyn Completion) -> T, T> CompletionResolver<'db,
                         db: self.infos.db,
                         file: self.infos.file,
                         name: symbol,
+                        name_def: None,
                         kind: CompletionItemKind::FIELD,
+                        allow_call_snippet: false,
                     },
                 );
                 self.items
@@ -514,6 +756,78 @@ impl<'db, C: for<'a> Fn(Range, &dyn Completion) -> T, T> CompletionResolver<'db,
         }
     }
 
+    /// Completes `def |` in a class body with the methods of the base classes, e.g.
+    /// `def meth(self, x: int) -> str:` including the decorators of the base method.
+    fn add_override_completions(&mut self, class: ClassDef, keyword_start: CodeIndex) {
+        let db = self.infos.db;
+        let file = self.infos.file;
+        let code = file.tree.code();
+        let line_start = code[..keyword_start as usize]
+            .rfind('\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let indent = &code[line_start..keyword_start as usize];
+        if !indent.chars().all(|c| c == ' ' || c == '\t') {
+            return;
+        }
+        let rest = &self.infos.node.rest;
+        let typed_keywords = &code[keyword_start as usize..rest.start() as usize];
+        let typed_async = typed_keywords.starts_with("async");
+        let add_override_decorator = file.lookup_symbol("override").is_some();
+        // Decorators are added in front of `def`, therefore the keywords are replaced as well.
+        let replace_range = (
+            file.byte_to_position_infos(db, keyword_start),
+            self.replace_range.1,
+        );
+        let class_ref = ClassNodeRef::new(file, class.index());
+        class_ref.ensure_cached_class_infos(&InferenceState::new(db, file));
+        let symbol_table = &class_ref.class_storage().class_symbol_table;
+        let current_name = rest.as_code();
+        for (_, type_or_class) in Class::with_self_generics(db, class_ref).mro(db).skip(1) {
+            let TypeOrClass::Class(base) = type_or_class else {
+                continue;
+            };
+            let base_ref = base.node_ref.to_db_lifetime(db);
+            let base_file = base_ref.file;
+            for (name, node_index) in base_ref.class_storage().class_symbol_table.iter() {
+                if is_private(name)
+                    || name != current_name && symbol_table.lookup_symbol(name).is_some()
+                {
+                    continue;
+                }
+                let name_def =
+                    NameDef::by_index(&base_file.tree, node_index - NAME_DEF_TO_NAME_DIFFERENCE);
+                let Some(func) = name_def.maybe_name_of_func() else {
+                    continue;
+                };
+                if !self.maybe_add(name) {
+                    continue;
+                }
+                let is_async = typed_async
+                    || matches!(
+                        func.parent(),
+                        FunctionParent::Async | FunctionParent::DecoratedAsync(_)
+                    );
+                let comp = OverrideCompletion {
+                    name,
+                    filter_text: format!("{typed_keywords}{name}"),
+                    stub: override_stub(
+                        db,
+                        file,
+                        base_file,
+                        func,
+                        add_override_decorator,
+                        is_async,
+                        indent,
+                    ),
+                };
+                let result = (self.on_result)(replace_range, &comp);
+                self.items
+                    .push((CompletionSortPriority::new_symbol(name), result))
+            }
+        }
+    }
+
     fn maybe_add(&mut self, symbol: &'db str) -> bool {
         self.maybe_add_cow(Cow::Borrowed(symbol))
     }
@@ -549,7 +863,9 @@ impl<'db, C: for<'a> Fn(Range, &dyn Completion) -> T, T> CompletionResolver<'db,
                 db: self.infos.db,
                 file,
                 name,
+                name_def: Some(name_def),
                 kind,
+                allow_call_snippet: self.allow_call_snippets,
             },
         );
         self.items
@@ -557,6 +873,280 @@ impl<'db, C: for<'a> Fn(Range, &dyn Completion) -> T, T> CompletionResolver<'db,
     }
 }
 
+fn callables_for_type(i_s: &InferenceState, t: &Type) -> Vec<Arc<CallableContent>> {
+    let mut result = vec![];
+    let t = unpack_union_types(i_s.db, Cow::Borrowed(t));
+    for t in t.iter_with_unpacked_unions(i_s.db) {
+        match t.maybe_callable(i_s) {
+            Some(CallableLike::Callable(c)) => result.push(c),
+            Some(CallableLike::Overload(overload)) => {
+                result.extend(overload.iter_functions().cloned())
+            }
+            None => (),
+        }
+    }
+    result
+}
+
+/// The type of the parameter that receives the argument at the cursor.
+fn expected_argument_type(
+    db: &Database,
+    params: &[CallableParam],
+    call: &CallContext,
+) -> Option<Type> {
+    match call.position {
+        ArgumentPosition::KeywordValue(name) => params.iter().find_map(|p| match &p.type_ {
+            ParamType::PositionalOrKeyword(t) | ParamType::KeywordOnly(t)
+                if p.name.as_ref().is_some_and(|n| n.as_str(db) == name) =>
+            {
+                Some(t.clone())
+            }
+            ParamType::StarStar(StarStarParamType::UnpackTypedDict(td)) => td
+                .members(db)
+                .iter()
+                .find(|m| m.name.as_str(db) == name)
+                .map(|m| m.type_.clone()),
+            ParamType::StarStar(StarStarParamType::ValueType(t)) => Some(t.clone()),
+            _ => None,
+        }),
+        ArgumentPosition::NewArgument => {
+            let index = call.positional_index()?;
+            let mut positional = 0;
+            for p in params.iter() {
+                match &p.type_ {
+                    ParamType::PositionalOnly(t) | ParamType::PositionalOrKeyword(t) => {
+                        if positional == index {
+                            return Some(t.clone());
+                        }
+                        positional += 1;
+                    }
+                    ParamType::Star(StarParamType::ArbitraryLen(t)) => return Some(t.clone()),
+                    _ => return None,
+                }
+            }
+            None
+        }
+    }
+}
+
+/// Returns the positional index of the param that is a callable using the ParamSpec of `usage`,
+/// e.g. `func` in `def run(func: Callable[P, T], *args: P.args, **kwargs: P.kwargs)`.
+fn param_spec_callable_index(params: &[CallableParam], usage: &ParamSpecUsage) -> Option<usize> {
+    params
+        .iter()
+        .take_while(|p| {
+            matches!(
+                p.type_,
+                ParamType::PositionalOnly(_) | ParamType::PositionalOrKeyword(_)
+            )
+        })
+        .position(|p| {
+            let Some(Type::Callable(c)) = p.type_.maybe_type() else {
+                return false;
+            };
+            let CallableParams::Simple(params) = &c.params else {
+                return false;
+            };
+            params.iter().any(|p| {
+                matches!(
+                    &p.type_,
+                    ParamType::StarStar(StarStarParamType::ParamSpecKwargs(u)) if u == usage
+                )
+            })
+        })
+}
+
+/// Formats a string literal for completions. If the cursor is already in a closed string, the
+/// closing quote is not inserted.
+fn quoted_string(value: &str, string: Option<StringStart>) -> Option<(String, Option<String>)> {
+    let quote = string.map(|s| s.quote).unwrap_or('"');
+    if value.contains([quote, '\\']) || value.chars().any(|c| c.is_control()) {
+        return None;
+    }
+    let label = format!("{quote}{value}{quote}");
+    let insert_text = string
+        .is_some_and(|s| s.is_closed)
+        .then(|| format!("{quote}{value}"));
+    Some((label, insert_text))
+}
+
+/// Copies the signature of a base class method, e.g. `@classmethod\ndef meth(cls) -> int:`.
+/// Annotations that would not refer to the same definitions in the current file are left out.
+fn override_stub(
+    db: &Database,
+    file: &PythonFile,
+    base_file: &PythonFile,
+    func: FunctionDef,
+    add_override_decorator: bool,
+    is_async: bool,
+    indent: &str,
+) -> String {
+    let mut stub = String::new();
+    let mut add_line = |line: &str| {
+        stub += line;
+        stub.push('\n');
+        stub += indent;
+    };
+    if add_override_decorator {
+        add_line("@override")
+    }
+    if let Some(decorated) = func.maybe_decorated() {
+        for decorator in decorated.decorators().iter() {
+            let expr = decorator.named_expression().as_code();
+            let last_part = expr.rsplit('.').next().unwrap_or(expr);
+            if !matches!(
+                last_part,
+                "overload" | "abstractmethod" | "final" | "override"
+            ) {
+                add_line(&format!("@{expr}"))
+            }
+        }
+    }
+    if is_async {
+        stub += "async ";
+    }
+    stub += "def ";
+    let base_code = base_file.tree.code();
+    let base_line_start = base_code[..func.start() as usize]
+        .rfind('\n')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let base_indent: String = base_code[base_line_start..]
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect();
+    let mut signature = String::new();
+    let mut position = func.name().start() as usize;
+    let mut strip = |start: CodeIndex, end: CodeIndex| {
+        // Whitespace in front of the annotation (e.g. before `->`) is removed as well.
+        let start = base_code[..start as usize].trim_end().len();
+        signature += &base_code[position..start];
+        position = end as usize;
+    };
+    for param in func.params().iter() {
+        if let Some(annotation) = param.annotation() {
+            let is_usable = match annotation.maybe_starred() {
+                Err(expr) => names_are_usable(db, file, base_file, expr),
+                Ok(_) => file.file_index == base_file.file_index,
+            };
+            if !is_usable {
+                let index = annotation.index();
+                strip(
+                    base_file.tree.node_start_position(index),
+                    base_file.tree.node_end_position(index),
+                )
+            }
+        }
+    }
+    if let Some(return_annotation) = func.return_annotation()
+        && !names_are_usable(db, file, base_file, return_annotation.expression())
+    {
+        strip(return_annotation.start(), return_annotation.end())
+    }
+    signature += &base_code[position..func.end_position_of_colon() as usize];
+    // Parameters on separate lines need the indentation of the new method
+    stub += &signature.replace(&format!("\n{base_indent}"), &format!("\n{indent}"));
+    stub.push('\n');
+    stub += indent;
+    stub += if indent.contains('\t') { "\t" } else { "    " };
+    stub += "...";
+    stub
+}
+
+/// Checks that the names an annotation starts with (e.g. `foo` in `foo.Bar[int]`) refer to the
+/// same definitions in `file` as in `base_file`.
+fn names_are_usable(
+    db: &Database,
+    file: &PythonFile,
+    base_file: &PythonFile,
+    expr: Expression,
+) -> bool {
+    if file.file_index == base_file.file_index {
+        return true;
+    }
+    expr.search_names()
+        .filter(|name| matches!(name.parent(), NameParent::Atom(_)))
+        .all(|name| {
+            let name = name.as_code();
+            match (base_file.lookup_symbol(name), file.lookup_symbol(name)) {
+                // Builtins are available everywhere as long as they are not shadowed.
+                (None, None) => true,
+                (Some(base_symbol), Some(symbol)) => {
+                    let infer = |node_ref: NodeRef| {
+                        node_ref.infer_name_of_definition_by_index(&InferenceState::new(
+                            db,
+                            node_ref.file,
+                        ))
+                    };
+                    let link = infer(base_symbol).maybe_saved_link();
+                    link.is_some() && link == infer(symbol).maybe_saved_link()
+                }
+                _ => false,
+            }
+        })
+}
+
+/// Creates a call with placeholders for the required arguments, e.g. `func(${1:a}, ${2:b})$0`.
+/// The params are taken from the syntax tree, because inferring the signatures of all completions
+/// would be too slow.
+fn call_snippet(name: &str, func: Option<FunctionDef>, is_method: bool) -> String {
+    let Some(func) = func else {
+        return format!("{name}($0)");
+    };
+    let decorators: Vec<_> = func
+        .maybe_decorated()
+        .map(|decorated| {
+            decorated
+                .decorators()
+                .iter()
+                .map(|d| d.named_expression().as_code())
+                .collect()
+        })
+        .unwrap_or_default();
+    let is_decorated_with = |name| {
+        decorators
+            .iter()
+            .any(|d| d.rsplit('.').next() == Some(name))
+    };
+    let keeps_signature = decorators.iter().all(|d| {
+        matches!(
+            d.rsplit('.').next(),
+            Some("staticmethod" | "classmethod" | "override" | "final" | "abstractmethod")
+        )
+    });
+    if !keeps_signature {
+        return format!("{name}($0)");
+    }
+    let skip_first =
+        is_method && !is_decorated_with("staticmethod") || is_decorated_with("classmethod");
+    let mut arguments = vec![];
+    let mut has_optional_params = false;
+    for param in func.params().iter().skip(skip_first.into()) {
+        let index = arguments.len() + 1;
+        let name = param.name_def().as_code();
+        match param.kind() {
+            ParamKind::PositionalOnly | ParamKind::PositionalOrKeyword
+                if param.default().is_none() =>
+            {
+                arguments.push(format!("${{{index}:{name}}}"))
+            }
+            ParamKind::KeywordOnly if param.default().is_none() => {
+                arguments.push(format!("{name}=${{{index}:{name}}}"))
+            }
+            _ => has_optional_params = true,
+        }
+    }
+    if arguments.is_empty() {
+        if has_optional_params {
+            format!("{name}($0)")
+        } else {
+            format!("{name}()$0")
+        }
+    } else {
+        format!("{name}({})$0", arguments.join(", "))
+    }
+}
+
 fn find_kind_and_try_to_follow_imports(
     db: &Database,
     file: &PythonFile,
@@ -697,12 +1287,32 @@ pub trait Completion {
     fn insert_text(&self) -> String {
         self.label().to_string()
     }
+    /// An alternative to the insert text with placeholders in the LSP snippet syntax, e.g.
+    /// `func(${1:a}, ${2:b})$0`.
+    fn snippet(&self) -> Option<String> {
+        None
+    }
+    /// The text that clients use for filtering if it is different from the label.
+    fn filter_text(&self) -> Option<String> {
+        None
+    }
     fn kind(&self) -> CompletionItemKind;
     fn file_path(&self) -> Option<&str>;
     fn deprecated(&self) -> bool {
         false
     }
-    fn documentation(&self) -> Option<&str> {
+    /// The module where the completion is defined.
+    fn module_name(&self) -> Option<String> {
+        None
+    }
+    // The following infos are expensive to calculate and should therefore only be used when a
+    // single completion is resolved.
+    /// The formatted type or signature of the completion.
+    fn type_description(&self) -> Option<String> {
+        None
+    }
+    /// The docstring rendered as Markdown.
+    fn documentation(&self) -> Option<String> {
         None
     }
 }
@@ -711,7 +1321,37 @@ struct CompletionTreeName<'db> {
     db: &'db Database,
     file: &'db PythonFile,
     name: &'db str,
+    name_def: Option<NameDef<'db>>,
     kind: CompletionItemKind,
+    allow_call_snippet: bool,
+}
+
+impl<'db> CompletionTreeName<'db> {
+    fn with_value_name<T>(
+        &self,
+        callback: impl FnOnce(&InferenceState, ValueName) -> T,
+    ) -> Option<T> {
+        let db = self.db;
+        let (file, name_def) = follow_imports(db, self.file, self.name_def?);
+        let name = name_def.name();
+        let tree_name = TreeName::with_unknown_parent_scope(db, file, name);
+        if let Scope::Class(class) = tree_name.parent_scope {
+            cache_class_name(NodeRef::new(file, class.name_def().index()), class);
+            ClassNodeRef::new(file, class.index())
+                .ensure_cached_class_infos(&InferenceState::new(db, file));
+        }
+        with_i_s_non_self(db, file, tree_name.parent_scope, |i_s| {
+            let inf = file.inference(i_s).infer_name_of_definition(name);
+            let type_ = inf.as_cow_type(i_s);
+            Some(callback(
+                i_s,
+                ValueName {
+                    type_: &type_,
+                    name: Name::TreeName(tree_name),
+                },
+            ))
+        })
+    }
 }
 
 impl<'db> Completion for CompletionTreeName<'db> {
@@ -726,6 +1366,76 @@ impl<'db> Completion for CompletionTreeName<'db> {
     fn file_path(&self) -> Option<&str> {
         Some(self.file.file_path(self.db))
     }
+
+    fn type_description(&self) -> Option<String> {
+        self.with_value_name(|_, value_name| {
+            let description = value_name
+                .maybe_pretty_function_type()
+                .unwrap_or_else(|| value_name.type_description())
+                .into_string();
+            match self.kind {
+                // Show `Foo` instead of `Type[Foo]` for classes
+                CompletionItemKind::CLASS | CompletionItemKind::ENUM => description
+                    .strip_prefix("Type[")
+                    .and_then(|d| d.strip_suffix(']'))
+                    .map(|d| d.to_owned())
+                    .unwrap_or(description),
+                _ => description,
+            }
+        })
+    }
+
+    fn snippet(&self) -> Option<String> {
+        if !self.allow_call_snippet
+            || !matches!(
+                self.kind,
+                CompletionItemKind::FUNCTION | CompletionItemKind::METHOD
+            )
+        {
+            return None;
+        }
+        let (_, name_def) = follow_imports(self.db, self.file, self.name_def?);
+        Some(call_snippet(
+            self.name,
+            name_def.maybe_name_of_func(),
+            self.kind == CompletionItemKind::METHOD,
+        ))
+    }
+
+    fn documentation(&self) -> Option<String> {
+        self.with_value_name(|_, value_name| value_name.documentation_markdown())
+            .filter(|doc| !doc.is_empty())
+    }
+
+    fn module_name(&self) -> Option<String> {
+        let (file, _) = follow_imports(self.db, self.file, self.name_def?);
+        Some(file.qualified_name(self.db))
+    }
+}
+
+/// Follows the imports of a name to the definition that was imported, as far as the imports were
+/// already inferred.
+fn follow_imports<'db>(
+    db: &'db Database,
+    mut file: &'db PythonFile,
+    mut name_def: NameDef<'db>,
+) -> (&'db PythonFile, NameDef<'db>) {
+    while name_def.maybe_import().is_some() {
+        let p = NodeRef::new(file, name_def.index()).point();
+        if !p.calculated() || p.kind() != PointKind::Redirect {
+            break;
+        }
+        let node_ref = p.as_redirected_node_ref(db);
+        let Some(n) = node_ref.maybe_name().and_then(|name| name.name_def()) else {
+            break;
+        };
+        if n.index() == name_def.index() && node_ref.file.file_index == file.file_index {
+            break;
+        }
+        file = node_ref.file;
+        name_def = n;
+    }
+    (file, name_def)
 }
 
 #[expect(dead_code)]
@@ -779,6 +1489,66 @@ impl Completion for KeywordCompletion {
     }
 }
 
+struct OverrideCompletion<'db> {
+    name: &'db str,
+    filter_text: String,
+    stub: String,
+}
+
+impl Completion for OverrideCompletion<'_> {
+    fn label(&self) -> &str {
+        self.name
+    }
+
+    fn insert_text(&self) -> String {
+        self.stub.clone()
+    }
+
+    fn filter_text(&self) -> Option<String> {
+        Some(self.filter_text.clone())
+    }
+
+    fn kind(&self) -> CompletionItemKind {
+        CompletionItemKind::METHOD
+    }
+
+    fn file_path(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Completions that are not names, like `name=` for keyword arguments or literal values.
+struct SyntheticCompletion {
+    label: String,
+    insert_text: Option<String>,
+    kind: CompletionItemKind,
+    type_description: Option<String>,
+}
+
+impl Completion for SyntheticCompletion {
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    fn insert_text(&self) -> String {
+        self.insert_text
+            .clone()
+            .unwrap_or_else(|| self.label.clone())
+    }
+
+    fn kind(&self) -> CompletionItemKind {
+        self.kind
+    }
+
+    fn file_path(&self) -> Option<&str> {
+        None
+    }
+
+    fn type_description(&self) -> Option<String> {
+        self.type_description.clone()
+    }
+}
+
 struct EnumMemberCompletion<'db> {
     db: &'db Database,
     enum_: &'db Enum,
@@ -797,6 +1567,22 @@ impl Completion for EnumMemberCompletion<'_> {
     fn file_path(&self) -> Option<&str> {
         Some(self.db.file_path(self.enum_.defined_at.file))
     }
+
+    fn type_description(&self) -> Option<String> {
+        Some(format!(
+            "{}.{}",
+            self.enum_.name.as_str(self.db),
+            self.member.name(self.db)
+        ))
+    }
+
+    fn module_name(&self) -> Option<String> {
+        Some(
+            self.db
+                .loaded_python_file(self.enum_.defined_at.file)
+                .qualified_name(self.db),
+        )
+    }
 }
 
 struct NamedTupleMemberCompletion<'db> {
@@ -817,12 +1603,25 @@ impl Completion for NamedTupleMemberCompletion<'_> {
     fn file_path(&self) -> Option<&str> {
         Some(self.db.file_path(self.file))
     }
+
+    fn type_description(&self) -> Option<String> {
+        let t = self.param.type_.maybe_type()?;
+        Some(t.format_short(self.db).into_string())
+    }
+
+    fn module_name(&self) -> Option<String> {
+        Some(
+            self.db
+                .loaded_python_file(self.file)
+                .qualified_name(self.db),
+        )
+    }
 }
 
 #[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone)]
 enum CompletionSortPriority<'db> {
-    //Literal,    // e.g. TypedDict literal
-    //NamedParam, // e.g. def foo(*, bar) => `foo(b` completes to bar=
+    Literal,    // e.g. TypedDict literal
+    NamedParam, // e.g. def foo(*, bar) => `foo(b` completes to bar=
     EnumMember,
     Default(&'db str),
     Dunder(&'db str), // e.g. __eq__
@@ -3,7 +3,10 @@ use std::{
     cell::Cell,
     fmt, mem,
     ops::Range,
-    sync::{Arc, Mutex, OnceLock, RwLock},
+    sync::{
+        Arc, Mutex, OnceLock, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 
 use config::{OverrideConfig, Settings};
@@ -17,6 +20,7 @@ use vfs::{
 use crate::{
     ProjectOptions, TypeCheckerFlags, debug,
     file::{ClassNodeRef, File, PythonFile},
+    matching::invalidate_protocol_cache,
     node_ref::NodeRef,
     python_state::PythonState,
     recoverable_error, sys_path,
@@ -539,6 +543,7 @@ pub(crate) enum Specific {
     TypingTypeGuard,
     TypingTypeIs,
     RevealTypeFunction,
+    RevealLocalsFunction,
     AssertTypeFunction,
     TypingNamedTuple,      // typing.NamedTuple
     CollectionsNamedTuple, // collections.namedtuple
@@ -970,6 +975,8 @@ pub(crate) struct Database {
     pub python_state: PythonState,
     pub project: PythonProject,
     pub mode: Mode,
+    // Set by a language server if the result of a running request is not needed anymore.
+    pub cancellation_flag: Arc<AtomicBool>,
 }
 
 impl Database {
@@ -983,12 +990,7 @@ impl Database {
         mode: Mode,
         recovery: Option<vfs::VfsPanicRecovery<Tree>>,
     ) -> Self {
-        let project = PythonProject {
-            sys_path: sys_path::create_sys_path(&*vfs_handler, &options.settings),
-            settings: options.settings,
-            flags: options.flags,
-            overrides: options.overrides,
-        };
+        let project = PythonProject::new(&*vfs_handler, options);
 
         let mut vfs = Vfs::new(vfs_handler);
 
@@ -1016,8 +1018,8 @@ impl Database {
             )
         }
 
-        for p in &project.sys_path {
-            vfs.add_workspace(p.clone(), WorkspaceKind::SitePackages)
+        for p in project.sys_paths_of_all_environments() {
+            vfs.add_workspace(p, WorkspaceKind::SitePackages)
         }
         // This AbsPath is not really an absolute path, it's just a fallback so anything can be
         // part of it.
@@ -1040,12 +1042,17 @@ impl Database {
             python_state: PythonState::reserve(),
             project,
             mode,
+            cancellation_flag: Default::default(),
         };
 
         this.generate_python_state();
         this
     }
 
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation_flag.load(Ordering::Relaxed)
+    }
+
     pub fn from_recovery(
         vfs_handler: Box<dyn VfsHandler>,
         options: ProjectOptions,
@@ -1056,12 +1063,7 @@ impl Database {
     }
 
     pub fn try_to_reuse_project_resources_for_tests(&mut self, options: ProjectOptions) -> Self {
-        let project = PythonProject {
-            sys_path: sys_path::create_sys_path(&*self.vfs.handler, &options.settings),
-            settings: options.settings,
-            flags: options.flags,
-            overrides: options.overrides,
-        };
+        let project = PythonProject::new(&*self.vfs.handler, options);
 
         let mut mypy_path_iter = project.settings.mypy_path.iter().map(|p| &**p);
         assert!(
@@ -1077,12 +1079,11 @@ impl Database {
             python_state: self.python_state.clone(),
             mode: self.mode,
             project,
+            cancellation_flag: Default::default(),
         };
 
-        for p in &new_db.project.sys_path {
-            new_db
-                .vfs
-                .add_workspace(p.clone(), WorkspaceKind::SitePackages)
+        for p in new_db.project.sys_paths_of_all_environments() {
+            new_db.vfs.add_workspace(p, WorkspaceKind::SitePackages)
         }
 
         let mut set_pointer = |pointer_ref: &mut *const PythonFile, name, is_package| {
@@ -1188,17 +1189,30 @@ impl Database {
     }
 
     pub fn store_in_memory_file(&mut self, path: PathWithScheme, code: Box<str>) -> FileIndex {
+        // Parsing of changed in memory files reuses the unchanged parts of the previous tree.
+        let previous_tree = self
+            .vfs
+            .in_memory_file(&path)
+            .and_then(|file_index| self.vfs.file(file_index))
+            .map(|file| file.tree.clone());
         let (file_index, invalidation) = self.vfs.store_in_memory_file(
             self.project.flags.case_sensitive,
             path,
             code,
-            |file_index, file_entry, new_code| {
-                PythonFile::from_file_entry_and_code(
+            |file_index, file_entry, new_code| match &previous_tree {
+                Some(tree) => PythonFile::from_file_entry_and_changed_code(
                     &self.project,
                     file_index,
                     file_entry,
+                    tree,
                     new_code,
-                )
+                ),
+                None => PythonFile::from_file_entry_and_code(
+                    &self.project,
+                    file_index,
+                    file_entry,
+                    new_code,
+                ),
             },
         );
         self.handle_invalidation(invalidation);
@@ -1206,6 +1220,8 @@ impl Database {
     }
 
     fn handle_invalidation(&mut self, invalidation_result: InvalidationResult) {
+        // The protocol cache might contain types of invalidated files.
+        invalidate_protocol_cache();
         if invalidation_result == InvalidationResult::InvalidatedDb {
             self.invalidate_db();
         }
@@ -1298,6 +1314,13 @@ impl Database {
         self.loaded_python_file(file_index)
     }
 
+    pub fn record_narrowed_names(&mut self, index: FileIndex) {
+        self.vfs.files[index.0 as usize]
+            .file_mut()
+            .expect("Narrowed names can only be recorded for loaded files")
+            .record_narrowed_names()
+    }
+
     pub fn loaded_python_file(&self, index: FileIndex) -> &PythonFile {
         self.vfs.file(index).unwrap_or_else(|| {
             panic!(
@@ -1419,10 +1442,60 @@ pub(crate) struct PythonProject {
     pub settings: Settings,
     pub flags: TypeCheckerFlags,
     pub(crate) overrides: Vec<OverrideConfig>,
+    pub(crate) sub_projects: Vec<SubProject>,
     // is_django: bool,  // TODO maybe add?
 }
 
+/// A directory within the project that has its own config (e.g. a separate workspace folder or
+/// a nested config file). Files within it use its flags and its environment (Python version,
+/// platform and sys path) instead of the ones of the project.
+#[derive(Debug)]
+pub(crate) struct SubProject {
+    pub root: Arc<NormalizedPath>,
+    pub sys_path: Vec<Arc<NormalizedPath>>,
+    pub settings: Settings,
+    pub flags: TypeCheckerFlags,
+    pub overrides: Vec<OverrideConfig>,
+}
+
 impl PythonProject {
+    fn new(handler: &dyn VfsHandler, options: ProjectOptions) -> Self {
+        let sys_path = sys_path::create_sys_path(handler, &options.settings);
+        let mut sub_projects = vec![];
+        for sub_project in options.sub_projects {
+            sub_projects.push(SubProject {
+                root: sub_project.root,
+                sys_path: sys_path::create_sys_path(handler, &sub_project.options.settings),
+                settings: sub_project.options.settings,
+                flags: sub_project.options.flags,
+                overrides: sub_project.options.overrides,
+            })
+        }
+        // The most nested sub projects should be found first.
+        sub_projects.sort_by_key(|sub_project| std::cmp::Reverse(sub_project.root.len()));
+        Self {
+            sys_path,
+            settings: options.settings,
+            flags: options.flags,
+            overrides: options.overrides,
+            sub_projects,
+        }
+    }
+
+    /// The sys paths of all environments. Each of them is a workspace of the VFS, but a file only
+    /// imports from the ones of its own environment.
+    pub fn sys_paths_of_all_environments(&self) -> Vec<Arc<NormalizedPath>> {
+        let mut sys_paths = self.sys_path.clone();
+        for sub_project in &self.sub_projects {
+            for p in &sub_project.sys_path {
+                if !sys_paths.contains(p) {
+                    sys_paths.push(p.clone())
+                }
+            }
+        }
+        sys_paths
+    }
+
     pub fn strict_optional_partials(&self) -> bool {
         // Mypy is currently just replacing the nullable partial to a non-nullable one.
         self.settings.mypy_compatible
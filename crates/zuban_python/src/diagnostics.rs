@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use config::DiagnosticConfig;
-use parsa_python_cst::{CodeIndex, NodeIndex, Tree};
+use parsa_python_cst::{CodeIndex, NodeIndex, SyntaxErrorKind, Tree};
 use utils::InsertOnlyVec;
 
 use crate::{
@@ -22,8 +22,11 @@ use crate::{
 #[rustfmt::skip]  // This is way more readable if we are not auto-formatting this.
 pub(crate) enum IssueKind {
     InvalidSyntax,
+    SyntaxError(SyntaxErrorKind),
     InvalidSyntaxInTypeComment { type_comment: Box<str> },
     InvalidSyntaxInTypeAnnotation,
+    SyntaxNotSupportedInPythonVersion { feature: &'static str, minor: usize },
+    TemplateStringMixedWithString,
     StarExceptionWithoutTypingSupport,
     TypeIgnoreWithErrorCodeNotSupportedForModules { ignore_code: Box<str> },
     DirectiveSyntaxError(Box<str>),
@@ -472,6 +475,7 @@ pub(crate) enum IssueKind {
     NonOverlappingEqualityCheck { left_type: Box<str>, right_type: Box<str> }, // From --strict-equality
     NonOverlappingIdentityCheck { left_type: Box<str>, right_type: Box<str> }, // From --strict-equality
     NonOverlappingContainsCheck { element_type: Box<str>, container_type: Box<str> }, // From --strict-equality
+    MatchIsNotExhaustive { unmatched: Box<str> }, // From --enable-error-code exhaustive-match
 
     InvariantNote { actual: &'static str, maybe: &'static str },
     AnnotationInUntypedFunction,
@@ -484,8 +488,11 @@ impl IssueKind {
         Some(match &self {
             Note(_) | InvariantNote { .. } => return None,
             InvalidSyntax
+            | SyntaxError(_)
             | InvalidSyntaxInTypeComment { .. }
             | InvalidSyntaxInTypeAnnotation
+            | SyntaxNotSupportedInPythonVersion { .. }
+            | TemplateStringMixedWithString
             | TypeIgnoreWithErrorCodeNotSupportedForModules { .. }
             | DirectiveSyntaxError(..) => "syntax",
             AttributeError { .. }
@@ -589,6 +596,7 @@ impl IssueKind {
             | NonOverlappingIdentityCheck { .. } => "comparison-overlap",
             UnimportedRevealType => "unimported-reveal",
             DisallowedAnyExplicit => "explicit-any",
+            MatchIsNotExhaustive { .. } => "exhaustive-match",
 
             _ => "misc",
         })
@@ -752,6 +760,13 @@ impl<'db> Diagnostic<'db> {
         use IssueKind::*;
         match &self.issue.kind {
             InvalidSyntax => "invalid syntax".to_string(),
+            SyntaxError(kind) => kind.to_string(),
+            SyntaxNotSupportedInPythonVersion { feature, minor } => {
+                format!("{feature} only supported in Python 3.{minor} and greater")
+            }
+            TemplateStringMixedWithString => {
+                "cannot mix t-string literals with string or bytes literals".to_string()
+            }
             InvalidSyntaxInTypeComment { type_comment } => format!(
                 r#"Syntax error in type comment "{type_comment}""#
             ),
@@ -1176,8 +1191,14 @@ impl<'db> Diagnostic<'db> {
             NonOverlappingContainsCheck { element_type, container_type } => format!(
                 r#"Non-overlapping container check (element type: "{element_type}", container item type: "{container_type}")"#
             ),
+            MatchIsNotExhaustive { unmatched } => {
+                additional_notes.push(
+                    "If match statement is intended to be non-exhaustive, add `case _: pass`".into()
+                );
+                format!(r#"Match statement has unhandled case for values of type "{unmatched}""#)
+            }
             UnimportedRevealType => {
-                let module = if self.db.project.settings.python_version_or_default() < PythonVersion::new(3, 11) {
+                let module = if self.file.settings(self.db).python_version_or_default() < PythonVersion::new(3, 11) {
                     "typing_extensions"
                 } else {
                     "typing"
@@ -0,0 +1,622 @@
+//! Renders Python docstrings as Markdown. Google, NumPy and Sphinx/reST docstrings are
+//! understood, everything else is passed through mostly unchanged.
+
+use regex::{Captures, Regex};
+
+lazy_static::lazy_static! {
+    static ref ROLE: Regex = Regex::new(
+        r":(?:py:)?(?:func|meth|class|mod|attr|exc|obj|data|const|ref|term|any):`([^`]+)`"
+    ).unwrap();
+    static ref DOUBLE_BACKTICKS: Regex = Regex::new(r"``([^`]+)``").unwrap();
+    static ref GOOGLE_SECTION: Regex = Regex::new(r"^([A-Z][A-Za-z ]*):\s*$").unwrap();
+    static ref GOOGLE_PARAM: Regex =
+        Regex::new(r"^(\*{0,2}\w+)\s*(?:\(([^)]*)\))?\s*:(?:\s+(.*))?$").unwrap();
+    static ref SPHINX_FIELD: Regex = Regex::new(r"^:(\w+)([^:]*):(?:\s+(.*))?$").unwrap();
+    static ref DIRECTIVE: Regex = Regex::new(r"^\.\.\s+([\w-]+)::(?:\s+(.*))?$").unwrap();
+}
+
+/// Converts a cleaned docstring to Markdown. `param_types` are the formatted types of the
+/// signature's params and are used if a documented param has no type.
+pub(crate) fn docstring_to_markdown(docstring: &str, param_types: &[(&str, Box<str>)]) -> String {
+    let lines: Vec<&str> = docstring.lines().collect();
+    let mut blocks = vec![];
+    let mut text = vec![];
+    let mut sphinx_fields = SphinxFields::default();
+    let mut sphinx_block_index = None;
+    let flush_text = |text: &mut Vec<&str>, blocks: &mut Vec<String>| {
+        blocks.push(render_text(text));
+        text.clear();
+    };
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if indentation(line) == 0 {
+            let title = line.trim();
+            if let Some(kind) = numpy_section(&lines, i) {
+                flush_text(&mut text, &mut blocks);
+                let end = (i + 2..lines.len())
+                    .find(|&j| numpy_section(&lines, j).is_some())
+                    .unwrap_or(lines.len());
+                blocks.push(kind.render(title, &lines[i + 2..end], true, param_types));
+                i = end;
+                continue;
+            }
+            if let Some(c) = GOOGLE_SECTION.captures(title)
+                && let Some(kind) = SectionKind::from_title(&c[1])
+            {
+                let end = (i + 1..lines.len())
+                    .find(|&j| !lines[j].trim().is_empty() && indentation(lines[j]) == 0)
+                    .unwrap_or(lines.len());
+                if lines[i + 1..end].iter().any(|l| !l.trim().is_empty()) {
+                    flush_text(&mut text, &mut blocks);
+                    blocks.push(kind.render(&c[1], &lines[i + 1..end], false, param_types));
+                    i = end;
+                    continue;
+                }
+            }
+            if let Some(c) = SPHINX_FIELD.captures(line) {
+                let mut description = c.get(3).map(|m| m.as_str().trim()).unwrap_or("").to_owned();
+                let mut end = i + 1;
+                while end < lines.len()
+                    && !lines[end].trim().is_empty()
+                    && indentation(lines[end]) > 0
+                {
+                    append_line(&mut description, lines[end]);
+                    end += 1;
+                }
+                if sphinx_fields.add(&c[1], c[2].trim(), description) {
+                    if sphinx_block_index.is_none() {
+                        flush_text(&mut text, &mut blocks);
+                        sphinx_block_index = Some(blocks.len());
+                        blocks.push(String::new());
+                    }
+                    i = end;
+                    continue;
+                }
+            }
+        }
+        text.push(line);
+        i += 1;
+    }
+    flush_text(&mut text, &mut blocks);
+    if let Some(index) = sphinx_block_index {
+        blocks[index] = sphinx_fields.render(param_types);
+    }
+    blocks.retain(|block| !block.is_empty());
+    blocks.join("\n\n")
+}
+
+#[derive(Clone, Copy)]
+enum SectionKind {
+    Params,
+    Returns,
+    Raises,
+    Other,
+}
+
+impl SectionKind {
+    fn from_title(title: &str) -> Option<Self> {
+        Some(match title {
+            "Args" | "Arguments" | "Parameters" | "Params" | "Keyword Args"
+            | "Keyword Arguments" | "Other Parameters" | "Attributes" => Self::Params,
+            "Returns" | "Return" | "Yields" | "Yield" | "Receives" => Self::Returns,
+            "Raises" | "Warns" => Self::Raises,
+            "Example" | "Examples" | "Note" | "Notes" | "Warning" | "Warnings" | "See Also"
+            | "References" | "Todo" | "Methods" => Self::Other,
+            _ => return None,
+        })
+    }
+
+    fn render(
+        self,
+        title: &str,
+        body: &[&str],
+        is_numpy: bool,
+        param_types: &[(&str, Box<str>)],
+    ) -> String {
+        let title = match title {
+            "Args" | "Arguments" | "Params" => "Parameters",
+            "Keyword Args" => "Keyword Arguments",
+            "Return" => "Returns",
+            "Yield" => "Yields",
+            "Example" => "Examples",
+            "Note" => "Notes",
+            "Warning" => "Warnings",
+            _ => title,
+        };
+        let body = dedent(body);
+        let content = match self {
+            Self::Params => {
+                let mut entries = parse_entries(&body, is_numpy);
+                // NumPy params without a type only consist of the name.
+                for entry in &mut entries {
+                    if entry.name.is_none() {
+                        entry.name = entry.type_.take();
+                    }
+                }
+                render_params_table(&entries, param_types)
+            }
+            Self::Returns => {
+                let entries = match is_numpy {
+                    true => parse_entries(&body, true),
+                    false => vec![parse_google_return(&body)],
+                };
+                render_list(&entries)
+            }
+            Self::Raises => {
+                let mut entries = parse_entries(&body, is_numpy);
+                // Google style uses the param syntax with the exception as the name.
+                for entry in &mut entries {
+                    if entry.type_.is_none() {
+                        entry.type_ = entry.name.take();
+                    }
+                }
+                render_list(&entries)
+            }
+            Self::Other => render_text(&body),
+        };
+        format!("**{title}**\n\n{content}")
+    }
+}
+
+#[derive(Default)]
+struct Entry {
+    name: Option<String>,
+    type_: Option<String>,
+    description: String,
+}
+
+fn numpy_section(lines: &[&str], i: usize) -> Option<SectionKind> {
+    let underline = lines.get(i + 1)?.trim();
+    if indentation(lines[i]) != 0 || underline.len() < 3 || !underline.chars().all(|c| c == '-') {
+        return None;
+    }
+    SectionKind::from_title(lines[i].trim())
+}
+
+/// Parses params, raises and NumPy returns. An entry starts at the base indentation and is
+/// described by the indented lines after it.
+fn parse_entries(body: &[&str], is_numpy: bool) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = vec![];
+    for line in body {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if indentation(line) > 0 {
+            if let Some(entry) = entries.last_mut() {
+                append_line(&mut entry.description, line);
+            }
+            continue;
+        }
+        let line = line.trim();
+        let entry = if is_numpy {
+            match line.split_once(" : ").or_else(|| line.split_once(": ")) {
+                Some((name, type_)) => Entry {
+                    name: Some(name.trim().to_owned()),
+                    type_: Some(type_.trim().to_owned()),
+                    description: String::new(),
+                },
+                None => Entry {
+                    type_: Some(line.to_owned()),
+                    ..Default::default()
+                },
+            }
+        } else if let Some(c) = GOOGLE_PARAM.captures(line) {
+            Entry {
+                name: Some(c[1].to_owned()),
+                type_: c.get(2).map(|m| m.as_str().trim().to_owned()),
+                description: c.get(3).map(|m| m.as_str().to_owned()).unwrap_or_default(),
+            }
+        } else if let Some(last) = entries.last_mut() {
+            append_line(&mut last.description, line);
+            continue;
+        } else {
+            Entry {
+                description: line.to_owned(),
+                ..Default::default()
+            }
+        };
+        entries.push(entry);
+    }
+    entries
+}
+
+fn parse_google_return(body: &[&str]) -> Entry {
+    let mut description = String::new();
+    for line in body {
+        append_line(&mut description, line);
+    }
+    if let Some((type_, rest)) = description.split_once(": ")
+        && type_
+            .replace(", ", ",")
+            .replace(" | ", "|")
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_.[]|,".contains(c))
+    {
+        return Entry {
+            name: None,
+            type_: Some(type_.to_owned()),
+            description: rest.to_owned(),
+        };
+    }
+    Entry {
+        description,
+        ..Default::default()
+    }
+}
+
+fn render_params_table(entries: &[Entry], param_types: &[(&str, Box<str>)]) -> String {
+    let mut out = "| Name | Type | Description |\n| --- | --- | --- |".to_owned();
+    for entry in entries {
+        let name = entry.name.as_deref().unwrap_or("");
+        let type_ = entry.type_.as_deref().or_else(|| {
+            let bare_name = name.trim_start_matches('*');
+            param_types
+                .iter()
+                .find(|(n, _)| *n == bare_name)
+                .map(|(_, t)| t.as_ref())
+        });
+        out += &format!(
+            "\n| {} | {} | {} |",
+            table_cell(&code(name)),
+            table_cell(&type_.map(code).unwrap_or_default()),
+            table_cell(&convert_inline(&entry.description)),
+        );
+    }
+    out
+}
+
+fn render_list(entries: &[Entry]) -> String {
+    let items: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let description = convert_inline(&entry.description);
+            let mut out = "- ".to_owned();
+            match (&entry.name, &entry.type_) {
+                (Some(name), Some(type_)) => out += &format!("{} ({})", code(name), code(type_)),
+                (Some(name), None) => out += &code(name),
+                (None, Some(type_)) => out += &code(type_),
+                (None, None) => return out + &description,
+            }
+            if !description.is_empty() {
+                out += ": ";
+                out += &description;
+            }
+            out
+        })
+        .collect();
+    items.join("\n")
+}
+
+#[derive(Default)]
+struct SphinxFields {
+    params: Vec<Entry>,
+    attributes: Vec<Entry>,
+    returns: Option<Entry>,
+    yields: Option<Entry>,
+    raises: Vec<Entry>,
+}
+
+impl SphinxFields {
+    /// Returns false if the field is unknown.
+    fn add(&mut self, field: &str, argument: &str, description: String) -> bool {
+        fn entry<'x>(entries: &'x mut Vec<Entry>, name: &str) -> &'x mut Entry {
+            if let Some(index) = entries.iter().position(|e| e.name.as_deref() == Some(name)) {
+                return &mut entries[index];
+            }
+            entries.push(Entry {
+                name: Some(name.to_owned()),
+                ..Default::default()
+            });
+            entries.last_mut().unwrap()
+        }
+        let split_type_and_name = |argument: &str| match argument.rsplit_once(char::is_whitespace) {
+            Some((type_, name)) => (Some(type_.trim().to_owned()), name.to_owned()),
+            None => (None, argument.to_owned()),
+        };
+        match field {
+            "param" | "parameter" | "arg" | "argument" | "key" | "keyword" | "var" | "ivar"
+            | "cvar" => {
+                if argument.is_empty() {
+                    return false;
+                }
+                let entries = match field {
+                    "var" | "ivar" | "cvar" => &mut self.attributes,
+                    _ => &mut self.params,
+                };
+                let (type_, name) = split_type_and_name(argument);
+                let e = entry(entries, &name);
+                e.description = description;
+                if type_.is_some() {
+                    e.type_ = type_;
+                }
+            }
+            "type" | "vartype" => {
+                if argument.is_empty() {
+                    return false;
+                }
+                let entries = match field {
+                    "vartype" => &mut self.attributes,
+                    _ => &mut self.params,
+                };
+                entry(entries, argument).type_ = Some(description);
+            }
+            "returns" | "return" => self.returns.get_or_insert_default().description = description,
+            "rtype" => self.returns.get_or_insert_default().type_ = Some(description),
+            "yields" | "yield" => self.yields.get_or_insert_default().description = description,
+            "ytype" => self.yields.get_or_insert_default().type_ = Some(description),
+            "raises" | "raise" | "except" | "exception" => self.raises.push(Entry {
+                name: None,
+                type_: (!argument.is_empty()).then(|| argument.to_owned()),
+                description,
+            }),
+            _ => return false,
+        }
+        true
+    }
+
+    fn render(&self, param_types: &[(&str, Box<str>)]) -> String {
+        let mut blocks = vec![];
+        if !self.params.is_empty() {
+            blocks.push(format!(
+                "**Parameters**\n\n{}",
+                render_params_table(&self.params, param_types)
+            ));
+        }
+        if !self.attributes.is_empty() {
+            blocks.push(format!(
+                "**Attributes**\n\n{}",
+                render_params_table(&self.attributes, &[])
+            ));
+        }
+        if let Some(returns) = &self.returns {
+            blocks.push(format!(
+                "**Returns**\n\n{}",
+                render_list(std::slice::from_ref(returns))
+            ));
+        }
+        if let Some(yields) = &self.yields {
+            blocks.push(format!(
+                "**Yields**\n\n{}",
+                render_list(std::slice::from_ref(yields))
+            ));
+        }
+        if !self.raises.is_empty() {
+            blocks.push(format!("**Raises**\n\n{}", render_list(&self.raises)));
+        }
+        blocks.join("\n\n")
+    }
+}
+
+/// Renders free text: doctests and reST code blocks become code fences, reST roles and
+/// directives are simplified.
+fn render_text(lines: &[&str]) -> String {
+    let mut out: Vec<String> = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim_start();
+        let indent = indentation(line);
+        if trimmed.starts_with(">>>") {
+            let start = i;
+            while i < lines.len() && !lines[i].trim().is_empty() {
+                i += 1;
+            }
+            push_fence(&mut out, "python", &lines[start..i]);
+            continue;
+        }
+        if let Some(c) = DIRECTIVE.captures(trimmed) {
+            let argument = c.get(2).map(|m| m.as_str().trim()).unwrap_or("");
+            let start = i + 1;
+            i = block_end(lines, start, indent);
+            let body = &lines[start..i];
+            match &c[1] {
+                "code-block" | "code" | "sourcecode" => {
+                    let body: Vec<_> = body
+                        .iter()
+                        .copied()
+                        .skip_while(|l| l.trim().is_empty() || l.trim_start().starts_with(':'))
+                        .collect();
+                    push_fence(&mut out, argument, &body);
+                }
+                directive => {
+                    let title = match directive {
+                        "seealso" => "See also".to_owned(),
+                        "versionadded" => "Added in version".to_owned(),
+                        "versionchanged" => "Changed in version".to_owned(),
+                        "deprecated" => "Deprecated since version".to_owned(),
+                        _ => {
+                            let mut chars = directive.chars();
+                            chars
+                                .next()
+                                .map(|c| c.to_uppercase().collect::<String>())
+                                .unwrap_or_default()
+                                + chars.as_str()
+                        }
+                    };
+                    let mut text = argument.to_owned();
+                    for line in body {
+                        append_line(&mut text, line);
+                    }
+                    out.push(format!("**{title}:** {}", convert_inline(&text)));
+                }
+            }
+            continue;
+        }
+        if let Some(before) = trimmed.strip_suffix("::")
+            && let Some(next) = lines[i + 1..].iter().find(|l| !l.trim().is_empty())
+            && indentation(next) > indent
+        {
+            // A reST literal block, `Example::` is rendered as `Example:`.
+            let before = before.trim_end();
+            if !before.is_empty() {
+                out.push(format!("{}{before}:", &line[..indent]));
+            }
+            let start = i + 1;
+            i = block_end(lines, start, indent);
+            let body: Vec<_> = lines[start..i]
+                .iter()
+                .copied()
+                .skip_while(|l| l.trim().is_empty())
+                .collect();
+            push_fence(&mut out, "", &body);
+            continue;
+        }
+        out.push(convert_inline(line));
+        i += 1;
+    }
+    let start = out
+        .iter()
+        .position(|l| !l.trim().is_empty())
+        .unwrap_or(out.len());
+    let end = out
+        .iter()
+        .rposition(|l| !l.trim().is_empty())
+        .map_or(start, |e| e + 1);
+    out[start..end].join("\n")
+}
+
+/// Returns the end of a block that is indented more than `indent`.
+fn block_end(lines: &[&str], start: usize, indent: usize) -> usize {
+    let mut end = start;
+    for (i, line) in lines.iter().enumerate().skip(start) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if indentation(line) <= indent {
+            break;
+        }
+        end = i + 1;
+    }
+    end
+}
+
+fn push_fence(out: &mut Vec<String>, language: &str, lines: &[&str]) {
+    out.push(format!("```{language}"));
+    out.extend(dedent(lines).into_iter().map(|l| l.to_owned()));
+    out.push("```".to_owned());
+}
+
+fn convert_inline(text: &str) -> String {
+    let text = ROLE.replace_all(text, |c: &Captures| {
+        let target = c[1].trim();
+        // `Title <target>` is displayed as the title and `~a.b.c` as `c`.
+        let target = match target.split_once(" <") {
+            Some((title, _)) => title.trim(),
+            None => match target.strip_prefix('~') {
+                Some(t) => t.rsplit('.').next().unwrap(),
+                None => target,
+            },
+        };
+        format!("`{target}`")
+    });
+    DOUBLE_BACKTICKS.replace_all(&text, "`$1`").into_owned()
+}
+
+fn code(s: &str) -> String {
+    if s.is_empty() {
+        return String::new();
+    }
+    format!("`{s}`")
+}
+
+fn table_cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+fn append_line(description: &mut String, line: &str) {
+    let line = line.trim();
+    if line.is_empty() {
+        return;
+    }
+    if !description.is_empty() {
+        description.push(' ');
+    }
+    description.push_str(line);
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn dedent<'x>(lines: &[&'x str]) -> Vec<&'x str> {
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| indentation(l))
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|l| {
+            if l.len() >= indent {
+                &l[indent..]
+            } else {
+                l.trim_start()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_markdown(docstring: &str) -> String {
+        docstring_to_markdown(docstring, &[("x", "int".into()), ("y", "str".into())])
+    }
+
+    #[test]
+    fn test_plain_docstring_is_unchanged() {
+        let doc = "Some text\n\nwith *two* paragraphs\n    and indentation";
+        assert_eq!(to_markdown(doc), doc);
+    }
+
+    #[test]
+    fn test_google() {
+        let doc = "Summary.\n\nArgs:\n    x: The x.\n        Continued.\n    y (bytes): The y.\n\n\
+                   Returns:\n    bool: Whether it worked.\n\nRaises:\n    ValueError: If bad.";
+        assert_eq!(
+            to_markdown(doc),
+            "Summary.\n\n**Parameters**\n\n| Name | Type | Description |\n| --- | --- | --- |\n\
+             | `x` | `int` | The x. Continued. |\n| `y` | `bytes` | The y. |\n\n\
+             **Returns**\n\n- `bool`: Whether it worked.\n\n\
+             **Raises**\n\n- `ValueError`: If bad."
+        );
+    }
+
+    #[test]
+    fn test_numpy() {
+        let doc = "Summary.\n\nParameters\n----------\nx : int | None\n    The x.\ny\n    The y.\n\n\
+                   Returns\n-------\nfloat\n    A number.\n\nExamples\n--------\n>>> f(1)\n2";
+        assert_eq!(
+            to_markdown(doc),
+            "Summary.\n\n**Parameters**\n\n| Name | Type | Description |\n| --- | --- | --- |\n\
+             | `x` | `int \\| None` | The x. |\n| `y` | `str` | The y. |\n\n\
+             **Returns**\n\n- `float`: A number.\n\n\
+             **Examples**\n\n```python\n>>> f(1)\n2\n```"
+        );
+    }
+
+    #[test]
+    fn test_sphinx() {
+        let doc = "Uses :func:`~foo.bar` and ``code``.\n\n:param x: The x.\n:param bytes y: The y.\n\
+                   :returns: The result.\n:rtype: float\n:raises KeyError: Sometimes.";
+        assert_eq!(
+            to_markdown(doc),
+            "Uses `bar` and `code`.\n\n**Parameters**\n\n| Name | Type | Description |\n\
+             | --- | --- | --- |\n| `x` | `int` | The x. |\n| `y` | `bytes` | The y. |\n\n\
+             **Returns**\n\n- `float`: The result.\n\n**Raises**\n\n- `KeyError`: Sometimes."
+        );
+    }
+
+    #[test]
+    fn test_rest_blocks() {
+        let doc = "Example::\n\n    x = 1\n\n.. code-block:: bash\n    :linenos:\n\n    ls -l\n\n\
+                   .. note:: Be careful\n   with this.";
+        assert_eq!(
+            to_markdown(doc),
+            "Example:\n```\nx = 1\n```\n\n```bash\nls -l\n```\n\n**Note:** Be careful with this."
+        );
+    }
+}
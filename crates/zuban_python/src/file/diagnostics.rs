@@ -2017,6 +2017,18 @@ impl Inference<'_, '_, '_> {
     }
 
     pub fn calc_fstring_diagnostics(&self, fstring: FString) {
+        if let Some((index, feature)) = fstring.find_pep_701_syntax()
+            && !self
+                .file
+                .settings(self.i_s.db)
+                .python_version_or_default()
+                .at_least_3_dot(12)
+        {
+            self.add_issue(
+                index,
+                IssueKind::SyntaxNotSupportedInPythonVersion { feature, minor: 12 },
+            );
+        }
         self.calc_fstring_content_diagnostics(fstring.iter_content())
     }
 
@@ -11,11 +11,11 @@ use parsa_python_cst::{
     BreakStmt, CaseBlock, CasePattern, CompIfIterator, ComparisonContent, Comparisons, Conjunction,
     ContinueStmt, DelTarget, DelTargets, Disjunction, ElseBlock, ExceptExpression, Expression,
     ExpressionContent, ExpressionPart, ForIfClauseIterator, ForStmt, IfBlockIterator, IfBlockType,
-    IfStmt, KeyEntryInPattern, MappingPatternItem, MatchStmt, Name, NameDef, NamedExpression,
-    NamedExpressionContent, NodeIndex, Operand, ParamPattern, Pattern, PatternKind, Primary,
-    PrimaryContent, PrimaryOrAtom, PrimaryTarget, PrimaryTargetOrAtom, SequencePatternItem,
-    SliceType as CSTSliceType, StarPatternContent, Target, Ternary, TryBlockType, TryStmt,
-    WhileStmt,
+    IfStmt, KeyEntryInPattern, LiteralPattern, LiteralPatternContent, MappingPatternItem,
+    MatchStmt, Name, NameDef, NamedExpression, NamedExpressionContent, NodeIndex, Operand,
+    ParamPattern, Pattern, PatternKind, Primary, PrimaryContent, PrimaryOrAtom, PrimaryTarget,
+    PrimaryTargetOrAtom, SequencePatternItem, SliceType as CSTSliceType, StarPatternContent,
+    SubjectExprContent, Target, Ternary, TryBlockType, TryStmt, WhileStmt,
 };
 
 use crate::{
@@ -1255,6 +1255,61 @@ fn split_off_singleton(
     Some((truthy, falsey))
 }
 
+fn split_off_literal(
+    i_s: &InferenceState,
+    of_type: &Type,
+    literal: &Literal,
+) -> Option<(Type, Type)> {
+    let literal_t = Type::Literal(literal.clone());
+    let mut truthy = Type::Never(NeverCause::Other);
+    let mut falsey = Type::Never(NeverCause::Other);
+    let true_literal = || {
+        let mut new_literal = literal.clone();
+        new_literal.implicit = false;
+        Type::Literal(new_literal)
+    };
+    for sub_t in of_type.iter_with_unpacked_unions(i_s.db) {
+        match sub_t {
+            Type::Literal(literal2) if literal.value(i_s.db) == literal2.value(i_s.db) => {
+                truthy.union_in_place(true_literal())
+            }
+            _ => {
+                if let Some((t, f)) = maybe_split_bool_from_literal(i_s.db, sub_t, &literal.kind) {
+                    truthy.union_in_place(t);
+                    falsey.union_in_place(f);
+                    continue;
+                }
+                if has_custom_eq(i_s, sub_t) {
+                    return None;
+                }
+                if sub_t.is_simple_super_type_of(i_s, &literal_t).bool() {
+                    truthy.union_in_place(literal_t.clone())
+                }
+                falsey.union_in_place(sub_t.clone())
+            }
+        }
+    }
+    Some((truthy, falsey))
+}
+
+/// Splits a type by a value or literal pattern of a match statement.
+fn split_off_value_pattern(
+    i_s: &InferenceState,
+    of_type: &Type,
+    value_t: &Type,
+) -> Option<(Type, Type)> {
+    match value_t {
+        Type::None => split_off_singleton(i_s, of_type, value_t, false),
+        Type::EnumMember(member) => {
+            let mut member = member.clone();
+            member.implicit = false;
+            split_off_enum_member(i_s, of_type, &member, false)
+        }
+        Type::Literal(literal) => split_off_literal(i_s, of_type, literal),
+        _ => None,
+    }
+}
+
 fn narrow_is_or_eq(
     i_s: &InferenceState,
     key: FlowKey,
@@ -1316,36 +1371,7 @@ fn narrow_is_or_eq(
             if is_eq && (!literal1.implicit || has_explicit_literal(i_s.db, checking_t))
                 || !is_eq && matches!(literal1.kind, LiteralKind::Bool(_)) =>
         {
-            let mut true_type = Type::Never(NeverCause::Other);
-            let mut false_type = Type::Never(NeverCause::Other);
-            let true_literal = || {
-                let mut new_literal = literal1.clone();
-                new_literal.implicit = false;
-                Type::Literal(new_literal)
-            };
-            for sub_t in checking_t.iter_with_unpacked_unions(i_s.db) {
-                match sub_t {
-                    Type::Literal(literal2) if literal1.value(i_s.db) == literal2.value(i_s.db) => {
-                        true_type.union_in_place(true_literal())
-                    }
-                    _ => {
-                        if let Some((truthy, falsey)) =
-                            maybe_split_bool_from_literal(i_s.db, sub_t, &literal1.kind)
-                        {
-                            true_type.union_in_place(truthy);
-                            false_type.union_in_place(falsey);
-                            continue;
-                        }
-                        if has_custom_eq(i_s, sub_t) {
-                            return None;
-                        }
-                        if sub_t.is_simple_super_type_of(i_s, other_t).bool() {
-                            true_type.union_in_place(other_t.clone())
-                        }
-                        false_type.union_in_place(sub_t.clone())
-                    }
-                }
-            }
+            let (true_type, false_type) = split_off_literal(i_s, checking_t, literal1)?;
             Some((
                 Frame::from_type(key.clone(), true_type),
                 Frame::from_type(key, false_type),
@@ -1589,6 +1615,12 @@ impl Inference<'_, '_, '_> {
         if deleted {
             self.add_issue(original_name_index, IssueKind::ReadingDeletedVariable)
         }
+        if let Some(narrowed_names) = &self.file.narrowed_names {
+            narrowed_names.write().unwrap().insert(
+                original_name_index,
+                result.as_cow_type(self.i_s).into_owned(),
+            );
+        }
         debug!(
             "Use narrowed {} as {}",
             NodeRef::from_link(self.i_s.db, name_link).as_code(),
@@ -1714,6 +1746,14 @@ impl Inference<'_, '_, '_> {
             RedefinitionResult::TypeMismatch(false) => false,
             RedefinitionResult::RedefinitionAllowed => true,
         };
+        if let Type::NewType(n) = declaration_t
+            && n.native_int.is_some()
+            && !matches!(new_t, Type::NewType(_) | Type::Never(_))
+        {
+            // Assigning an int to a native int keeps the native int type.
+            self.save_narrowed(key, declaration_t.clone(), allow_redefinition);
+            return;
+        }
         self.save_narrowed(key, new_t.clone(), allow_redefinition);
     }
 
@@ -2310,9 +2350,9 @@ impl Inference<'_, '_, '_> {
                 let mut check_block = |except_expr: Option<ExceptExpression>, block, is_star| {
                     let mut name_def = None;
                     let except_type = if let Some(except_expr) = except_expr {
-                        let expr;
-                        (expr, name_def) = except_expr.unpack();
-                        let inf = self.infer_expression(expr);
+                        let types;
+                        (types, name_def) = except_expr.unpack();
+                        let inf = self.infer_except_types(types);
                         let inf_t = inf.as_cow_type(self.i_s);
                         if let Some(name_def) = name_def {
                             let instantiated = match is_star {
@@ -2460,34 +2500,98 @@ impl Inference<'_, '_, '_> {
     ) {
         let (subject_expr, case_blocks) = match_stmt.unpack();
         let subject = self.infer_subject_expr(subject_expr);
-        self.process_match_cases(&subject, case_blocks, class, func);
+        let subject_key = match subject_expr.unpack() {
+            SubjectExprContent::NamedExpression(named_expr) => {
+                self.key_from_namedexpression(named_expr).key
+            }
+            SubjectExprContent::Tuple(_) => None,
+        };
+        let was_reachable = !self.is_unreachable();
+        let unmatched = self.process_match_cases(
+            subject_key.as_ref(),
+            subject.as_cow_type(self.i_s).into_owned(),
+            case_blocks,
+            class,
+            func,
+        );
+        if was_reachable
+            && !unmatched.is_never()
+            && !unmatched.is_any()
+            && self
+                .flags()
+                .enabled_error_codes
+                .iter()
+                .any(|c| c == "exhaustive-match")
+        {
+            self.add_issue(
+                subject_expr.index(),
+                IssueKind::MatchIsNotExhaustive {
+                    unmatched: unmatched.format_short(self.i_s.db),
+                },
+            );
+        }
     }
 
+    /// Returns the part of the subject type that is not matched by any of the cases.
     fn process_match_cases<'x>(
         &self,
-        subject: &Inferred,
+        subject_key: Option<&FlowKey>,
+        subject_t: Type,
         mut case_blocks: impl Iterator<Item = CaseBlock<'x>>,
         class: Option<Class>,
         func: Option<&Function>,
-    ) {
+    ) -> Type {
         let Some(case_block) = case_blocks.next() else {
-            return;
+            return subject_t;
         };
         let (case_pattern, guard, block) = case_block.unpack();
-        let (true_frame, false_frame) = self.find_guards_in_case_pattern(subject, case_pattern);
-        if let Some(guard) = guard {
-            self.infer_named_expression(guard.named_expr());
-        }
-        // TODO use patterns
+        let split = self.find_guards_in_case_pattern(&subject_t, case_pattern);
+        let (true_frame, false_frame, rest) = match split {
+            Some((matched, rest)) => {
+                debug!(
+                    "Narrowed match subject to {} and the rest to {}",
+                    matched.format_short(self.i_s.db),
+                    rest.format_short(self.i_s.db),
+                );
+                // A guard can always fail, so the rest is not narrowed.
+                let rest = match guard {
+                    Some(_) => subject_t,
+                    None => rest,
+                };
+                let as_frame = |t: Type| match subject_key {
+                    Some(key) => Frame::from_type(key.clone(), t),
+                    None => Frame::from_type_without_entry(t),
+                };
+                let false_frame = match guard {
+                    Some(_) => Frame::new_conditional(),
+                    None => as_frame(rest.clone()),
+                };
+                (as_frame(matched), false_frame, rest)
+            }
+            None => (
+                Frame::new_conditional(),
+                Frame::new_conditional(),
+                subject_t,
+            ),
+        };
         FLOW_ANALYSIS.with(|fa| {
             let true_frame = fa.with_frame(true_frame, || {
-                self.calc_block_diagnostics(block, class, func)
+                if let Some(guard) = guard {
+                    let (_, truthy, _) = self.find_guards_in_named_expr(guard.named_expr());
+                    let guarded =
+                        fa.with_frame(truthy, || self.calc_block_diagnostics(block, class, func));
+                    fa.overwrite_frame(self.i_s.db, guarded);
+                } else {
+                    self.calc_block_diagnostics(block, class, func)
+                }
             });
+            let mut unmatched = Type::Never(NeverCause::Other);
             let false_frame = fa.with_frame(false_frame, || {
-                self.process_match_cases(subject, case_blocks, class, func)
+                unmatched = self.process_match_cases(subject_key, rest, case_blocks, class, func)
             });
             fa.merge_conditional(self.i_s, true_frame, false_frame);
-        });
+            unmatched
+        })
     }
 
     fn check_conjunction(
@@ -2500,7 +2604,7 @@ impl Inference<'_, '_, '_> {
     ) {
         let (left, right) = and.unpack();
         match is_expr_part_reachable_for_name_binder(
-            &self.i_s.db.project.settings,
+            self.file.settings(self.i_s.db),
             self.flags(),
             left,
         ) {
@@ -2568,7 +2672,7 @@ impl Inference<'_, '_, '_> {
     ) {
         let (left, right) = or.unpack();
         match is_expr_part_reachable_for_name_binder(
-            &self.i_s.db.project.settings,
+            self.file.settings(self.i_s.db),
             self.flags(),
             left,
         ) {
@@ -2683,30 +2787,47 @@ impl Inference<'_, '_, '_> {
         }
     }
 
+    /// Returns the part of `t` that might match the pattern and the part that definitely does
+    /// not match it. `None` means that the pattern cannot be used for narrowing.
     fn find_guards_in_case_pattern(
         &self,
-        inf: &Inferred,
+        t: &Type,
         case_pattern: CasePattern,
-    ) -> (Frame, Frame) {
+    ) -> Option<(Type, Type)> {
         match case_pattern {
-            CasePattern::Pattern(pattern) => self.find_guards_in_pattern(inf, pattern),
+            CasePattern::Pattern(pattern) => self.find_guards_in_pattern(t, pattern, false),
             CasePattern::OpenSequencePattern(seq) => {
-                self.find_guards_in_sequence_pattern(seq.iter())
+                self.find_guards_in_sequence_pattern(t, seq.iter(), false)
             }
         }
     }
 
-    fn find_guards_in_pattern(&self, inf: &Inferred, pattern: Pattern) -> (Frame, Frame) {
+    fn find_guards_in_pattern(
+        &self,
+        t: &Type,
+        pattern: Pattern,
+        captures_as_any: bool,
+    ) -> Option<(Type, Type)> {
         let (pattern_kind, as_name) = pattern.unpack();
-        let result = self.find_guards_in_pattern_kind(inf, pattern_kind);
+        let result = self.find_guards_in_pattern_kind(t, pattern_kind, captures_as_any);
         if let Some(as_name) = as_name {
             let from = NodeRef::new(self.file, pattern.index());
-            self.assign_to_name_def_simple(as_name, from, inf, AssignKind::Normal);
+            let captured = match &result {
+                _ if captures_as_any => Inferred::new_any_from_error(),
+                Some((matched, _)) if !matched.is_never() => Inferred::from_type(matched.clone()),
+                _ => Inferred::from_type(t.clone()),
+            };
+            self.assign_to_name_def_simple(as_name, from, &captured, AssignKind::Normal);
         }
         result
     }
 
-    fn find_guards_in_pattern_kind(&self, inf: &Inferred, kind: PatternKind) -> (Frame, Frame) {
+    fn find_guards_in_pattern_kind(
+        &self,
+        t: &Type,
+        kind: PatternKind,
+        captures_as_any: bool,
+    ) -> Option<(Type, Type)> {
         let assign_any = |name_def| {
             // This is just temporary until the TODOs are resolved below
             self.assign_to_name_def_simple(
@@ -2718,49 +2839,74 @@ impl Inference<'_, '_, '_> {
         };
         let assign_any_to_pattern = |pat| {
             // This is just temporary until the TODOs are resolved below
-            self.find_guards_in_pattern(&Inferred::new_any_from_error(), pat);
+            self.find_guards_in_pattern(&Type::ERROR, pat, true)
         };
+        let irrefutable = || Some((t.clone(), Type::Never(NeverCause::Other)));
         match kind {
             PatternKind::NameDef(name_def) => {
-                let from = NodeRef::new(self.file, name_def.index());
-                self.assign_to_name_def_simple(name_def, from, inf, AssignKind::Normal);
+                if captures_as_any {
+                    assign_any(name_def)
+                } else {
+                    let from = NodeRef::new(self.file, name_def.index());
+                    let inf = Inferred::from_type(t.clone());
+                    self.assign_to_name_def_simple(name_def, from, &inf, AssignKind::Normal);
+                }
+                irrefutable()
             }
-            PatternKind::WildcardPattern(_) => (),
+            PatternKind::WildcardPattern(_) => irrefutable(),
             PatternKind::DottedName(dotted_name) => {
-                self.infer_pattern_dotted_name(dotted_name);
-                // TODO use this
+                let inf = self.infer_pattern_dotted_name(dotted_name);
+                split_off_value_pattern(self.i_s, t, &inf.as_cow_type(self.i_s))
             }
             PatternKind::ClassPattern(class_pattern) => {
                 let (dotted, params) = class_pattern.unpack();
-                self.infer_pattern_dotted_name(dotted);
+                let inf = self.infer_pattern_dotted_name(dotted);
+                // If all the sub patterns are irrefutable, all instances of the class match.
+                let mut matches_all_instances = true;
                 for param in params {
-                    match param {
-                        ParamPattern::Positional(pat) => {
-                            // TODO
-                            assign_any_to_pattern(pat);
-                        }
-                        ParamPattern::Keyword(keyword_pattern) => {
-                            // TODO
-                            let (_, pat) = keyword_pattern.unpack();
-                            assign_any_to_pattern(pat);
-                        }
-                    }
+                    let pat = match param {
+                        ParamPattern::Positional(pat) => pat,
+                        ParamPattern::Keyword(keyword_pattern) => keyword_pattern.unpack().1,
+                    };
+                    // TODO
+                    matches_all_instances &=
+                        assign_any_to_pattern(pat).is_some_and(|(_, rest)| rest.is_never());
                 }
+                let Type::Type(class_t) = inf.as_cow_type(self.i_s).into_owned() else {
+                    return None;
+                };
+                if class_t.is_any() {
+                    return None;
+                }
+                let (matched, rest) = split_and_intersect(self.i_s, t, &class_t, |_| ());
+                if !matches_all_instances {
+                    return Some((matched, t.clone()));
+                }
+                Some((matched, rest))
             }
-            PatternKind::LiteralPattern(_literal_pattern) => {
-                // TODO
+            PatternKind::LiteralPattern(literal_pattern) => {
+                split_off_value_pattern(self.i_s, t, &self.literal_pattern_type(literal_pattern)?)
             }
             PatternKind::GroupPattern(group_pattern) => {
-                return self.find_guards_in_pattern(inf, group_pattern.inner());
+                self.find_guards_in_pattern(t, group_pattern.inner(), captures_as_any)
             }
             PatternKind::OrPattern(or_pattern) => {
+                let mut matched = Type::Never(NeverCause::Other);
+                let mut rest = t.clone();
                 for pat in or_pattern.iter() {
-                    // TODO
-                    self.find_guards_in_pattern_kind(&Inferred::new_any_from_error(), pat);
+                    // TODO captures are inferred as Any for now
+                    match self.find_guards_in_pattern_kind(&rest, pat, true) {
+                        Some((m, r)) => {
+                            matched.union_in_place(m);
+                            rest = r;
+                        }
+                        None => matched.union_in_place(rest.clone()),
+                    }
                 }
+                Some((matched, rest))
             }
             PatternKind::SequencePattern(sequence_pattern) => {
-                return self.find_guards_in_sequence_pattern(sequence_pattern.iter());
+                self.find_guards_in_sequence_pattern(t, sequence_pattern.iter(), captures_as_any)
             }
             PatternKind::MappingPattern(mapping_pattern) => {
                 for item in mapping_pattern.iter() {
@@ -2780,15 +2926,17 @@ impl Inference<'_, '_, '_> {
                         }
                     }
                 }
+                None
             }
         }
-        (Frame::new_conditional(), Frame::new_conditional())
     }
 
     fn find_guards_in_sequence_pattern<'x>(
         &self,
+        t: &Type,
         iter: impl Iterator<Item = SequencePatternItem<'x>>,
-    ) -> (Frame, Frame) {
+        captures_as_any: bool,
+    ) -> Option<(Type, Type)> {
         let assign_any = |name_def| {
             // This is just temporary until the TODOs are resolved below
             self.assign_to_name_def_simple(
@@ -2798,24 +2946,111 @@ impl Inference<'_, '_, '_> {
                 AssignKind::Normal,
             )
         };
-        for item in iter {
-            match item {
-                SequencePatternItem::Entry(pattern) => {
-                    // TODO
-                    self.find_guards_in_pattern(&Inferred::new_any_from_error(), pattern);
+        let items: Vec<_> = iter.collect();
+        let has_star = items
+            .iter()
+            .any(|item| matches!(item, SequencePatternItem::Rest(_)));
+        // Only fixed length tuples are narrowed for now. Other types might always match.
+        let mut tuple_entries = None;
+        let mut matched = Type::Never(NeverCause::Other);
+        let mut rest = Type::Never(NeverCause::Other);
+        for sub_t in t.iter_with_unpacked_unions(self.i_s.db) {
+            match sub_t {
+                Type::Tuple(tup) if !has_star && tuple_entries.is_none() => match &tup.args {
+                    TupleArgs::FixedLen(ts) if ts.len() == items.len() => {
+                        tuple_entries = Some(ts.clone());
+                        continue;
+                    }
+                    _ => (),
+                },
+                // None can never match a sequence pattern
+                Type::None => {
+                    rest.union_in_place(Type::None);
+                    continue;
                 }
-                SequencePatternItem::Rest(star_pattern) => {
-                    match star_pattern.unpack() {
-                        StarPatternContent::NameDef(name_def) => {
-                            // TODO
-                            assign_any(name_def)
+                _ => (),
+            }
+            matched.union_in_place(sub_t.clone());
+            rest.union_in_place(sub_t.clone());
+        }
+        let Some(tuple_entries) = tuple_entries else {
+            for item in items {
+                match item {
+                    SequencePatternItem::Entry(pattern) => {
+                        // TODO
+                        self.find_guards_in_pattern(&Type::ERROR, pattern, true);
+                    }
+                    SequencePatternItem::Rest(star_pattern) => {
+                        match star_pattern.unpack() {
+                            StarPatternContent::NameDef(name_def) => {
+                                // TODO
+                                assign_any(name_def)
+                            }
+                            StarPatternContent::WildcardPattern(_) => (),
                         }
-                        StarPatternContent::WildcardPattern(_) => (),
                     }
                 }
             }
-        }
-        (Frame::new_conditional(), Frame::new_conditional())
+            return None;
+        };
+        let splits: Vec<_> = items
+            .into_iter()
+            .zip(tuple_entries.iter())
+            .map(|(item, entry)| {
+                let SequencePatternItem::Entry(pattern) = item else {
+                    unreachable!("Star patterns are not narrowed")
+                };
+                self.find_guards_in_pattern(entry, pattern, captures_as_any)
+                    .unwrap_or_else(|| (entry.clone(), entry.clone()))
+            })
+            .collect();
+        if !splits.iter().any(|(m, _)| m.is_never()) {
+            matched.union_in_place(Type::Tuple(Tuple::new_fixed_length(
+                splits.iter().map(|(m, _)| m.clone()).collect(),
+            )));
+        }
+        // The rest can only be narrowed if at most one of the entries is not fully matched,
+        // because otherwise the rest is not expressible as a single tuple.
+        let mut refutable = splits
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, r))| !r.is_never());
+        match (refutable.next(), refutable.next()) {
+            (None, _) => (),
+            (Some((index, (_, entry_rest))), None) => {
+                let mut entries = tuple_entries.to_vec();
+                entries[index] = entry_rest.clone();
+                rest.union_in_place(Type::Tuple(Tuple::new_fixed_length(entries.into())));
+            }
+            (Some(_), Some(_)) => {
+                rest.union_in_place(Type::Tuple(Tuple::new_fixed_length(tuple_entries)))
+            }
+        }
+        Some((matched, rest))
+    }
+
+    fn literal_pattern_type(&self, literal_pattern: LiteralPattern) -> Option<Type> {
+        let kind = match literal_pattern.unpack() {
+            LiteralPatternContent::Strings(strings) => {
+                let s = strings.maybe_single_string_literal()?;
+                LiteralKind::String(DbString::from_python_string(
+                    self.file.file_index,
+                    s.as_python_string(),
+                )?)
+            }
+            LiteralPatternContent::Bytes(bytes) => {
+                let b = bytes.maybe_single_bytes_literal()?;
+                LiteralKind::Bytes(DbBytes::Link(NodeRef::new(self.file, b.index()).as_link()))
+            }
+            LiteralPatternContent::SignedNumber(number) => {
+                let i = self.parse_int(number.maybe_int()?)?;
+                LiteralKind::Int(if number.is_negative() { -i } else { i })
+            }
+            LiteralPatternContent::ComplexNumber(_) => return None,
+            LiteralPatternContent::NoneLiteral => return Some(Type::None),
+            LiteralPatternContent::Bool(b) => LiteralKind::Bool(b.as_code() == "True"),
+        };
+        Some(Type::Literal(Literal::new(kind)))
     }
 
     fn find_guards_in_named_expr(
@@ -22,6 +22,7 @@ use crate::{
     },
     format_data::FormatData,
     getitem::SliceType,
+    imports::{ImportResult, global_import},
     inference_state::InferenceState,
     inferred::{
         ApplyClassDescriptorsOrigin, AttributeKind, Inferred, MroIndex,
@@ -427,8 +428,12 @@ impl<'db, 'file> Inference<'db, 'file, '_> {
                     assignment_definition: PointLink::new(self.file.file_index, assignment.index()),
                 },
             };
-            self.infer_assignment_right_side(right_side, &mut result_context)
-                .avoid_implicit_literal(self.i_s)
+            let right = self.infer_assignment_right_side(right_side, &mut result_context);
+            match return_type.as_deref() {
+                // Native ints need the literal value to check if it's in range.
+                Some(Type::NewType(n)) if n.native_int.is_some() => right,
+                _ => right.avoid_implicit_literal(self.i_s),
+            }
         };
         let n = NodeRef::new(self.file, right_side.index());
         for target in targets {
@@ -2438,6 +2443,11 @@ impl<'db, 'file> Inference<'db, 'file, '_> {
                         UnionValue::Any => (),
                     }
                 }
+                if let Type::NewType(n) = inf.as_cow_type(self.i_s).as_ref()
+                    && n.native_int.is_some()
+                {
+                    return Inferred::from_type(Type::NewType(n.clone()));
+                }
                 let method_name = match operand.as_code() {
                     "-" => "__neg__",
                     "+" => "__pos__",
@@ -2883,6 +2893,26 @@ impl<'db, 'file> Inference<'db, 'file, '_> {
                         if let Type::Any(cause) = r_type {
                             return add_to_union(Inferred::new_any(*cause));
                         }
+                        match native_int_operation(i_s.db, op_infos.magic_method, l_type, r_type) {
+                            NativeIntOperation::Result(t) => {
+                                add_to_union(Inferred::from_type(t));
+                                continue;
+                            }
+                            NativeIntOperation::Incompatible => {
+                                had_error = true;
+                                from.add_issue(
+                                    i_s,
+                                    IssueKind::UnsupportedOperand {
+                                        operand: Box::from(op_infos.operand),
+                                        left: l_type.format_short(i_s.db),
+                                        right: r_type.format_short(i_s.db),
+                                    },
+                                );
+                                add_to_union(Inferred::new_any_from_error());
+                                continue;
+                            }
+                            NativeIntOperation::Default => (),
+                        }
                         let instance;
                         let (r_defined_in, right_op_method) = match r_type {
                             Type::Class(r_class) => {
@@ -3375,11 +3405,20 @@ impl<'db, 'file> Inference<'db, 'file, '_> {
             Float(_) => Specific::Float,
             Complex(_) => Specific::Complex,
             Strings(s_o_b) => {
+                let mut has_template_string = false;
                 for string in s_o_b.iter() {
                     if let StringType::FString(f) = string {
+                        has_template_string |= f.is_template_string();
                         self.calc_fstring_diagnostics(f)
                     }
                 }
+                if has_template_string {
+                    return self.infer_template_strings(s_o_b).save_redirect(
+                        i_s,
+                        self.file,
+                        atom.index(),
+                    );
+                }
                 if let Some(s) = s_o_b.maybe_single_string_literal() {
                     return check_literal(result_context, i_s, s.index(), Specific::StringLiteral);
                 } else {
@@ -3461,6 +3500,59 @@ impl<'db, 'file> Inference<'db, 'file, '_> {
         Inferred::new_and_save(self.file, atom.index(), point)
     }
 
+    fn infer_template_strings(&self, strings: Strings) -> Inferred {
+        let db = self.i_s.db;
+        if !self
+            .file
+            .settings(db)
+            .python_version_or_default()
+            .at_least_3_dot(14)
+        {
+            self.add_issue(
+                strings.index(),
+                IssueKind::SyntaxNotSupportedInPythonVersion {
+                    feature: "t-strings are",
+                    minor: 14,
+                },
+            );
+        }
+        if !strings
+            .iter()
+            .all(|s| matches!(s, StringType::FString(f) if f.is_template_string()))
+        {
+            self.add_issue(strings.index(), IssueKind::TemplateStringMixedWithString);
+        }
+        // Template strings are instances of `string.templatelib.Template`
+        let template = global_import(db, self.file, "string")
+            .and_then(|string| string.import(db, self.file, "templatelib"))
+            .and_then(|templatelib| {
+                let ImportResult::File(file_index) = templatelib else {
+                    return None;
+                };
+                let inf = db
+                    .loaded_python_file(file_index)
+                    .lookup(db, |_| (), "Template")
+                    .into_maybe_inferred()?;
+                match inf.as_cow_type(self.i_s).as_ref() {
+                    Type::Type(t) => Some(t.as_ref().clone()),
+                    _ => None,
+                }
+            });
+        match template {
+            Some(t) => Inferred::from_type(t),
+            None => Inferred::new_any_from_error(),
+        }
+    }
+
+    pub fn infer_except_types(&self, types: ExceptTypes) -> Inferred {
+        match types {
+            ExceptTypes::Expression(expr) => self.infer_expression(expr),
+            ExceptTypes::Tuple(tuple) => {
+                self.infer_tuple_iterator(tuple, &mut ResultContext::Unknown)
+            }
+        }
+    }
+
     fn infer_tuple_iterator<'x>(
         &self,
         iterator: impl ClonableTupleIterator<'x>,
@@ -4033,9 +4125,9 @@ impl<'db, 'file> Inference<'db, 'file, '_> {
                     match block {
                         TryBlockType::Except(except) => {
                             if let (Some(except_expr), _) = except.unpack() {
-                                let (expr, name_def) = except_expr.unpack();
+                                let (types, name_def) = except_expr.unpack();
                                 if let Some(name_def) = name_def {
-                                    let inf = self.infer_expression(expr);
+                                    let inf = self.infer_except_types(types);
                                     Inferred::from_type(instantiate_except(
                                         self.i_s,
                                         &inf.as_cow_type(self.i_s),
@@ -4051,9 +4143,9 @@ impl<'db, 'file> Inference<'db, 'file, '_> {
                         }
                         TryBlockType::ExceptStar(except_star) => {
                             let (except_expr, _) = except_star.unpack();
-                            let (expr, name_def) = except_expr.unpack();
+                            let (types, name_def) = except_expr.unpack();
                             if let Some(name_def) = name_def {
-                                let inf = self.infer_expression(expr);
+                                let inf = self.infer_except_types(types);
                                 Inferred::from_type(
                                     self.instantiate_except_star(
                                         name_def,
@@ -4523,6 +4615,57 @@ fn gather_except_star(i_s: &InferenceState, t: &Type) -> Type {
     }
 }
 
+enum NativeIntOperation {
+    Result(Type),
+    Incompatible,
+    Default,
+}
+
+fn native_int_operation(
+    db: &Database,
+    magic_method: &str,
+    left: &Type,
+    right: &Type,
+) -> NativeIntOperation {
+    // Arithmetic on native ints (e.g. mypy_extensions.i64) keeps the native int type when mixed
+    // with int, while distinct native ints cannot be mixed at all.
+    if !matches!(
+        magic_method,
+        "__add__"
+            | "__sub__"
+            | "__mul__"
+            | "__floordiv__"
+            | "__mod__"
+            | "__and__"
+            | "__or__"
+            | "__xor__"
+            | "__lshift__"
+            | "__rshift__"
+    ) {
+        return NativeIntOperation::Default;
+    }
+    let as_native_int = |t: &Type| match t {
+        Type::NewType(n) if n.native_int.is_some() => Some(n.clone()),
+        _ => None,
+    };
+    let is_int = |t: &Type| match t {
+        Type::Literal(literal) => {
+            matches!(literal.kind, LiteralKind::Int(_) | LiteralKind::Bool(_))
+        }
+        Type::Class(c) => {
+            c.link == db.python_state.int_link() || c.link == db.python_state.bool_link()
+        }
+        _ => false,
+    };
+    match (as_native_int(left), as_native_int(right)) {
+        (Some(n1), Some(n2)) if n1 == n2 => NativeIntOperation::Result(Type::NewType(n1)),
+        (Some(_), Some(_)) => NativeIntOperation::Incompatible,
+        (Some(n), None) if is_int(right) => NativeIntOperation::Result(Type::NewType(n)),
+        (None, Some(n)) if is_int(left) => NativeIntOperation::Result(Type::NewType(n)),
+        _ => NativeIntOperation::Default,
+    }
+}
+
 fn get_generator_return_type(db: &Database, had_issue: &impl Fn(), t: &Type) -> Type {
     match t {
         Type::Class(c) => {
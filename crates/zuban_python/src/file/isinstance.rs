@@ -36,7 +36,7 @@ impl Inference<'_, '_, '_> {
             };
             match t {
                 Type::TypedDict(_) => cannot_use_with("TypedDict"),
-                Type::NewType(_) => cannot_use_with("NewType"),
+                Type::NewType(n) if n.native_int.is_none() => cannot_use_with("NewType"),
                 _ => (),
             }
         }
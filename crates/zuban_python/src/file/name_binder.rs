@@ -73,6 +73,8 @@ pub(crate) struct DbInfos<'db> {
     pub all_imports: &'db RefCell<Vec<NodeIndex>>,
     pub file_index: FileIndex,
     pub is_stub: bool,
+    // The lines in front of the code, if the tree is only a part of a file, like a type comment.
+    pub line_offset: usize,
 }
 
 pub(crate) struct NameBinder<'db> {
@@ -526,6 +528,11 @@ impl<'db> NameBinder<'db> {
                 StmtLikeContent::GlobalStmt(g) => self.index_non_block_node(&g, ordered),
                 StmtLikeContent::NonlocalStmt(n) => self.index_non_block_node(&n, ordered),
                 StmtLikeContent::TypeAlias(type_alias) => {
+                    self.add_issue_if_python_version_is_lower(
+                        type_alias.index(),
+                        "Type statement is",
+                        12,
+                    );
                     let (name_def, type_params, expr) = type_alias.unpack();
                     self.add_new_definition_with_cause(
                         name_def,
@@ -603,13 +610,13 @@ impl<'db> NameBinder<'db> {
                 StmtLikeContent::Error(error) => {
                     if !last_was_an_error {
                         last_was_an_error = true;
-                        self.add_issue(error.index(), IssueKind::InvalidSyntax);
+                        self.add_syntax_error(error.index());
                     }
                     self.index_non_block_node(&error, false);
                     continue;
                 }
                 StmtLikeContent::BrokenScope(broken) => {
-                    self.add_issue(broken.index(), IssueKind::InvalidSyntax);
+                    self.add_syntax_error(broken.index());
                     self.index_stmts(broken.iter_stmt_likes(), false)
                 }
                 StmtLikeContent::Newline | StmtLikeContent::PassStmt(_) => (),
@@ -742,6 +749,13 @@ impl<'db> NameBinder<'db> {
     fn index_with_stmt(&mut self, with_stmt: WithStmt<'db>, ordered: bool) {
         self.following_nodes_need_flow_analysis = true;
         let (with_items, block) = with_stmt.unpack();
+        if with_items.is_parenthesized() {
+            self.add_issue_if_python_version_is_lower(
+                with_items.index(),
+                "Parenthesized context managers are",
+                9,
+            );
+        }
         for with_item in with_items.iter() {
             self.index_non_block_node(&with_item, ordered);
         }
@@ -816,6 +830,11 @@ impl<'db> NameBinder<'db> {
                     }
                 }
                 TryBlockType::ExceptStar(except_star) => {
+                    self.add_issue_if_python_version_is_lower(
+                        except_star.index(),
+                        "Exception groups are",
+                        11,
+                    );
                     let (except_expression, block) = except_star.unpack();
                     self.index_except_expression_with_block(except_expression, block, ordered)
                 }
@@ -831,11 +850,25 @@ impl<'db> NameBinder<'db> {
         block: Block<'db>,
         ordered: bool,
     ) {
-        let (expression, name_def) = except_expr.unpack();
+        let (types, name_def) = except_expr.unpack();
         if let Some(name_def) = name_def {
             self.add_new_definition(name_def, Point::new_uncalculated())
         }
-        self.index_non_block_node(&expression, ordered);
+        match types {
+            ExceptTypes::Expression(expression) => self.index_non_block_node(&expression, ordered),
+            ExceptTypes::Tuple(tuple) => {
+                self.add_issue_if_python_version_is_lower(
+                    except_expr.index(),
+                    "except expressions without parentheses are",
+                    14,
+                );
+                for expr in tuple {
+                    if let StarLikeExpression::Expression(expression) = expr {
+                        self.index_non_block_node(&expression, ordered)
+                    }
+                }
+            }
+        }
         self.index_block(block, ordered);
     }
 
@@ -851,6 +884,7 @@ impl<'db> NameBinder<'db> {
 
     fn index_class(&mut self, class_def: ClassDef<'db>) {
         let type_params = class_def.type_params();
+        self.check_type_params_are_supported(type_params);
         self.index_type_param_bounds(type_params);
         self.with_latest_type_params(type_params, |slf| slf.index_class_internal(class_def))
     }
@@ -979,8 +1013,11 @@ impl<'db> NameBinder<'db> {
     }
 
     fn index_match_stmt(&mut self, match_stmt: MatchStmt<'db>, ordered: bool) {
+        self.add_issue_if_python_version_is_lower(match_stmt.index(), "Pattern matching is", 10);
         let (subject_expr, case_blocks) = match_stmt.unpack();
         self.index_non_block_node(&subject_expr, ordered);
+        // Case patterns narrow the subject.
+        self.following_nodes_need_flow_analysis = true;
         for case_block in case_blocks {
             let (case_pattern, guard, block) = case_block.unpack();
             match case_pattern {
@@ -1245,6 +1282,11 @@ impl<'db> NameBinder<'db> {
                     self.maybe_add_reference(dotted_name.first_name(), ordered);
                 }
                 InterestingNode::Walrus(walrus) => {
+                    self.add_issue_if_python_version_is_lower(
+                        walrus.index(),
+                        "Assignment expressions are",
+                        8,
+                    );
                     let (name_def, expr) = walrus.unpack();
                     self.index_non_block_node_full(&expr, ordered, cause);
                     self.add_new_walrus_definition(name_def)
@@ -1262,6 +1304,39 @@ impl<'db> NameBinder<'db> {
         self.latest_return_or_yield = keyword_index;
     }
 
+    fn add_syntax_error(&self, node_index: NodeIndex) {
+        let SyntaxError { index, kind } = self
+            .db_infos
+            .tree
+            .syntax_error(node_index, self.db_infos.line_offset);
+        self.add_issue(
+            index,
+            match kind {
+                SyntaxErrorKind::InvalidSyntax => IssueKind::InvalidSyntax,
+                kind => IssueKind::SyntaxError(kind),
+            },
+        )
+    }
+
+    fn add_issue_if_python_version_is_lower(
+        &self,
+        node_index: NodeIndex,
+        feature: &'static str,
+        minor: usize,
+    ) {
+        if !self
+            .db_infos
+            .settings
+            .python_version_or_default()
+            .at_least_3_dot(minor)
+        {
+            self.add_issue(
+                node_index,
+                IssueKind::SyntaxNotSupportedInPythonVersion { feature, minor },
+            )
+        }
+    }
+
     fn add_issue_for_async_comprehension_not_in_async_func(
         &self,
         for_if_clauses: ForIfClauseIterator,
@@ -1390,6 +1465,16 @@ impl<'db> NameBinder<'db> {
             .is_some_and(|specific| specific == search)
     }
 
+    fn check_type_params_are_supported(&self, type_params: Option<TypeParams<'db>>) {
+        if let Some(type_params) = type_params {
+            self.add_issue_if_python_version_is_lower(
+                type_params.index(),
+                "Type parameter lists are",
+                12,
+            );
+        }
+    }
+
     fn index_type_param_bounds(&mut self, maybe_type_params: Option<TypeParams<'db>>) {
         if let Some(type_params) = maybe_type_params {
             for type_param in type_params.iter() {
@@ -1452,6 +1537,7 @@ impl<'db> NameBinder<'db> {
         });
 
         let (name_def, type_params, params, return_annotation, _) = func.unpack();
+        self.check_type_params_are_supported(type_params);
         self.index_type_param_bounds(type_params);
 
         for param in params.iter() {
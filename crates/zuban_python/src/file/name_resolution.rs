@@ -309,6 +309,7 @@ impl<'db, 'file, 'i_s> NameResolution<'db, 'file, 'i_s> {
                 }
                 Point::new_specific(Specific::RevealTypeFunction, Locality::Todo)
             }
+            "reveal_locals" => Point::new_specific(Specific::RevealLocalsFunction, Locality::Todo),
             "__builtins__" => Point::new_file_reference(builtins.file_index, Locality::Todo),
             "__debug__" => {
                 return PointResolution::Inferred(
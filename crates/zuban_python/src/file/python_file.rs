@@ -3,13 +3,16 @@ use std::{
     cell::RefCell,
     collections::{HashMap, VecDeque},
     fmt,
-    sync::{Arc, OnceLock, RwLock},
+    sync::{
+        Arc, OnceLock, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
 };
 
-use config::{DiagnosticConfig, IniOrTomlValue, set_flag_and_return_ignore_errors};
+use config::{DiagnosticConfig, IniOrTomlValue, Settings, set_flag_and_return_ignore_errors};
 use parsa_python_cst::*;
 use utils::InsertOnlyVec;
-use vfs::{Directory, DirectoryEntry, FileEntry, FileIndex, PathWithScheme};
+use vfs::{Directory, DirectoryEntry, FileEntry, FileIndex, NormalizedPath, PathWithScheme};
 
 use super::{
     FLOW_ANALYSIS,
@@ -24,6 +27,7 @@ use crate::{
     InputPosition, TypeCheckerFlags,
     database::{
         ComplexPoint, Database, Locality, Point, PointLink, Points, PythonProject, Specific,
+        SubProject,
     },
     debug,
     diagnostics::{Diagnostic, Diagnostics, Issue, IssueKind},
@@ -31,7 +35,7 @@ use crate::{
     inference_state::InferenceState,
     lines::{BytePositionInfos, NewlineIndices, PositionInfos},
     node_ref::NodeRef,
-    type_::{DbString, LookupResult},
+    type_::{DbString, LookupResult, Type},
     utils::SymbolTable,
 };
 
@@ -62,9 +66,12 @@ impl ComplexValues {
         unsafe { self.0.iter() }
     }
 
-    #[expect(dead_code)]
-    pub fn clear(&mut self) {
-        self.0.clear()
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.0.as_vec_mut().truncate(len)
     }
 }
 
@@ -87,6 +94,7 @@ pub(crate) struct PythonFile {
     maybe_dunder_all: OnceLock<Option<Box<[DbString]>>>, // For __all__
     pub points: Points,
     pub complex_points: ComplexValues,
+    complex_points_after_name_binding: usize,
     pub file_index: FileIndex,
     pub issues: Diagnostics,
     pub star_imports: Box<[StarImport]>,
@@ -96,11 +104,22 @@ pub(crate) struct PythonFile {
     stub_cache: Option<StubCache>,
     pub ignore_type_errors: bool,
     flags: Option<TypeCheckerFlags>,
+    // The index of the sub project with its own environment this file belongs to.
+    sub_project: Option<usize>,
     pub(super) delayed_diagnostics: RwLock<VecDeque<DelayedDiagnostic>>,
+    // Narrowed names are not saved in points, because they are only valid within the flow
+    // analysis. They are only recorded if a language server wants to show them, because type
+    // checking does not need them.
+    pub(super) narrowed_names: Option<RwLock<HashMap<NodeIndex, Type>>>,
+    // Set once the diagnostics are calculated and reset when they are invalidated, see
+    // `diagnostics_generation`.
+    diagnostics_generation: OnceLock<u64>,
 
     newline_indices: NewlineIndices,
 }
 
+static NEXT_DIAGNOSTICS_GENERATION: AtomicU64 = AtomicU64::new(0);
+
 impl Clone for PythonFile {
     fn clone(&self) -> Self {
         Self {
@@ -109,6 +128,7 @@ impl Clone for PythonFile {
             maybe_dunder_all: self.maybe_dunder_all.clone(),
             points: self.points.clone(),
             complex_points: self.complex_points.clone(),
+            complex_points_after_name_binding: self.complex_points_after_name_binding,
             file_index: self.file_index,
             issues: self.issues.clone(),
             star_imports: self.star_imports.clone(),
@@ -118,7 +138,13 @@ impl Clone for PythonFile {
             stub_cache: self.stub_cache.clone(),
             ignore_type_errors: self.ignore_type_errors,
             flags: self.flags.clone(),
+            sub_project: self.sub_project,
             delayed_diagnostics: RwLock::new(self.delayed_diagnostics.read().unwrap().clone()),
+            narrowed_names: self
+                .narrowed_names
+                .as_ref()
+                .map(|names| RwLock::new(names.read().unwrap().clone())),
+            diagnostics_generation: self.diagnostics_generation.clone(),
             newline_indices: self.newline_indices.clone(),
         }
     }
@@ -158,6 +184,8 @@ impl File for PythonFile {
             // annotation strings, etc.
             let result = self.ensure_calculated_diagnostics(db);
             debug_assert!(result.is_ok());
+            self.diagnostics_generation
+                .get_or_init(|| NEXT_DIAGNOSTICS_GENERATION.fetch_add(1, Ordering::Relaxed));
         }
         let flags = self.flags(db);
         let mut vec: Vec<_> = unsafe {
@@ -190,6 +218,8 @@ impl File for PythonFile {
             self.flags.take(),
             project,
             self.ignore_type_errors,
+            0,
+            self.sub_project,
         );
     }
 
@@ -212,9 +242,25 @@ impl vfs::VfsFile for PythonFile {
     fn invalidate_references_to(&mut self, file_index: Option<FileIndex>) {
         self.points.invalidate_references_to(file_index);
         self.issues.invalidate_non_name_binder_issues();
+        self.diagnostics_generation = OnceLock::new();
+        if let Some(narrowed_names) = self.narrowed_names.as_mut() {
+            narrowed_names.get_mut().unwrap().clear();
+        }
         if let Some(cache) = self.stub_cache.as_mut() {
             *cache = StubCache::default();
         }
+        // All complex points that were added after name binding are referenced by points that
+        // were just invalidated and can therefore be freed.
+        self.complex_points
+            .truncate(self.complex_points_after_name_binding);
+        debug_assert!(self.points.iter().all(|p| {
+            p.maybe_complex_index()
+                .is_none_or(|i| i < self.complex_points_after_name_binding)
+        }));
+    }
+
+    fn sub_files(&self) -> Vec<FileIndex> {
+        self.sub_files.read().unwrap().values().copied().collect()
     }
 }
 
@@ -251,6 +297,18 @@ impl<'db> PythonFile {
         PythonFile::new(project, file_index, file_entry, tree)
     }
 
+    pub fn from_file_entry_and_changed_code(
+        project: &PythonProject,
+        file_index: FileIndex,
+        file_entry: &FileEntry,
+        previous_tree: &Tree,
+        code: Box<str>,
+    ) -> Self {
+        debug!("Reinitialize {} ({file_index})", file_entry.name);
+        let tree = Tree::parse_incrementally(previous_tree, code);
+        PythonFile::new(project, file_index, file_entry, tree)
+    }
+
     pub fn new(
         project_options: &PythonProject,
         file_index: FileIndex,
@@ -276,8 +334,10 @@ impl<'db> PythonFile {
                         .ok();
                     true
                 });
+        let sub_project = sub_project_for_file_entry(project_options, file_entry);
         let directives_info = info_from_directives(
             project_options,
+            sub_project.map(|index| &project_options.sub_projects[index]),
             file_entry,
             &issues,
             tree.mypy_inline_config_directives(),
@@ -293,6 +353,8 @@ impl<'db> PythonFile {
             directives_info.flags,
             project_options,
             ignore_type_errors,
+            0,
+            sub_project,
         )
     }
 
@@ -305,6 +367,8 @@ impl<'db> PythonFile {
         flags: Option<TypeCheckerFlags>,
         project: &PythonProject,
         ignore_type_errors: bool,
+        line_offset: usize,
+        sub_project: Option<usize>,
     ) -> Self {
         let complex_points = Default::default();
         let star_imports: RefCell<Vec<StarImport>> = Default::default();
@@ -312,7 +376,10 @@ impl<'db> PythonFile {
         let symbol_table = NameBinder::with_global_binder(
             DbInfos {
                 // TODO this does not use flags of the super file. Is this an issue?
-                settings: &project.settings,
+                settings: match sub_project {
+                    Some(index) => &project.sub_projects[index].settings,
+                    None => &project.settings,
+                },
                 flags: flags.as_ref().unwrap_or(&project.flags),
                 tree: &tree,
                 points: &points,
@@ -322,6 +389,7 @@ impl<'db> PythonFile {
                 all_imports: &all_imports,
                 file_index,
                 is_stub,
+                line_offset,
             },
             |binder| binder.index_file(tree.root()),
         );
@@ -331,6 +399,7 @@ impl<'db> PythonFile {
             symbol_table,
             maybe_dunder_all: OnceLock::default(),
             points,
+            complex_points_after_name_binding: complex_points.len(),
             complex_points,
             star_imports: star_imports.into_inner().into_boxed_slice(),
             all_imports: all_imports.into_inner().into_boxed_slice(),
@@ -341,10 +410,20 @@ impl<'db> PythonFile {
             stub_cache: is_stub.then(StubCache::default),
             ignore_type_errors,
             flags,
+            sub_project,
             delayed_diagnostics: Default::default(),
+            narrowed_names: None,
+            diagnostics_generation: OnceLock::new(),
         }
     }
 
+    /// Identifies the currently calculated diagnostics of this file. It is unique across all
+    /// files and changes whenever the diagnostics are calculated again, e.g. after the file or
+    /// one of its dependencies changed. `None` means that they are not calculated.
+    pub fn diagnostics_generation(&self) -> Option<u64> {
+        self.diagnostics_generation.get().copied()
+    }
+
     pub fn inference<'file, 'i_s>(
         &'file self,
         i_s: &'i_s InferenceState<'db, 'i_s>,
@@ -402,6 +481,36 @@ impl<'db> PythonFile {
         (entry, is_package_name(entry))
     }
 
+    /// Saves a type that is not inferred from the code, but set when the Python state is
+    /// initialized. It is kept when the file is invalidated, like the results of name binding.
+    pub(crate) fn insert_file_local_type(&mut self, node_index: NodeIndex, t: Type) {
+        NodeRef::new(self, node_index)
+            .insert_complex(ComplexPoint::TypeInstance(t), Locality::File);
+        self.complex_points_after_name_binding = self.complex_points.len();
+    }
+
+    pub(crate) fn records_narrowed_names(&self) -> bool {
+        self.narrowed_names.is_some()
+    }
+
+    /// Makes sure that narrowed names are recorded. The file is invalidated if they were not
+    /// recorded before, so that the flow analysis runs again.
+    pub(crate) fn record_narrowed_names(&mut self) {
+        if self.narrowed_names.is_none() {
+            self.narrowed_names = Some(Default::default());
+            vfs::VfsFile::invalidate_references_to(self, Some(self.file_index));
+        }
+    }
+
+    pub fn narrowed_name_type(&self, name_index: NodeIndex) -> Option<Type> {
+        self.narrowed_names
+            .as_ref()?
+            .read()
+            .unwrap()
+            .get(&name_index)
+            .cloned()
+    }
+
     pub fn ensure_calculated_diagnostics(&self, db: &Database) -> Result<(), ()> {
         self.inference(&InferenceState::new(db, self))
             .calculate_module_diagnostics()
@@ -424,6 +533,7 @@ impl<'db> PythonFile {
         // TODO should probably not need a newline
         let code = code.into_owned() + "\n";
         let tree = Tree::parse(code.into_boxed_str());
+        let line_offset = self.byte_to_position_infos(db, start).line_zero_based();
         let points = Points::new(tree.length());
         let f = db.load_sub_file(self, |file_index| {
             let mut file = PythonFile::new_internal(
@@ -435,6 +545,8 @@ impl<'db> PythonFile {
                 None,
                 &db.project,
                 self.ignore_type_errors,
+                line_offset,
+                self.sub_project,
             );
             file.super_file = Some(SuperFile {
                 file: self.file_index,
@@ -718,6 +830,22 @@ impl<'db> PythonFile {
             .unwrap_or(&db.project.flags)
     }
 
+    /// The settings of the environment this file is checked in, like the Python version.
+    pub fn settings<'x>(&self, db: &'x Database) -> &'x Settings {
+        match self.sub_project {
+            Some(index) => &db.project.sub_projects[index].settings,
+            None => &db.project.settings,
+        }
+    }
+
+    /// The sys path of the environment this file is checked in.
+    pub fn sys_path<'x>(&self, db: &'x Database) -> &'x [Arc<NormalizedPath>] {
+        match self.sub_project {
+            Some(index) => &db.project.sub_projects[index].sys_path,
+            None => &db.project.sys_path,
+        }
+    }
+
     pub fn should_infer_untyped_returns(&self, db: &Database) -> bool {
         !db.project.settings.mypy_compatible && self.flags(db).check_untyped_defs
     }
@@ -795,6 +923,7 @@ fn name_and_parent_dir(entry: &FileEntry, skip_stubs: bool) -> (&str, Option<Arc
 
 fn info_from_directives<'x>(
     project: &PythonProject,
+    sub_project: Option<&SubProject>,
     file_entry: &FileEntry,
     issues: &Diagnostics,
     directives: impl Iterator<Item = (CodeIndex, &'x str)>,
@@ -803,15 +932,23 @@ fn info_from_directives<'x>(
     let mut ignore_errors = false;
     let mut flags = None;
 
-    if !project.overrides.is_empty() {
+    let (base_flags, overrides) = match sub_project {
+        Some(sub_project) => {
+            flags = Some(sub_project.flags.clone());
+            (&sub_project.flags, &sub_project.overrides)
+        }
+        None => (&project.flags, &project.overrides),
+    };
+
+    if !overrides.is_empty() {
         let (name, parent_dir) = name_and_parent_dir(file_entry, true);
-        for override_ in &project.overrides {
+        for override_ in overrides {
             if override_
                 .module
                 .matches_file_path(name, parent_dir.as_deref())
             {
                 if flags.is_none() {
-                    flags = Some(project.flags.clone());
+                    flags = Some(base_flags.clone());
                 }
                 ignore_errors |= override_
                     .apply_to_flags_and_return_ignore_errors(flags.as_mut().unwrap())
@@ -829,7 +966,7 @@ fn info_from_directives<'x>(
         for (name, value) in splitter {
             let name = name.replace('-', "_");
             if flags.is_none() {
-                flags = Some(project.flags.clone());
+                flags = Some(base_flags.clone());
             }
             let mut check = || -> anyhow::Result<_> {
                 let value = match value {
@@ -860,6 +997,52 @@ fn info_from_directives<'x>(
     }
 }
 
+/// Files outside of the sub projects and their environments, most importantly Typeshed, use the
+/// settings of the project. They are shared by all sub projects, which means that e.g.
+/// `sys.version_info` checks in Typeshed's stubs use the Python version of the project.
+fn sub_project_for_file_entry(project: &PythonProject, file_entry: &FileEntry) -> Option<usize> {
+    if project.sub_projects.is_empty() {
+        return None;
+    }
+    let workspace_path = file_entry.parent.workspace_path();
+    // Files in the site packages of a sub project's environment are part of that environment.
+    if !project.sys_path.contains(&workspace_path)
+        && let Some(index) = project
+            .sub_projects
+            .iter()
+            .position(|sub_project| sub_project.sys_path.contains(&workspace_path))
+    {
+        return Some(index);
+    }
+    let workspace_path: &str = &workspace_path;
+    let is_separator = |c: char| c == '/' || c == '\\';
+    // The names of the directories between the workspace and the file, outermost first.
+    let mut dirs = vec![];
+    let mut parent = file_entry.parent.maybe_dir().ok();
+    while let Some(dir) = parent {
+        parent = dir.parent.maybe_dir().ok();
+        dirs.push(dir);
+    }
+    dirs.reverse();
+    // Sub projects are sorted, the most nested ones are first.
+    project.sub_projects.iter().position(|sub_project| {
+        let root: &str = &sub_project.root;
+        if let Some(rest) = workspace_path.strip_prefix(root) {
+            // The workspace is within the sub project
+            rest.is_empty() || rest.starts_with(is_separator)
+        } else if let Some(rest) = root.strip_prefix(workspace_path)
+            && (rest.is_empty() || rest.starts_with(is_separator))
+        {
+            let mut dirs = dirs.iter();
+            rest.split(is_separator)
+                .filter(|part| !part.is_empty())
+                .all(|part| dirs.next().is_some_and(|dir| *dir.name == *part))
+        } else {
+            false
+        }
+    })
+}
+
 struct DirectivesInfos {
     flags: Option<TypeCheckerFlags>,
     ignore_errors: bool,
@@ -331,6 +331,7 @@ impl<'db, 'file> NameResolution<'db, 'file, '_> {
             }
             ComplexPoint::TypeInstance(Type::Type(t)) => match t.as_ref() {
                 t @ Type::Enum(_) => TypeContent::Type(t.clone()),
+                t @ Type::NewType(n) if n.native_int.is_some() => TypeContent::Type(t.clone()),
                 Type::None => TypeContent::Type(Type::None),
                 _ => return None,
             },
@@ -17,6 +17,7 @@ use crate::{
         AnyCause, CallableContent, CallableParam, CallableParams, DbString, NamedTuple, ParamType,
         ReplaceTypeVarLikes, StringSlice, Type,
     },
+    utils::is_identifier,
 };
 
 use super::{TypeComputation, TypeComputationOrigin, TypeContent, TypeVarCallbackReturn};
@@ -441,14 +442,6 @@ fn check_named_tuple_has_no_fields_with_underscore(
     }
 }
 
-fn is_identifier(s: &str) -> bool {
-    let mut chars = s.chars();
-    if !chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') {
-        return false;
-    }
-    chars.all(|c| c.is_alphanumeric() || c == '_')
-}
-
 pub fn add_named_tuple_param(
     named_tuple: &'static str,
     db: &Database,
@@ -0,0 +1,519 @@
+/*
+ * Calculates the edits that are needed in other files when modules or packages are moved, e.g.
+ * `import pkg.a` has to be changed to `import pkg.sub.a` if `pkg/a.py` is moved to
+ * `pkg/sub/a.py`.
+ * */
+
+use std::sync::Arc;
+
+use parsa_python_cst::{
+    CodeIndex, DottedAsNameContent, ImportFrom, ImportFromAsName, ImportFromTargets, ImportName,
+    ImportStatement, Primary, PrimaryContent, PrimaryParent,
+};
+use utils::FastHashSet;
+use vfs::{DirOrFile, DirectoryEntry, FileEntry, PathWithScheme, WorkspaceKind};
+
+use crate::{
+    database::Database,
+    file::{File, PythonFile},
+    name::Range,
+    type_::DbString,
+    utils::is_identifier,
+};
+
+#[derive(Debug)]
+pub struct TextEdit<'db> {
+    pub range: Range<'db>,
+    pub new_text: String,
+}
+
+#[derive(Debug)]
+pub struct SingleFileEdits<'db> {
+    pub path: &'db PathWithScheme,
+    pub edits: Vec<TextEdit<'db>>,
+}
+
+struct ModuleMove {
+    old: Vec<String>,
+    new: Vec<String>,
+    old_path: String,
+    // Files only move themselves, while directories also move all their submodules
+    is_dir: bool,
+}
+
+pub(crate) fn edits_for_moved_paths<'db>(
+    db: &'db Database,
+    moves: &[(PathWithScheme, PathWithScheme)],
+) -> Vec<SingleFileEdits<'db>> {
+    let moves: Vec<_> = moves
+        .iter()
+        .filter_map(|(from, to)| {
+            let is_dir = match db.vfs.search_path(db.project.flags.case_sensitive, from)? {
+                DirOrFile::Dir(_) => true,
+                DirOrFile::File(_) => false,
+            };
+            let old = module_name_for_path(db, from, is_dir)?;
+            let new = module_name_for_path(db, to, is_dir)?;
+            (old != new).then(|| ModuleMove {
+                old,
+                new,
+                old_path: from.path().to_string(),
+                is_dir,
+            })
+        })
+        .collect();
+    if moves.is_empty() {
+        return vec![];
+    }
+    let names = moves
+        .iter()
+        .map(|m| regex::escape(m.old.last().unwrap()))
+        .collect::<Vec<_>>()
+        .join("|");
+    let name_regex = regex::Regex::new(&format!(r"\b({names})\b")).unwrap();
+
+    let separator = db.vfs.handler.separator();
+    let is_moved_path = |path: &str| {
+        moves.iter().any(|m| {
+            path.strip_prefix(&m.old_path)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(separator))
+        })
+    };
+    let mut files = vec![];
+    let mut maybe_check_file = |file_entry: &Arc<FileEntry>| {
+        let is_moved = is_moved_path(file_entry.absolute_path(&*db.vfs.handler).path());
+        if let Some(file_index) = db.vfs.ensure_file_for_file_entry_with_conditional(
+            file_entry.clone(),
+            false,
+            |code| is_moved || name_regex.is_match(code),
+            |file_index, code| {
+                PythonFile::from_file_entry_and_code(&db.project, file_index, file_entry, code)
+            },
+        ) {
+            files.push(db.loaded_python_file(file_index));
+        }
+    };
+    for entries in db.vfs.workspaces.entries_to_type_check() {
+        entries.walk_entries(&*db.vfs.handler, &mut |_, dir_entry| {
+            if let DirectoryEntry::File(file) = dir_entry
+                && (file.name.ends_with(".py") || file.name.ends_with(".pyi"))
+            {
+                maybe_check_file(file)
+            }
+            true
+        });
+    }
+
+    let mover = ModuleMover { db, moves: &moves };
+    files
+        .into_iter()
+        .filter_map(|file| {
+            let edits = mover.edits_for_file(file);
+            (!edits.is_empty()).then(|| SingleFileEdits {
+                path: file.file_path_with_scheme(db),
+                edits,
+            })
+        })
+        .collect()
+}
+
+fn module_name_for_path(db: &Database, path: &PathWithScheme, is_dir: bool) -> Option<Vec<String>> {
+    let vfs = &*db.vfs.handler;
+    let path: &str = path.path();
+    // Nested workspaces are possible, the innermost one defines the module name.
+    let relative = db
+        .vfs
+        .workspaces
+        .iter()
+        .filter(|workspace| workspace.kind == WorkspaceKind::TypeChecking)
+        .filter_map(|workspace| {
+            vfs.strip_separator_prefix(path.strip_prefix(&**workspace.root_path())?)
+        })
+        .min_by_key(|relative| relative.len())?;
+    let mut names: Vec<String> = relative.split(vfs.separator()).map(String::from).collect();
+    if !is_dir {
+        let last = names.pop()?;
+        let name = last
+            .strip_suffix(".py")
+            .or_else(|| last.strip_suffix(".pyi"))?;
+        if name != "__init__" {
+            names.push(name.to_string());
+        }
+    }
+    (!names.is_empty() && names.iter().all(|name| is_identifier(name))).then_some(names)
+}
+
+struct ModuleMover<'db, 'a> {
+    db: &'db Database,
+    moves: &'a [ModuleMove],
+}
+
+impl<'db> ModuleMover<'db, '_> {
+    fn map_module(&self, name: &[String]) -> Option<Vec<String>> {
+        self.map(name, false)
+    }
+
+    /// Like `map_module`, but the name may also reference an attribute of a module, e.g.
+    /// `pkg.a.Foo`.
+    fn map_dotted_reference(&self, name: &[String]) -> Option<Vec<String>> {
+        self.map(name, true)
+    }
+
+    fn map(&self, name: &[String], allow_attributes: bool) -> Option<Vec<String>> {
+        let m = self
+            .moves
+            .iter()
+            .filter(|m| {
+                if m.is_dir || allow_attributes {
+                    name.starts_with(&m.old)
+                } else {
+                    name == m.old.as_slice()
+                }
+            })
+            .max_by_key(|m| m.old.len())?;
+        Some(
+            m.new
+                .iter()
+                .chain(name[m.old.len()..].iter())
+                .cloned()
+                .collect(),
+        )
+    }
+
+    fn edits_for_file(&self, file: &'db PythonFile) -> Vec<TextEdit<'db>> {
+        let db = self.db;
+        let old_name: Vec<String> = file
+            .qualified_name(db)
+            .split('.')
+            .map(String::from)
+            .collect();
+        let new_name = self
+            .map_module(&old_name)
+            .unwrap_or_else(|| old_name.clone());
+        let (_, is_package) = file.file_entry_and_is_package(db);
+        let package_of = |name: &[String]| {
+            if is_package {
+                name.to_vec()
+            } else {
+                name[..name.len() - 1].to_vec()
+            }
+        };
+        let mut edits = FileEdits {
+            file,
+            old_package: package_of(&old_name),
+            new_package: package_of(&new_name),
+            mover: self,
+            moved_dotted_imports: vec![],
+            edits: vec![],
+        };
+        for import in file.tree.iter_imports() {
+            match import {
+                ImportStatement::ImportName(import_name) => edits.add_import_name(import_name),
+                ImportStatement::ImportFrom(import_from) => edits.add_import_from(import_from),
+            }
+        }
+        edits.add_dotted_module_usages();
+        edits.add_string_references(is_package);
+        edits.edits.sort_by_key(|edit| edit.range.0.byte_position);
+        edits.edits
+    }
+}
+
+struct FileEdits<'db, 'a> {
+    file: &'db PythonFile,
+    old_package: Vec<String>,
+    new_package: Vec<String>,
+    mover: &'a ModuleMover<'db, 'a>,
+    // Imports like `import pkg.a` without an alias, together with the new module path
+    moved_dotted_imports: Vec<(Vec<String>, Vec<String>)>,
+    edits: Vec<TextEdit<'db>>,
+}
+
+impl<'db> FileEdits<'db, '_> {
+    fn add(&mut self, start: CodeIndex, end: CodeIndex, new_text: String) {
+        let db = self.mover.db;
+        self.edits.push(TextEdit {
+            range: (
+                self.file.byte_to_position_infos(db, start),
+                self.file.byte_to_position_infos(db, end),
+            ),
+            new_text,
+        })
+    }
+
+    fn add_import_name(&mut self, import_name: ImportName) {
+        // import foo.bar, baz as qux
+        for dotted_as_name in import_name.iter_dotted_as_names() {
+            let (start, end, has_alias) = match dotted_as_name.unpack() {
+                DottedAsNameContent::Simple(name_def, rest) => (
+                    name_def.start(),
+                    rest.map(|rest| rest.end())
+                        .unwrap_or_else(|| name_def.end()),
+                    false,
+                ),
+                DottedAsNameContent::WithAs(dotted, _) => (dotted.start(), dotted.end(), true),
+            };
+            let old_code = &self.file.tree.code()[start as usize..end as usize];
+            let old = split_dotted(old_code);
+            let Some(new) = self.mover.map_module(&old) else {
+                continue;
+            };
+            let mut new_text = new.join(".");
+            if !has_alias {
+                if old.len() == 1 {
+                    // `import a` becomes `import b as a`, so usages of `a` keep working.
+                    new_text += " as ";
+                    new_text += old_code;
+                } else {
+                    self.moved_dotted_imports.push((old, new));
+                }
+            }
+            self.add(start, end, new_text)
+        }
+    }
+
+    /// `import pkg.a` makes the module available as `pkg.a`, so usages like `pkg.a.x` need to
+    /// be changed as well.
+    fn add_dotted_module_usages(&mut self) {
+        let mut moved = std::mem::take(&mut self.moved_dotted_imports);
+        // The longest module paths need to be matched first, e.g. `pkg.a.b` before `pkg.a`.
+        moved.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        moved.dedup();
+        for name in self.file.tree.filter_all_names() {
+            let Some(primary) = name.maybe_atom_of_primary() else {
+                continue;
+            };
+            for (old, new) in &moved {
+                if name.as_str() == old[0]
+                    && let Some(end) = end_of_attribute_chain(primary, &old[1..])
+                {
+                    self.add(name.start(), end, new.join("."));
+                    break;
+                }
+            }
+        }
+    }
+
+    fn add_import_from(&mut self, import_from: ImportFrom) {
+        let (level, dotted) = import_from.level_with_dotted_name();
+        let mut base = if level == 0 {
+            vec![]
+        } else {
+            let Some(up) = self.old_package.len().checked_sub(level - 1) else {
+                // The import is not resolvable
+                return;
+            };
+            self.old_package[..up].to_vec()
+        };
+        if let Some(dotted) = dotted {
+            base.extend(split_dotted(dotted.as_code()));
+        }
+        if base.is_empty() {
+            return;
+        }
+        let (module_start, module_end) = import_from.module_range();
+        let old_module_code: String = self.file.tree.code()
+            [module_start as usize..module_end as usize]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let add_module_edit = |slf: &mut Self, target: &[String]| {
+            let new_text = slf.module_text(level, target);
+            if new_text != old_module_code {
+                slf.add(module_start, module_end, new_text)
+            }
+        };
+
+        if let Some(new_base) = self.mover.map_module(&base) {
+            add_module_edit(self, &new_base);
+            return;
+        }
+        let ImportFromTargets::Iterator(targets) = import_from.unpack_targets() else {
+            add_module_edit(self, &base);
+            return;
+        };
+        // `from pkg import a` might import a module that was moved.
+        let mut moved = vec![];
+        let mut staying = vec![];
+        for as_name in targets {
+            let (name, _) = as_name.unpack();
+            let mut full = base.clone();
+            full.push(name.as_code().to_string());
+            match self.mover.map_module(&full) {
+                Some(new) => moved.push((as_name, new)),
+                None => staying.push(as_name),
+            }
+        }
+        let Some((_, first_new)) = moved.first() else {
+            add_module_edit(self, &base);
+            return;
+        };
+        let parent = |new: &[String]| new[..new.len() - 1].to_vec();
+        let first_parent = parent(first_new);
+        if staying.is_empty() && moved.iter().all(|(_, new)| parent(new) == first_parent) {
+            // All names can be changed in place
+            add_module_edit(self, &first_parent);
+            for (as_name, new) in &moved {
+                let (name, name_def) = as_name.unpack();
+                let new_name = new.last().unwrap();
+                if name.as_code() != new_name {
+                    let new_text = if name.start() == name_def.start() {
+                        format!("{new_name} as {}", name.as_code())
+                    } else {
+                        new_name.clone()
+                    };
+                    self.add(name.start(), name.end(), new_text)
+                }
+            }
+            return;
+        }
+
+        // The import needs to be split up in multiple imports
+        let mut parents: Vec<Vec<String>> = vec![];
+        for (_, new) in &moved {
+            let p = parent(new);
+            if !parents.contains(&p) {
+                parents.push(p)
+            }
+        }
+        let new_imports: Vec<String> = parents
+            .iter()
+            .map(|p| {
+                let names: Vec<String> = moved
+                    .iter()
+                    .filter(|(_, new)| &parent(new) == p)
+                    .map(|(as_name, new)| import_target_text(as_name, new.last().unwrap()))
+                    .collect();
+                format!(
+                    "from {} import {}",
+                    self.module_text(level, p),
+                    names.join(", ")
+                )
+            })
+            .collect();
+        let code = self.file.tree.code();
+        let statement_start = import_from.start();
+        let line_start = code[..statement_start as usize]
+            .rfind('\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let indent = &code[line_start..statement_start as usize];
+        let separator = if indent.chars().all(char::is_whitespace) {
+            format!("\n{indent}")
+        } else {
+            // e.g. `if x: from foo import bar`
+            "; ".to_string()
+        };
+        let new_imports = new_imports.join(&separator);
+        if staying.is_empty() {
+            self.add(statement_start, import_from.end(), new_imports)
+        } else {
+            add_module_edit(self, &base);
+            let (targets_start, targets_end) = import_from.targets_range();
+            let staying: Vec<&str> = staying.iter().map(|as_name| as_name.as_code()).collect();
+            self.add(targets_start, targets_end, staying.join(", "));
+            self.add(statement_start, statement_start, new_imports + &separator)
+        }
+    }
+
+    /// The module part of a `from ... import` statement. Relative imports stay relative if
+    /// possible.
+    fn module_text(&self, level: usize, target: &[String]) -> String {
+        if level > 0 {
+            let common = self
+                .new_package
+                .iter()
+                .zip(target)
+                .take_while(|(a, b)| a == b)
+                .count();
+            if common > 0 {
+                return ".".repeat(self.new_package.len() - common + 1)
+                    + &target[common..].join(".");
+            }
+        }
+        target.join(".")
+    }
+
+    fn add_string_references(&mut self, is_package: bool) {
+        let db = self.mover.db;
+        let file = self.file;
+        // Entries of `__all__` in an `__init__.py` may reference submodules.
+        let mut dunder_all_starts = FastHashSet::default();
+        if is_package && let Some(dunder_all) = file.maybe_dunder_all(db) {
+            for s in dunder_all {
+                if let DbString::StringSlice(slice) = s
+                    && slice.file_index == file.file_index
+                {
+                    dunder_all_starts.insert(slice.start);
+                }
+            }
+        }
+        for literal in file.tree.iter_string_literals() {
+            let (start, end) = literal.content_start_and_end_in_literal();
+            let (start, end) = (literal.start() + start, literal.start() + end);
+            let content = literal.content();
+            if dunder_all_starts.contains(&start) {
+                let mut full = self.old_package.clone();
+                full.push(content.to_string());
+                if let Some(new) = self.mover.map_module(&full)
+                    && new[..new.len() - 1] == self.new_package
+                    && new.last().unwrap() != content
+                    // If the name is defined in the module, it is not the submodule.
+                    && file.lookup_symbol(content).is_none()
+                {
+                    self.add(start, end, new.last().unwrap().clone())
+                }
+            } else if content.contains('.') && literal.is_forward_reference() {
+                // Dotted forward references like "pkg.a.Foo", e.g. for imports within `if
+                // TYPE_CHECKING:` blocks. Other strings are not changed, they might not be
+                // references to modules at all.
+                let names = split_dotted(content);
+                if names.iter().all(|name| is_identifier(name))
+                    && let Some(new) = self.mover.map_dotted_reference(&names)
+                {
+                    self.add(start, end, new.join("."))
+                }
+            }
+        }
+    }
+}
+
+fn split_dotted(dotted: &str) -> Vec<String> {
+    dotted
+        .split('.')
+        .map(|name| name.trim().to_string())
+        .collect()
+}
+
+/// Returns the end of e.g. `.a.b` in `pkg.a.b.x` if the primary is `pkg.a`.
+fn end_of_attribute_chain(mut primary: Primary, names: &[String]) -> Option<CodeIndex> {
+    let mut end = None;
+    for (i, expected) in names.iter().enumerate() {
+        if i > 0 {
+            match primary.parent() {
+                PrimaryParent::Primary(parent) => primary = parent,
+                PrimaryParent::Other => return None,
+            }
+        }
+        match primary.second() {
+            PrimaryContent::Attribute(attr) if attr.as_str() == expected => end = Some(attr.end()),
+            _ => return None,
+        }
+    }
+    end
+}
+
+fn import_target_text(as_name: &ImportFromAsName, new_name: &str) -> String {
+    let (name, name_def) = as_name.unpack();
+    if name.start() == name_def.start() {
+        if name.as_code() == new_name {
+            new_name.to_string()
+        } else {
+            format!("{new_name} as {}", name.as_code())
+        }
+    } else if name_def.as_code() == new_name {
+        new_name.to_string()
+    } else {
+        format!("{new_name} as {}", name_def.as_code())
+    }
+}
@@ -6,9 +6,10 @@
 use std::{borrow::Cow, cell::Cell, sync::Arc};
 
 use parsa_python_cst::{
-    Atom, DottedAsNameContent, DottedImportName, GotoNode, Name as CSTName, NameDefParent,
-    NameImportParent, NameParent, NodeIndex, Primary, PrimaryContent, PrimaryOrAtom, PrimaryTarget,
-    PrimaryTargetOrAtom, Scope,
+    Argument, ArgumentsDetails, Atom, DottedAsNameContent, DottedImportName, GotoNode,
+    Name as CSTName, NameDefParent, NameImportParent, NameParent, NodeIndex, ParamKind, Primary,
+    PrimaryContent, PrimaryOrAtom, PrimaryTarget, PrimaryTargetOrAtom, Scope, StringKeyUsage,
+    StringLiteral,
 };
 use utils::FastHashSet;
 use vfs::{DirectoryEntry, Entries, FileEntry, FileIndex};
@@ -18,7 +19,10 @@ use crate::{
     completion::ScopesIterator,
     database::{Database, ParentScope, PointKind, Specific},
     debug,
-    file::{ClassInitializer, ClassNodeRef, File, FuncNodeRef, PythonFile, first_defined_name},
+    file::{
+        ClassInitializer, ClassNodeRef, File, FuncNodeRef, PythonFile, first_defined_name,
+        use_cached_param_annotation_type,
+    },
     format_data::FormatData,
     inference_state::{InferenceState, Mode},
     inferred::Inferred,
@@ -26,8 +30,11 @@ use crate::{
     name::{ModuleName, Name, NodeName, Range, TreeName},
     node_ref::NodeRef,
     recoverable_error,
-    type_::{LookupResult, Type, TypeVarLikeName, TypeVarName, UnionType},
-    type_helpers::TypeOrClass,
+    type_::{
+        CallableContent, CallableLike, CallableParams, DbString, LookupResult, ParamType,
+        StarStarParamType, StringSlice, Type, TypeVarLikeName, TypeVarName, UnionType,
+    },
+    type_helpers::{Function, TypeOrClass, cache_class_name},
 };
 
 pub(crate) struct PositionalDocument<'db, T> {
@@ -76,7 +83,7 @@ impl<'db> PositionalDocument<'db, GotoNode<'db>> {
         })
     }
 
-    fn infer_position(&self) -> Option<Inferred> {
+    pub fn infer_position(&self) -> Option<Inferred> {
         match self.node {
             GotoNode::Name(name) => self.infer_name(name),
             GotoNode::ImportFromAsName { import_as_name, .. } => {
@@ -404,7 +411,8 @@ impl<'db, C: for<'a> FnMut(Name<'db, 'a>) -> T, T> GotoResolver<'db, C> {
             None
         };
         match node {
-            GotoNode::Name(name) => lookup_on_name(name),
+            GotoNode::Name(name) => lookup_on_name(name)
+                .or_else(|| self.goto_keyword(name, follow_imports).map(|r| vec![r])),
             GotoNode::Primary(primary) => match primary.second() {
                 PrimaryContent::Attribute(name) => lookup_on_name(name).or_else(|| {
                     let base = self.infos.infer_primary_or_atom(primary.first());
@@ -451,6 +459,72 @@ impl<'db, C: for<'a> FnMut(Name<'db, 'a>) -> T, T> GotoResolver<'db, C> {
             GotoNode::Atom(_) | GotoNode::None => None,
         }
     }
+    /// Goes to the param of a keyword argument, e.g. `x` in `f(x=1)`, or to the attribute that
+    /// is matched by a keyword pattern, e.g. `x` in `case Point(x=1)`.
+    fn goto_keyword(&mut self, name: CSTName, follow_imports: bool) -> Option<T> {
+        let db = self.infos.db;
+        let file = self.infos.file;
+        match name.parent() {
+            NameParent::Kwarg(kwarg) => {
+                let call = kwarg.maybe_call()?;
+                let callee = self.infos.infer_primary_or_atom(call.first());
+                let param_name = self.infos.with_i_s(|i_s| {
+                    if is_replace_function(i_s, &callee) {
+                        return Some(None);
+                    }
+                    let callable = callee.as_cow_type(i_s).maybe_callable(i_s)?;
+                    let callables: Vec<_> = match &callable {
+                        CallableLike::Callable(c) => vec![c.clone()],
+                        CallableLike::Overload(o) => o.iter_functions().cloned().collect(),
+                    };
+                    callables
+                        .iter()
+                        .find_map(|c| keyword_param_name(db, c, name.as_code()))
+                        .map(Some)
+                })?;
+                match param_name {
+                    Some(param_name) => {
+                        let param_file = db.loaded_python_file(param_name.file_index);
+                        let name = param_file.tree.name_by_start(param_name.start)?;
+                        Some(self.calculate_return(Name::TreeName(
+                            TreeName::with_unknown_parent_scope(db, param_file, name),
+                        )))
+                    }
+                    None => {
+                        // `replace(obj, x=1)` changes the attribute `x` of the first argument
+                        let PrimaryContent::Execution(ArgumentsDetails::Node(args)) = call.second()
+                        else {
+                            return None;
+                        };
+                        let Some(Argument::Positional(first)) = args.iter().next() else {
+                            return None;
+                        };
+                        let obj = self
+                            .infos
+                            .with_i_s(|i_s| file.inference(i_s).infer_named_expression(first));
+                        self.goto_primary_attr(obj, name.as_code(), follow_imports, false)?
+                            .into_iter()
+                            .next()
+                    }
+                }
+            }
+            NameParent::KeywordPattern(keyword_pattern) => {
+                let (dotted, _) = keyword_pattern.class_pattern().unpack();
+                let cls = self.infos.with_i_s(|i_s| {
+                    let inf = file.inference(i_s).infer_pattern_dotted_name(dotted);
+                    match inf.as_cow_type(i_s).as_ref() {
+                        Type::Type(t) => Some(Inferred::from_type((**t).clone())),
+                        _ => None,
+                    }
+                })?;
+                self.goto_primary_attr(cls, name.as_code(), follow_imports, false)?
+                    .into_iter()
+                    .next()
+            }
+            _ => None,
+        }
+    }
+
     fn goto_primary_attr(
         &mut self,
         base: Inferred,
@@ -519,13 +593,46 @@ impl<'db, C: FnMut(Name<'db, '_>) -> T, T> ReferencesResolver<'db, C, T> {
 
     pub fn references(mut self, goal: ReferencesGoal, include_declarations: bool) -> Vec<T> {
         debug!("Calculate references for {:?}", self.infos.node);
-        let Some(on_name) = self.infos.node.on_name() else {
+        let Some((search_name, is_globally_reachable)) =
+            self.find_definitions(goal, include_declarations)
+        else {
             return vec![];
         };
+        let db = self.infos.db;
+
+        // 2. Find all the references to the original definitions
+
+        match goal {
+            _ if !is_globally_reachable => {
+                self.find_references_in_file(self.infos.file, search_name)
+            }
+            ReferencesGoal::OnlyCurrentFile => {
+                self.find_references_in_file(self.infos.file, search_name)
+            }
+            ReferencesGoal::OnlyTypeCheckedWorkspaces => self.find_references_in_workspace_entries(
+                db.vfs.workspaces.entries_to_type_check(),
+                search_name,
+            ),
+            ReferencesGoal::AllFilesIncludingDependencies => self
+                .find_references_in_workspace_entries(
+                    db.vfs.workspaces.iter().map(|x| &x.entries),
+                    search_name,
+                ),
+        }
+        self.results
+    }
+
+    /// Returns the name that is searched and whether references may be found outside of the
+    /// current file.
+    fn find_definitions(
+        &mut self,
+        goal: ReferencesGoal,
+        include_declarations: bool,
+    ) -> Option<(&'db str, bool)> {
+        let on_name = self.infos.node.on_name()?;
         let search_name = on_name.as_code();
 
         let mut is_globally_reachable = false;
-        let db = self.infos.db;
 
         //  1. Find the original definition
 
@@ -588,34 +695,14 @@ impl<'db, C: FnMut(Name<'db, '_>) -> T, T> ReferencesResolver<'db, C, T> {
                 self.results.push((self.on_result)(n))
             } else {
                 debug!("Did not find the original rename definition for {search_name}");
-                return vec![];
+                return None;
             }
         }
         debug!(
             "Finding references on {search_name} now for definitions {:?}",
             &self.definitions
         );
-
-        // 2. Find all the references to the original definitions
-
-        match goal {
-            _ if !is_globally_reachable => {
-                self.find_references_in_file(self.infos.file, search_name)
-            }
-            ReferencesGoal::OnlyCurrentFile => {
-                self.find_references_in_file(self.infos.file, search_name)
-            }
-            ReferencesGoal::OnlyTypeCheckedWorkspaces => self.find_references_in_workspace_entries(
-                db.vfs.workspaces.entries_to_type_check(),
-                search_name,
-            ),
-            ReferencesGoal::AllFilesIncludingDependencies => self
-                .find_references_in_workspace_entries(
-                    db.vfs.workspaces.iter().map(|x| &x.entries),
-                    search_name,
-                ),
-        }
-        self.results
+        Some((search_name, is_globally_reachable))
     }
 
     fn find_references_in_file(&mut self, file: &'db PythonFile, search_name: &str) {
@@ -623,24 +710,9 @@ impl<'db, C: FnMut(Name<'db, '_>) -> T, T> ReferencesResolver<'db, C, T> {
         debug_assert!(result.is_ok());
         for name in file.tree.filter_all_names() {
             if name.as_code() == search_name {
-                let mut add_all_names = false;
-                GotoResolver::new(
-                    PositionalDocument::for_goto(
-                        self.infos.db,
-                        file,
-                        InputPosition::NthUTF8Byte(name.start() as usize),
-                    )
-                    .unwrap(),
-                    GotoGoal::Indifferent,
-                    |n: Name| {
-                        follow_goto_if_necessary(n, &mut |n| {
-                            if self.definitions.contains(&to_unique_position(&n)) {
-                                add_all_names = true;
-                            }
-                        })
-                    },
-                )
-                .goto_name(false, false);
+                let add_all_names = definitions_of_reference(self.infos.db, file, name)
+                    .iter()
+                    .any(|definition| self.definitions.contains(definition));
                 if add_all_names {
                     let n = Name::TreeName(TreeName::with_unknown_parent_scope(
                         self.infos.db,
@@ -660,35 +732,292 @@ impl<'db, C: FnMut(Name<'db, '_>) -> T, T> ReferencesResolver<'db, C, T> {
         workspaces_entries: impl Iterator<Item = &'x Entries>,
         search_name: &str,
     ) {
-        let db = self.infos.db;
-        let in_name_regex = regex::Regex::new(&format!(r"\b{search_name}\b")).unwrap();
-        let mut files = vec![];
-        let mut maybe_check_file = |file_entry: &Arc<FileEntry>| {
-            if let Some(file_index) = db.vfs.ensure_file_for_file_entry_with_conditional(
-                file_entry.clone(),
-                false,
-                |code| in_name_regex.is_match(code),
-                |file_index, code| {
-                    PythonFile::from_file_entry_and_code(&db.project, file_index, file_entry, code)
-                },
-            ) {
-                files.push(db.loaded_python_file(file_index));
+        for file in files_containing_names(self.infos.db, workspaces_entries, &[search_name]) {
+            if self.infos.db.is_cancelled() {
+                return;
             }
-        };
-        for entries in workspaces_entries {
-            entries.walk_entries(&*db.vfs.handler, &mut |_, dir_entry| {
-                if let DirectoryEntry::File(file) = dir_entry {
-                    maybe_check_file(file)
+            self.find_references_in_file(file, search_name);
+        }
+    }
+}
+
+/// Finds the references of multiple names in the type checked workspaces like
+/// `ReferencesResolver::references`, but the workspaces are searched only once for all of them.
+/// The results are grouped by the index of the name they refer to and declarations are not
+/// included.
+pub(crate) fn batched_references<'db, T>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    names: &[CSTName<'db>],
+    mut on_result: impl FnMut(Name<'db, '_>) -> T,
+) -> Vec<Vec<T>> {
+    let searches: Vec<Option<BatchedSearch>> = names
+        .iter()
+        .map(|name| {
+            let document = PositionalDocument::for_goto(
+                db,
+                file,
+                InputPosition::NthUTF8Byte(name.start() as usize),
+            )
+            .ok()?;
+            let mut resolver = ReferencesResolver::new(document, |_| ());
+            let (search_name, is_globally_reachable) =
+                resolver.find_definitions(ReferencesGoal::OnlyTypeCheckedWorkspaces, false)?;
+            Some(BatchedSearch {
+                search_name,
+                is_globally_reachable,
+                definitions: resolver.definitions,
+            })
+        })
+        .collect();
+    let mut global_names: Vec<&str> = searches
+        .iter()
+        .flatten()
+        .filter(|search| search.is_globally_reachable)
+        .map(|search| search.search_name)
+        .collect();
+    global_names.sort();
+    global_names.dedup();
+    let mut files = if global_names.is_empty() {
+        vec![]
+    } else {
+        files_containing_names(db, db.vfs.workspaces.entries_to_type_check(), &global_names)
+    };
+    if !files.iter().any(|f| f.file_index == file.file_index) {
+        files.push(file)
+    }
+
+    let mut results: Vec<Vec<T>> = names.iter().map(|_| vec![]).collect();
+    for f in files {
+        if db.is_cancelled() {
+            break;
+        }
+        let result = f.ensure_calculated_diagnostics(db);
+        debug_assert!(result.is_ok());
+        for name in f.tree.filter_all_names() {
+            let mut definitions = None;
+            for (i, search) in searches.iter().enumerate() {
+                let Some(search) = search else {
+                    continue;
+                };
+                if search.search_name != name.as_code()
+                    || !search.is_globally_reachable && f.file_index != file.file_index
+                {
+                    continue;
                 }
-                true
+                let definitions =
+                    definitions.get_or_insert_with(|| definitions_of_reference(db, f, name));
+                let n = Name::TreeName(TreeName::with_unknown_parent_scope(db, f, name));
+                if !search.definitions.contains(&to_unique_position(&n))
+                    && definitions.iter().any(|d| search.definitions.contains(d))
+                {
+                    results[i].push(on_result(n))
+                }
+            }
+        }
+    }
+    results
+}
+
+struct BatchedSearch<'db> {
+    search_name: &'db str,
+    // Whether references may be found outside of the current file
+    is_globally_reachable: bool,
+    definitions: FastHashSet<(FileIndex, usize)>,
+}
+
+/// The positions of the definitions that a name refers to
+fn definitions_of_reference<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    name: CSTName<'db>,
+) -> Vec<(FileIndex, usize)> {
+    let mut definitions = vec![];
+    GotoResolver::new(
+        PositionalDocument::for_goto(db, file, InputPosition::NthUTF8Byte(name.start() as usize))
+            .unwrap(),
+        GotoGoal::Indifferent,
+        |n: Name| follow_goto_if_necessary(n, &mut |n| definitions.push(to_unique_position(&n))),
+    )
+    .goto_name(false, false);
+    definitions
+}
+
+fn files_containing_names<'db, 'x>(
+    db: &'db Database,
+    workspaces_entries: impl Iterator<Item = &'x Entries>,
+    search_names: &[&str],
+) -> Vec<&'db PythonFile> {
+    let alternatives: Vec<_> = search_names.iter().map(|n| regex::escape(n)).collect();
+    let in_name_regex = regex::Regex::new(&format!(r"\b(?:{})\b", alternatives.join("|"))).unwrap();
+    let mut files = vec![];
+    let mut maybe_check_file = |file_entry: &Arc<FileEntry>| {
+        if let Some(file_index) = db.vfs.ensure_file_for_file_entry_with_conditional(
+            file_entry.clone(),
+            false,
+            |code| in_name_regex.is_match(code),
+            |file_index, code| {
+                PythonFile::from_file_entry_and_code(&db.project, file_index, file_entry, code)
+            },
+        ) {
+            files.push(db.loaded_python_file(file_index));
+        }
+    };
+    for entries in workspaces_entries {
+        entries.walk_entries(&*db.vfs.handler, &mut |_, dir_entry| {
+            if db.is_cancelled() {
+                return false;
+            }
+            if let DirectoryEntry::File(file) = dir_entry {
+                maybe_check_file(file)
+            }
+            true
+        });
+    }
+    files
+}
+
+/// Only members of TypedDicts and `**kwargs: Unpack[TD]` params can be referred to by the string
+/// keys of a TypedDict.
+pub(crate) fn may_be_typed_dict_key(db: &Database, file: &PythonFile, name: CSTName) -> bool {
+    let Some(name_def) = name.name_def() else {
+        return false;
+    };
+    match name.parent_scope() {
+        Scope::Class(class) => {
+            cache_class_name(NodeRef::new(file, class.name_def().index()), class);
+            let class_node_ref = ClassNodeRef::new(file, class.index());
+            class_node_ref.ensure_cached_class_infos(&InferenceState::new(db, file));
+            class_node_ref.maybe_typed_dict_definition().is_some()
+        }
+        Scope::Function(func) => {
+            let Some(annotation) = func
+                .params()
+                .iter()
+                .find(|param| {
+                    param.name_def().index() == name_def.index()
+                        && param.kind() == ParamKind::StarStar
+                })
+                .and_then(|param| param.annotation())
+            else {
+                return false;
+            };
+            let func_ref = FuncNodeRef::new(file, func.index());
+            Function::new_with_unknown_parent(db, *func_ref)
+                .ensure_cached_func(&InferenceState::new(db, file));
+            matches!(
+                use_cached_param_annotation_type(db, file, annotation).as_ref(),
+                Type::TypedDict(_)
+            )
+        }
+        _ => false,
+    }
+}
+
+/// Finds the string literals in the type checked workspaces that are used as keys of TypedDicts,
+/// e.g. `d["key"]` or `{"key": 1}`, where the key is one of the member definitions.
+pub(crate) fn typed_dict_key_references<'db>(
+    db: &'db Database,
+    member_definitions: &FastHashSet<(FileIndex, usize)>,
+    key: &str,
+    mut on_result: impl FnMut(&'db PythonFile, StringLiteral<'db>),
+) {
+    let is_member = |t: &Type| {
+        t.iter_with_unpacked_unions(db).any(|t| match t {
+            Type::TypedDict(td) => td.members(db).iter().any(|member| {
+                member.name.as_str(db) == key
+                    && member_definitions
+                        .contains(&(member.name.file_index, member.name.start as usize))
+            }),
+            _ => false,
+        })
+    };
+    let entries = db.vfs.workspaces.entries_to_type_check();
+    for file in files_containing_names(db, entries, &[key]) {
+        if db.is_cancelled() {
+            return;
+        }
+        let result = file.ensure_calculated_diagnostics(db);
+        debug_assert!(result.is_ok());
+        for (literal, usage) in file.tree.string_keys(key) {
+            let matches = with_i_s_non_self(db, file, usage.parent_scope(), |i_s| {
+                let inference = file.inference(i_s);
+                let inf = match usage {
+                    StringKeyUsage::Subscript(primary) => {
+                        Some(inference.infer_primary_or_atom(primary.first()))
+                    }
+                    StringKeyUsage::SubscriptTarget(target) => {
+                        Some(inference.infer_primary_target_or_atom(target.first()))
+                    }
+                    // Dict literals are only TypedDicts if they were inferred with a TypedDict
+                    // context, which is cached after the diagnostics were calculated.
+                    StringKeyUsage::Dict(atom) => {
+                        NodeRef::new(file, atom.index()).maybe_inferred(i_s)
+                    }
+                };
+                inf.is_some_and(|inf| is_member(&inf.as_cow_type(i_s)))
             });
+            if matches {
+                on_result(file, literal)
+            }
         }
-        for file in files {
-            self.find_references_in_file(file, search_name);
+    }
+}
+
+/// `dataclasses.replace` and `copy.replace` take the attributes to change as keyword arguments.
+fn is_replace_function(i_s: &InferenceState, callee: &Inferred) -> bool {
+    let db = i_s.db;
+    let in_replace_module = |node_ref: NodeRef| {
+        matches!(
+            node_ref.file.qualified_name(db).as_str(),
+            "dataclasses" | "copy"
+        )
+    };
+    // `dataclasses.replace` has a custom behavior and therefore no callable type.
+    if let Some(node_ref) = callee.maybe_saved_node_ref(db)
+        && node_ref
+            .maybe_name()
+            .is_some_and(|name| name.as_code() == "replace")
+        && in_replace_module(node_ref)
+    {
+        return true;
+    }
+    match callee.as_cow_type(i_s).as_ref() {
+        Type::Callable(c) => {
+            c.name
+                .as_ref()
+                .is_some_and(|name| name.as_str(db) == "replace")
+                && in_replace_module(NodeRef::from_link(db, c.defined_at))
         }
+        _ => false,
     }
 }
 
+/// Returns where the param that can be passed with the keyword is defined, e.g. the param of a
+/// function, the field of a dataclass or the key of an unpacked TypedDict.
+fn keyword_param_name(
+    db: &Database,
+    callable: &CallableContent,
+    name: &str,
+) -> Option<StringSlice> {
+    let CallableParams::Simple(params) = &callable.params else {
+        return None;
+    };
+    params.iter().find_map(|param| match &param.type_ {
+        ParamType::PositionalOnly(_) | ParamType::Star(_) => None,
+        ParamType::StarStar(StarStarParamType::UnpackTypedDict(td)) => td
+            .members(db)
+            .iter()
+            .find(|member| member.name.as_str(db) == name)
+            .map(|member| member.name),
+        ParamType::StarStar(_) => None,
+        _ => match &param.name {
+            Some(DbString::StringSlice(slice)) if slice.as_str(db) == name => Some(*slice),
+            _ => None,
+        },
+    })
+}
+
 fn to_unique_position(n: &Name) -> (FileIndex, usize) {
     (n.file().file_index, n.name_range().0.byte_position)
 }
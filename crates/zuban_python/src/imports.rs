@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use utils::match_case;
-use vfs::{Directory, DirectoryEntry, Entries, FileIndex, WorkspaceKind};
+use vfs::{Directory, DirectoryEntry, Entries, FileIndex, Workspace, WorkspaceKind};
 
 use crate::{
     database::Database,
@@ -167,9 +167,7 @@ pub fn global_import<'a>(
         python_import_with_needs_exact_case(
             db,
             from_file,
-            db.vfs
-                .workspaces
-                .iter()
+            workspaces_of_environment(db, from_file)
                 .map(|w| (&w.entries, w.part_of_site_packages())),
             name,
             false,
@@ -194,11 +192,23 @@ fn global_import_without_stubs_first<'a>(
     python_import(
         db,
         from_file,
-        db.vfs.workspaces.iter().map(|d| &d.entries),
+        workspaces_of_environment(db, from_file).map(|d| &d.entries),
         name,
     )
 }
 
+fn workspaces_of_environment<'db>(
+    db: &'db Database,
+    from_file: &PythonFile,
+) -> impl Iterator<Item = &'db Workspace> {
+    // Site packages of other environments in the project are not importable.
+    let sys_path = from_file.sys_path(db);
+    db.vfs.workspaces.iter().filter(move |workspace| {
+        !workspace.part_of_site_packages()
+            || sys_path.iter().any(|p| ***p == *workspace.root_path())
+    })
+}
+
 pub fn namespace_import_with_unloaded_file(
     db: &Database,
     from_file: &PythonFile,
@@ -1,5 +1,7 @@
 use std::cell::Cell;
 
+use config::Settings;
+
 use crate::{
     TypeCheckerFlags,
     database::{Database, ParentScope},
@@ -252,4 +254,13 @@ impl<'db, 'a> InferenceState<'db, 'a> {
             &self.db.project.flags
         }
     }
+
+    /// The settings of the file that is inferred, e.g. for its Python version.
+    pub fn settings(&self) -> &'db Settings {
+        if let Some(file) = self.context.current_file() {
+            file.settings(self.db)
+        } else {
+            &self.db.project.settings
+        }
+    }
 }
@@ -1,11 +1,15 @@
 #![allow(clippy::nonminimal_bool)] // I don't like this rule
 #![allow(clippy::too_many_arguments)] // TODO For now this is easier, but probably enable again
 
+mod annotate;
 mod arguments;
+mod code_lens;
 mod completion;
 mod database;
 mod diagnostics;
+mod docstring;
 mod file;
+mod file_moves;
 mod format_data;
 mod getitem;
 mod goto;
@@ -16,31 +20,43 @@ mod lines;
 mod matching;
 mod name;
 mod node_ref;
+mod organize_imports;
 mod params;
 mod python_state;
+mod refactoring;
 mod select_files;
 mod sys_path;
 mod type_;
 mod type_helpers;
 mod utils;
 
-use std::{cell::OnceCell, path::Path};
+use std::{
+    cell::OnceCell,
+    path::Path,
+    sync::{Arc, atomic::AtomicBool},
+};
 
-use ::utils::FastHashMap;
+use ::utils::{FastHashMap, FastHashSet};
 use anyhow::bail;
+pub use code_lens::CodeLensKind;
 use completion::CompletionResolver;
 pub use completion::{Completion, CompletionItemKind};
 pub use goto::{GotoGoal, ReferencesGoal};
-use goto::{GotoResolver, PositionalDocument, ReferencesResolver};
+use goto::{
+    GotoResolver, PositionalDocument, ReferencesResolver, may_be_typed_dict_key,
+    typed_dict_key_references,
+};
 use name::Range;
-use parsa_python_cst::{GotoNode, Tree};
+pub use parsa_python_cst::FoldingRangeKind;
+use parsa_python_cst::{CodeIndex, GotoNode, Tree};
 use vfs::{AbsPath, DirOrFile, FileIndex, LocalFS, PathWithScheme, VfsHandler};
 
 use config::{ProjectOptions, PythonVersion, Settings, TypeCheckerFlags};
 pub use database::Mode;
 use database::{Database, PythonProject};
 pub use diagnostics::Severity;
-use file::File;
+use file::{File, PythonFile};
+pub use file_moves::{SingleFileEdits, TextEdit};
 use inference_state::InferenceState;
 use inferred::Inferred;
 pub use lines::PositionInfos;
@@ -80,6 +96,12 @@ impl Project {
         self.db.invalidate_path(path)
     }
 
+    /// Long running operations like reference searches stop early once this flag is set. Their
+    /// results are incomplete in that case and should be discarded.
+    pub fn set_cancellation_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.db.cancellation_flag = flag;
+    }
+
     pub fn into_panic_recovery(self) -> PanicRecovery {
         PanicRecovery {
             vfs: self.db.vfs.into_panic_recovery(),
@@ -159,6 +181,14 @@ impl Project {
         Project { db }
     }
 
+    /// Returns the paths of all files that `zuban check` would check.
+    pub fn paths_to_check(&mut self) -> anyhow::Result<Vec<PathWithScheme>> {
+        Ok(select_files::files_to_check(&self.db)?
+            .into_iter()
+            .map(|file| file.file_path_with_scheme(&self.db).clone())
+            .collect())
+    }
+
     pub fn document(&mut self, path: &PathWithScheme) -> Option<Document<'_>> {
         let DirOrFile::File(file_entry) = self
             .db
@@ -176,6 +206,26 @@ impl Project {
         })
     }
 
+    /// Like [`Project::document`], but makes sure that narrowed types are recorded while checking
+    /// the file, which is needed for [`Document::reveal_type`].
+    pub fn document_for_reveal_type(&mut self, path: &PathWithScheme) -> Option<Document<'_>> {
+        let file_index = self.document(path)?.file_index;
+        self.db.record_narrowed_names(file_index);
+        Some(Document {
+            project: self,
+            file_index,
+        })
+    }
+
+    /// Returns the edits that are needed to keep imports working when files or directories are
+    /// moved from the first to the second path of the pairs.
+    pub fn edits_for_moved_paths(
+        &self,
+        moves: &[(PathWithScheme, PathWithScheme)],
+    ) -> Vec<SingleFileEdits<'_>> {
+        file_moves::edits_for_moved_paths(&self.db, moves)
+    }
+
     pub fn vfs_handler(&self) -> &dyn VfsHandler {
         self.db.vfs.handler.as_ref()
     }
@@ -200,6 +250,16 @@ impl<'project> Document<'project> {
         python_file.diagnostics(&self.project.db)
     }
 
+    /// Changes whenever the diagnostics of this document need to be calculated again. Callers
+    /// can use it to cache their own results that are derived from the diagnostics. `None` means
+    /// that the diagnostics were not calculated since the last change.
+    pub fn diagnostics_generation(&self) -> Option<u64> {
+        self.project
+            .db
+            .loaded_python_file(self.file_index)
+            .diagnostics_generation()
+    }
+
     fn positional_document(
         &self,
         position: InputPosition,
@@ -269,13 +329,19 @@ impl<'project> Document<'project> {
 
         let mut file_renames: Vec<&'project PathWithScheme> = vec![];
         let mut file_changes = FastHashMap::default();
+        let mut tree_name_positions = FastHashSet::default();
+        let mut may_have_key_references = false;
         let references = ReferencesResolver::new(document, |name| match &name {
             Name::TreeName(tree_name) => {
+                may_have_key_references |=
+                    may_be_typed_dict_key(db, tree_name.file, tree_name.cst_name);
                 let file_index = tree_name.file.file_index;
+                let range = name.name_range();
+                tree_name_positions.insert((file_index, range.0.byte_position));
                 file_changes
                     .entry(file_index)
                     .or_insert_with(std::vec::Vec::new)
-                    .push(name.name_range());
+                    .push(range);
             }
             Name::ModuleName(module_name) => {
                 file_renames.push(module_name.file.file_path_with_scheme(db))
@@ -289,6 +355,19 @@ impl<'project> Document<'project> {
                 name.as_code()
             );
         }
+        if may_have_key_references {
+            // Renaming a TypedDict member also renames the string keys that refer to it
+            typed_dict_key_references(db, &tree_name_positions, name.as_code(), |file, literal| {
+                let (start, end) = literal.content_start_and_end_in_literal();
+                file_changes
+                    .entry(file.file_index)
+                    .or_insert_with(std::vec::Vec::new)
+                    .push((
+                        file.byte_to_position_infos(db, literal.start() + start),
+                        file.byte_to_position_infos(db, literal.start() + end),
+                    ));
+            });
+        }
         let changes: Vec<SingleFileRenameChanges<'project>> = file_changes
             .into_iter()
             .map(|(file_index, changes)| {
@@ -339,7 +418,7 @@ impl<'project> Document<'project> {
                             .into_string(),
                     );
                 }
-                n.name.documentation().to_string()
+                n.documentation_markdown()
             },
         );
         let mut results = resolver.infer_definition();
@@ -404,6 +483,31 @@ impl<'project> Document<'project> {
         }))
     }
 
+    /// Returns the type of the expression under the cursor, including all the narrowing that
+    /// applies at that position. The document needs to be created with
+    /// [`Project::document_for_reveal_type`].
+    pub fn reveal_type(&self, position: InputPosition) -> anyhow::Result<Option<String>> {
+        let document = self.positional_document(position)?;
+        if !document.file.records_narrowed_names() {
+            bail!("Narrowed types are not recorded for this document")
+        }
+        // Narrowed names are only known after the flow analysis of the file.
+        let _ = document
+            .file
+            .ensure_calculated_diagnostics(&self.project.db);
+        if let GotoNode::Name(name) = document.node
+            && let Some(t) = document.file.narrowed_name_type(name.index())
+        {
+            return Ok(Some(t.format_short(&self.project.db).into_string()));
+        }
+        let Some(inf) = document.infer_position() else {
+            return Ok(None);
+        };
+        Ok(Some(
+            document.with_i_s(|i_s| inf.format_short(i_s).into_string()),
+        ))
+    }
+
     pub fn is_valid_rename_location(
         &self,
         position: InputPosition,
@@ -426,6 +530,180 @@ impl<'project> Document<'project> {
             file.byte_to_position_infos(&self.project.db, name.end()),
         )))
     }
+
+    /// Extracts the expression between start and end into a variable that is assigned before the
+    /// statement of the expression.
+    pub fn extract_variable(
+        &self,
+        start: InputPosition,
+        end: InputPosition,
+        new_name: &str,
+    ) -> anyhow::Result<Vec<TextEdit<'project>>> {
+        let (db, file, start, end) = self.file_and_byte_range(start, end)?;
+        refactoring::extract_variable(db, file, start, end, new_name)
+    }
+
+    /// Extracts the expression or the statements between start and end into a new function.
+    pub fn extract_function(
+        &self,
+        start: InputPosition,
+        end: InputPosition,
+        new_name: &str,
+    ) -> anyhow::Result<Vec<TextEdit<'project>>> {
+        let (db, file, start, end) = self.file_and_byte_range(start, end)?;
+        refactoring::extract_function(db, file, start, end, new_name)
+    }
+
+    /// Checks cheaply whether `extract_function` might succeed, e.g. to decide if the refactoring
+    /// is offered at all. Errors contain the reason why it is not possible.
+    pub fn check_extract_function(
+        &self,
+        start: InputPosition,
+        end: InputPosition,
+    ) -> anyhow::Result<()> {
+        let (_, file, start, end) = self.file_and_byte_range(start, end)?;
+        refactoring::check_extract_function(file, start, end)
+    }
+
+    /// Replaces all usages of the variable under the cursor with its value and removes the
+    /// assignment.
+    pub fn inline(&self, position: InputPosition) -> anyhow::Result<Vec<TextEdit<'project>>> {
+        refactoring::inline(self.positional_document(position)?)
+    }
+
+    /// Checks cheaply whether `inline` might succeed, without searching for the usages.
+    pub fn check_inline(&self, position: InputPosition) -> anyhow::Result<()> {
+        refactoring::check_inline(self.positional_document(position)?)
+    }
+
+    /// Returns the ranges of the syntax nodes around the position, from the innermost to the
+    /// outermost.
+    pub fn selection_ranges(&self, position: InputPosition) -> anyhow::Result<Vec<Range<'_>>> {
+        let db = &self.project.db;
+        let file = db.loaded_python_file(self.file_index);
+        let position = file.line_column_to_byte(position)?.byte;
+        Ok(file
+            .tree
+            .selection_ranges(position)
+            .into_iter()
+            .map(|(start, end)| {
+                (
+                    file.byte_to_position_infos(db, start),
+                    file.byte_to_position_infos(db, end),
+                )
+            })
+            .collect())
+    }
+
+    pub fn folding_ranges(&self) -> Vec<(Range<'_>, FoldingRangeKind)> {
+        let db = &self.project.db;
+        let file = db.loaded_python_file(self.file_index);
+        file.tree
+            .folding_ranges()
+            .into_iter()
+            .map(|fold| {
+                (
+                    (
+                        file.byte_to_position_infos(db, fold.start),
+                        file.byte_to_position_infos(db, fold.end),
+                    ),
+                    fold.kind,
+                )
+            })
+            .collect()
+    }
+
+    /// Sorts and merges the module level imports and removes the unused ones.
+    pub fn organize_imports(&self) -> Vec<TextEdit<'project>> {
+        let db = &self.project.db;
+        organize_imports::organize_imports(db, db.loaded_python_file(self.file_index))
+    }
+
+    /// Checks cheaply whether there are module level imports that `organize_imports` might
+    /// change.
+    pub fn check_organize_imports(&self) -> anyhow::Result<()> {
+        let db = &self.project.db;
+        organize_imports::check_organize_imports(db.loaded_python_file(self.file_index))
+    }
+
+    /// Adds the missing annotations of the function under the cursor, inferred from its body and
+    /// from the arguments at its call sites.
+    pub fn annotate_function(
+        &self,
+        position: InputPosition,
+    ) -> anyhow::Result<Vec<TextEdit<'project>>> {
+        annotate::annotate_function(self.positional_document(position)?)
+    }
+
+    /// Checks whether the function under the cursor misses annotations, without inferring them.
+    pub fn check_annotate_function(&self, position: InputPosition) -> anyhow::Result<()> {
+        annotate::check_annotate_function(self.positional_document(position)?)
+    }
+
+    /// Adds the missing annotations of all functions in the file.
+    pub fn annotate_file(&self) -> Vec<TextEdit<'project>> {
+        let db = &self.project.db;
+        annotate::annotate_file(db, db.loaded_python_file(self.file_index))
+    }
+
+    /// Returns the ranges of the class and function names that should have a lens.
+    pub fn code_lenses(&self) -> Vec<(Range<'_>, CodeLensKind)> {
+        let db = &self.project.db;
+        let file = db.loaded_python_file(self.file_index);
+        code_lens::code_lenses(db, file)
+            .into_iter()
+            .map(|(name, kind)| {
+                (
+                    (
+                        file.byte_to_position_infos(db, name.start()),
+                        file.byte_to_position_infos(db, name.end()),
+                    ),
+                    kind,
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the subclasses of a class or the methods in subclasses overriding a method.
+    pub fn implementations<T>(
+        &self,
+        position: InputPosition,
+        on_name: impl for<'a> Fn(Name) -> T,
+    ) -> anyhow::Result<Vec<T>> {
+        Ok(code_lens::implementations(
+            self.positional_document(position)?,
+            on_name,
+        ))
+    }
+
+    /// Returns the method of a base class that is overridden by the method under the cursor.
+    pub fn overridden_method<T>(
+        &self,
+        position: InputPosition,
+        on_name: impl for<'a> Fn(Name) -> T,
+    ) -> anyhow::Result<Option<T>> {
+        Ok(code_lens::overridden_method_at(
+            self.positional_document(position)?,
+            on_name,
+        ))
+    }
+
+    fn file_and_byte_range(
+        &self,
+        start: InputPosition,
+        end: InputPosition,
+    ) -> anyhow::Result<(
+        &'project Database,
+        &'project PythonFile,
+        CodeIndex,
+        CodeIndex,
+    )> {
+        let db = &self.project.db;
+        let file = db.loaded_python_file(self.file_index);
+        let start = file.line_column_to_byte(start)?.byte;
+        let end = file.line_column_to_byte(end)?.byte;
+        Ok((db, file, start, end))
+    }
 }
 
 pub struct DocumentationResult<'a> {
@@ -507,6 +785,15 @@ pub enum InputPosition {
     CodePoints { line: usize, column: usize },
 }
 
+impl InputPosition {
+    /// Returns the byte offset in the code, columns that are out of bounds are moved to the end
+    /// of the line.
+    pub fn to_byte_offset(self, code: &str) -> anyhow::Result<usize> {
+        let infos = lines::NewlineIndices::new().line_column_to_byte(code, self)?;
+        Ok(infos.byte as usize)
+    }
+}
+
 /*
 impl<'a> Script<'a> {
     fn leaf(&self, position: Position) -> Leaf {
@@ -521,27 +808,6 @@ impl<'a> Script<'a> {
     pub fn complete_search(&self, _text: String, _all_scopes: bool, _fuzzy: bool) {}
     pub fn signatures(&self, _position: Position) {}
     pub fn context(&self, _position: Position) {}
-
-    pub fn extract_variable(
-        &self,
-        _position: Position,
-        _new_name: &str,
-        _until_line: Option<usize>,
-        _until_column: Option<usize>,
-    ) {
-    }
-    pub fn extract_function(
-        &self,
-        _position: Position,
-        _new_name: &str,
-        _until_line: Option<usize>,
-        _until_column: Option<usize>,
-    ) {
-    }
-    pub fn inline(&self, _position: Position) {}
-
-    pub fn selection_ranges() {
-    }
 }
 */
 
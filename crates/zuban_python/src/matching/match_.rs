@@ -3,7 +3,7 @@ use std::{
     sync::Arc,
 };
 
-use crate::type_::{Type, TypeVar};
+use crate::type_::{NativeIntKind, Type, TypeVar};
 
 #[derive(Debug)]
 pub(crate) struct ArgumentIndexWithParam {
@@ -65,6 +65,10 @@ pub(crate) enum MismatchReason {
     },
     SequenceInsteadOfListNeeded,
     MappingInsteadOfDictNeeded,
+    NativeIntOutOfRange {
+        value: i64,
+        kind: NativeIntKind,
+    },
 }
 
 impl Match {
@@ -287,6 +287,11 @@ impl ErrorTypes<'_> {
                     add_issue(IssueKind::Note(note.clone()));
                 }
             }
+            MismatchReason::NativeIntOutOfRange { value, kind } => {
+                add_issue(IssueKind::Note(
+                    format!("Value {value} is out of range for \"{}\"", kind.name()).into(),
+                ));
+            }
             _ => (),
         }
     }
@@ -7,11 +7,12 @@ use crate::{
     PositionInfos,
     completion::ScopesIterator,
     database::{Database, ParentScope},
+    docstring::docstring_to_markdown,
     file::{ClassNodeRef, File, FuncNodeRef, PythonFile},
     format_data::FormatData,
     inference_state::InferenceState,
     node_ref::NodeRef,
-    type_::{LookupResult, Type},
+    type_::{CallableParams, LookupResult, ParamType, StarParamType, StarStarParamType, Type},
     type_helpers::Class,
 };
 
@@ -42,6 +43,34 @@ impl ValueName<'_, '_> {
         }
     }
 
+    /// The docstring rendered as Markdown. Params without a documented type use the types of
+    /// the signature.
+    pub fn documentation_markdown(&self) -> String {
+        let db = self.name.db();
+        let mut param_types = vec![];
+        if let Type::Callable(c) = self.type_
+            && let CallableParams::Simple(params) = &c.params
+        {
+            let format_data = FormatData::new_short(db);
+            for param in params.iter() {
+                let t = match &param.type_ {
+                    ParamType::PositionalOnly(t)
+                    | ParamType::PositionalOrKeyword(t)
+                    | ParamType::KeywordOnly(t)
+                    | ParamType::Star(StarParamType::ArbitraryLen(t))
+                    | ParamType::StarStar(StarStarParamType::ValueType(t)) => t,
+                    _ => continue,
+                };
+                if let Some(name) = &param.name
+                    && !matches!(t, Type::Any(_))
+                {
+                    param_types.push((name.as_str(db), t.format(&format_data)));
+                }
+            }
+        }
+        docstring_to_markdown(&self.name.documentation(), &param_types)
+    }
+
     /// This is mostly for testing, you should probably not use this.
     pub fn is_instance(&self) -> bool {
         !matches!(
@@ -0,0 +1,388 @@
+//! Sorts, merges and removes unused module level imports.
+//!
+//! Only imports that are the sole statement on their lines are touched. Consecutive imports form
+//! a block that is rewritten as a whole. Comments between imports therefore separate blocks and
+//! imports with trailing comments are left alone, so that no comment gets lost or moved.
+
+use std::fmt::Write as _;
+
+use parsa_python_cst::{
+    CodeIndex, DottedAsNameContent, ImportFrom, ImportFromTargets, ImportName, NameDef, NodeIndex,
+    StmtLikeContent,
+};
+use utils::FastHashSet;
+use vfs::{DirectoryEntry, WorkspaceKind};
+
+use crate::{
+    database::{Database, PointKind},
+    file::{File, PythonFile},
+    file_moves::TextEdit,
+    node_ref::NodeRef,
+};
+
+const MAX_LINE_LENGTH: usize = 88;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Section {
+    Future,
+    StandardLibrary,
+    ThirdParty,
+    FirstParty,
+    Local,
+}
+
+#[derive(Clone, Copy)]
+enum Import<'db> {
+    Name(ImportName<'db>),
+    From(ImportFrom<'db>),
+}
+
+impl Import<'_> {
+    fn start(&self) -> CodeIndex {
+        match self {
+            Self::Name(import) => import.start(),
+            Self::From(import) => import.start(),
+        }
+    }
+
+    fn end(&self) -> CodeIndex {
+        match self {
+            Self::Name(import) => import.end(),
+            Self::From(import) => import.end(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct FromImport {
+    names: Vec<(String, Option<String>)>,
+    has_star: bool,
+}
+
+pub(crate) fn organize_imports<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+) -> Vec<TextEdit<'db>> {
+    let usages = Usages::new(db, file);
+    let code = file.tree.code();
+    let mut edits = vec![];
+    for block in import_blocks(file) {
+        let start = block.first().unwrap().start();
+        let mut end = block.last().unwrap().end();
+        let new_text = organize_block(db, file, &usages, &block);
+        if new_text.is_empty() {
+            // Remove the whole lines
+            let rest = &code[end as usize..];
+            end += (rest.len() - rest.trim_start_matches([' ', '\t', '\r']).len()) as CodeIndex;
+            if code[end as usize..].starts_with('\n') {
+                end += 1;
+            }
+        } else if new_text == code[start as usize..end as usize] {
+            continue;
+        }
+        edits.push(TextEdit {
+            range: (
+                file.byte_to_position_infos(db, start),
+                file.byte_to_position_infos(db, end),
+            ),
+            new_text,
+        });
+    }
+    edits
+}
+
+/// Checks cheaply whether there are imports that might be organized.
+pub(crate) fn check_organize_imports(file: &PythonFile) -> anyhow::Result<()> {
+    if import_blocks(file).is_empty() {
+        anyhow::bail!("There are no imports to organize");
+    }
+    Ok(())
+}
+
+/// Returns the blocks of consecutive module level imports.
+fn import_blocks(file: &PythonFile) -> Vec<Vec<Import<'_>>> {
+    let code = file.tree.code();
+    let is_on_own_line = |import: &Import| {
+        let before = &code[..import.start() as usize];
+        let after = &code[import.end() as usize..];
+        before[before.rfind('\n').map(|i| i + 1).unwrap_or(0)..]
+            .trim()
+            .is_empty()
+            && after[..after.find('\n').unwrap_or(after.len())]
+                .trim()
+                .is_empty()
+    };
+    let mut blocks = vec![];
+    let mut current: Vec<Import> = vec![];
+    for stmt in file.tree.root().iter_stmt_likes() {
+        let import = match stmt.node {
+            StmtLikeContent::ImportName(import) => Import::Name(import),
+            StmtLikeContent::ImportFrom(import) => Import::From(import),
+            _ => {
+                blocks.extend((!current.is_empty()).then(|| std::mem::take(&mut current)));
+                continue;
+            }
+        };
+        let separated_by_comment = current.last().is_some_and(|last| {
+            !code[last.end() as usize..import.start() as usize]
+                .trim()
+                .is_empty()
+        });
+        if separated_by_comment || !is_on_own_line(&import) {
+            blocks.extend((!current.is_empty()).then(|| std::mem::take(&mut current)));
+        }
+        if is_on_own_line(&import) {
+            current.push(import)
+        }
+    }
+    blocks.extend((!current.is_empty()).then_some(current));
+    blocks
+}
+
+fn organize_block(db: &Database, file: &PythonFile, usages: &Usages, block: &[Import]) -> String {
+    let mut straight_imports: Vec<(String, Option<String>)> = vec![];
+    let mut from_imports: Vec<(String, FromImport)> = vec![];
+    for import in block {
+        match import {
+            Import::Name(import) => {
+                for dotted_as_name in import.iter_dotted_as_names() {
+                    let entry = match dotted_as_name.unpack() {
+                        DottedAsNameContent::Simple(name_def, rest) => {
+                            if !usages.is_used(file, name_def, false) {
+                                continue;
+                            }
+                            let mut module = name_def.as_code().to_owned();
+                            if let Some(rest) = rest {
+                                module.push('.');
+                                module += &without_whitespace(rest.as_code());
+                            }
+                            (module, None)
+                        }
+                        DottedAsNameContent::WithAs(dotted, name_def) => {
+                            let module = without_whitespace(dotted.as_code());
+                            let is_reexport = module == name_def.as_code();
+                            if !usages.is_used(file, name_def, is_reexport) {
+                                continue;
+                            }
+                            (module, Some(name_def.as_code().to_owned()))
+                        }
+                    };
+                    if !straight_imports.contains(&entry) {
+                        straight_imports.push(entry)
+                    }
+                }
+            }
+            Import::From(import) => {
+                let (level, dotted) = import.level_with_dotted_name();
+                let mut module = ".".repeat(level);
+                if let Some(dotted) = dotted {
+                    module += &without_whitespace(dotted.as_code());
+                }
+                let is_future = module == "__future__";
+                let position = match from_imports.iter().position(|(m, _)| *m == module) {
+                    Some(position) => position,
+                    None => {
+                        from_imports.push((module, FromImport::default()));
+                        from_imports.len() - 1
+                    }
+                };
+                let from_import = &mut from_imports[position].1;
+                match import.unpack_targets() {
+                    ImportFromTargets::Star(_) => from_import.has_star = true,
+                    ImportFromTargets::Iterator(targets) => {
+                        for target in targets {
+                            let (name, name_def) = target.unpack();
+                            let is_reexport = name.index() != name_def.name_index()
+                                && name.as_str() == name_def.as_code();
+                            if !is_future && !usages.is_used(file, name_def, is_reexport) {
+                                continue;
+                            }
+                            let entry = (
+                                name.as_str().to_owned(),
+                                (name.index() != name_def.name_index())
+                                    .then(|| name_def.as_code().to_owned()),
+                            );
+                            if !from_import.names.contains(&entry) {
+                                from_import.names.push(entry)
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut lines: Vec<(Section, bool, String, String)> = vec![];
+    for (module, alias) in straight_imports {
+        let line = match &alias {
+            Some(alias) => format!("import {module} as {alias}"),
+            None => format!("import {module}"),
+        };
+        lines.push((section(db, &module), false, module.to_lowercase(), line));
+    }
+    for (module, mut from_import) in from_imports {
+        let section = section(db, &module);
+        let key = module.to_lowercase();
+        if from_import.has_star {
+            lines.push((
+                section,
+                true,
+                key.clone(),
+                format!("from {module} import *"),
+            ));
+        }
+        if from_import.names.is_empty() {
+            continue;
+        }
+        from_import.names.sort_by_cached_key(|(name, alias)| {
+            (name_kind(name), name.to_lowercase(), alias.clone())
+        });
+        let names: Vec<String> = from_import
+            .names
+            .into_iter()
+            .map(|(name, alias)| match alias {
+                Some(alias) => format!("{name} as {alias}"),
+                None => name,
+            })
+            .collect();
+        let mut line = format!("from {module} import {}", names.join(", "));
+        if line.len() > MAX_LINE_LENGTH {
+            line = format!("from {module} import (\n");
+            for name in names {
+                writeln!(line, "    {name},").unwrap();
+            }
+            line.push(')');
+        }
+        lines.push((section, true, key, line));
+    }
+    lines.sort();
+
+    let mut result = String::new();
+    let mut previous_section = None;
+    for (section, _, _, line) in lines {
+        if previous_section.is_some_and(|previous| previous != section) {
+            result.push('\n');
+        }
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        result += &line;
+        previous_section = Some(section);
+    }
+    result
+}
+
+/// Sorts constants before classes before everything else, like isort does.
+fn name_kind(name: &str) -> u8 {
+    if name.len() > 1 && !name.chars().any(|c| c.is_lowercase()) {
+        0
+    } else if name.starts_with(|c: char| c.is_uppercase()) {
+        1
+    } else {
+        2
+    }
+}
+
+fn without_whitespace(code: &str) -> String {
+    code.split_whitespace().collect()
+}
+
+/// Decides the section by the workspace where the top level module is found, i.e. typeshed's
+/// stdlib, the site packages of the sys path or the type checked workspaces.
+fn section(db: &Database, module: &str) -> Section {
+    if module == "__future__" {
+        return Section::Future;
+    }
+    if module.starts_with('.') {
+        return Section::Local;
+    }
+    let top_level = module.split('.').next().unwrap();
+    let candidates = [
+        top_level.to_owned(),
+        format!("{top_level}.py"),
+        format!("{top_level}.pyi"),
+        format!("{top_level}-stubs"),
+    ];
+    for workspace in db.vfs.workspaces.iter() {
+        if candidates.iter().any(|candidate| {
+            // Missing entries are only remembered to invalidate failed imports
+            workspace
+                .entries
+                .search(candidate)
+                .is_some_and(|entry| !matches!(*entry, DirectoryEntry::MissingEntry(_)))
+        }) {
+            return match workspace.kind {
+                WorkspaceKind::Typeshed => Section::StandardLibrary,
+                WorkspaceKind::SitePackages => Section::ThirdParty,
+                WorkspaceKind::TypeChecking | WorkspaceKind::Fallback => Section::FirstParty,
+            };
+        }
+    }
+    Section::ThirdParty
+}
+
+struct Usages {
+    /// The definitions the name binder resolved references to
+    referenced_definitions: FastHashSet<NodeIndex>,
+    /// Where names start, to find out which occurrences of a name are in strings and comments
+    name_starts: FastHashSet<CodeIndex>,
+    /// Imports in stubs and `__init__.py` files are usually there to be re-exported
+    keeps_all_imports: bool,
+    /// The names in `__all__`, which are exported
+    dunder_all: FastHashSet<String>,
+}
+
+impl Usages {
+    fn new(db: &Database, file: &PythonFile) -> Self {
+        let mut referenced_definitions = FastHashSet::default();
+        let mut name_starts = FastHashSet::default();
+        for name in file.tree.filter_all_names() {
+            name_starts.insert(name.start());
+            if !name.is_reference() {
+                continue;
+            }
+            let point = NodeRef::new(file, name.index()).point();
+            if point.calculated()
+                && point.kind() == PointKind::Redirect
+                && point.file_index() == file.file_index
+            {
+                referenced_definitions.insert(point.node_index());
+            }
+        }
+        Self {
+            referenced_definitions,
+            name_starts,
+            keeps_all_imports: file.is_stub() || file.file_entry_and_is_package(db).1,
+            dunder_all: file
+                .maybe_dunder_all(db)
+                .unwrap_or_default()
+                .iter()
+                .map(|name| name.as_str(db).to_owned())
+                .collect(),
+        }
+    }
+
+    /// Imports in stubs and `__init__.py` files, names in `__all__` and explicit re-exports like
+    /// `import foo as foo` are always used. Names in strings (e.g. string annotations) and
+    /// comments (e.g. type comments) are not resolved by the name binder and count as usages as
+    /// well.
+    fn is_used(&self, file: &PythonFile, name_def: NameDef, is_reexport: bool) -> bool {
+        let name = name_def.as_code();
+        if is_reexport || self.keeps_all_imports || self.dunder_all.contains(name) {
+            return true;
+        }
+        if file
+            .lookup_symbol(name)
+            .is_some_and(|symbol| self.referenced_definitions.contains(&symbol.node_index))
+        {
+            return true;
+        }
+        let code = file.tree.code();
+        let is_identifier_char = |c: char| c.is_alphanumeric() || c == '_';
+        code.match_indices(name).any(|(position, _)| {
+            !code[..position].ends_with(is_identifier_char)
+                && !code[position + name.len()..].starts_with(is_identifier_char)
+                && !self.name_starts.contains(&(position as CodeIndex))
+        })
+    }
+}
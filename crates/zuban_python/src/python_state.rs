@@ -14,7 +14,8 @@ use crate::{
     node_ref::NodeRef,
     type_::{
         AnyCause, CallableContent, CallableParam, CallableParams, ClassGenerics, CustomBehavior,
-        NeverCause, ParamType, Tuple, Type, TypeArgs, TypeVarLikes, dataclasses_replace,
+        NativeIntKind, NeverCause, NewType, ParamType, Tuple, Type, TypeArgs, TypeVarLikes,
+        dataclasses_replace,
     },
     type_helpers::{Class, FirstParamProperties, Function, Instance, cache_class_name},
 };
@@ -218,6 +219,7 @@ pub(crate) struct PythonState {
     abc_abstractproperty_index: NodeIndex,
     functools_cached_property_index: NodeIndex,
     functools_total_ordering_index: NodeIndex,
+    functools_partial_index: NodeIndex,
     enum_enum_meta_index: NodeIndex,
     enum_enum_index: NodeIndex,
     enum_auto_index: NodeIndex,
@@ -346,6 +348,7 @@ impl PythonState {
             abc_abstractproperty_index: 0,
             functools_cached_property_index: 0,
             functools_total_ordering_index: 0,
+            functools_partial_index: 0,
             enum_enum_meta_index: 0,
             enum_enum_index: 0,
             enum_auto_index: 0,
@@ -715,6 +718,7 @@ impl PythonState {
             functools,
             "cached_property"
         );
+        cache_index!(functools_partial_index, functools, "partial");
         cache_index!(dataclasses_capital_field_index, dataclasses_file, "Field");
 
         cache_index!(builtins_isinstance_index, builtins, "isinstance", true);
@@ -858,6 +862,27 @@ impl PythonState {
                 .use_cached_class_infos(db)
                 .set_promote_to(Some(s.bytes_node_ref().as_link()));
         }
+
+        // Native ints like i64 are aliases of int in typeshed, but mypy treats them as separate
+        // types.
+        let int_type = s.int_type();
+        let mypy_extensions_index = s.mypy_extensions().file_index;
+        let mypy_extensions = db.vfs.files[mypy_extensions_index.0 as usize]
+            .file_mut()
+            .unwrap();
+        for kind in NativeIntKind::ALL {
+            if let Some(node_index) = mypy_extensions.symbol_table.lookup_symbol(kind.name()) {
+                let new_type = NewType::new_native_int(
+                    PointLink::new(mypy_extensions_index, node_index),
+                    kind,
+                    int_type.clone(),
+                );
+                mypy_extensions.insert_file_local_type(
+                    node_index,
+                    Type::Type(Arc::new(Type::NewType(Arc::new(new_type)))),
+                );
+            }
+        }
     }
 
     #[inline]
@@ -1027,6 +1052,7 @@ impl PythonState {
         collections_namedtuple_index
     );
     class_node_ref!(_collections_abc, pub _collections_abc_dict_keys_node_ref, _collections_abc_dict_keys_index);
+    class_node_ref!(functools, pub functools_partial_node_ref, functools_partial_index);
     attribute_node_ref!(functools, pub total_ordering_node_ref, functools_total_ordering_index);
     attribute_node_ref!(typing, pub runtime_checkable_node_ref, typing_runtime_checkable_index);
     attribute_node_ref!(typing_extensions, pub typing_extensions_runtime_checkable_node_ref, typing_extensions_runtime_checkable_index);
@@ -1397,6 +1423,9 @@ fn calculate_mro_for_class(db: &Database, class: Class) -> Box<[BaseClass]> {
     mro.into_boxed_slice()
 }
 
+/// Typeshed is shared by all environments of a project, so the special definitions in it are
+/// cached with the Python version of the project and not with the ones of nested configs or
+/// workspace folders with their own environment.
 fn legacy_new_type(db: &Database) -> bool {
     db.project.settings.python_version_or_default() < PythonVersion::new(3, 10)
 }
@@ -0,0 +1,886 @@
+/*
+ * Refactorings that rewrite code within a single file: Extracting variables and functions and
+ * inlining variables.
+ * */
+
+use anyhow::bail;
+use parsa_python_cst::{
+    Assignment, CodeIndex, ControlFlow, Expression, ExpressionContent, ExpressionPart, GotoNode,
+    ImportFromTargets, Name as CSTName, NameDef, Scope, SelectedExpression,
+    SelectedExpressionContent, SelectedStatements, StandaloneStatement, StmtLikeContent,
+    VariableUsage,
+};
+use utils::FastHashSet;
+
+use crate::{
+    GotoGoal, InputPosition, ReferencesGoal,
+    database::{Database, PointLink, Specific},
+    file::{ClassNodeRef, File, PythonFile},
+    file_moves::TextEdit,
+    goto::{GotoResolver, PositionalDocument, ReferencesResolver, with_i_s_non_self},
+    inference_state::InferenceState,
+    name::Name,
+    type_::{ClassGenerics, Type},
+    utils::is_identifier,
+};
+
+struct Edits<'db> {
+    db: &'db Database,
+    file: &'db PythonFile,
+    edits: Vec<TextEdit<'db>>,
+}
+
+impl<'db> Edits<'db> {
+    fn new(db: &'db Database, file: &'db PythonFile) -> Self {
+        Self {
+            db,
+            file,
+            edits: vec![],
+        }
+    }
+
+    fn add(&mut self, start: CodeIndex, end: CodeIndex, new_text: String) {
+        self.edits.push(TextEdit {
+            range: (
+                self.file.byte_to_position_infos(self.db, start),
+                self.file.byte_to_position_infos(self.db, end),
+            ),
+            new_text,
+        })
+    }
+
+    /// Inserts code before a replacement. Both are merged if they start at the same position,
+    /// because the order of edits at the same position is not well defined.
+    fn insert_and_replace(
+        &mut self,
+        insert_at: CodeIndex,
+        insertion: String,
+        start: CodeIndex,
+        end: CodeIndex,
+        replacement: String,
+    ) {
+        if insert_at == start {
+            self.add(start, end, insertion + &replacement)
+        } else {
+            self.add(insert_at, insert_at, insertion);
+            self.add(start, end, replacement)
+        }
+    }
+}
+
+pub(crate) fn extract_variable<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    start: CodeIndex,
+    end: CodeIndex,
+    new_name: &str,
+) -> anyhow::Result<Vec<TextEdit<'db>>> {
+    let code = file.tree.code();
+    let Some(expr) = file.tree.expression_in_range(start, end) else {
+        bail!("The selection is not an expression");
+    };
+    if expr.is_expression_statement() {
+        bail!("Cannot extract a variable from an expression statement");
+    }
+    let Some(statement_start) = expr.statement_start() else {
+        bail!("Cannot extract a variable from this expression, it might not always be evaluated");
+    };
+    let new_name = unique_name(code, new_name)?;
+    let mut expr_code = expr.as_code().to_string();
+    if expr_code.contains('\n') && !expr.is_atomic() {
+        expr_code = format!("({expr_code})");
+    }
+    let before_statement = &code[line_start(code, statement_start)..statement_start as usize];
+    let assignment = if before_statement.trim().is_empty() {
+        format!("{new_name} = {expr_code}\n{before_statement}")
+    } else {
+        // The statement is not at the start of the line, e.g. in `if x: foo(1)`
+        format!("{new_name} = {expr_code}; ")
+    };
+    let mut edits = Edits::new(db, file);
+    edits.insert_and_replace(
+        statement_start,
+        assignment,
+        expr.start(),
+        expr.end(),
+        new_name,
+    );
+    Ok(edits.edits)
+}
+
+pub(crate) fn extract_function<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    start: CodeIndex,
+    end: CodeIndex,
+    new_name: &str,
+) -> anyhow::Result<Vec<TextEdit<'db>>> {
+    let tree = &file.tree;
+    let code = tree.code();
+    let new_name = unique_name(code, new_name)?;
+    let target = extraction_target(file, start, end)?;
+    // The types are only available after the file was checked
+    file.ensure_calculated_diagnostics(db).ok();
+    let mut writer = AnnotationWriter::new(db, file);
+
+    let extraction = match target {
+        ExtractionTarget::Expression(expr, scope, usages) => {
+            extract_expression(db, file, &mut writer, expr, scope, &usages)
+        }
+        ExtractionTarget::Statements(statements, scope, flow) => {
+            let usages = tree.variable_usages(scope, statements.start(), statements.end());
+            let returns = returned_names(file, scope, statements.end(), &usages);
+
+            let before_statements =
+                &code[line_start(code, statements.start())..statements.start() as usize];
+            let mut body = String::new();
+            for line in code[statements.start() as usize..statements.end() as usize].split('\n') {
+                let line = line.strip_prefix(before_statements).unwrap_or(line);
+                if !line.trim().is_empty() {
+                    body += "    ";
+                    body += line;
+                }
+                body.push('\n');
+            }
+            let return_annotation = if flow.has_yield {
+                None
+            } else if flow.has_return {
+                match scope {
+                    Scope::Function(f) => f
+                        .return_annotation()
+                        .map(|annotation| annotation.expression().as_code().to_string()),
+                    _ => None,
+                }
+            } else if returns.is_empty() {
+                Some("None".to_string())
+            } else {
+                let types: Option<Vec<_>> = returns
+                    .iter()
+                    .map(|last_definition| name_annotation(db, file, &mut writer, *last_definition))
+                    .collect();
+                types.map(|types| match types.as_slice() {
+                    [t] => t.clone(),
+                    _ => format!("tuple[{}]", types.join(", ")),
+                })
+            };
+            if !returns.is_empty() {
+                body += "    return ";
+                body += &join_names(&returns);
+                body.push('\n');
+            }
+            Extraction {
+                start: statements.start(),
+                end: statements.end(),
+                top_level_statement_start: statements.top_level_statement_start(),
+                params: params_for_usages(db, file, &mut writer, scope, &usages),
+                body,
+                return_annotation,
+                returns,
+                is_async: flow.has_await,
+                is_generator: flow.has_yield,
+                is_tail: flow.has_return,
+            }
+        }
+    };
+
+    let params = extraction
+        .params
+        .iter()
+        .map(|(name, t)| match t {
+            Some(t) => format!("{name}: {t}"),
+            None => name.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let return_annotation = match &extraction.return_annotation {
+        Some(t) => format!(" -> {t}"),
+        None => String::new(),
+    };
+    let async_ = if extraction.is_async { "async " } else { "" };
+    let function = format!(
+        "{async_}def {new_name}({params}){return_annotation}:\n{}\n\n",
+        extraction.body
+    );
+
+    let args = extraction
+        .params
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut call = format!("{new_name}({args})");
+    if extraction.is_async {
+        call = format!("await {call}");
+    }
+    if extraction.is_generator {
+        call = format!("yield from {call}");
+        if extraction.is_tail {
+            call = format!("({call})");
+        }
+    }
+    if extraction.is_tail {
+        call = format!("return {call}");
+    } else if !extraction.returns.is_empty() {
+        call = format!("{} = {call}", join_names(&extraction.returns));
+    }
+
+    let mut edits = Edits::new(db, file);
+    let mut function = function;
+    for (position, imports) in writer.import_insertions() {
+        if position == extraction.top_level_statement_start {
+            function = imports + &function;
+        } else {
+            edits.add(position, position, imports)
+        }
+    }
+    edits.insert_and_replace(
+        extraction.top_level_statement_start,
+        function,
+        extraction.start,
+        extraction.end,
+        call,
+    );
+    Ok(edits.edits)
+}
+
+/// Checks whether the code between start and end can be extracted into a function. Only the
+/// syntax tree is used, which makes it cheap enough to decide if the refactoring is offered.
+pub(crate) fn check_extract_function(
+    file: &PythonFile,
+    start: CodeIndex,
+    end: CodeIndex,
+) -> anyhow::Result<()> {
+    extraction_target(file, start, end).map(|_| ())
+}
+
+enum ExtractionTarget<'db> {
+    Expression(SelectedExpression<'db>, Scope<'db>, Vec<VariableUsage<'db>>),
+    Statements(SelectedStatements<'db>, Scope<'db>, ControlFlow),
+}
+
+fn extraction_target(
+    file: &PythonFile,
+    start: CodeIndex,
+    end: CodeIndex,
+) -> anyhow::Result<ExtractionTarget<'_>> {
+    let tree = &file.tree;
+    if let Some(expr) = tree
+        .expression_in_range(start, end)
+        .filter(|expr| !expr.is_expression_statement())
+    {
+        let scope = check_extraction_scope(expr.scope())?;
+        if expr.contains_yield() {
+            bail!("Cannot extract an expression that contains `yield`");
+        }
+        let usages = tree.variable_usages(scope, expr.start(), expr.end());
+        if usages.iter().any(|usage| usage.is_definition) {
+            bail!("Cannot extract an expression that defines variables");
+        }
+        Ok(ExtractionTarget::Expression(expr, scope, usages))
+    } else if let Some(statements) = tree.statements_in_range(start, end) {
+        let scope = check_extraction_scope(statements.scope())?;
+        let flow = statements.control_flow();
+        if flow.has_loop_control_outside_of_loop {
+            bail!("Cannot extract `break` or `continue` without their loop");
+        }
+        if flow.has_global_or_nonlocal {
+            bail!("Cannot extract `global` or `nonlocal` statements");
+        }
+        if flow.has_yield && flow.has_await {
+            bail!("Cannot extract code that uses both `yield` and `await`");
+        }
+        if flow.has_return && !statements.is_at_end_of_function() {
+            bail!("Cannot extract a `return` that is not at the end of the function");
+        }
+        Ok(ExtractionTarget::Statements(statements, scope, flow))
+    } else {
+        bail!("The selection must be an expression or complete statements");
+    }
+}
+
+struct Extraction<'db> {
+    start: CodeIndex,
+    end: CodeIndex,
+    top_level_statement_start: CodeIndex,
+    params: Vec<(&'db str, Option<String>)>,
+    body: String,
+    return_annotation: Option<String>,
+    // The last definitions of the names that need to be returned
+    returns: Vec<CSTName<'db>>,
+    is_async: bool,
+    is_generator: bool,
+    // The code is at the end of a function and contains returns
+    is_tail: bool,
+}
+
+fn extract_expression<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    writer: &mut AnnotationWriter<'db>,
+    expr: SelectedExpression<'db>,
+    scope: Scope<'db>,
+    usages: &[VariableUsage<'db>],
+) -> Extraction<'db> {
+    let return_type = with_i_s_non_self(db, file, scope, |i_s| {
+        let inference = file.inference(i_s);
+        let inf = match expr.unpack() {
+            SelectedExpressionContent::Expression(expr) => inference.infer_expression(expr),
+            SelectedExpressionContent::ExpressionPart(part) => {
+                inference.infer_expression_part(part)
+            }
+        };
+        inf.avoid_implicit_literal(i_s).as_type(i_s)
+    });
+    let mut expr_code = expr.as_code().to_string();
+    if expr_code.contains('\n') && !expr.is_atomic() {
+        expr_code = format!("({expr_code})");
+    }
+    Extraction {
+        start: expr.start(),
+        end: expr.end(),
+        top_level_statement_start: expr.top_level_statement_start(),
+        params: params_for_usages(db, file, writer, scope, usages),
+        body: format!("    return {expr_code}\n"),
+        return_annotation: writer.annotation(&return_type),
+        returns: vec![],
+        is_async: expr.contains_await(),
+        is_generator: false,
+        is_tail: false,
+    }
+}
+
+fn check_extraction_scope(scope: Scope) -> anyhow::Result<Scope> {
+    match scope {
+        Scope::Module | Scope::Function(_) => Ok(scope),
+        Scope::Class(_) => bail!("Cannot extract a function from a class body"),
+        Scope::Lambda(_) => bail!("Cannot extract a function from a lambda"),
+    }
+}
+
+/// The variables that are read in the extracted code before they are written need to be passed
+/// as params. Module variables can also be accessed as globals, as long as they are not written.
+/// The variables of enclosing functions are not visible in the extracted function either.
+fn params_for_usages<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    writer: &mut AnnotationWriter<'db>,
+    scope: Scope,
+    usages: &[VariableUsage<'db>],
+) -> Vec<(&'db str, Option<String>)> {
+    let mut local_names: FastHashSet<&str> = FastHashSet::default();
+    let mut current = scope;
+    loop {
+        current = match current {
+            Scope::Function(f) => {
+                local_names.extend(
+                    file.tree
+                        .variable_usages(current, f.start(), f.end())
+                        .into_iter()
+                        .filter(|usage| usage.is_definition)
+                        .map(|usage| usage.name.as_code()),
+                );
+                f.parent_scope()
+            }
+            // Class variables are not visible in the functions of the class
+            Scope::Class(c) => c.parent_scope(),
+            Scope::Module | Scope::Lambda(_) => break,
+        }
+    }
+    let defined_in_selection: FastHashSet<&str> = usages
+        .iter()
+        .filter(|usage| usage.is_definition)
+        .map(|usage| usage.name.as_code())
+        .collect();
+    let mut seen = FastHashSet::default();
+    let mut params = vec![];
+    for usage in usages {
+        let name = usage.name.as_code();
+        if seen.insert(name)
+            && !usage.is_definition
+            && (local_names.contains(name) || defined_in_selection.contains(name))
+        {
+            params.push((name, name_annotation(db, file, writer, usage.name)))
+        }
+    }
+    params
+}
+
+/// Returns the last definitions of variables that are defined in the extracted code and used
+/// after it.
+fn returned_names<'db>(
+    file: &'db PythonFile,
+    scope: Scope,
+    end: CodeIndex,
+    usages: &[VariableUsage<'db>],
+) -> Vec<CSTName<'db>> {
+    let scope_end = match scope {
+        Scope::Function(f) => f.end(),
+        _ => file.tree.length() as CodeIndex,
+    };
+    let read_later: FastHashSet<&str> = file
+        .tree
+        .variable_usages(scope, end, scope_end)
+        .into_iter()
+        .filter(|usage| !usage.is_definition)
+        .map(|usage| usage.name.as_code())
+        .collect();
+    let mut returns: Vec<CSTName> = vec![];
+    for usage in usages.iter().filter(|usage| usage.is_definition) {
+        let name = usage.name.as_code();
+        if !read_later.contains(name) {
+            continue;
+        }
+        if let Some(previous) = returns.iter_mut().find(|n| n.as_code() == name) {
+            *previous = usage.name
+        } else {
+            returns.push(usage.name)
+        }
+    }
+    returns
+}
+
+fn join_names(names: &[CSTName]) -> String {
+    names
+        .iter()
+        .map(|name| name.as_code())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn name_annotation<'db>(
+    db: &'db Database,
+    file: &'db PythonFile,
+    writer: &mut AnnotationWriter<'db>,
+    name: CSTName,
+) -> Option<String> {
+    let t = if let Some(t) = file.narrowed_name_type(name.index()) {
+        t.avoid_implicit_literal(db)
+    } else {
+        let document = PositionalDocument::for_goto(
+            db,
+            file,
+            InputPosition::NthUTF8Byte(name.start() as usize),
+        )
+        .ok()?;
+        let inf = document.infer_position()?;
+        document.with_i_s(|i_s| inf.avoid_implicit_literal(i_s).as_type(i_s))
+    };
+    writer.annotation(&t)
+}
+
+pub(crate) fn inline<'db>(
+    document: PositionalDocument<'db, GotoNode<'db>>,
+) -> anyhow::Result<Vec<TextEdit<'db>>> {
+    let db = document.db;
+    let file = document.file;
+    let Some(on_name) = document.node.on_name() else {
+        bail!("Could not find a variable under the cursor to inline");
+    };
+    let references = ReferencesResolver::new(document, |name| match name {
+        Name::TreeName(tree_name) if tree_name.file.file_index == file.file_index => {
+            Some(tree_name.cst_name)
+        }
+        _ => None,
+    })
+    .references(ReferencesGoal::OnlyTypeCheckedWorkspaces, true);
+    let Some(mut references) = references.into_iter().collect::<Option<Vec<_>>>() else {
+        bail!(
+            "Cannot inline {:?}, it is used in other files",
+            on_name.as_code()
+        );
+    };
+    references.sort_by_key(|name| name.start());
+    references.dedup_by_key(|name| name.start());
+
+    let (definitions, reads): (Vec<_>, Vec<_>) = references
+        .into_iter()
+        .partition(|name| name.name_def().is_some());
+    let [definition] = definitions.as_slice() else {
+        bail!(
+            "Cannot inline {:?}, it needs to be assigned exactly once",
+            on_name.as_code()
+        );
+    };
+    let (assignment, name_def, expr, statement) = inlinable_assignment(*definition)?;
+    if reads.is_empty() {
+        bail!("Cannot inline {:?}, it is never used", name_def.as_code());
+    }
+    if reads
+        .iter()
+        .any(|name| name.start() >= statement.start && name.start() < statement.end)
+    {
+        bail!(
+            "Cannot inline {:?}, it is used in its own definition",
+            name_def.as_code()
+        );
+    }
+    if reads.len() > 1 && expr.may_have_side_effects() {
+        bail!(
+            "Cannot inline {:?}, it is used more than once and its value might have side effects",
+            name_def.as_code()
+        );
+    }
+    // The inlined expression needs to refer to the same variables at every use
+    let scope = definition.parent_scope();
+    let tree = &file.tree;
+    let free_variables: Vec<_> = tree
+        .variable_usages(scope, expr.start(), expr.end())
+        .into_iter()
+        .filter(|usage| !usage.is_definition)
+        .map(|usage| usage.name.as_code())
+        .collect();
+    let last_read = reads.last().unwrap().start();
+    if let Some(reassigned) = tree
+        .variable_usages(scope, statement.end, last_read)
+        .into_iter()
+        .find(|usage| usage.is_definition && free_variables.contains(&usage.name.as_code()))
+    {
+        bail!(
+            "Cannot inline {:?}, {:?} is assigned again before it is used",
+            name_def.as_code(),
+            reassigned.name.as_code()
+        );
+    }
+
+    let is_atomic = matches!(
+        expr.unpack(),
+        ExpressionContent::ExpressionPart(ExpressionPart::Atom(_) | ExpressionPart::Primary(_))
+    );
+    let mut edits = Edits::new(db, file);
+    if statement.is_only_statement_in_block {
+        edits.add(assignment.start(), assignment.end(), "pass".to_string());
+    } else {
+        let code = file.tree.code();
+        edits.add(
+            line_start(code, statement.start) as CodeIndex,
+            statement.end,
+            String::new(),
+        );
+    }
+    for name in reads {
+        let new_text = if is_atomic || name.is_complete_expression() {
+            expr.as_code().to_string()
+        } else {
+            format!("({})", expr.as_code())
+        };
+        edits.add(name.start(), name.end(), new_text);
+    }
+    Ok(edits.edits)
+}
+
+/// Checks whether the name under the cursor could be inlined. Unlike `inline`, this doesn't
+/// search for references and is therefore cheap enough to decide if the refactoring is offered.
+pub(crate) fn check_inline(document: PositionalDocument<'_, GotoNode<'_>>) -> anyhow::Result<()> {
+    let file = document.file;
+    let Some(on_name) = document.node.on_name() else {
+        bail!("Could not find a variable under the cursor to inline");
+    };
+    let definition = if on_name.name_def().is_some() {
+        on_name
+    } else {
+        let definitions =
+            GotoResolver::new(document, GotoGoal::Indifferent, |name: Name| match name {
+                Name::TreeName(tree_name) if tree_name.file.file_index == file.file_index => {
+                    Some(tree_name.cst_name.index())
+                }
+                _ => None,
+            })
+            .goto(false);
+        let [Some(definition)] = definitions.as_slice() else {
+            bail!(
+                "Cannot inline {:?}, it needs to be assigned exactly once",
+                on_name.as_code()
+            );
+        };
+        CSTName::by_index(&file.tree, *definition)
+    };
+    inlinable_assignment(definition).map(|_| ())
+}
+
+fn inlinable_assignment(
+    definition: CSTName,
+) -> anyhow::Result<(Assignment, NameDef, Expression, StandaloneStatement)> {
+    let Some(assignment) = definition.maybe_assignment_definition_name() else {
+        bail!(
+            "Cannot inline {:?}, it is not a variable",
+            definition.as_code()
+        );
+    };
+    let Some((name_def, _, expr)) = assignment
+        .maybe_simple_type_expression_assignment()
+        .filter(|(name_def, _, _)| name_def.index() == definition.name_def().unwrap().index())
+    else {
+        bail!(
+            "Cannot inline {:?}, only simple assignments can be inlined",
+            definition.as_code()
+        );
+    };
+    let Some(statement) = assignment.maybe_standalone_statement() else {
+        bail!("Cannot inline an assignment that shares its line with other statements");
+    };
+    Ok((assignment, name_def, expr, statement))
+}
+
+fn line_start(code: &str, position: CodeIndex) -> usize {
+    code[..position as usize]
+        .rfind('\n')
+        .map(|newline| newline + 1)
+        .unwrap_or(0)
+}
+
+/// Returns the name or the name with a number appended if it's already used in the code.
+fn unique_name(code: &str, name: &str) -> anyhow::Result<String> {
+    if !is_identifier(name) {
+        bail!("{name:?} is not a valid identifier");
+    }
+    let is_used = |name: &str| {
+        regex::Regex::new(&format!(r"\b{name}\b"))
+            .unwrap()
+            .is_match(code)
+    };
+    if !is_used(name) {
+        return Ok(name.to_string());
+    }
+    Ok((1..)
+        .map(|i| format!("{name}{i}"))
+        .find(|name| !is_used(name))
+        .unwrap())
+}
+
+enum ImportedDefinition {
+    Class(PointLink),
+    Specific(Specific),
+}
+
+fn typing_import(name: &str, specific: Specific) -> (String, String, ImportedDefinition) {
+    (
+        "typing".to_owned(),
+        name.to_owned(),
+        ImportedDefinition::Specific(specific),
+    )
+}
+
+/// Writes types as annotations in a file and collects the imports that they need.
+pub(crate) struct AnnotationWriter<'db> {
+    db: &'db Database,
+    file: &'db PythonFile,
+    imports: Vec<(String, String)>,
+    // Annotations like `int | None` or `list[int]` are not valid at runtime in older Python
+    // versions, `from __future__ import annotations` avoids evaluating them.
+    needs_future_annotations: bool,
+}
+
+impl<'db> AnnotationWriter<'db> {
+    pub fn new(db: &'db Database, file: &'db PythonFile) -> Self {
+        Self {
+            db,
+            file,
+            imports: vec![],
+            needs_future_annotations: false,
+        }
+    }
+
+    /// Returns the annotation for the type and remembers the imports it needs. Types that cannot
+    /// be written without further changes (e.g. type vars, modules or nested classes) are not
+    /// used and neither is `Any`, because it is not worth writing.
+    pub fn annotation(&mut self, t: &Type) -> Option<String> {
+        let db = self.db;
+        if t.is_any() {
+            return None;
+        }
+        let mut imports = vec![];
+        // The minor Python version that is needed to evaluate the annotation
+        let mut needed_minor_version = 0;
+        let is_unusable = t.find_in_type(db, &mut |t| {
+            let class_ref = match t {
+                Type::Class(c) => {
+                    if !matches!(c.generics, ClassGenerics::None) {
+                        needed_minor_version = needed_minor_version.max(9);
+                    }
+                    c.node_ref(db)
+                }
+                Type::Dataclass(d) => d.class(db).node_ref,
+                Type::Enum(e) => ClassNodeRef::from_link(db, e.class),
+                Type::Literal(_) => {
+                    imports.push(typing_import("Literal", Specific::TypingLiteral));
+                    return false;
+                }
+                Type::Any(_) => {
+                    imports.push(typing_import("Any", Specific::TypingAny));
+                    return false;
+                }
+                Type::Union(_) => {
+                    needed_minor_version = 10;
+                    return false;
+                }
+                Type::Tuple(_) | Type::Type(_) => {
+                    needed_minor_version = needed_minor_version.max(9);
+                    return false;
+                }
+                Type::None => return false,
+                _ => return true,
+            };
+            if class_ref.file_index() == self.file.file_index {
+                return !matches!(class_ref.node().parent_scope(), Scope::Module);
+            }
+            let module = class_ref.file.qualified_name(db);
+            if module == "builtins" {
+                return false;
+            }
+            let is_private = module.split('.').any(|part| part.starts_with('_'));
+            let is_nested = !matches!(class_ref.node().parent_scope(), Scope::Module);
+            if is_private || is_nested {
+                return true;
+            }
+            let name = class_ref.name().to_owned();
+            imports.push((module, name, ImportedDefinition::Class(class_ref.as_link())));
+            false
+        });
+        if is_unusable {
+            return None;
+        }
+        let mut missing = vec![];
+        for (module, name, definition) in imports {
+            if let Some(symbol) = self.file.lookup_symbol(&name) {
+                // The name is only usable if it refers to the same definition.
+                let inf =
+                    symbol.infer_name_of_definition_by_index(&InferenceState::new(db, self.file));
+                let is_same = match definition {
+                    ImportedDefinition::Class(link) => inf.maybe_saved_link() == Some(link),
+                    ImportedDefinition::Specific(specific) => {
+                        inf.maybe_specific(db) == Some(specific)
+                    }
+                };
+                if !is_same {
+                    return None;
+                }
+            } else if self
+                .imports
+                .iter()
+                .chain(&missing)
+                .any(|(m, n)| *n == name && *m != module)
+            {
+                // Two different definitions with the same name would need aliases.
+                return None;
+            } else {
+                missing.push((module, name))
+            }
+        }
+        for import in missing {
+            if !self.imports.contains(&import) {
+                self.imports.push(import)
+            }
+        }
+        if !self.file.is_stub()
+            && !self
+                .file
+                .settings(db)
+                .python_version_or_default()
+                .at_least_3_dot(needed_minor_version)
+            && !self.has_future_annotations()
+        {
+            self.needs_future_annotations = true;
+        }
+        Some(t.format_short(db).into_string())
+    }
+
+    fn has_future_annotations(&self) -> bool {
+        self.file.tree.root().iter_stmt_likes().any(|stmt| {
+            let StmtLikeContent::ImportFrom(import) = stmt.node else {
+                return false;
+            };
+            let (level, dotted) = import.level_with_dotted_name();
+            level == 0
+                && dotted.is_some_and(|dotted| dotted.as_code() == "__future__")
+                && match import.unpack_targets() {
+                    ImportFromTargets::Star(_) => false,
+                    ImportFromTargets::Iterator(mut targets) => {
+                        targets.any(|target| target.unpack().0.as_str() == "annotations")
+                    }
+                }
+        })
+    }
+
+    /// Returns the positions and the code of the imports that are needed by the annotations.
+    /// They are added after the module level imports at the top of the file, only
+    /// `from __future__ import annotations` is added before them. The positions are distinct.
+    pub fn import_insertions(&self) -> Vec<(CodeIndex, String)> {
+        const FUTURE_IMPORT: &str = "from __future__ import annotations\n";
+        if self.imports.is_empty() && !self.needs_future_annotations {
+            return vec![];
+        }
+        let mut modules: Vec<&str> = self.imports.iter().map(|(m, _)| m.as_str()).collect();
+        modules.sort();
+        modules.dedup();
+        let mut text = String::new();
+        for module in modules {
+            let mut names: Vec<&str> = self
+                .imports
+                .iter()
+                .filter(|(m, _)| m == module)
+                .map(|(_, name)| name.as_str())
+                .collect();
+            names.sort();
+            text += &format!("from {module} import {}\n", names.join(", "));
+        }
+
+        let root = self.file.tree.root();
+        let code = self.file.tree.code();
+        // Future imports need to be the first statements after the docstring
+        let first_statement_line = match root.docstring() {
+            Some(docstring) => line_after(code, docstring.end()),
+            None => {
+                let mut position = 0;
+                for line in code.split_inclusive('\n') {
+                    let trimmed = line.trim_start();
+                    if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                        break;
+                    }
+                    position += line.len();
+                }
+                position as CodeIndex
+            }
+        };
+        let mut last_end = root.docstring().map(|docstring| docstring.end());
+        let mut after_import = false;
+        for stmt in root.iter_stmt_likes() {
+            match stmt.node {
+                StmtLikeContent::ImportName(import) => last_end = Some(import.end()),
+                StmtLikeContent::ImportFrom(import) => last_end = Some(import.end()),
+                _ if !after_import && stmt.node.maybe_string().is_some() => continue,
+                _ => break,
+            }
+            after_import = true;
+        }
+        let mut insertions = vec![];
+        if !after_import {
+            if self.needs_future_annotations {
+                if !text.is_empty() {
+                    text.insert(0, '\n');
+                }
+                text.insert_str(0, FUTURE_IMPORT);
+            }
+            match last_end {
+                Some(_) => text.insert(0, '\n'),
+                None => text.push('\n'),
+            }
+            insertions.push((first_statement_line, text));
+        } else {
+            if self.needs_future_annotations {
+                insertions.push((first_statement_line, format!("{FUTURE_IMPORT}\n")));
+            }
+            if !text.is_empty() {
+                insertions.push((line_after(code, last_end.unwrap()), text));
+            }
+        }
+        insertions
+    }
+}
+
+/// The start of the line after the position
+fn line_after(code: &str, position: CodeIndex) -> CodeIndex {
+    code[position as usize..]
+        .find('\n')
+        .map(|i| position as usize + i + 1)
+        .unwrap_or(code.len()) as CodeIndex
+}
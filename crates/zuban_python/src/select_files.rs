@@ -17,7 +17,7 @@ pub(crate) fn diagnostics_for_relevant_files<'db>(
     db: &'db Database,
     on_file: impl FnMut(&'db PythonFile) -> Vec<Diagnostic<'db>>,
 ) -> anyhow::Result<Vec<Diagnostic<'db>>> {
-    let files = FileSelector::find_files(db)?;
+    let files = files_to_check(db)?;
     Ok(files
         .into_iter()
         .map(on_file)
@@ -28,6 +28,11 @@ pub(crate) fn diagnostics_for_relevant_files<'db>(
         .unwrap_or_default())
 }
 
+/// Finds all files that would be checked by `zuban check` with the current settings.
+pub(crate) fn files_to_check(db: &Database) -> anyhow::Result<Vec<&PythonFile>> {
+    FileSelector::find_files(db)
+}
+
 fn should_skip(flags: &TypeCheckerFlags, path: &AbsPath) -> bool {
     if !path.ends_with(".py") && !path.ends_with(".pyi") {
         return true;
@@ -207,6 +207,21 @@ impl Intersection {
     }
 
     pub fn format(&self, format_data: &FormatData) -> Box<str> {
+        if let [
+            Type::Callable(_) | Type::FunctionOverload(_),
+            partial @ Type::Class(c),
+        ] = self.entries.as_ref()
+            && c.link
+                == format_data
+                    .db
+                    .python_state
+                    .functools_partial_node_ref()
+                    .as_link()
+        {
+            // functools.partial(...) is inferred as the remaining signature combined with the
+            // partial instance, but should look like the instance.
+            return partial.format(format_data);
+        }
         let mut format_data = *format_data;
         // For whatever reason, the names are always formatted as qualified names
         format_data.style = FormatStyle::MypyRevealType;
@@ -128,6 +128,21 @@ impl Type {
             },
             Type::NewType(new_type1) => match value_type {
                 Type::NewType(new_type2) => (new_type1 == new_type2).into(),
+                Type::Literal(literal) if let Some(kind) = new_type1.native_int => {
+                    if let LiteralKind::Int(value) = literal.kind
+                        && !kind.contains(value)
+                    {
+                        return Match::False {
+                            reason: MismatchReason::NativeIntOutOfRange { value, kind },
+                            similar: false,
+                        };
+                    }
+                    new_type1.type_.matches(i_s, matcher, value_type, variance)
+                }
+                // Native ints are implicitly compatible with int.
+                Type::Class(_) if new_type1.native_int.is_some() => {
+                    new_type1.type_.matches(i_s, matcher, value_type, variance)
+                }
                 _ => Match::new_false(),
             },
             t1 @ Type::RecursiveType(rec1) => {
@@ -466,8 +481,16 @@ impl Type {
                     self.matches(i_s, matcher, t, variance)
                 })
             }
-            Type::NewType(n2) if variance == Variance::Covariant => {
-                return self.matches(i_s, matcher, &n2.type_, variance);
+            Type::NewType(n2)
+                if variance == Variance::Covariant
+                    || n2.native_int.is_some() && matches!(self, Type::Class(_)) =>
+            {
+                // Distinct native ints are not compatible with each other.
+                if n2.native_int.is_none()
+                    || !matches!(self, Type::NewType(n1) if n1.native_int.is_some())
+                {
+                    return self.matches(i_s, matcher, &n2.type_, variance);
+                }
             }
             Type::Never(_) if variance == Variance::Covariant => return Match::new_true(), // Never is assignable to anything
             Type::Self_ if variance == Variance::Covariant => {
@@ -1727,6 +1727,7 @@ pub(crate) struct NewType {
     pub name_node: PointLink,
     pub name_string: PointLink,
     pub type_: Type,
+    pub native_int: Option<NativeIntKind>,
 }
 
 impl NewType {
@@ -1735,6 +1736,17 @@ impl NewType {
             name_node,
             name_string,
             type_,
+            native_int: None,
+        }
+    }
+
+    pub fn new_native_int(name_node: PointLink, kind: NativeIntKind, int_type: Type) -> Self {
+        // There is no string for native ints, so the name node is used instead.
+        Self {
+            name_node,
+            name_string: name_node,
+            type_: int_type,
+            native_int: Some(kind),
         }
     }
 
@@ -1748,6 +1760,9 @@ impl NewType {
     }
 
     pub fn name<'db>(&self, db: &'db Database) -> &'db str {
+        if let Some(kind) = self.native_int {
+            return kind.name();
+        }
         NodeRef::from_link(db, self.name_string)
             .maybe_str()
             .unwrap()
@@ -1756,12 +1771,39 @@ impl NewType {
 
     pub fn qualified_name(&self, db: &Database) -> Box<str> {
         let node_ref = NodeRef::from_link(db, self.name_string);
-        format!(
-            "{}.{}",
-            node_ref.file.qualified_name(db),
-            node_ref.maybe_str().unwrap().content()
-        )
-        .into()
+        format!("{}.{}", node_ref.file.qualified_name(db), self.name(db)).into()
+    }
+}
+
+/// The native integer types of mypyc, defined in `mypy_extensions`. They are aliases of `int` in
+/// typeshed, but are treated as distinct types that are implicitly compatible with `int`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum NativeIntKind {
+    I64,
+    I32,
+    I16,
+    U8,
+}
+
+impl NativeIntKind {
+    pub const ALL: [Self; 4] = [Self::I64, Self::I32, Self::I16, Self::U8];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::I64 => "i64",
+            Self::I32 => "i32",
+            Self::I16 => "i16",
+            Self::U8 => "u8",
+        }
+    }
+
+    pub fn contains(self, value: i64) -> bool {
+        match self {
+            Self::I64 => true,
+            Self::I32 => i32::try_from(value).is_ok(),
+            Self::I16 => i16::try_from(value).is_ok(),
+            Self::U8 => u8::try_from(value).is_ok(),
+        }
     }
 }
 
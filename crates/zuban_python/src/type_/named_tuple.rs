@@ -208,9 +208,7 @@ impl NamedTuple {
             "_replace" => replace_method("_replace"),
             "__replace__"
                 if i_s
-                    .db
-                    .project
-                    .settings
+                    .settings()
                     .python_version_or_default()
                     .at_least_3_dot(13) =>
             {
@@ -285,9 +283,7 @@ impl NamedTuple {
             }
             "__match_args__"
                 if i_s
-                    .db
-                    .project
-                    .settings
+                    .settings()
                     .python_version_or_default()
                     .at_least_3_dot(10) =>
             {
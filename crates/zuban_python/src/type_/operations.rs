@@ -1044,7 +1044,12 @@ pub(crate) fn execute_type_of_type<'db>(
             Inferred::from_type(type_.clone())
         }
         Type::NewType(n) => {
-            n.check_initialization_args(i_s, args, on_type_error);
+            if n.native_int.is_some() {
+                // Native ints are constructed like ints, e.g. i64("1") is fine.
+                execute_type_of_type(i_s, args, result_context, on_type_error, &n.type_);
+            } else {
+                n.check_initialization_args(i_s, args, on_type_error);
+            }
             Inferred::from_type(Type::NewType(n.clone()))
         }
         Type::Self_ => {
@@ -7,7 +7,10 @@ use std::{
 
 use parsa_python_cst::{Assignment, AssignmentContent, AtomContent, ClassDef, Name, TypeLike};
 
-use super::{Callable, Instance, InstanceLookupOptions, LookupDetails, overload::OverloadResult};
+use super::{
+    Callable, Instance, InstanceLookupOptions, LookupDetails, execute_functools_partial,
+    overload::OverloadResult,
+};
 use crate::{
     arguments::Args,
     database::{
@@ -1239,6 +1242,13 @@ impl<'db: 'a, 'a> Class<'a> {
                 return inf;
             }
         }
+        if self.node_ref == i_s.db.python_state.functools_partial_node_ref()
+            && matches!(self.generics, Generics::NotDefinedYet { .. })
+            && let Some(args) = args.maybe_simple_args()
+            && let Some(inf) = execute_functools_partial(i_s, args)
+        {
+            return inf;
+        }
         match self.execute_and_return_generics(
             i_s,
             args,
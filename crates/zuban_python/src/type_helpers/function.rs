@@ -191,7 +191,7 @@ impl<'db: 'a + 'class, 'a, 'class> Function<'a, 'class> {
         }
     }
 
-    fn ensure_cached_untyped_return(&self, i_s: &InferenceState) -> Inferred {
+    pub(crate) fn ensure_cached_untyped_return(&self, i_s: &InferenceState) -> Inferred {
         let had_error = &Cell::new(false);
         let inner_i_s = &i_s
             .with_func_context(self)
@@ -0,0 +1,338 @@
+use std::sync::Arc;
+
+use crate::{
+    arguments::{Arg, ArgKind, Args, PartialBoundArgs, SimpleArgs},
+    database::Database,
+    debug,
+    diagnostics::IssueKind,
+    file::on_argument_type_error,
+    inference_state::InferenceState,
+    inferred::Inferred,
+    matching::{OnTypeError, ResultContext, calc_callable_type_vars},
+    new_class,
+    type_::{
+        AnyCause, CallableContent, CallableLike, CallableParams, DbString, FunctionOverload,
+        Intersection, NeverCause, ParamType, ReplaceTypeVarLikes, Type, TypeVarIndex, TypeVarLikes,
+    },
+};
+
+use super::{Callable, OverloadedFunction};
+
+/// Infers `functools.partial(func, *args, **kwargs)`. The bound arguments are checked against
+/// `func` and the result is a `partial[T]` that can only be called with the remaining signature
+/// of `func`. Returns `None` if the call cannot be understood, in which case the normal class
+/// execution of `partial` is used.
+pub(crate) fn execute_functools_partial<'db>(
+    i_s: &InferenceState<'db, '_>,
+    args: &SimpleArgs<'db, '_>,
+) -> Option<Inferred> {
+    let first = args.iter(i_s.mode).next()?;
+    let ArgKind::Positional(func_arg) = &first.kind else {
+        return None;
+    };
+    let func = func_arg.infer(&mut ResultContext::Unknown);
+    let func_type = func.as_cow_type(i_s);
+    let Some(callable_like) = func_type.maybe_callable(i_s) else {
+        // Mypy reports the parts that are not callable in addition to the mismatch with
+        // `partial.__new__`, which is reported by the normal class execution.
+        for t in func_type.iter_with_unpacked_unions(i_s.db) {
+            if !matches!(t, Type::Any(_)) && t.maybe_callable(i_s).is_none() {
+                args.add_issue(
+                    i_s,
+                    IssueKind::NotCallable {
+                        type_: format!("\"{}\"", t.format_short(i_s.db)).into(),
+                    },
+                )
+            }
+        }
+        return None;
+    };
+    if let Type::Type(t) = func_type.as_ref()
+        && let Some(cls) = t.maybe_class(i_s.db)
+    {
+        // Only the class itself is checked, `type[A]` might be a concrete subclass.
+        let class_infos = cls.use_cached_class_infos(i_s.db);
+        if func
+            .maybe_saved_node_ref(i_s.db)
+            .is_some_and(|node_ref| node_ref.maybe_class().is_some())
+            && !class_infos.abstract_attributes.is_empty()
+            && !class_infos.incomplete_mro
+        {
+            args.add_issue(
+                i_s,
+                IssueKind::CannotInstantiateAbstractClass {
+                    name: cls.name().into(),
+                    abstract_attributes: class_infos.abstract_attributes.clone(),
+                },
+            )
+        }
+    }
+    let class_name = match func_type.as_ref() {
+        Type::Type(t) => t.maybe_class(i_s.db).map(|cls| cls.name()),
+        _ => None,
+    };
+    let bound_args = PartialBoundArgs(args);
+    let remaining = match callable_like {
+        CallableLike::Callable(c) => {
+            bind_callable(i_s, &with_class_name(&c, class_name), &bound_args)?
+        }
+        CallableLike::Overload(o) => {
+            let o = match class_name {
+                Some(_) => FunctionOverload::new(
+                    o.iter_functions()
+                        .map(|c| Arc::new(with_class_name(c, class_name)))
+                        .collect(),
+                ),
+                None => o,
+            };
+            bind_overload(i_s, &o, &bound_args)?
+        }
+    };
+    let return_type = match &remaining {
+        Type::Callable(c) => partial_return_type(i_s.db, c),
+        Type::FunctionOverload(o) => {
+            let mut t = Type::Never(NeverCause::Other);
+            for c in o.iter_functions() {
+                t.union_in_place(partial_return_type(i_s.db, c))
+            }
+            t
+        }
+        _ => {
+            return Some(Inferred::from_type(new_class!(
+                i_s.db.python_state.functools_partial_node_ref().as_link(),
+                Type::Any(AnyCause::FromError),
+            )));
+        }
+    };
+    let partial = new_class!(
+        i_s.db.python_state.functools_partial_node_ref().as_link(),
+        return_type,
+    );
+    debug!(
+        "Remaining signature of functools.partial: {}",
+        remaining.format_short(i_s.db)
+    );
+    // The partial object is still a partial instance (e.g. for `.func`), but calling it uses the
+    // remaining signature.
+    Some(Inferred::from_type(Type::Intersection(Intersection::new(
+        Arc::new([remaining, partial]),
+    ))))
+}
+
+/// Like for normal class calls, issues of class objects are reported with the name of the class
+/// and not with the name of `__init__`.
+fn with_class_name(callable: &CallableContent, class_name: Option<&str>) -> CallableContent {
+    let mut callable = callable.clone();
+    if let Some(class_name) = class_name {
+        callable.name = Some(DbString::ArcStr(class_name.into()));
+        callable.class_name = None;
+    }
+    callable
+}
+
+fn partial_return_type(db: &Database, callable: &CallableContent) -> Type {
+    // Type vars that are still generic are only bound when the partial object is called.
+    callable
+        .return_type
+        .replace_type_var_likes(db, &mut |usage| {
+            (usage.in_definition() == callable.defined_at)
+                .then(|| usage.as_type_var_like().as_any_generic_item())
+        })
+        .unwrap_or_else(|| callable.return_type.clone())
+}
+
+fn bind_callable<'db>(
+    i_s: &InferenceState<'db, '_>,
+    callable: &CallableContent,
+    bound_args: &PartialBoundArgs<'db, '_>,
+) -> Option<Type> {
+    let split = split_callable(i_s.db, callable, bound_args.iter(i_s.mode))?;
+    Some(check_bound_args(i_s, &split, bound_args))
+}
+
+fn bind_overload<'db>(
+    i_s: &InferenceState<'db, '_>,
+    overload: &Arc<FunctionOverload>,
+    bound_args: &PartialBoundArgs<'db, '_>,
+) -> Option<Type> {
+    let splits = overload
+        .iter_functions()
+        .map(|c| split_callable(i_s.db, c, bound_args.iter(i_s.mode)))
+        .collect::<Option<Vec<_>>>()?;
+    let mut matching = vec![];
+    for split in &splits {
+        let (t, had_error) =
+            i_s.avoid_errors_within(|i_s| check_bound_args(i_s, split, bound_args));
+        if !had_error && let Type::Callable(c) = t {
+            matching.push(c)
+        }
+    }
+    Some(match matching.len() {
+        0 => {
+            // Report the usual overload mismatch.
+            OverloadedFunction::new(overload, None).execute(
+                i_s,
+                bound_args,
+                &mut ResultContext::Unknown,
+                OnTypeError::new(&on_argument_type_error),
+            );
+            Type::Any(AnyCause::FromError)
+        }
+        1 => Type::Callable(matching.pop().unwrap()),
+        _ => Type::FunctionOverload(FunctionOverload::new(matching.into())),
+    })
+}
+
+/// Type checks the bound arguments and returns the remaining callable. Type vars that are used by
+/// bound params are replaced with the inferred types, all others remain generic.
+fn check_bound_args<'db>(
+    i_s: &InferenceState<'db, '_>,
+    split: &SplitCallable,
+    bound_args: &PartialBoundArgs<'db, '_>,
+) -> Type {
+    let calculated = calc_callable_type_vars(
+        i_s,
+        Callable::new(&split.checking, None),
+        bound_args.iter(i_s.mode),
+        |issue| bound_args.add_issue(i_s, issue),
+        false,
+        &mut ResultContext::Unknown,
+        None,
+        Some(OnTypeError::new(&on_argument_type_error)),
+    );
+    let remaining = Type::Callable(Arc::new(split.remaining.clone()));
+    let Some(type_arguments) = calculated.type_arguments_into_generics(i_s.db) else {
+        return remaining;
+    };
+    let defined_at = split.checking.defined_at;
+    let mut new_type_vars = vec![];
+    let new_indices: Vec<_> = split
+        .checking
+        .type_vars
+        .iter()
+        .zip(&split.bound_type_vars)
+        .map(|(tv, is_bound)| {
+            (!is_bound).then(|| {
+                new_type_vars.push(tv.clone());
+                TypeVarIndex::from(new_type_vars.len() - 1)
+            })
+        })
+        .collect();
+    let Some(Type::Callable(c)) = remaining.replace_type_var_likes(i_s.db, &mut |usage| {
+        (usage.in_definition() == defined_at).then(|| match new_indices[usage.index().as_usize()] {
+            Some(index) => usage.into_generic_item_with_new_index(index),
+            None => type_arguments[usage.index()].clone(),
+        })
+    }) else {
+        return remaining;
+    };
+    let mut c = Arc::unwrap_or_clone(c);
+    c.type_vars = TypeVarLikes::from_vec(new_type_vars);
+    Type::Callable(Arc::new(c))
+}
+
+struct SplitCallable {
+    // Used to check the bound arguments, all params are optional.
+    checking: CallableContent,
+    // The callable that remains after binding the arguments.
+    remaining: CallableContent,
+    // For each type var of the callable, whether it is used by a bound param.
+    bound_type_vars: Vec<bool>,
+}
+
+fn split_callable<'db: 'a, 'a>(
+    db: &Database,
+    callable: &CallableContent,
+    bound_args: impl Iterator<Item = Arg<'db, 'a>>,
+) -> Option<SplitCallable> {
+    let mut checking = callable.clone();
+    let mut remaining = callable.clone();
+    remaining.guard = None;
+    let mut bound_type_vars = vec![false; callable.type_vars.len()];
+    let CallableParams::Simple(params) = &callable.params else {
+        return Some(SplitCallable {
+            checking,
+            remaining,
+            bound_type_vars,
+        });
+    };
+    if params.iter().any(|p| p.type_.maybe_param_spec().is_some()) {
+        return None;
+    }
+    let mut remaining_params: Vec<_> = params.iter().cloned().enumerate().collect();
+    let mut bound_params = vec![];
+    for arg in bound_args {
+        match &arg.kind {
+            ArgKind::Positional(_)
+            | ArgKind::Inferred {
+                is_keyword: None,
+                in_args_or_kwargs_and_arbitrary_len: false,
+                ..
+            } => {
+                // Positional arguments are bound to the first positional params. If there are
+                // too many, they are either consumed by *args or reported while checking.
+                if let Some(index) = remaining_params.iter().position(|(_, p)| {
+                    matches!(
+                        p.type_,
+                        ParamType::PositionalOnly(_)
+                            | ParamType::PositionalOrKeyword(_)
+                            | ParamType::Star(_)
+                    )
+                }) {
+                    if matches!(remaining_params[index].1.type_, ParamType::Star(_)) {
+                        bound_params.push(remaining_params[index].0);
+                    } else {
+                        bound_params.push(remaining_params.remove(index).0);
+                    }
+                }
+            }
+            ArgKind::Keyword(_)
+            | ArgKind::Inferred {
+                is_keyword: Some(Some(_)),
+                ..
+            } => {
+                let key = arg.keyword_name(db).unwrap();
+                if let Some(index) = remaining_params.iter().position(|(_, p)| {
+                    matches!(
+                        p.type_,
+                        ParamType::PositionalOrKeyword(_) | ParamType::KeywordOnly(_)
+                    ) && p.name.as_ref().is_some_and(|n| n.as_str(db) == key)
+                }) {
+                    bound_params.push(remaining_params[index].0);
+                    // Keyword arguments can be overwritten in a later call, but the param and
+                    // all params after it can no longer be passed positionally.
+                    remaining_params[index].1.has_default = true;
+                    for (_, param) in &mut remaining_params[index..] {
+                        if let ParamType::PositionalOrKeyword(t) = &param.type_ {
+                            param.type_ = ParamType::KeywordOnly(t.clone());
+                        }
+                    }
+                } else if let Some((index, _)) = remaining_params
+                    .iter()
+                    .find(|(_, p)| matches!(p.type_, ParamType::StarStar(_)))
+                {
+                    bound_params.push(*index);
+                }
+            }
+            _ => return None,
+        }
+    }
+    CallableParams::Simple(bound_params.iter().map(|&i| params[i].clone()).collect())
+        .search_type_vars(&mut |usage| {
+            if usage.in_definition() == callable.defined_at {
+                bound_type_vars[usage.index().as_usize()] = true;
+            }
+        });
+    let mut checking_params = params.to_vec();
+    for param in &mut checking_params {
+        param.has_default = true;
+    }
+    checking.params = CallableParams::Simple(checking_params.into());
+    remaining.params =
+        CallableParams::Simple(remaining_params.into_iter().map(|(_, p)| p).collect());
+    Some(SplitCallable {
+        checking,
+        remaining,
+        bound_type_vars,
+    })
+}
@@ -2,6 +2,7 @@ mod bound_method;
 mod callable;
 mod class;
 mod function;
+mod functools;
 mod instance;
 mod overload;
 mod typing;
@@ -14,9 +15,12 @@ pub(crate) use class::{
 pub(crate) use function::{
     FirstParamKind, FirstParamProperties, Function, GeneratorType, is_private,
 };
+pub(crate) use functools::execute_functools_partial;
 pub(crate) use instance::{
     Instance, InstanceLookupOptions, LookupDetails, execute_isinstance, execute_issubclass,
     execute_super,
 };
 pub(crate) use overload::{OverloadResult, OverloadedFunction};
-pub(crate) use typing::{execute_assert_type, execute_cast, execute_reveal_type};
+pub(crate) use typing::{
+    execute_assert_type, execute_cast, execute_reveal_locals, execute_reveal_type,
+};
@@ -1,13 +1,17 @@
 use std::borrow::Cow;
 
+use parsa_python_cst::{DefiningStmt, NAME_DEF_TO_NAME_DIFFERENCE, NameDef};
+
 use crate::{
     arguments::{ArgKind, Args, InferredArg},
-    database::Database,
+    database::{Database, PointLink},
     diagnostics::IssueKind,
+    file::first_defined_name,
     format_data::FormatData,
     inference_state::InferenceState,
     inferred::Inferred,
     matching::{CouldBeALiteral, Generic, ResultContext},
+    node_ref::NodeRef,
     type_::{
         CallableParams, ClassGenerics, GenericClass, ParamType, StarParamType, StarStarParamType,
         TupleArgs, Type, TypeVarKind, TypedDict, TypedDictGenerics,
@@ -146,6 +150,87 @@ pub(crate) fn execute_reveal_type<'db>(
     inferred
 }
 
+pub(crate) fn execute_reveal_locals<'db>(
+    i_s: &InferenceState<'db, '_>,
+    args: &dyn Args<'db>,
+) -> Inferred {
+    if args.iter(i_s.mode).next().is_some() {
+        args.add_issue(
+            i_s,
+            IssueKind::TooManyArguments(r#" for "reveal_locals""#.into()),
+        );
+        return Inferred::new_any_from_error();
+    }
+    let Some(file) = args.in_file() else {
+        return Inferred::new_none();
+    };
+    let current_func = i_s.current_function().map(|func| func.node());
+    let mut name_defs = vec![];
+    if let Some(func_node) = current_func {
+        func_node.on_name_def_in_scope(&mut |name_def| name_defs.push(name_def));
+    } else if let Some(class) = i_s.in_class_scope() {
+        name_defs.extend(
+            class
+                .class_storage
+                .class_symbol_table
+                .iter()
+                .map(|(_, &index)| {
+                    NameDef::by_index(&file.tree, index - NAME_DEF_TO_NAME_DIFFERENCE)
+                }),
+        )
+    } else {
+        name_defs.extend(
+            file.symbol_table.iter().map(|(_, &index)| {
+                NameDef::by_index(&file.tree, index - NAME_DEF_TO_NAME_DIFFERENCE)
+            }),
+        )
+    }
+
+    // Like Mypy we only reveal variables and not functions, classes or modules.
+    let is_variable = |name_def: &NameDef| match name_def.expect_defining_stmt() {
+        // Params are the only names that are defined by the current function.
+        DefiningStmt::FunctionDef(f) => current_func.is_some_and(|func| func.index() == f.index()),
+        DefiningStmt::ClassDef(_)
+        | DefiningStmt::ImportName(_)
+        | DefiningStmt::ImportFromAsName(_)
+        | DefiningStmt::Lambda(_)
+        | DefiningStmt::Comprehension(_)
+        | DefiningStmt::DictComprehension(_)
+        | DefiningStmt::TypeAlias(_)
+        | DefiningStmt::GlobalStmt(_)
+        | DefiningStmt::NonlocalStmt(_) => false,
+        _ => true,
+    };
+    let inference = file.inference(i_s);
+    let mut locals: Vec<_> = name_defs
+        .into_iter()
+        .filter(is_variable)
+        .filter_map(|name_def| {
+            let first = first_defined_name(file, name_def.name_index());
+            if first != name_def.name_index()
+                || NodeRef::new(file, name_def.index()).point().calculating()
+            {
+                return None;
+            }
+            let inf = inference
+                .maybe_lookup_narrowed_name(first, PointLink::new(file.file_index, first))
+                .unwrap_or_else(|| inference.infer_name_of_definition_by_index(first));
+            Some((
+                name_def.as_code(),
+                reveal_type_info(i_s, &inf.as_cow_type(i_s)),
+            ))
+        })
+        .collect();
+    locals.sort_by_key(|(name, _)| *name);
+    locals.dedup_by_key(|(name, _)| *name);
+
+    args.add_issue(i_s, IssueKind::Note("Revealed local types are:".into()));
+    for (name, t) in locals {
+        args.add_issue(i_s, IssueKind::Note(format!("    {name}: {t}").into()));
+    }
+    Inferred::new_none()
+}
+
 fn reveal_type_info(i_s: &InferenceState, t: &Type) -> Box<str> {
     let format_data = FormatData::new_reveal_type(i_s.db);
     if let Type::Type(type_) = t {
@@ -194,6 +194,14 @@ pub fn arc_slice_into_vec<T: Clone>(this: Arc<[T]>) -> Vec<T> {
     Vec::from(this.as_ref())
 }
 
+pub(crate) fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    if !chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') {
+        return false;
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
 #[inline]
 pub fn is_magic_method(name: &str) -> bool {
     name.starts_with("__") && name.ends_with("__")
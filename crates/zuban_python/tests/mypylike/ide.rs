@@ -26,6 +26,7 @@ pub enum Commands {
     Goto(GotoArgs),
     Infer(InferArgs),
     Documentation(DocumentationArgs),
+    RevealType,
     References(ReferencesArgs),
     Rename(RenameArgs),
 }
@@ -36,6 +37,9 @@ pub struct CompleteArgs {
     pub filter: Option<Vec<String>>,
     #[arg(long)]
     pub show_kind: bool,
+    /// Shows the snippet or the insert text if it differs from the label
+    #[arg(long)]
+    pub show_insert_text: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -107,7 +111,11 @@ pub(crate) fn find_and_check_ide_tests(
             let cli =
                 Cli::parse_from(std::iter::once("".to_string()).chain(Shlex::new(after_comment)));
             let p = base_path_join(project.vfs_handler(), path);
-            let document = project.document(&p).unwrap();
+            let document = match cli.command {
+                Commands::RevealType => project.document_for_reveal_type(&p),
+                _ => project.document(&p),
+            }
+            .unwrap();
             let position = {
                 let line = line_nr + 1;
                 match (
@@ -181,11 +189,18 @@ pub(crate) fn find_and_check_ide_tests(
             let (kind, out) = match cli.command {
                 Commands::Complete(complete_args) => {
                     let mut result = document.complete(position, true, |_, name| {
-                        if complete_args.show_kind {
+                        let mut item = if complete_args.show_kind {
                             format!("{}:{:?}", name.label(), name.kind())
                         } else {
                             name.label().to_owned()
+                        };
+                        if complete_args.show_insert_text {
+                            let insert_text = name.snippet().unwrap_or_else(|| name.insert_text());
+                            if insert_text != name.label() {
+                                item += &format!("={insert_text:?}");
+                            }
                         }
+                        item
                     });
                     if let Some(filter) = complete_args.filter
                         && let Ok(r) = result
@@ -223,6 +238,12 @@ pub(crate) fn find_and_check_ide_tests(
                             }]
                         }),
                 ),
+                Commands::RevealType => (
+                    "reveal-type",
+                    document
+                        .reveal_type(position)
+                        .map(|result| vec![result.unwrap_or_else(|| "No type found".to_string())]),
+                ),
                 Commands::References(references) => {
                     let goal = match references.only_check_file {
                         true => ReferencesGoal::OnlyCurrentFile,
@@ -12,7 +12,9 @@ use std::{
 
 use clap::Parser;
 
-use config::{DiagnosticConfig, ProjectOptions, PythonVersion, Settings, TypeCheckerFlags};
+use config::{
+    DiagnosticConfig, EditorSettings, ProjectOptions, PythonVersion, Settings, TypeCheckerFlags,
+};
 use ide::find_and_check_ide_tests;
 use regex::{Captures, Regex, Replacer};
 use test_utils::{Step, calculate_steps};
@@ -44,6 +46,7 @@ const SKIP_MYPY_TEST_FILES: [&str; 27] = [
     "fine-grained-inspect.test",
     // Won't do, because they test mypy internals
     "check-incomplete-fixture.test",
+    // Not verified to pass, native ints are tested in tests/new_type.test instead
     "check-native-int.test",
     "semanal-symtable.test",
     "daemon.test",
@@ -156,6 +159,7 @@ impl TestCase<'_, '_> {
                     base_path,
                     &ini,
                     &mut diagnostic_config,
+                    &EditorSettings::default(),
                 )
                 .expect("Expected there to be no errors in the mypy.ini")
                 .unwrap_or_else(ProjectOptions::mypy_default)
@@ -178,6 +182,7 @@ impl TestCase<'_, '_> {
                     base_path,
                     &ini,
                     &mut diagnostic_config,
+                    &EditorSettings::default(),
                     mypy_compatible,
                 )
                 .expect("Expected there to be no errors in the pyproject.toml")
@@ -2,21 +2,26 @@
 
 //! Advertises the capabilities of the LSP Server.
 use lsp_types::{
-    CompletionOptions, DeclarationCapability, HoverProviderCapability,
-    ImplementationProviderCapability, OneOf, PositionEncodingKind, RenameOptions,
+    CodeActionKind, CodeActionOptions, CodeActionProviderCapability, CodeLensOptions,
+    CompletionOptions, DeclarationCapability, ExecuteCommandOptions, FileOperationFilter,
+    FileOperationPattern, FileOperationPatternKind, FileOperationRegistrationOptions,
+    FoldingRangeProviderCapability, HoverProviderCapability, ImplementationProviderCapability,
+    OneOf, PositionEncodingKind, RenameOptions, SelectionRangeProviderCapability,
     ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
     TypeDefinitionProviderCapability, WorkDoneProgressOptions,
     WorkspaceFileOperationsServerCapabilities, WorkspaceFoldersServerCapabilities,
     WorkspaceServerCapabilities,
 };
 
+use crate::request_handlers::REVEAL_TYPE_COMMAND;
+
 pub(crate) fn server_capabilities(client_capabilities: &ClientCapabilities) -> ServerCapabilities {
     ServerCapabilities {
         position_encoding: Some(client_capabilities.negotiated_encoding().into()),
         text_document_sync: Some(TextDocumentSyncCapability::Options(
             TextDocumentSyncOptions {
                 open_close: Some(true),
-                change: Some(TextDocumentSyncKind::FULL),
+                change: Some(TextDocumentSyncKind::INCREMENTAL),
                 will_save: None,
                 will_save_wait_until: None,
                 save: None, // Currently not needed
@@ -25,7 +30,7 @@ pub(crate) fn server_capabilities(client_capabilities: &ClientCapabilities) -> S
         notebook_document_sync: None,
         hover_provider: Some(HoverProviderCapability::Simple(true)),
         completion_provider: Some(CompletionOptions {
-            resolve_provider: None, // TODO
+            resolve_provider: Some(true),
             trigger_characters: Some(vec![
                 ".".to_owned(),
                 "@".to_owned(),
@@ -43,15 +48,30 @@ pub(crate) fn server_capabilities(client_capabilities: &ClientCapabilities) -> S
         implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
         references_provider: Some(OneOf::Left(true)),
         document_highlight_provider: Some(OneOf::Left(true)),
-        document_symbol_provider: None,             // TODO
-        workspace_symbol_provider: None,            // TODO
-        code_action_provider: None,                 // TODO
-        code_lens_provider: None,                   // TODO
+        document_symbol_provider: None,  // TODO
+        workspace_symbol_provider: None, // TODO
+        code_action_provider: Some(if client_capabilities.code_action_literals() {
+            CodeActionProviderCapability::Options(CodeActionOptions {
+                code_action_kinds: Some(vec![
+                    CodeActionKind::REFACTOR_EXTRACT,
+                    CodeActionKind::REFACTOR_INLINE,
+                    CodeActionKind::REFACTOR_REWRITE,
+                    CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                ]),
+                work_done_progress_options: Default::default(),
+                resolve_provider: Some(true),
+            })
+        } else {
+            CodeActionProviderCapability::Simple(true)
+        }),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(true),
+        }),
         document_formatting_provider: None,         // TODO
         document_range_formatting_provider: None,   // TODO
         document_on_type_formatting_provider: None, // TODO?
-        selection_range_provider: None,             // TODO
-        folding_range_provider: None,               // TODO
+        selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
         rename_provider: Some(OneOf::Right(RenameOptions {
             prepare_provider: Some(true),
             work_done_progress_options: Default::default(),
@@ -59,7 +79,10 @@ pub(crate) fn server_capabilities(client_capabilities: &ClientCapabilities) -> S
         linked_editing_range_provider: None,
         document_link_provider: None,
         color_provider: None,
-        execute_command_provider: None,
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![REVEAL_TYPE_COMMAND.to_owned()],
+            work_done_progress_options: Default::default(),
+        }),
         workspace: Some(WorkspaceServerCapabilities {
             workspace_folders: Some(WorkspaceFoldersServerCapabilities {
                 supported: Some(true),
@@ -69,8 +92,12 @@ pub(crate) fn server_capabilities(client_capabilities: &ClientCapabilities) -> S
                 did_create: None,
                 will_create: None,
                 did_rename: None,
-                // TODO do we need this?
-                will_rename: None,
+                will_rename: Some(FileOperationRegistrationOptions {
+                    filters: vec![
+                        file_operation_filter("**/*.{py,pyi}", FileOperationPatternKind::File),
+                        file_operation_filter("**", FileOperationPatternKind::Folder),
+                    ],
+                }),
                 did_delete: None,
                 will_delete: None,
             }),
@@ -85,8 +112,7 @@ pub(crate) fn server_capabilities(client_capabilities: &ClientCapabilities) -> S
             lsp_types::DiagnosticOptions {
                 identifier: None,
                 inter_file_dependencies: true,
-                // FIXME
-                workspace_diagnostics: false,
+                workspace_diagnostics: true,
                 work_done_progress_options: WorkDoneProgressOptions {
                     work_done_progress: None,
                 },
@@ -96,6 +122,17 @@ pub(crate) fn server_capabilities(client_capabilities: &ClientCapabilities) -> S
     }
 }
 
+fn file_operation_filter(glob: &str, matches: FileOperationPatternKind) -> FileOperationFilter {
+    FileOperationFilter {
+        scheme: Some("file".to_owned()),
+        pattern: FileOperationPattern {
+            glob: glob.to_owned(),
+            matches: Some(matches),
+            options: None,
+        },
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) struct ClientCapabilities {
     caps: lsp_types::ClientCapabilities,
@@ -179,6 +216,10 @@ impl ClientCapabilities {
         .unwrap_or_default()
     }
 
+    pub(crate) fn workspace_configuration(&self) -> bool {
+        (|| self.caps.workspace.as_ref()?.configuration)().unwrap_or_default()
+    }
+
     pub(crate) fn location_link(&self) -> bool {
         (|| self.caps.text_document.as_ref()?.definition?.link_support)().unwrap_or_default()
     }
@@ -272,6 +313,34 @@ impl ClientCapabilities {
         .unwrap_or_default()
     }
 
+    pub(crate) fn completion_snippets(&self) -> bool {
+        (|| {
+            self.caps
+                .text_document
+                .as_ref()?
+                .completion
+                .as_ref()?
+                .completion_item
+                .as_ref()?
+                .snippet_support
+        })()
+        .unwrap_or_default()
+    }
+
+    pub(crate) fn completion_label_details(&self) -> bool {
+        (|| {
+            self.caps
+                .text_document
+                .as_ref()?
+                .completion
+                .as_ref()?
+                .completion_item
+                .as_ref()?
+                .label_details_support
+        })()
+        .unwrap_or_default()
+    }
+
     fn text_document_diagnostic(caps: &lsp_types::ClientCapabilities) -> bool {
         (|| caps.text_document.as_ref()?.diagnostic.as_ref())().is_some()
     }
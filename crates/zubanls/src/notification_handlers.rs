@@ -1,9 +1,16 @@
+use std::path::Path;
+
 use anyhow::bail;
 use lsp_types::{
-    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DidChangeConfigurationParams, DidChangeTextDocumentParams, DidChangeWatchedFilesParams,
+    DidChangeWorkspaceFoldersParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    TextDocumentContentChangeEvent, WorkDoneProgressCancelParams,
 };
 
-use crate::server::GlobalState;
+use crate::{
+    capabilities::NegotiatedEncoding,
+    server::{GlobalState, GlobalStateSnapshot, patch_path_prefix},
+};
 
 impl GlobalState<'_> {
     pub(crate) fn handle_did_open_text_document(
@@ -20,25 +27,15 @@ impl GlobalState<'_> {
     ) -> anyhow::Result<()> {
         let _p = tracing::info_span!("handle_did_change_text_document").entered();
 
-        let len = params.content_changes.len();
-        if len == 0 {
-            bail!("Expected there to be at least one config change")
-        }
-        /*
-         * TODO do something like this (check rust-analyzer as a reference)
-        let Some(code) = self.project().code_of_in_memory_file(path) else {
+        let encoding = self.client_capabilities.negotiated_encoding();
+        let project = self.project();
+        let path = Self::uri_to_path(project, params.text_document.uri)?;
+        let Some(code) = project.code_of_in_memory_file(&path) else {
             bail!("Should be an in memory file, because we have opened it before")
         };
-        let code = apply_document_changes(encoding, code, params.content_changes);
-        */
-        let change = params.content_changes.into_iter().next().unwrap();
-        if len != 1 || change.range.is_some() || change.range_length.is_some() {
-            bail!(
-                "Expected there to be exactly one content change, because we \
-                   don't support TextDocumentSyncKind::INCREMENTAL yet"
-            )
-        }
-        self.store_in_memory_file(params.text_document.uri, change.text.into())
+        let code = apply_document_changes(encoding, code, params.content_changes)?;
+        project.store_in_memory_file(path, code.into());
+        Ok(())
     }
 
     fn store_in_memory_file(&mut self, uri: lsp_types::Uri, code: Box<str>) -> anyhow::Result<()> {
@@ -63,6 +60,94 @@ impl GlobalState<'_> {
             .map_err(|err| anyhow::anyhow!("{err}"))
     }
 
+    pub(crate) fn handle_did_change_watched_files(
+        &mut self,
+        params: DidChangeWatchedFilesParams,
+    ) -> anyhow::Result<()> {
+        let _p = tracing::info_span!("handle_did_change_watched_files").entered();
+        for change in params.changes {
+            let path = patch_path_prefix(&change.uri)?;
+            tracing::info!("Watched file changed ({:?}): {path}", change.typ);
+            if !self.invalidate_changed_path(Path::new(&path)) {
+                break;
+            }
+        }
+        self.refresh_diagnostics();
+        Ok(())
+    }
+
+    pub(crate) fn handle_did_change_workspace_folders(
+        &mut self,
+        params: DidChangeWorkspaceFoldersParams,
+    ) -> anyhow::Result<()> {
+        let _p = tracing::info_span!("handle_did_change_workspace_folders").entered();
+        let removed = params
+            .event
+            .removed
+            .iter()
+            .map(|folder| patch_path_prefix(&folder.uri))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut roots: Vec<String> = self
+            .roots
+            .iter()
+            .filter(|root| !removed.contains(root))
+            .cloned()
+            .collect();
+        for folder in params.event.added {
+            let root = patch_path_prefix(&folder.uri)?;
+            if !roots.contains(&root) {
+                roots.push(root)
+            }
+        }
+        if roots.is_empty() {
+            // There always needs to be a root, so we just keep the old ones.
+            tracing::info!("Ignored the removal of all workspace folders");
+            return Ok(());
+        }
+        tracing::info!("Changed workspace roots to {roots:?}");
+        self.folder_editor_settings
+            .retain(|root, _| roots.contains(root));
+        self.roots = roots.into();
+        // The roots are part of the project options (e.g. the mypy_path), so the project needs to
+        // be recreated.
+        self.recover_from_panic();
+        // New workspace folders might have their own settings.
+        self.request_configuration();
+        Ok(())
+    }
+
+    pub(crate) fn handle_did_change_configuration(
+        &mut self,
+        params: DidChangeConfigurationParams,
+    ) -> anyhow::Result<()> {
+        let _p = tracing::info_span!("handle_did_change_configuration").entered();
+        // Clients that use the pull model only send an empty notification and expect the server
+        // to ask for the settings.
+        if params.settings.is_null()
+            || params
+                .settings
+                .as_object()
+                .is_some_and(|settings| settings.is_empty())
+        {
+            self.request_configuration();
+        } else if let Some(settings) = params.settings.get("zuban") {
+            // Clients send all their settings, the zuban settings are in their own section.
+            self.change_editor_settings(settings.clone());
+        } else {
+            tracing::info!("Ignored a configuration change without zuban settings");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_work_done_progress_cancel(
+        &mut self,
+        params: WorkDoneProgressCancelParams,
+    ) -> anyhow::Result<()> {
+        // All our progress reports are not cancellable, so this should not really happen.
+        tracing::info!("Ignored cancellation of progress {:?}", params.token);
+        Ok(())
+    }
+
     #[inline(never)]
     pub(crate) fn test_panic(&mut self, _: ()) -> anyhow::Result<()> {
         panic!("Test Panic in thread {:?}", std::thread::current().id())
@@ -75,3 +160,29 @@ impl lsp_types::notification::Notification for TestPanic {
     type Params = ();
     const METHOD: &'static str = "test-panic";
 }
+
+fn apply_document_changes(
+    encoding: NegotiatedEncoding,
+    code: &str,
+    changes: Vec<TextDocumentContentChangeEvent>,
+) -> anyhow::Result<String> {
+    let mut code = code.to_string();
+    for change in changes {
+        match change.range {
+            Some(range) => {
+                let to_byte_offset = |code: &str, position| {
+                    GlobalStateSnapshot::to_input_position(encoding, position).to_byte_offset(code)
+                };
+                let start = to_byte_offset(&code, range.start)?;
+                let end = to_byte_offset(&code, range.end)?;
+                if start > end {
+                    bail!("Invalid range {range:?} for a content change")
+                }
+                code.replace_range(start..end, &change.text);
+            }
+            // A change without a range replaces the whole document.
+            None => code = change.text,
+        }
+    }
+    Ok(code)
+}
@@ -1,41 +1,80 @@
-use std::str::FromStr;
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash as _, Hasher as _},
+    str::FromStr,
+};
 
 use anyhow::bail;
 use lsp_server::ErrorCode;
 use lsp_types::{
-    CompletionItem, CompletionParams, CompletionResponse, CompletionTextEdit, Diagnostic,
-    DiagnosticSeverity, DocumentChangeOperation, DocumentChanges, DocumentDiagnosticParams,
-    DocumentDiagnosticReport, DocumentDiagnosticReportResult, DocumentHighlight,
-    DocumentHighlightKind, DocumentHighlightParams, FullDocumentDiagnosticReport,
-    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams, Location,
-    LocationLink, MarkupContent, MarkupKind, OneOf, OptionalVersionedTextDocumentIdentifier,
-    Position, PrepareRenameResponse, ReferenceParams, RelatedFullDocumentDiagnosticReport,
-    RenameFile, RenameParams, ResourceOp, ResourceOperationKind, TextDocumentEdit,
-    TextDocumentIdentifier, TextDocumentPositionParams, TextEdit, Uri, WorkspaceEdit,
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CodeActionResponse,
+    CodeLens, CodeLensParams, Command, CompletionItem, CompletionItemKind,
+    CompletionItemLabelDetails, CompletionParams, CompletionResponse, CompletionTextEdit,
+    Diagnostic, DiagnosticSeverity, DocumentChangeOperation, DocumentChanges,
+    DocumentDiagnosticParams, DocumentDiagnosticReport, DocumentDiagnosticReportResult,
+    DocumentHighlight, DocumentHighlightKind, DocumentHighlightParams, Documentation,
+    ExecuteCommandParams, FoldingRange, FoldingRangeKind, FoldingRangeParams,
+    FullDocumentDiagnosticReport, GotoDefinitionParams, GotoDefinitionResponse, Hover,
+    HoverContents, HoverParams, InsertTextFormat, Location, LocationLink, MarkupContent,
+    MarkupKind, OneOf, OptionalVersionedTextDocumentIdentifier, Position, PrepareRenameResponse,
+    ReferenceParams, RelatedFullDocumentDiagnosticReport, RenameFile, RenameFilesParams,
+    RenameParams, ResourceOp, ResourceOperationKind, SelectionRange, SelectionRangeParams,
+    TextDocumentEdit, TextDocumentIdentifier, TextDocumentPositionParams, TextEdit, Uri,
+    WorkspaceDiagnosticParams, WorkspaceDiagnosticReport, WorkspaceDiagnosticReportPartialResult,
+    WorkspaceDiagnosticReportResult, WorkspaceDocumentDiagnosticReport, WorkspaceEdit,
+    WorkspaceFullDocumentDiagnosticReport,
     request::{
         GotoDeclarationParams, GotoDeclarationResponse, GotoImplementationParams,
         GotoImplementationResponse, GotoTypeDefinitionParams, GotoTypeDefinitionResponse,
     },
 };
+use serde::{Deserialize, Serialize};
 use zuban_python::{
-    Document, GotoGoal, InputPosition, Name, PositionInfos, ReferencesGoal, Severity,
+    CodeLensKind, Document, GotoGoal, InputPosition, Name, PositionInfos, ReferencesGoal, Severity,
 };
 
 use crate::{
     capabilities::{ClientCapabilities, NegotiatedEncoding},
-    server::{GlobalState, LspError},
+    server::{GlobalState, GlobalStateSnapshot, LspError, from_json},
 };
 
-impl GlobalState<'_> {
+/// Returns the narrowed type of the expression at a position, like `reveal_type` would, without
+/// having to edit the file. The only argument is a `TextDocumentPositionParams`.
+pub(crate) const REVEAL_TYPE_COMMAND: &str = "zuban.revealType";
+
+/// The command of resolved code lenses. It is built into VS Code, which shows the locations in a
+/// peek view. The arguments are the URI, the position of the lens and the locations as JSON.
+///
+/// VS Code expects `Uri`, `Position` and `Location` objects, so clients need middleware that
+/// converts the arguments before the command is executed, e.g. in `resolveCodeLens`:
+///
+/// ```text
+/// const [uri, position, locations] = lens.command.arguments;
+/// lens.command.arguments = [
+///     client.protocol2CodeConverter.asUri(uri),
+///     client.protocol2CodeConverter.asPosition(position),
+///     locations.map(client.protocol2CodeConverter.asLocation),
+/// ];
+/// ```
+pub(crate) const SHOW_REFERENCES_COMMAND: &str = "editor.action.showReferences";
+
+/// The number of file reports that are sent at once when a client asks for partial results of
+/// workspace diagnostics.
+const WORKSPACE_DIAGNOSTICS_CHUNK_SIZE: usize = 50;
+
+/// The result ids of the last workspace diagnostics request by URI, together with the
+/// diagnostics generation of the file they were calculated for. As long as the generation does
+/// not change, the diagnostics of a file do not need to be calculated again.
+pub(crate) type WorkspaceDiagnosticsCache = HashMap<String, (u64, String)>;
+
+impl GlobalStateSnapshot {
     pub(crate) fn handle_document_diagnostics(
         &mut self,
         params: DocumentDiagnosticParams,
     ) -> anyhow::Result<lsp_types::DocumentDiagnosticReportResult> {
-        self.sent_diagnostic_count += 1;
         tracing::info!(
-            "Requested diagnostics for {} (#{} overall)",
-            params.text_document.uri.as_str(),
-            self.sent_diagnostic_count
+            "Requested diagnostics for {}",
+            params.text_document.uri.as_str()
         );
         let encoding = self.client_capabilities.negotiated_encoding();
         let document = self.document(params.text_document)?;
@@ -50,6 +89,106 @@ impl GlobalState<'_> {
         ))
     }
 
+    pub(crate) fn handle_workspace_diagnostics(
+        &mut self,
+        params: WorkspaceDiagnosticParams,
+    ) -> anyhow::Result<WorkspaceDiagnosticReportResult> {
+        let encoding = self.client_capabilities.negotiated_encoding();
+        let previous_result_ids: HashMap<String, String> = params
+            .previous_result_ids
+            .into_iter()
+            .map(|previous| (previous.uri.as_str().to_owned(), previous.value))
+            .collect();
+        let partial_result_token = params.partial_result_params.partial_result_token;
+        let paths = self.project().paths_to_check().unwrap_or_else(|err| {
+            tracing::info!("No files for workspace diagnostics: {err}");
+            vec![]
+        });
+        tracing::info!("Requested workspace diagnostics for {} files", paths.len());
+        let mut cache = std::mem::take(&mut self.workspace_diagnostics_cache);
+        let mut new_cache = WorkspaceDiagnosticsCache::with_capacity(paths.len());
+        let mut items = vec![];
+        for path in paths {
+            if let Err(err) = self.check_cancelled() {
+                // Keep what is already known for the next request.
+                new_cache.extend(cache);
+                self.workspace_diagnostics_cache = new_cache;
+                return Err(err);
+            }
+            let uri = Uri::from_str(&path.as_uri())?;
+            let Some(document) = self.project().document(&path) else {
+                continue;
+            };
+            let generation = document.diagnostics_generation();
+            if let Some((cached_generation, result_id)) = cache.remove(uri.as_str())
+                && Some(cached_generation) == generation
+                && previous_result_ids.get(uri.as_str()) == Some(&result_id)
+            {
+                // The client still knows the diagnostics of unchanged files.
+                new_cache.insert(uri.as_str().to_owned(), (cached_generation, result_id));
+                continue;
+            }
+            let diagnostics = Self::diagnostics_for_file(document, encoding);
+            let result_id = Self::diagnostics_result_id(&diagnostics);
+            if let Some(generation) = self
+                .project()
+                .document(&path)
+                .and_then(|document| document.diagnostics_generation())
+            {
+                new_cache.insert(uri.as_str().to_owned(), (generation, result_id.clone()));
+            }
+            if previous_result_ids.get(uri.as_str()) == Some(&result_id) {
+                // The diagnostics were calculated again, but did not change.
+                continue;
+            }
+            items.push(WorkspaceDocumentDiagnosticReport::Full(
+                WorkspaceFullDocumentDiagnosticReport {
+                    uri,
+                    version: None,
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: Some(result_id),
+                        items: diagnostics,
+                    },
+                },
+            ));
+            if let Some(token) = &partial_result_token
+                && items.len() >= WORKSPACE_DIAGNOSTICS_CHUNK_SIZE
+            {
+                self.send_partial_result(
+                    token,
+                    WorkspaceDiagnosticReportPartialResult {
+                        items: std::mem::take(&mut items),
+                    },
+                );
+            }
+        }
+        if let Some(token) = &partial_result_token
+            && !items.is_empty()
+        {
+            // Everything is sent as partial results, the final response is empty then.
+            self.send_partial_result(
+                token,
+                WorkspaceDiagnosticReportPartialResult {
+                    items: std::mem::take(&mut items),
+                },
+            );
+        }
+        self.workspace_diagnostics_cache = new_cache;
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
+    }
+
+    /// The result id changes whenever the diagnostics of a file change. This allows clients to
+    /// skip files that did not change between two workspace diagnostic requests.
+    fn diagnostics_result_id(diagnostics: &[Diagnostic]) -> String {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(diagnostics)
+            .expect("Diagnostics should always be serializable")
+            .hash(&mut hasher);
+        format!("{:x}", hasher.finish())
+    }
+
     fn to_range(
         encoding: NegotiatedEncoding,
         range: (PositionInfos, PositionInfos),
@@ -98,7 +237,7 @@ impl GlobalState<'_> {
 
     fn document(&mut self, text_document: TextDocumentIdentifier) -> anyhow::Result<Document<'_>> {
         let project = self.project();
-        let path = Self::uri_to_path(project, text_document.uri)?;
+        let path = GlobalState::uri_to_path(project, text_document.uri)?;
         let Some(document) = project.document(&path) else {
             tracing::error!("File {} does not exist", path.as_uri());
             bail!(LspError {
@@ -114,17 +253,35 @@ impl GlobalState<'_> {
         params: CompletionParams,
     ) -> anyhow::Result<Option<CompletionResponse>> {
         let encoding = self.client_capabilities.negotiated_encoding();
-        let (document, pos) = self.document_with_pos(params.text_document_position)?;
+        let position = params.text_document_position;
+        let use_snippets = self.use_completion_snippets();
+        let use_label_details = self.client_capabilities.completion_label_details();
+        let (document, pos) = self.document_with_pos(position.clone())?;
         let mut completions = document.complete(pos, false, |replace_range, completion| {
+            let snippet = use_snippets.then(|| completion.snippet()).flatten();
+            let data = CompletionData {
+                position: position.clone(),
+                label: completion.label().to_owned(),
+                kind: completion.kind(),
+                file_path: completion.file_path().map(|path| path.to_owned()),
+            };
             CompletionItem {
                 label: completion.label().to_string(),
+                label_details: use_label_details
+                    .then(|| completion.module_name())
+                    .flatten()
+                    .map(|module_name| CompletionItemLabelDetails {
+                        detail: None,
+                        description: Some(module_name),
+                    }),
                 kind: Some(completion.kind()),
+                insert_text_format: snippet.is_some().then_some(InsertTextFormat::SNIPPET),
                 text_edit: Some(CompletionTextEdit::Edit(TextEdit {
                     range: Self::to_range(encoding, replace_range),
-                    new_text: completion.insert_text(),
+                    new_text: snippet.unwrap_or_else(|| completion.insert_text()),
                 })),
-                // TODO
-                // documentation: Some(Documentation::String(completion.documentation().unwrap_or_else())),
+                filter_text: completion.filter_text(),
+                data: Some(serde_json::to_value(data).unwrap()),
                 ..Default::default()
             }
         })?;
@@ -139,6 +296,39 @@ impl GlobalState<'_> {
         Ok(Some(CompletionResponse::Array(completions)))
     }
 
+    /// Fills in the type and the documentation of a completion, which would be too slow to
+    /// calculate for all completions.
+    pub fn handle_completion_resolve(
+        &mut self,
+        mut item: CompletionItem,
+    ) -> anyhow::Result<CompletionItem> {
+        let Some(data) = &item.data else {
+            return Ok(item);
+        };
+        let data: CompletionData = from_json("CompletionData", data)?;
+        let (document, pos) = self.document_with_pos(data.position.clone())?;
+        let resolved = document
+            .complete(pos, false, |_, completion| {
+                let is_same = completion.label() == data.label
+                    && completion.kind() == data.kind
+                    && completion.file_path() == data.file_path.as_deref();
+                is_same.then(|| (completion.type_description(), completion.documentation()))
+            })?
+            .into_iter()
+            .flatten()
+            .next();
+        if let Some((type_description, documentation)) = resolved {
+            item.detail = type_description;
+            item.documentation = documentation.map(|value| {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                })
+            });
+        }
+        Ok(item)
+    }
+
     pub fn handle_hover(&mut self, params: HoverParams) -> anyhow::Result<Option<Hover>> {
         let encoding = self.client_capabilities.negotiated_encoding();
         let (document, pos) = self.document_with_pos(params.text_document_position_params)?;
@@ -258,14 +448,22 @@ impl GlobalState<'_> {
         &mut self,
         position: TextDocumentPositionParams,
     ) -> anyhow::Result<(Document<'_>, InputPosition)> {
-        let line = position.position.line as usize;
-        let column = position.position.character as usize;
-        let pos = match self.client_capabilities.negotiated_encoding() {
+        let encoding = self.client_capabilities.negotiated_encoding();
+        let pos = Self::to_input_position(encoding, position.position);
+        Ok((self.document(position.text_document)?, pos))
+    }
+
+    pub(crate) fn to_input_position(
+        encoding: NegotiatedEncoding,
+        position: Position,
+    ) -> InputPosition {
+        let line = position.line as usize;
+        let column = position.character as usize;
+        match encoding {
             NegotiatedEncoding::UTF8 => InputPosition::Utf8Bytes { line, column },
             NegotiatedEncoding::UTF16 => InputPosition::Utf16CodeUnits { line, column },
             NegotiatedEncoding::UTF32 => InputPosition::CodePoints { line, column },
-        };
-        Ok((self.document(position.text_document)?, pos))
+        }
     }
 
     pub fn handle_references(
@@ -376,12 +574,513 @@ impl GlobalState<'_> {
         })
     }
 
+    pub fn handle_will_rename_files(
+        &mut self,
+        params: RenameFilesParams,
+    ) -> anyhow::Result<Option<WorkspaceEdit>> {
+        let encoding = self.client_capabilities.negotiated_encoding();
+        let project = self.project();
+        let to_path = |uri: &str| match Uri::from_str(uri) {
+            Ok(uri) => GlobalState::uri_to_path(project, uri),
+            Err(err) => bail!("Invalid uri {uri:?}: {err}"),
+        };
+        let moves = params
+            .files
+            .iter()
+            .map(|rename| Ok((to_path(&rename.old_uri)?, to_path(&rename.new_uri)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let workspace_changes: Vec<_> = project
+            .edits_for_moved_paths(&moves)
+            .into_iter()
+            .map(|change| {
+                Self::to_text_document_edit(encoding, to_uri(change.path.as_uri()), change.edits)
+            })
+            .collect();
+        Ok((!workspace_changes.is_empty()).then(|| WorkspaceEdit {
+            changes: None,
+            document_changes: Some(DocumentChanges::Edits(workspace_changes)),
+            change_annotations: None,
+        }))
+    }
+
+    fn to_text_document_edit(
+        encoding: NegotiatedEncoding,
+        uri: Uri,
+        edits: Vec<zuban_python::TextEdit>,
+    ) -> TextDocumentEdit {
+        TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+            edits: edits
+                .into_iter()
+                .map(|edit| {
+                    OneOf::Left(TextEdit {
+                        range: Self::to_range(encoding, edit.range),
+                        new_text: edit.new_text,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    pub fn handle_code_action(
+        &mut self,
+        params: CodeActionParams,
+    ) -> anyhow::Result<Option<CodeActionResponse>> {
+        if !self.client_capabilities.code_action_literals() {
+            // All our code actions are edits, which cannot be expressed as plain commands.
+            return Ok(None);
+        }
+        let encoding = self.client_capabilities.negotiated_encoding();
+        let only = params.context.only;
+        let is_wanted = |kind: &CodeActionKind| {
+            only.as_ref().is_none_or(|only| {
+                only.iter().any(|o| {
+                    kind.as_str()
+                        .strip_prefix(o.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+                })
+            })
+        };
+        let lazy = self.client_capabilities.code_action_resolve();
+        let text_document = params.text_document;
+        let uri = text_document.uri.clone();
+        let start = Self::to_input_position(encoding, params.range.start);
+        let end = Self::to_input_position(encoding, params.range.end);
+        let document = self.document(text_document.clone())?;
+
+        let mut actions = vec![];
+        // Actions with data but without an edit are resolved in `codeAction/resolve`.
+        let mut add_action = |title: &str,
+                              kind: CodeActionKind,
+                              edit: anyhow::Result<Option<WorkspaceEdit>>,
+                              data: Option<CodeActionData>| {
+            match edit {
+                Ok(None) if data.is_none() => (),
+                Ok(edit) => actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: title.to_owned(),
+                    kind: Some(kind),
+                    edit,
+                    data: data.map(|data| serde_json::to_value(data).unwrap()),
+                    ..Default::default()
+                })),
+                Err(err) => tracing::debug!("Code action {title:?} is not available: {err}"),
+            }
+        };
+        // Expensive actions are only checked cheaply here if the client can resolve them later.
+        let expensive = |check: anyhow::Result<()>, data: CodeActionData| match check {
+            Err(err) => (Err(err), None),
+            Ok(()) if lazy => (Ok(None), Some(data)),
+            Ok(()) => (Self::code_action_edit(encoding, &document, &data), None),
+        };
+        if params.range.start != params.range.end && is_wanted(&CodeActionKind::REFACTOR_EXTRACT) {
+            add_action(
+                "Extract variable",
+                CodeActionKind::REFACTOR_EXTRACT,
+                document
+                    .extract_variable(start, end, "new_var")
+                    .map(|edits| Self::to_workspace_edit(encoding, uri.clone(), edits)),
+                None,
+            );
+            let (edit, data) = expensive(
+                document.check_extract_function(start, end),
+                CodeActionData::ExtractFunction {
+                    text_document: text_document.clone(),
+                    range: params.range,
+                },
+            );
+            add_action(
+                "Extract function",
+                CodeActionKind::REFACTOR_EXTRACT,
+                edit,
+                data,
+            );
+        }
+        let position = TextDocumentPositionParams::new(text_document, params.range.start);
+        if is_wanted(&CodeActionKind::REFACTOR_INLINE) {
+            let (edit, data) = expensive(
+                document.check_inline(start),
+                CodeActionData::InlineVariable(position.clone()),
+            );
+            add_action(
+                "Inline variable",
+                CodeActionKind::REFACTOR_INLINE,
+                edit,
+                data,
+            );
+        }
+        if is_wanted(&CodeActionKind::REFACTOR_REWRITE) {
+            let (edit, data) = expensive(
+                document.check_annotate_function(start),
+                CodeActionData::AddTypeAnnotations(position),
+            );
+            add_action(
+                "Add inferred type annotations",
+                CodeActionKind::REFACTOR_REWRITE,
+                edit,
+                data,
+            );
+        }
+        if is_wanted(&CodeActionKind::SOURCE_ORGANIZE_IMPORTS) {
+            let (edit, data) = expensive(
+                document.check_organize_imports(),
+                CodeActionData::OrganizeImports(TextDocumentIdentifier { uri }),
+            );
+            add_action(
+                "Organize imports",
+                CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                edit,
+                data,
+            );
+        }
+        Ok(Some(actions))
+    }
+
+    pub fn handle_code_action_resolve(
+        &mut self,
+        mut code_action: CodeAction,
+    ) -> anyhow::Result<CodeAction> {
+        let encoding = self.client_capabilities.negotiated_encoding();
+        let Some(data) = code_action.data.take() else {
+            bail!(LspError {
+                code: ErrorCode::InvalidParams as i32,
+                message: "Code actions can only be resolved with their data".to_owned(),
+            });
+        };
+        let code_action_data: CodeActionData = from_json("CodeActionData", &data)?;
+        let text_document = match &code_action_data {
+            CodeActionData::ExtractFunction { text_document, .. }
+            | CodeActionData::OrganizeImports(text_document) => text_document,
+            CodeActionData::InlineVariable(position)
+            | CodeActionData::AddTypeAnnotations(position) => &position.text_document,
+        };
+        let document = self.document(text_document.clone())?;
+        match Self::code_action_edit(encoding, &document, &code_action_data) {
+            Ok(Some(edit)) => code_action.edit = Some(edit),
+            Ok(None) if matches!(code_action_data, CodeActionData::OrganizeImports(_)) => {
+                // Imports are often organized on save, which should not fail if they already are.
+                code_action.edit = Some(WorkspaceEdit::default())
+            }
+            Ok(None) => bail!(LspError {
+                code: ErrorCode::RequestFailed as i32,
+                message: format!("{:?} does not change anything", code_action.title),
+            }),
+            Err(err) => bail!(LspError {
+                code: ErrorCode::RequestFailed as i32,
+                message: err.to_string(),
+            }),
+        }
+        code_action.data = Some(data);
+        Ok(code_action)
+    }
+
+    fn code_action_edit(
+        encoding: NegotiatedEncoding,
+        document: &Document,
+        data: &CodeActionData,
+    ) -> anyhow::Result<Option<WorkspaceEdit>> {
+        let (uri, edits) = match data {
+            CodeActionData::ExtractFunction {
+                text_document,
+                range,
+            } => (
+                &text_document.uri,
+                document.extract_function(
+                    Self::to_input_position(encoding, range.start),
+                    Self::to_input_position(encoding, range.end),
+                    "new_function",
+                )?,
+            ),
+            CodeActionData::InlineVariable(position) => (
+                &position.text_document.uri,
+                document.inline(Self::to_input_position(encoding, position.position))?,
+            ),
+            CodeActionData::AddTypeAnnotations(position) => (
+                &position.text_document.uri,
+                document.annotate_function(Self::to_input_position(encoding, position.position))?,
+            ),
+            CodeActionData::OrganizeImports(text_document) => {
+                (&text_document.uri, document.organize_imports())
+            }
+        };
+        Ok(Self::to_workspace_edit(encoding, uri.clone(), edits))
+    }
+
+    /// Returns `None` if there are no edits.
+    fn to_workspace_edit(
+        encoding: NegotiatedEncoding,
+        uri: Uri,
+        edits: Vec<zuban_python::TextEdit>,
+    ) -> Option<WorkspaceEdit> {
+        if edits.is_empty() {
+            return None;
+        }
+        let edit = Self::to_text_document_edit(encoding, uri, edits);
+        Some(WorkspaceEdit {
+            changes: None,
+            document_changes: Some(DocumentChanges::Edits(vec![edit])),
+            change_annotations: None,
+        })
+    }
+
+    pub fn handle_selection_range(
+        &mut self,
+        params: SelectionRangeParams,
+    ) -> anyhow::Result<Option<Vec<SelectionRange>>> {
+        let encoding = self.client_capabilities.negotiated_encoding();
+        let document = self.document(params.text_document)?;
+        let selection_ranges = params
+            .positions
+            .into_iter()
+            .map(|position| {
+                let ranges =
+                    document.selection_ranges(Self::to_input_position(encoding, position))?;
+                // Every range links to the range around it
+                let mut result: Option<SelectionRange> = None;
+                for range in ranges.into_iter().rev() {
+                    result = Some(SelectionRange {
+                        range: Self::to_range(encoding, range),
+                        parent: result.map(Box::new),
+                    });
+                }
+                let result = result.unwrap_or_else(|| SelectionRange {
+                    range: lsp_types::Range::new(position, position),
+                    parent: None,
+                });
+                Ok(result)
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Some(selection_ranges))
+    }
+
+    pub fn handle_folding_range(
+        &mut self,
+        params: FoldingRangeParams,
+    ) -> anyhow::Result<Option<Vec<FoldingRange>>> {
+        let encoding = self.client_capabilities.negotiated_encoding();
+        let line_folding_only = self.client_capabilities.line_folding_only();
+        let document = self.document(params.text_document)?;
+        let folding_ranges = document
+            .folding_ranges()
+            .into_iter()
+            .filter_map(|(range, kind)| {
+                let range = Self::to_range(encoding, range);
+                (range.start.line < range.end.line).then(|| FoldingRange {
+                    start_line: range.start.line,
+                    start_character: (!line_folding_only).then_some(range.start.character),
+                    end_line: range.end.line,
+                    end_character: (!line_folding_only).then_some(range.end.character),
+                    kind: match kind {
+                        zuban_python::FoldingRangeKind::Comment => Some(FoldingRangeKind::Comment),
+                        zuban_python::FoldingRangeKind::Imports => Some(FoldingRangeKind::Imports),
+                        zuban_python::FoldingRangeKind::Region => Some(FoldingRangeKind::Region),
+                        zuban_python::FoldingRangeKind::Other => None,
+                    },
+                    collapsed_text: None,
+                })
+            })
+            .collect();
+        Ok(Some(folding_ranges))
+    }
+
+    pub fn handle_code_lens(
+        &mut self,
+        params: CodeLensParams,
+    ) -> anyhow::Result<Option<Vec<CodeLens>>> {
+        let encoding = self.client_capabilities.negotiated_encoding();
+        let text_document = params.text_document;
+        let document = self.document(text_document.clone())?;
+        let code_lenses = document
+            .code_lenses()
+            .into_iter()
+            .map(|(range, kind)| {
+                let range = Self::to_range(encoding, range);
+                let position = TextDocumentPositionParams::new(text_document.clone(), range.start);
+                let data = match kind {
+                    CodeLensKind::References => CodeLensData::References(position),
+                    CodeLensKind::Implementations => CodeLensData::Implementations(position),
+                    CodeLensKind::Overrides => CodeLensData::Overrides(position),
+                };
+                CodeLens {
+                    range,
+                    command: None,
+                    data: Some(serde_json::to_value(data).unwrap()),
+                }
+            })
+            .collect();
+        Ok(Some(code_lenses))
+    }
+
+    pub fn handle_code_lens_resolve(
+        &mut self,
+        mut code_lens: CodeLens,
+    ) -> anyhow::Result<CodeLens> {
+        let encoding = self.client_capabilities.negotiated_encoding();
+        let Some(data) = code_lens.data.take() else {
+            bail!(LspError {
+                code: ErrorCode::InvalidParams as i32,
+                message: "Code lenses can only be resolved with their data".to_owned(),
+            });
+        };
+        let to_location = |name: Name| {
+            Location::new(
+                Uri::from_str(&name.file_uri()).expect("Expected a valid URI"),
+                Self::to_range(encoding, name.name_range()),
+            )
+        };
+        let plural = |count: usize, word| match count {
+            1 => format!("1 {word}"),
+            _ => format!("{count} {word}s"),
+        };
+        let (position, title, locations) = match from_json("CodeLensData", &data)? {
+            CodeLensData::References(position) => {
+                let (document, pos) = self.document_with_pos(position.clone())?;
+                let locations = document.references(
+                    pos,
+                    ReferencesGoal::OnlyTypeCheckedWorkspaces,
+                    false,
+                    to_location,
+                )?;
+                (position, plural(locations.len(), "reference"), locations)
+            }
+            CodeLensData::Implementations(position) => {
+                let (document, pos) = self.document_with_pos(position.clone())?;
+                let locations = document.implementations(pos, to_location)?;
+                (
+                    position,
+                    plural(locations.len(), "implementation"),
+                    locations,
+                )
+            }
+            CodeLensData::Overrides(position) => {
+                let (document, pos) = self.document_with_pos(position.clone())?;
+                match document
+                    .overridden_method(pos, |name| (name.qualified_name(), to_location(name)))?
+                {
+                    Some((qualified_name, location)) => (
+                        position,
+                        format!("overrides {qualified_name}"),
+                        vec![location],
+                    ),
+                    // The file changed since the lenses were created.
+                    None => bail!(LspError {
+                        code: ErrorCode::ContentModified as i32,
+                        message: "The method does not override a method anymore".to_owned(),
+                    }),
+                }
+            }
+        };
+        code_lens.command = Some(Command {
+            title,
+            command: SHOW_REFERENCES_COMMAND.to_owned(),
+            arguments: Some(vec![
+                serde_json::to_value(position.text_document.uri).unwrap(),
+                serde_json::to_value(position.position).unwrap(),
+                serde_json::to_value(locations).unwrap(),
+            ]),
+        });
+        code_lens.data = Some(data);
+        Ok(code_lens)
+    }
+
+    pub fn handle_execute_command(
+        &mut self,
+        params: ExecuteCommandParams,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        match params.command.as_str() {
+            REVEAL_TYPE_COMMAND => {
+                let [argument] = params.arguments.as_slice() else {
+                    bail!(LspError {
+                        code: ErrorCode::InvalidParams as i32,
+                        message: format!("{REVEAL_TYPE_COMMAND} expects exactly one argument"),
+                    });
+                };
+                let position: TextDocumentPositionParams =
+                    from_json(REVEAL_TYPE_COMMAND, argument)?;
+                let encoding = self.client_capabilities.negotiated_encoding();
+                let pos = Self::to_input_position(encoding, position.position);
+                let project = self.project();
+                let path = GlobalState::uri_to_path(project, position.text_document.uri)?;
+                let Some(document) = project.document_for_reveal_type(&path) else {
+                    bail!(LspError {
+                        code: ErrorCode::InvalidParams as i32,
+                        message: format!("File {} does not exist", path.as_uri()),
+                    });
+                };
+                Ok(document.reveal_type(pos)?.map(serde_json::Value::String))
+            }
+            command => bail!(LspError {
+                code: ErrorCode::InvalidParams as i32,
+                message: format!("Unknown command {command:?}"),
+            }),
+        }
+    }
+
+    #[cfg(feature = "test_requests")]
+    pub(crate) fn test_wait_for_cancellation(
+        &mut self,
+        _: lsp_types::TextDocumentPositionParams,
+    ) -> anyhow::Result<()> {
+        loop {
+            self.check_cancelled()?;
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+}
+
+impl GlobalState<'_> {
     pub(crate) fn handle_shutdown(&mut self, _: ()) -> anyhow::Result<()> {
         self.shutdown_requested = true;
         Ok(())
     }
 }
 
+/// Blocks the worker until the request is cancelled. This is only used in tests, to have a
+/// request in flight for as long as needed.
+#[cfg(feature = "test_requests")]
+pub(crate) enum TestWaitForCancellation {}
+
+#[cfg(feature = "test_requests")]
+impl lsp_types::request::Request for TestWaitForCancellation {
+    type Params = lsp_types::TextDocumentPositionParams;
+    type Result = ();
+    const METHOD: &'static str = "test-wait-for-cancellation";
+}
+
+/// The data of an unresolved completion, which is needed by `completionItem/resolve`. The
+/// completion is found again by its label, kind and the file it comes from, because labels alone
+/// are not unique, e.g. for names that can be imported from different modules.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CompletionData {
+    position: TextDocumentPositionParams,
+    label: String,
+    kind: CompletionItemKind,
+    file_path: Option<String>,
+}
+
+/// The data of an unresolved code action, which is needed by `codeAction/resolve`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum CodeActionData {
+    #[serde(rename_all = "camelCase")]
+    ExtractFunction {
+        text_document: TextDocumentIdentifier,
+        range: lsp_types::Range,
+    },
+    InlineVariable(TextDocumentPositionParams),
+    AddTypeAnnotations(TextDocumentPositionParams),
+    OrganizeImports(TextDocumentIdentifier),
+}
+
+/// The data of an unresolved code lens, which is needed by `codeLens/resolve`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum CodeLensData {
+    References(TextDocumentPositionParams),
+    Implementations(TextDocumentPositionParams),
+    Overrides(TextDocumentPositionParams),
+}
+
 fn ensure_valid_workspace_edit(
     cap: &ClientCapabilities,
     edit: &WorkspaceEdit,
@@ -2,16 +2,17 @@
 
 use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::str::FromStr;
-use std::sync::atomic::AtomicI64;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::{Arc, RwLock};
+use std::thread;
 
 use anyhow::bail;
-use config::ProjectOptions;
-use crossbeam_channel::{Receiver, Sender, never, select};
+use config::{EditorSettings, ProjectOptions, SubProjectOptions};
+use crossbeam_channel::{Receiver, Sender, never, select_biased};
 use fluent_uri::Scheme;
 use lsp_server::{Connection, ExtractError, Message, Request};
 use lsp_types::Uri;
@@ -24,10 +25,7 @@ use zuban_python::{Mode, PanicRecovery, Project};
 use crate::capabilities::{ClientCapabilities, server_capabilities};
 use crate::notification_handlers::TestPanic;
 use crate::panic_hooks;
-
-// Since we currently don't do garbage collection, we simply delete the project and reindex,
-// because it's not that expensive after a specific amount of diagnostics.
-const REINDEX_AFTER_N_DIAGNOSTICS: usize = 1000;
+use crate::request_handlers::WorkspaceDiagnosticsCache;
 
 pub static GLOBAL_NOTIFY_EVENT_COUNTER: AtomicI64 = AtomicI64::new(0);
 
@@ -59,6 +57,7 @@ pub fn run_server_with_custom_connection(
         capabilities,
         workspace_folders,
         client_info,
+        initialization_options,
         ..
     } = from_json::<lsp_types::InitializeParams>("InitializeParams", &initialize_params)?;
 
@@ -117,18 +116,37 @@ pub fn run_server_with_custom_connection(
         return Err(e.into());
     }
 
-    thread_local! {
-        static LOCAL_SENDER: RefCell<Option<Sender<Message>>> = const { RefCell::new(None) };
-    }
+    // On panic, notify the client.
+    let _hook = enter_panic_hook(connection.sender.clone());
 
+    let mut global_state = GlobalState::new(
+        &connection.sender,
+        client_capabilities,
+        workspace_roots.clone(),
+        typeshed_path,
+    );
+    global_state.change_editor_settings(initialization_options.unwrap_or_default());
+    global_state.register_file_watchers();
+    global_state.request_configuration();
+    global_state.event_loop(&connection.receiver)?;
+    cleanup()?;
+    tracing::info!("Server did successfully shut down");
+    Ok(())
+}
+
+thread_local! {
+    static LOCAL_SENDER: RefCell<Option<Sender<Message>>> = const { RefCell::new(None) };
+}
+
+/// Installs a panic hook that notifies the client. It is only used on the current thread.
+fn enter_panic_hook(sender: Sender<Message>) -> panic_hooks::RestorePanicHook {
     // We need to use a thread local for the sender, because the hook is global and can therefore
     // be used to send something to the wrong thread.
     LOCAL_SENDER.with(|s| {
-        *s.borrow_mut() = Some(connection.sender.clone());
+        *s.borrow_mut() = Some(sender);
     });
 
-    // On panic, notify the client.
-    let _hook = panic_hooks::enter(Box::new(move |panic_info| {
+    panic_hooks::enter(Box::new(move |panic_info| {
         use std::io::Write;
 
         let backtrace = std::backtrace::Backtrace::force_capture();
@@ -175,18 +193,7 @@ pub fn run_server_with_custom_connection(
             tracing::warn!("Wanted to send panic information to the client, but got {err}");
         }
         tracing::error!("Panic hook: {panic_info}\n{backtrace}");
-    }));
-
-    let mut global_state = GlobalState::new(
-        &connection.sender,
-        client_capabilities,
-        workspace_roots.clone(),
-        typeshed_path,
-    );
-    global_state.event_loop(&connection.receiver)?;
-    cleanup()?;
-    tracing::info!("Server did successfully shut down");
-    Ok(())
+    }))
 }
 
 pub fn run_server() -> anyhow::Result<()> {
@@ -205,14 +212,118 @@ struct NotificationDispatcher<'a, 'sender> {
 pub(crate) struct GlobalState<'sender> {
     paths_that_invalidate_whole_project: HashSet<PathBuf>,
     sender: &'sender Sender<lsp_server::Message>,
-    roots: Rc<[String]>,
+    pub(crate) roots: Rc<[String]>,
     typeshed_path: Option<Arc<NormalizedPath>>,
     pub client_capabilities: ClientCapabilities,
+    // The settings of the editor and the JSON they were created from
+    editor_settings: (EditorSettings, serde_json::Value),
+    // The settings of workspace folders that differ from the settings above, by workspace root
+    pub(crate) folder_editor_settings: HashMap<String, (EditorSettings, serde_json::Value)>,
     project: Option<Project>,
     panic_recovery: Option<PanicRecovery>,
-    pub sent_diagnostic_count: usize,
     changed_in_memory_files: Arc<RwLock<Vec<PathWithScheme>>>,
     pub shutdown_requested: bool,
+    // Messages that were received, but not yet handled. Keeping them here allows us to drop
+    // requests that were cancelled before we got to them.
+    pending_messages: VecDeque<Message>,
+    next_request_id: i32,
+    sent_requests: HashSet<lsp_server::RequestId>,
+    // The roots the settings were requested for, in the order of the items of the request
+    configuration_request: Option<(lsp_server::RequestId, Rc<[String]>)>,
+    worker: Worker,
+    // The request that is currently handled by the worker. The project is owned by the worker
+    // until it is done.
+    in_flight: Option<InFlightRequest>,
+    // The receiver of the file watcher is kept here, because it is needed while the project is
+    // owned by the worker.
+    notify_receiver: Option<Receiver<NotifyEvent>>,
+    // File system events that arrived while a request was in flight
+    pending_notify_events: Vec<NotifyEvent>,
+    // Moved to the snapshot while a request is in flight
+    workspace_diagnostics_cache: WorkspaceDiagnosticsCache,
+}
+
+/// Everything a request handler needs that does not change the project. While a request is
+/// handled on the worker, it owns the project, which means that the main loop can still react
+/// to cancellations and changes of documents.
+pub(crate) struct GlobalStateSnapshot {
+    pub(crate) client_capabilities: ClientCapabilities,
+    use_completion_snippets: bool,
+    project: Project,
+    sender: Sender<lsp_server::Message>,
+    cancelled: Arc<AtomicBool>,
+    pub(crate) workspace_diagnostics_cache: WorkspaceDiagnosticsCache,
+}
+
+struct InFlightRequest {
+    // Kept to handle the request again if it is interrupted
+    request: Request,
+    // The uri of the text document the request is about
+    document: Option<String>,
+    cancelled: Arc<AtomicBool>,
+    // Set once the request is cancelled, it is answered with this error code.
+    cancel_code: Option<lsp_server::ErrorCode>,
+    // Set if the main thread needs the project back. The request is then not answered, but handled
+    // again.
+    interrupted: bool,
+}
+
+type WorkerJob =
+    Box<dyn FnOnce(&mut GlobalStateSnapshot) -> Result<lsp_server::Response, Cancelled> + Send>;
+
+struct WorkerResult {
+    snapshot: GlobalStateSnapshot,
+    response: thread::Result<Result<lsp_server::Response, Cancelled>>,
+}
+
+/// A thread that handles requests that only read the project. There is only one, because the
+/// database of a project caches inference results without synchronization and can therefore not
+/// be shared between threads. More workers would just wait for the project to be free.
+struct Worker {
+    jobs: Option<Sender<(GlobalStateSnapshot, WorkerJob)>>,
+    results: Receiver<WorkerResult>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Worker {
+    fn new(client_sender: Sender<lsp_server::Message>) -> Self {
+        let (jobs, job_receiver) =
+            crossbeam_channel::unbounded::<(GlobalStateSnapshot, WorkerJob)>();
+        let (result_sender, results) = crossbeam_channel::unbounded();
+        let thread = thread::Builder::new()
+            .name("zubanls-worker".to_owned())
+            .spawn(move || {
+                let _hook = enter_panic_hook(client_sender);
+                for (mut snapshot, job) in job_receiver {
+                    // Like on the main thread the project is recovered if a handler panics.
+                    let response = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        job(&mut snapshot)
+                    }));
+                    if result_sender
+                        .send(WorkerResult { snapshot, response })
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to spawn the worker thread");
+        Self {
+            jobs: Some(jobs),
+            results,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // Closing the channel stops the thread
+        self.jobs.take();
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
 }
 
 impl<'sender> GlobalState<'sender> {
@@ -228,48 +339,78 @@ impl<'sender> GlobalState<'sender> {
             roots,
             typeshed_path,
             client_capabilities,
+            editor_settings: Default::default(),
+            folder_editor_settings: Default::default(),
             project: None,
             panic_recovery: None,
             changed_in_memory_files: Default::default(),
-            sent_diagnostic_count: 0,
             shutdown_requested: false,
+            pending_messages: Default::default(),
+            next_request_id: 0,
+            sent_requests: Default::default(),
+            configuration_request: None,
+            worker: Worker::new(sender.clone()),
+            in_flight: None,
+            notify_receiver: None,
+            pending_notify_events: vec![],
+            workspace_diagnostics_cache: Default::default(),
         }
     }
 
     fn event_loop(&mut self, receiver: &Receiver<Message>) -> anyhow::Result<()> {
         loop {
-            // Make sure the project is basically loaded
-            self.project();
+            if self.in_flight.is_none() {
+                // Make sure the project is basically loaded
+                self.project();
+                if !self.pending_notify_events.is_empty() {
+                    for event in std::mem::take(&mut self.pending_notify_events) {
+                        self.on_notify_event(event)
+                    }
+                    self.refresh_diagnostics();
+                }
+            }
 
-            select! {
-                recv(receiver) -> msg => {
-                    if self.on_lsp_message_and_return_on_shutdown(msg?) {
-                        return Ok(());
+            let notify_receiver = self.notify_receiver.clone().unwrap_or_else(never);
+            if self.pending_messages.is_empty() || self.in_flight.is_some() {
+                // File system events are handled first, because requests should see the changes
+                // that happened before them.
+                select_biased! {
+                    recv(notify_receiver) -> msg => {
+                        if self.in_flight.is_some() {
+                            self.pending_notify_events.push(msg?);
+                            self.interrupt_in_flight_request();
+                        } else {
+                            self.on_notify_events(msg?)
+                        }
                     }
-                },
-                recv(self.notify_receiver().unwrap_or(&never())) -> msg =>
-                    self.on_notify_events(msg?)
+                    recv(receiver) -> msg => self.pending_messages.push_back(msg?),
+                    recv(self.worker.results) -> result => self.on_worker_result(result?),
+                }
+            } else if let Ok(event) = notify_receiver.try_recv() {
+                self.on_notify_events(event)
             }
-            // See comment on REINDEX_AFTER_N_DIAGNOSTICS
-            if self.sent_diagnostic_count > REINDEX_AFTER_N_DIAGNOSTICS {
-                self.sent_diagnostic_count = 0;
-                tracing::info!("Reindex for performance reasons");
-                self.recover_from_panic();
+            // Look at everything the client has sent in the meantime, because some of the pending
+            // requests might have been cancelled already.
+            self.pending_messages.extend(receiver.try_iter());
+            self.cancel_pending_requests();
+            self.handle_messages_while_in_flight();
+            if self.in_flight.is_some() {
+                // All other messages are handled once the worker returned the project.
+                continue;
             }
-
+            if let Some(msg) = self.pending_messages.pop_front()
+                && self.on_lsp_message_and_return_on_shutdown(msg)
+            {
+                return Ok(());
+            }
+            // Messages might have arrived before the request was sent to the worker.
+            self.handle_messages_while_in_flight();
             self.publish_diagnostics_if_necessary();
         }
     }
 
-    fn notify_receiver(&self) -> Option<&Receiver<NotifyEvent>> {
-        self.project.as_ref()?.vfs_handler().notify_receiver()
-    }
-
     pub(crate) fn project(&mut self) -> &mut Project {
-        let project = &mut self.project;
-        if let Some(p) = project {
-            p
-        } else {
+        if self.project.is_none() {
             let new_changed_files = self.changed_in_memory_files.clone();
             let should_push = self.client_capabilities.should_push_diagnostics();
             let vfs_handler = LocalFS::with_watcher(move |path| {
@@ -281,12 +422,59 @@ impl<'sender> GlobalState<'sender> {
                     }
                 }
             });
-            let first_root = self
-                .roots
-                .first()
+            tracing::info!("Using workspace roots {:?}", &self.roots);
+            let roots = self.roots.clone();
+            let (first_root, other_roots) = roots
+                .split_first()
                 .expect("There should always be at least one root at this point");
-            let first_root = vfs_handler.unchecked_abs_path(first_root);
-            let mut config = config::find_workspace_config(&vfs_handler, &first_root, |path| {
+            let mut config = self.load_workspace_config(&vfs_handler, first_root);
+            let mut nested = self.load_nested_workspace_configs(&vfs_handler, first_root, &config);
+            // Every workspace folder has its own config. They are all part of the same project
+            // though, so imports between them still work.
+            for root in other_roots {
+                let options = self.load_workspace_config(&vfs_handler, root);
+                nested.extend(self.load_nested_workspace_configs(&vfs_handler, root, &options));
+                let root = vfs_handler.unchecked_abs_path(root);
+                let root = vfs_handler.unchecked_normalized_path(root.clone());
+                config
+                    .settings
+                    .mypy_path
+                    .extend(options.settings.mypy_path.iter().cloned());
+                config
+                    .sub_projects
+                    .push(SubProjectOptions { root, options });
+            }
+            config.sub_projects.extend(nested);
+            config.settings.typeshed_path = self.typeshed_path.clone();
+
+            let vfs = Box::new(vfs_handler);
+            let progress_token = self.begin_progress("Indexing");
+            let new_project = if let Some(recovery) = self.panic_recovery.take() {
+                Project::from_recovery(vfs, config, recovery)
+            } else {
+                Project::new(vfs, config, Mode::LanguageServer)
+            };
+            if let Some(token) = progress_token {
+                self.end_progress(token);
+            }
+            self.notify_receiver = new_project.vfs_handler().notify_receiver().cloned();
+            self.project = Some(new_project);
+        }
+        self.project.as_mut().unwrap()
+    }
+
+    fn load_workspace_config<T: Fn(PathWithScheme) + Sync + Send>(
+        &mut self,
+        vfs_handler: &LocalFS<T>,
+        root_str: &str,
+    ) -> ProjectOptions {
+        let root = &vfs_handler.unchecked_abs_path(root_str);
+        let editor_settings = match self.folder_editor_settings.get(root_str) {
+            Some((settings, _)) => settings,
+            None => &self.editor_settings.0,
+        };
+        let mut config =
+            config::find_workspace_config(vfs_handler, root, editor_settings, |path| {
                 // Watch the file itself to make sure that we can invalidate when it changes.
                 let path = Path::new(&**path);
                 vfs_handler.watch(path);
@@ -312,56 +500,192 @@ impl<'sender> GlobalState<'sender> {
                 }
             })
             .unwrap_or_else(|err| {
-                use lsp_types::{
-                    MessageType, ShowMessageParams,
-                    notification::{Notification, ShowMessage},
-                };
                 tracing::warn!("Error while loading config: {}", err.to_string());
-                let not = lsp_server::Notification::new(
-                    ShowMessage::METHOD.to_owned(),
-                    ShowMessageParams {
-                        typ: MessageType::WARNING,
-                        message: err.to_string(),
-                    },
-                );
-                self.sender
-                    .send(lsp_server::Message::Notification(not))
-                    .unwrap();
+                self.show_warning(err.to_string());
                 ProjectOptions::default()
             });
 
-            tracing::info!("Using workspace roots {:?}", &self.roots);
-            // I'm not sure if this is correct. The problem is that the mypy_path currently does
-            // two things:
-            //
-            // 1. Adds it as a workspace to be type-checked
-            // 2. Adds it to the "sys path"
-            //
-            // It's questionable that we want those two things. And maybe there will also be a need
-            // for the type checker to understand what the mypy_path originally was.
-            if config.settings.mypy_path.is_empty() {
-                config.settings.mypy_path = self
-                    .roots
-                    .iter()
-                    .map(|p| {
-                        vfs_handler.unchecked_normalized_path(vfs_handler.unchecked_abs_path(p))
-                    })
-                    .collect();
+        // I'm not sure if this is correct. The problem is that the mypy_path currently does
+        // two things:
+        //
+        // 1. Adds it as a workspace to be type-checked
+        // 2. Adds it to the "sys path"
+        //
+        // It's questionable that we want those two things. And maybe there will also be a need
+        // for the type checker to understand what the mypy_path originally was.
+        if config.settings.mypy_path.is_empty() {
+            config.settings.mypy_path = vec![vfs_handler.unchecked_normalized_path(root.clone())];
+        }
+        config
+            .settings
+            .try_to_find_environment_if_not_defined(vfs_handler, root, |n| std::env::var(n));
+        config
+    }
+
+    /// Loads the configs in the subdirectories of a workspace folder. Unless they define (or
+    /// contain) their own environment, they use the one of the workspace folder.
+    fn load_nested_workspace_configs<T: Fn(PathWithScheme) + Sync + Send>(
+        &mut self,
+        vfs_handler: &LocalFS<T>,
+        root_str: &str,
+        root_options: &ProjectOptions,
+    ) -> Vec<SubProjectOptions> {
+        let root = &vfs_handler.unchecked_abs_path(root_str);
+        let editor_settings = match self.folder_editor_settings.get(root_str) {
+            Some((settings, _)) => settings,
+            None => &self.editor_settings.0,
+        };
+        let nested = config::find_nested_workspace_configs(
+            vfs_handler,
+            root,
+            editor_settings,
+            &root_options.flags.excludes,
+            |path| {
+                let path = Path::new(&**path);
+                vfs_handler.watch(path);
+                match std::fs::canonicalize(path) {
+                    Ok(path) => {
+                        self.paths_that_invalidate_whole_project.insert(path);
+                    }
+                    Err(err) => tracing::info!(
+                        "Canonicalizing of path that invalidates the whole project failed: {err}"
+                    ),
+                }
+            },
+        )
+        .unwrap_or_else(|err| {
+            tracing::warn!("Error while loading nested configs: {}", err.to_string());
+            self.show_warning(err.to_string());
+            vec![]
+        });
+        nested
+            .into_iter()
+            .map(|(dir, mut options)| {
+                let settings = &mut options.settings;
+                settings.try_to_find_environment_if_not_defined(vfs_handler, &dir, |n| {
+                    std::env::var(n)
+                });
+                if settings.environment.is_none() {
+                    settings.environment = root_options.settings.environment.clone();
+                }
+                if settings.python_version.is_none() {
+                    settings.python_version = root_options.settings.python_version;
+                }
+                if settings.platform.is_none() {
+                    settings.platform = root_options.settings.platform.clone();
+                }
+                SubProjectOptions {
+                    root: vfs_handler.unchecked_normalized_path(dir),
+                    options,
+                }
+            })
+            .collect()
+    }
+
+    fn show_warning(&self, message: String) {
+        use lsp_types::{MessageType, ShowMessageParams, notification::ShowMessage};
+        self.send_notification::<ShowMessage>(ShowMessageParams {
+            typ: MessageType::WARNING,
+            message,
+        })
+    }
+
+    /// Uses new settings from the editor (e.g. initialization options or the settings of
+    /// `workspace/didChangeConfiguration`). The project is reloaded if they changed.
+    pub(crate) fn change_editor_settings(&mut self, value: serde_json::Value) {
+        if value == self.editor_settings.1 {
+            return;
+        }
+        let first_root = self.roots[0].clone();
+        if let Some(editor_settings) = self.parse_editor_settings(&value, &first_root) {
+            tracing::info!("Using editor settings {value}");
+            self.editor_settings = (editor_settings, value);
+            self.reload_for_editor_settings();
+        }
+    }
+
+    /// Uses the settings of workspace folders from the response to `workspace/configuration`.
+    /// Folders without settings use the editor settings of the initialization options.
+    fn change_folder_editor_settings(&mut self, folders: Vec<(String, serde_json::Value)>) {
+        let mut changed = false;
+        for (root, value) in folders {
+            if value.is_null() {
+                changed |= self.folder_editor_settings.remove(&root).is_some();
+            } else if self
+                .folder_editor_settings
+                .get(&root)
+                .is_none_or(|(_, old)| *old != value)
+                && let Some(editor_settings) = self.parse_editor_settings(&value, &root)
+            {
+                tracing::info!("Using editor settings {value} for {root}");
+                self.folder_editor_settings
+                    .insert(root, (editor_settings, value));
+                changed = true;
             }
-            config.settings.typeshed_path = self.typeshed_path.clone();
-            config.settings.try_to_find_environment_if_not_defined(
-                &vfs_handler,
-                &first_root,
-                |n| std::env::var(n),
-            );
+        }
+        if changed {
+            self.reload_for_editor_settings();
+        }
+    }
 
-            let vfs = Box::new(vfs_handler);
-            *project = Some(if let Some(recovery) = self.panic_recovery.take() {
-                Project::from_recovery(vfs, config, recovery)
-            } else {
-                Project::new(vfs, config, Mode::LanguageServer)
-            });
-            project.as_mut().unwrap()
+    fn parse_editor_settings(
+        &self,
+        value: &serde_json::Value,
+        root: &str,
+    ) -> Option<EditorSettings> {
+        let vfs_handler = LocalFS::without_watcher();
+        let root = vfs_handler.unchecked_abs_path(root);
+        match editor_settings_from_json(value).and_then(|editor_settings| {
+            editor_settings.validate(&vfs_handler, &root)?;
+            Ok(editor_settings)
+        }) {
+            Ok(editor_settings) => Some(editor_settings),
+            Err(err) => {
+                tracing::warn!("Error while loading editor settings: {err}");
+                self.show_warning(err.to_string());
+                None
+            }
+        }
+    }
+
+    fn reload_for_editor_settings(&mut self) {
+        if self.project.is_some() {
+            // The editor settings are part of the project options.
+            self.recover_from_panic();
+            self.refresh_diagnostics();
+        }
+    }
+
+    /// Snippets are only used if the client supports them and the user enabled them.
+    pub(crate) fn use_completion_snippets(&self) -> bool {
+        let editor_settings = match self.folder_editor_settings.get(&self.roots[0]) {
+            Some((settings, _)) => settings,
+            None => &self.editor_settings.0,
+        };
+        self.client_capabilities.completion_snippets() && editor_settings.completion_snippets()
+    }
+
+    /// Asks the client for the "zuban" section of the settings of every workspace folder.
+    pub(crate) fn request_configuration(&mut self) {
+        use lsp_types::{ConfigurationItem, ConfigurationParams, request::WorkspaceConfiguration};
+        if self.client_capabilities.workspace_configuration() {
+            let vfs_handler = LocalFS::without_watcher();
+            let roots = self.roots.clone();
+            let items = roots
+                .iter()
+                .map(|root| {
+                    let path = vfs_handler.unchecked_abs_path(root);
+                    let path = PathWithScheme::with_file_scheme(
+                        vfs_handler.unchecked_normalized_path(path),
+                    );
+                    ConfigurationItem {
+                        scope_uri: Uri::from_str(&path.as_uri()).ok(),
+                        section: Some("zuban".to_owned()),
+                    }
+                })
+                .collect();
+            let id = self.send_request::<WorkspaceConfiguration>(ConfigurationParams { items });
+            self.configuration_request = Some((id, roots));
         }
     }
 
@@ -373,13 +697,13 @@ impl<'sender> GlobalState<'sender> {
             not: Some(not),
             global_state: self,
         }
-        //.on_sync_mut::<Cancel>(GlobalState::handle_cancel)
-        //.on_sync_mut::<WorkDoneProgressCancel>(GlobalState::handle_work_done_progress_cancel)
+        .on_sync_mut::<WorkDoneProgressCancel>(GlobalState::handle_work_done_progress_cancel)
         .on_sync_mut::<DidOpenTextDocument>(GlobalState::handle_did_open_text_document)
         .on_sync_mut::<DidChangeTextDocument>(GlobalState::handle_did_change_text_document)
         .on_sync_mut::<DidCloseTextDocument>(GlobalState::handle_did_close_text_document)
-        //.on_sync_mut::<DidChangeWorkspaceFolders>(GlobalState::handle_did_change_workspace_folders)
-        //.on_sync_mut::<notifs::DidChangeWatchedFiles>(GlobalState::handle_did_change_watched_files)
+        .on_sync_mut::<DidChangeWorkspaceFolders>(GlobalState::handle_did_change_workspace_folders)
+        .on_sync_mut::<DidChangeWatchedFiles>(GlobalState::handle_did_change_watched_files)
+        .on_sync_mut::<DidChangeConfiguration>(GlobalState::handle_did_change_configuration)
         .on_sync_mut::<TestPanic>(GlobalState::test_panic)
         .finish();
     }
@@ -396,23 +720,43 @@ impl<'sender> GlobalState<'sender> {
 
         use lsp_types::request::*;
 
-        RequestDispatcher {
+        let mut dispatcher = RequestDispatcher {
             request: Some(request),
             global_state: self,
-        }
-        .on_sync_mut::<DocumentDiagnosticRequest>(GlobalState::handle_document_diagnostics)
-        .on_sync_mut::<Completion>(GlobalState::handle_completion)
-        .on_sync_mut::<HoverRequest>(GlobalState::handle_hover)
-        .on_sync_mut::<GotoDeclaration>(GlobalState::handle_goto_declaration)
-        .on_sync_mut::<GotoDefinition>(GlobalState::handle_goto_definition)
-        .on_sync_mut::<GotoTypeDefinition>(GlobalState::handle_goto_type_definition)
-        .on_sync_mut::<GotoImplementation>(GlobalState::handle_goto_implementation)
-        .on_sync_mut::<References>(GlobalState::handle_references)
-        .on_sync_mut::<DocumentHighlightRequest>(GlobalState::handle_document_highlight)
-        .on_sync_mut::<PrepareRenameRequest>(GlobalState::prepare_rename)
-        .on_sync_mut::<Rename>(GlobalState::rename)
-        .on_sync_mut::<Shutdown>(GlobalState::handle_shutdown)
-        .finish();
+        };
+        dispatcher
+            .on_worker::<DocumentDiagnosticRequest>(
+                GlobalStateSnapshot::handle_document_diagnostics,
+            )
+            .on_worker::<WorkspaceDiagnosticRequest>(
+                GlobalStateSnapshot::handle_workspace_diagnostics,
+            )
+            .on_worker::<Completion>(GlobalStateSnapshot::handle_completion)
+            .on_worker::<ResolveCompletionItem>(GlobalStateSnapshot::handle_completion_resolve)
+            .on_worker::<HoverRequest>(GlobalStateSnapshot::handle_hover)
+            .on_worker::<GotoDeclaration>(GlobalStateSnapshot::handle_goto_declaration)
+            .on_worker::<GotoDefinition>(GlobalStateSnapshot::handle_goto_definition)
+            .on_worker::<GotoTypeDefinition>(GlobalStateSnapshot::handle_goto_type_definition)
+            .on_worker::<GotoImplementation>(GlobalStateSnapshot::handle_goto_implementation)
+            .on_worker::<References>(GlobalStateSnapshot::handle_references)
+            .on_worker::<DocumentHighlightRequest>(GlobalStateSnapshot::handle_document_highlight)
+            .on_worker::<PrepareRenameRequest>(GlobalStateSnapshot::prepare_rename)
+            .on_worker::<Rename>(GlobalStateSnapshot::rename)
+            .on_worker::<WillRenameFiles>(GlobalStateSnapshot::handle_will_rename_files)
+            .on_worker::<CodeActionRequest>(GlobalStateSnapshot::handle_code_action)
+            .on_worker::<CodeActionResolveRequest>(GlobalStateSnapshot::handle_code_action_resolve)
+            .on_worker::<CodeLensRequest>(GlobalStateSnapshot::handle_code_lens)
+            .on_worker::<CodeLensResolve>(GlobalStateSnapshot::handle_code_lens_resolve)
+            .on_worker::<SelectionRangeRequest>(GlobalStateSnapshot::handle_selection_range)
+            .on_worker::<FoldingRangeRequest>(GlobalStateSnapshot::handle_folding_range)
+            .on_worker::<ExecuteCommand>(GlobalStateSnapshot::handle_execute_command);
+        #[cfg(feature = "test_requests")]
+        dispatcher.on_worker::<crate::request_handlers::TestWaitForCancellation>(
+            GlobalStateSnapshot::test_wait_for_cancellation,
+        );
+        dispatcher
+            .on_sync_mut::<Shutdown>(GlobalState::handle_shutdown)
+            .finish();
     }
 
     fn on_lsp_message_and_return_on_shutdown(&mut self, msg: Message) -> bool {
@@ -437,31 +781,177 @@ impl<'sender> GlobalState<'sender> {
             false
         }));
         result.unwrap_or_else(|_| {
-            // The error was reported
-            tracing::warn!("Start panic recovery");
-            if let Some(request_id) = was_message {
+            self.recover_from_request_panic(was_message);
+            false
+        })
+    }
+
+    fn recover_from_request_panic(&mut self, request_id: Option<lsp_server::RequestId>) {
+        // The error was reported
+        tracing::warn!("Start panic recovery");
+        if let Some(request_id) = request_id {
+            self.respond(lsp_server::Response::new_err(
+                request_id,
+                lsp_server::ErrorCode::InternalError as i32,
+                format!(
+                    "Server paniced, will now restart (version {})",
+                    env!("CARGO_PKG_VERSION")
+                ),
+            ));
+        }
+        self.recover_from_panic();
+        tracing::info!("Recovered from panic");
+    }
+
+    /// Moves the project to the worker, which runs the job. The result is handled by
+    /// `on_worker_result`.
+    fn spawn_on_worker(&mut self, request: Request, job: WorkerJob) {
+        self.project();
+        let mut project = self.project.take().unwrap();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        project.set_cancellation_flag(cancelled.clone());
+        let snapshot = GlobalStateSnapshot {
+            client_capabilities: self.client_capabilities.clone(),
+            use_completion_snippets: self.use_completion_snippets(),
+            project,
+            sender: self.sender.clone(),
+            cancelled: cancelled.clone(),
+            workspace_diagnostics_cache: std::mem::take(&mut self.workspace_diagnostics_cache),
+        };
+        let document = request.params["textDocument"]["uri"]
+            .as_str()
+            .map(|uri| uri.to_owned());
+        self.in_flight = Some(InFlightRequest {
+            request,
+            document,
+            cancelled,
+            cancel_code: None,
+            interrupted: false,
+        });
+        self.worker
+            .jobs
+            .as_ref()
+            .unwrap()
+            .send((snapshot, job))
+            .expect("The worker thread should be alive");
+    }
+
+    fn on_worker_result(&mut self, result: WorkerResult) {
+        let in_flight = self
+            .in_flight
+            .take()
+            .expect("The worker only sends results for requests in flight");
+        let mut project = result.snapshot.project;
+        project.set_cancellation_flag(Default::default());
+        self.project = Some(project);
+        self.workspace_diagnostics_cache = result.snapshot.workspace_diagnostics_cache;
+        let id = in_flight.request.id.clone();
+        match result.response {
+            Ok(Ok(response)) if in_flight.cancel_code.is_none() => self.respond(response),
+            Ok(Err(Cancelled)) if in_flight.interrupted && in_flight.cancel_code.is_none() => {
+                tracing::info!("Request {id} was interrupted and will be handled again");
+                // The messages that interrupted the request are handled first, but it is still
+                // handled before requests that were sent after it.
+                let position = self
+                    .pending_messages
+                    .iter()
+                    .position(|msg| matches!(msg, Message::Request(_)))
+                    .unwrap_or(self.pending_messages.len());
+                self.pending_messages
+                    .insert(position, Message::Request(in_flight.request));
+            }
+            Ok(_) => {
+                let code = in_flight
+                    .cancel_code
+                    .unwrap_or(lsp_server::ErrorCode::RequestCanceled);
+                tracing::info!("Cancelled request {id} in flight");
+                let message = match code {
+                    lsp_server::ErrorCode::ContentModified => "Content modified",
+                    _ => "Request cancelled",
+                };
                 self.respond(lsp_server::Response::new_err(
-                    request_id,
-                    lsp_server::ErrorCode::InternalError as i32,
-                    format!(
-                        "Server paniced, will now restart (version {})",
-                        env!("CARGO_PKG_VERSION")
-                    ),
+                    id,
+                    code as i32,
+                    message.to_owned(),
                 ));
             }
-            self.recover_from_panic();
-            tracing::info!("Recovered from panic");
-            false
-        })
+            Err(_) => self.recover_from_request_panic(Some(id)),
+        }
+    }
+
+    /// Notifications and responses of the client need the project, which is owned by the worker
+    /// while a request is in flight. If the document of the request changed, its result would be
+    /// outdated and the request is cancelled. Otherwise it is interrupted and handled again
+    /// after the messages.
+    fn handle_messages_while_in_flight(&mut self) {
+        use lsp_types::notification::{
+            DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        };
+        let Some(in_flight) = &self.in_flight else {
+            return;
+        };
+        let mut needs_project = false;
+        let mut changes_document = false;
+        for msg in &self.pending_messages {
+            match msg {
+                Message::Notification(n) if !n.method.starts_with("$/") => {
+                    needs_project = true;
+                    if [
+                        DidOpenTextDocument::METHOD,
+                        DidChangeTextDocument::METHOD,
+                        DidCloseTextDocument::METHOD,
+                    ]
+                    .contains(&n.method.as_str())
+                        && in_flight.document.is_some()
+                        && n.params["textDocument"]["uri"].as_str() == in_flight.document.as_deref()
+                    {
+                        changes_document = true;
+                    }
+                }
+                Message::Response(_) => needs_project = true,
+                _ => (),
+            }
+        }
+        if changes_document {
+            self.cancel_in_flight_request(lsp_server::ErrorCode::ContentModified);
+        } else if needs_project {
+            self.interrupt_in_flight_request();
+        }
+    }
+
+    fn interrupt_in_flight_request(&mut self) {
+        if let Some(in_flight) = &mut self.in_flight
+            && in_flight.cancel_code.is_none()
+            && !in_flight.interrupted
+        {
+            tracing::info!("Interrupt request {} in flight", in_flight.request.id);
+            in_flight.interrupted = true;
+            in_flight.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn cancel_in_flight_request(&mut self, code: lsp_server::ErrorCode) {
+        if let Some(in_flight) = &mut self.in_flight
+            && in_flight.cancel_code.is_none()
+        {
+            tracing::info!(
+                "Cancel request {} in flight ({code:?})",
+                in_flight.request.id
+            );
+            in_flight.cancel_code = Some(code);
+            in_flight.cancelled.store(true, Ordering::Relaxed);
+        }
     }
 
-    fn recover_from_panic(&mut self) {
+    pub(crate) fn recover_from_panic(&mut self) {
         self.changed_in_memory_files
             .as_ref()
             .write()
             .unwrap()
             .clear();
         if let Some(project) = self.project.take() {
+            // The events of the old file watcher are not relevant anymore.
+            self.notify_receiver = None;
             self.panic_recovery = Some(project.into_panic_recovery());
         }
     }
@@ -469,7 +959,7 @@ impl<'sender> GlobalState<'sender> {
     fn on_notify_events(&mut self, event: NotifyEvent) {
         self.on_notify_event(event);
         // Check all events in the Notify queue
-        while let Some(next) = self.notify_receiver().and_then(|n| {
+        while let Some(next) = self.notify_receiver.as_ref().and_then(|n| {
             if cfg!(target_os = "windows") {
                 // On Windows some events simply cause multiple events (e.g. rename), but also writes
                 // to files may be a Create + Modify, so we simply wait. This is useful for tests, but
@@ -482,62 +972,114 @@ impl<'sender> GlobalState<'sender> {
         }) {
             self.on_notify_event(next);
         }
+        self.refresh_diagnostics();
     }
 
     fn on_notify_event(&mut self, event: NotifyEvent) {
-        if let Some(project) = &mut self.project {
-            match event {
-                Ok(event) => {
-                    match event.kind {
-                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
-                            // This is simply for tests
-                            GLOBAL_NOTIFY_EVENT_COUNTER
-                                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-
-                            tracing::info!("Notify Event: {event:?}");
-                            for path in event.paths.into_iter() {
-                                if self.paths_that_invalidate_whole_project.contains(&path) {
-                                    // Since invalidating the whole project is as bad as a panic we
-                                    // just use that mechanism to recover from such a worst case
-                                    // change. This might be something like changing the used
-                                    // python version.
-
-                                    // TODO this ignores a few events in the vec that might
-                                    // invalidate additional files that get caught in the panic
-                                    // recovery and reused. Currently this is not handled
-                                    // correctly. If the VFS rechecks files, then it could be fine,
-                                    // BUT we should document that here.
-                                    tracing::info!(
-                                        "Reindex because a file was changed that invalidates the whole project: {path:?}"
-                                    );
-                                    self.recover_from_panic();
-                                    return;
-                                }
-                                if let Some(p) = path.to_str() {
-                                    debug_assert!(path.is_absolute());
-                                    let s = if cfg!(target_os = "windows")
-                                        && p.starts_with(r#"\\?\"#)
-                                    {
-                                        &p[4..]
-                                    } else {
-                                        p
-                                    };
-                                    let p = project.vfs_handler().unchecked_abs_path(s);
-                                    project.invalidate_path(&p)
-                                }
+        if self.project.is_none() {
+            return;
+        }
+        match event {
+            Ok(event) => {
+                match event.kind {
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+                        // This is simply for tests
+                        GLOBAL_NOTIFY_EVENT_COUNTER
+                            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+                        tracing::info!("Notify Event: {event:?}");
+                        for path in event.paths.into_iter() {
+                            if !self.invalidate_changed_path(&path) {
+                                return;
                             }
                         }
-                        EventKind::Access(_) => (), // Ignore access, they are probably never relevant
-                        _ => tracing::debug!("Ignored Notify Event: {event:?}"),
                     }
-                }
-                Err(err) => {
-                    tracing::error!(
-                        "Invalidating project, because of a notify event error: {err:?}"
-                    );
-                    self.project = None;
+                    EventKind::Access(_) => (), // Ignore access, they are probably never relevant
+                    _ => tracing::debug!("Ignored Notify Event: {event:?}"),
                 }
             }
+            Err(err) => {
+                tracing::error!("Invalidating project, because of a notify event error: {err:?}");
+                self.project = None;
+                self.notify_receiver = None;
+            }
+        }
+    }
+
+    /// Invalidates a path that was changed outside of the editor. Returns false if the whole
+    /// project had to be reindexed, in which case invalidating other paths is not necessary.
+    pub(crate) fn invalidate_changed_path(&mut self, path: &Path) -> bool {
+        if self.paths_that_invalidate_whole_project.contains(path) {
+            // Since invalidating the whole project is as bad as a panic we just use that
+            // mechanism to recover from such a worst case change. This might be something like
+            // changing the used python version.
+
+            // TODO this ignores a few events in the vec that might invalidate additional files
+            // that get caught in the panic recovery and reused. Currently this is not handled
+            // correctly. If the VFS rechecks files, then it could be fine, BUT we should document
+            // that here.
+            tracing::info!(
+                "Reindex because a file was changed that invalidates the whole project: {path:?}"
+            );
+            self.recover_from_panic();
+            return false;
+        }
+        if let Some(project) = &mut self.project
+            && let Some(p) = path.to_str()
+        {
+            debug_assert!(path.is_absolute());
+            let s = if cfg!(target_os = "windows") && p.starts_with(r#"\\?\"#) {
+                &p[4..]
+            } else {
+                p
+            };
+            let p = project.vfs_handler().unchecked_abs_path(s);
+            project.invalidate_path(&p)
+        }
+        true
+    }
+
+    /// Asks the client to watch Python and config files. This is especially useful in remote or
+    /// containerized setups, where our own file watcher might not receive any events. Our own
+    /// watcher is still used as a fallback, because clients only watch files in the workspace.
+    fn register_file_watchers(&mut self) {
+        use lsp_types::{
+            DidChangeWatchedFilesRegistrationOptions, FileSystemWatcher, GlobPattern, Registration,
+            RegistrationParams, notification::DidChangeWatchedFiles, request::RegisterCapability,
+        };
+        if !self
+            .client_capabilities
+            .did_change_watched_files_dynamic_registration()
+        {
+            return;
+        }
+        let watchers = [
+            "**/*.{py,pyi}",
+            "**/{pyproject.toml,mypy.ini,.mypy.ini,setup.cfg}",
+        ]
+        .into_iter()
+        .map(|glob| FileSystemWatcher {
+            glob_pattern: GlobPattern::String(glob.to_owned()),
+            kind: None,
+        })
+        .collect();
+        self.send_request::<RegisterCapability>(RegistrationParams {
+            registrations: vec![Registration {
+                id: "zubanls/watched-files".to_owned(),
+                method: DidChangeWatchedFiles::METHOD.to_owned(),
+                register_options: Some(
+                    serde_json::to_value(DidChangeWatchedFilesRegistrationOptions { watchers })
+                        .unwrap(),
+                ),
+            }],
+        });
+    }
+
+    /// Asks clients that support it to pull diagnostics again, because files that are not open
+    /// in the editor might have new diagnostics now.
+    pub(crate) fn refresh_diagnostics(&mut self) {
+        if self.client_capabilities.diagnostics_refresh() {
+            self.send_request::<lsp_types::request::WorkspaceDiagnosticRefresh>(());
         }
     }
 
@@ -551,20 +1093,128 @@ impl<'sender> GlobalState<'sender> {
     }
 
     fn complete_request(&mut self, response: lsp_server::Response) {
-        tracing::error!("unhandled request: {:?}", response);
+        if !self.sent_requests.remove(&response.id) {
+            tracing::error!("unhandled request: {:?}", response);
+        } else if let Some(err) = response.error {
+            tracing::warn!("The client responded with an error: {err:?}");
+        } else if let Some((_, roots)) = self
+            .configuration_request
+            .take_if(|(id, _)| *id == response.id)
+        {
+            // There is one item for every workspace folder. Clients that don't know about the
+            // section return null, in that case the initialization options are used.
+            let items = match response.result {
+                Some(serde_json::Value::Array(items)) => items,
+                _ => vec![],
+            };
+            self.change_folder_editor_settings(roots.iter().cloned().zip(items).collect());
+        }
+    }
+
+    fn send_request<R: lsp_types::request::Request>(
+        &mut self,
+        params: R::Params,
+    ) -> lsp_server::RequestId {
+        let id = lsp_server::RequestId::from(self.next_request_id);
+        self.next_request_id += 1;
+        self.sent_requests.insert(id.clone());
+        let request = Request::new(id.clone(), R::METHOD.to_owned(), params);
+        _ = self.sender.send(request.into());
+        id
+    }
+
+    fn send_notification<N: lsp_types::notification::Notification>(&self, params: N::Params) {
+        let not = lsp_server::Notification::new(N::METHOD.to_owned(), params);
+        _ = self.sender.send(not.into());
+    }
+
+    /// Reports the start of a long running operation with `$/progress`, if the client supports
+    /// it. The returned token is needed to end the progress.
+    fn begin_progress(&mut self, title: &str) -> Option<lsp_types::ProgressToken> {
+        use lsp_types::{
+            ProgressParams, ProgressParamsValue, WorkDoneProgress, WorkDoneProgressBegin,
+            WorkDoneProgressCreateParams, notification::Progress, request::WorkDoneProgressCreate,
+        };
+        if !self.client_capabilities.work_done_progress() {
+            return None;
+        }
+        let token = lsp_types::ProgressToken::String(format!("zubanls/{}", self.next_request_id));
+        self.send_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+            token: token.clone(),
+        });
+        self.send_notification::<Progress>(ProgressParams {
+            token: token.clone(),
+            value: ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_owned(),
+                cancellable: Some(false),
+                message: None,
+                percentage: None,
+            })),
+        });
+        Some(token)
+    }
+
+    fn end_progress(&mut self, token: lsp_types::ProgressToken) {
+        use lsp_types::{
+            ProgressParams, ProgressParamsValue, WorkDoneProgress, WorkDoneProgressEnd,
+            notification::Progress,
+        };
+        self.send_notification::<Progress>(ProgressParams {
+            token,
+            value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(WorkDoneProgressEnd {
+                message: None,
+            })),
+        });
+    }
+
+    /// Applies all `$/cancelRequest` notifications that are pending. Requests that were not
+    /// handled yet are answered with `RequestCanceled`, the request in flight is answered once the
+    /// worker stopped and cancellations of requests that were already answered are ignored.
+    fn cancel_pending_requests(&mut self) {
+        use lsp_types::notification::{Cancel, Notification as _};
+        let mut cancelled = vec![];
+        self.pending_messages.retain(|msg| match msg {
+            Message::Notification(not) if not.method == Cancel::METHOD => {
+                match from_json::<lsp_types::CancelParams>(Cancel::METHOD, &not.params) {
+                    Ok(params) => cancelled.push(match params.id {
+                        lsp_types::NumberOrString::Number(id) => lsp_server::RequestId::from(id),
+                        lsp_types::NumberOrString::String(id) => lsp_server::RequestId::from(id),
+                    }),
+                    Err(err) => tracing::error!("{err}"),
+                }
+                false
+            }
+            _ => true,
+        });
+        for id in cancelled {
+            if self
+                .in_flight
+                .as_ref()
+                .is_some_and(|in_flight| in_flight.request.id == id)
+            {
+                self.cancel_in_flight_request(lsp_server::ErrorCode::RequestCanceled);
+                continue;
+            }
+            let before = self.pending_messages.len();
+            self.pending_messages
+                .retain(|msg| !matches!(msg, Message::Request(r) if r.id == id));
+            if self.pending_messages.len() != before {
+                tracing::info!("Cancelled request {id}");
+                self.respond(lsp_server::Response::new_err(
+                    id,
+                    lsp_server::ErrorCode::RequestCanceled as i32,
+                    "Request cancelled".to_owned(),
+                ));
+            }
+        }
     }
 
     fn publish_diagnostics_if_necessary(&mut self) {
         let encoding = self.client_capabilities.negotiated_encoding();
         let files = std::mem::take(&mut *self.changed_in_memory_files.as_ref().write().unwrap());
         if !files.is_empty() {
-            tracing::info!(
-                "Needs to publish diagnostics for {} files start at #{}",
-                files.len(),
-                self.sent_diagnostic_count
-            );
+            tracing::info!("Needs to publish diagnostics for {} files", files.len());
             for path in files {
-                self.sent_diagnostic_count += 1;
                 let project = self.project();
                 let Some(document) = project.document(&path) else {
                     tracing::info!(
@@ -573,12 +1223,8 @@ impl<'sender> GlobalState<'sender> {
                     );
                     continue;
                 };
-                let diagnostics = Self::diagnostics_for_file(document, encoding);
-                tracing::info!(
-                    "Publish diagnostics for {}, (#{} overall)",
-                    path.as_uri(),
-                    self.sent_diagnostic_count,
-                );
+                let diagnostics = GlobalStateSnapshot::diagnostics_for_file(document, encoding);
+                tracing::info!("Publish diagnostics for {}", path.as_uri());
                 tracing::trace!(
                     "Diagnostics [{}]",
                     diagnostics
@@ -627,6 +1273,39 @@ impl<'sender> GlobalState<'sender> {
     }
 }
 
+impl GlobalStateSnapshot {
+    pub(crate) fn project(&mut self) -> &mut Project {
+        &mut self.project
+    }
+
+    pub(crate) fn use_completion_snippets(&self) -> bool {
+        self.use_completion_snippets
+    }
+
+    /// Handlers should check this between steps of long running work, because the result of a
+    /// cancelled request is never used.
+    pub(crate) fn check_cancelled(&self) -> anyhow::Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            bail!(Cancelled)
+        }
+        Ok(())
+    }
+
+    /// Sends a part of the result of a request with `$/progress`, see the partial result
+    /// handling of the protocol.
+    pub(crate) fn send_partial_result(
+        &self,
+        token: &lsp_types::ProgressToken,
+        value: impl Serialize,
+    ) {
+        let not = lsp_server::Notification::new(
+            lsp_types::notification::Progress::METHOD.to_owned(),
+            serde_json::json!({ "token": token, "value": value }),
+        );
+        _ = self.sender.send(not.into());
+    }
+}
+
 impl<'sender> NotificationDispatcher<'_, 'sender> {
     fn on_sync_mut<N>(
         &mut self,
@@ -711,6 +1390,38 @@ impl<'sender> RequestDispatcher<'_, 'sender> {
         self
     }
 
+    /// Handles a request on the worker thread, see `GlobalStateSnapshot`.
+    fn on_worker<R>(
+        &mut self,
+        f: fn(&mut GlobalStateSnapshot, R::Params) -> anyhow::Result<R::Result>,
+    ) -> &mut Self
+    where
+        R: lsp_types::request::Request + 'static,
+        R::Params: DeserializeOwned + Send + std::fmt::Debug,
+        R::Result: Serialize,
+    {
+        let (req, params) = match self.parse::<R>() {
+            Some(it) => it,
+            None => return self,
+        };
+        let id = req.id.clone();
+        self.global_state.spawn_on_worker(
+            req.clone(),
+            Box::new(move |snapshot| {
+                let _guard =
+                    tracing::info_span!("request", method = ?req.method, "request_id" = ?req.id)
+                        .entered();
+                tracing::debug!(?params);
+                let result = f(snapshot, params);
+                if snapshot.check_cancelled().is_err() {
+                    return Err(Cancelled);
+                }
+                result_to_response::<R>(id, result)
+            }),
+        );
+        self
+    }
+
     fn parse<R>(&mut self) -> Option<(lsp_server::Request, R::Params)>
     where
         R: lsp_types::request::Request,
@@ -753,7 +1464,17 @@ pub fn from_json<T: DeserializeOwned>(
         .map_err(|e| anyhow::format_err!("Failed to deserialize {what}: {e}; {json}"))
 }
 
-struct Cancelled(); // TODO currently unused
+/// The error of a request handler that stopped, because the request was cancelled.
+#[derive(Debug)]
+struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Request cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
 
 fn result_to_response<R>(
     id: lsp_server::RequestId,
@@ -768,8 +1489,8 @@ where
         Ok(resp) => lsp_server::Response::new_ok(id, &resp),
         Err(e) => match e.downcast::<LspError>() {
             Ok(lsp_error) => lsp_server::Response::new_err(id, lsp_error.code, lsp_error.message),
-            Err(e) => match e.downcast::<u8/*TODO Cancelled*/>() {
-                Ok(_cancelled) => todo!(), // return Err(cancelled),
+            Err(e) => match e.downcast::<Cancelled>() {
+                Ok(cancelled) => return Err(cancelled),
                 Err(e) => lsp_server::Response::new_err(
                     id,
                     lsp_server::ErrorCode::InternalError as i32,
@@ -799,7 +1520,7 @@ impl std::fmt::Display for LspError {
 
 impl std::error::Error for LspError {}
 
-fn patch_path_prefix(path: &Uri) -> anyhow::Result<String> {
+pub(crate) fn patch_path_prefix(path: &Uri) -> anyhow::Result<String> {
     let (_, path) = unpack_uri(path)?;
     use std::path::{Component, Prefix};
     if cfg!(windows) {
@@ -875,3 +1596,32 @@ fn patch_path_prefix_works() {
     );
     */
 }
+
+fn editor_settings_from_json(value: &serde_json::Value) -> anyhow::Result<EditorSettings> {
+    use serde_json::Value as Json;
+    fn to_toml(value: &Json) -> anyhow::Result<toml_edit::Value> {
+        Ok(match value {
+            Json::Bool(b) => (*b).into(),
+            Json::Number(n) => match n.as_i64() {
+                Some(i) => i.into(),
+                None => n.as_f64().unwrap_or_default().into(),
+            },
+            Json::String(s) => s.as_str().into(),
+            Json::Array(items) => {
+                toml_edit::Value::Array(items.iter().map(to_toml).collect::<anyhow::Result<_>>()?)
+            }
+            Json::Null | Json::Object(_) => bail!("Unexpected value {value} in the zuban settings"),
+        })
+    }
+    let items = match value {
+        Json::Null => return Ok(EditorSettings::default()),
+        Json::Object(items) => items,
+        _ => bail!("Expected the zuban settings to be an object, but got {value}"),
+    };
+    EditorSettings::new(
+        items
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), to_toml(value)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?,
+    )
+}
@@ -63,11 +63,21 @@ impl Connection {
         position_encodings: Option<Vec<lsp_types::PositionEncodingKind>>,
         pull_diagnostics: bool,
     ) -> InitializeResult {
-        let capabilities = lsp_types::ClientCapabilities {
+        let capabilities = Self::client_capabilities(position_encodings, pull_diagnostics);
+        self.initialize_with_capabilities(roots, capabilities)
+    }
+
+    pub(crate) fn client_capabilities(
+        position_encodings: Option<Vec<lsp_types::PositionEncodingKind>>,
+        pull_diagnostics: bool,
+    ) -> lsp_types::ClientCapabilities {
+        lsp_types::ClientCapabilities {
             workspace: Some(lsp_types::WorkspaceClientCapabilities {
+                // Dynamic registration is not enabled, because the server would otherwise send
+                // registration requests that every test would have to handle.
                 did_change_watched_files: Some(
                     lsp_types::DidChangeWatchedFilesClientCapabilities {
-                        dynamic_registration: Some(true),
+                        dynamic_registration: None,
                         relative_pattern_support: None,
                     },
                 ),
@@ -87,10 +97,44 @@ impl Connection {
             }),
             text_document: Some(TextDocumentClientCapabilities {
                 diagnostic: pull_diagnostics.then(DiagnosticClientCapabilities::default),
+                completion: Some(lsp_types::CompletionClientCapabilities {
+                    completion_item: Some(lsp_types::CompletionItemCapability {
+                        snippet_support: Some(true),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                code_action: Some(lsp_types::CodeActionClientCapabilities {
+                    code_action_literal_support: Some(lsp_types::CodeActionLiteralSupport {
+                        code_action_kind: lsp_types::CodeActionKindLiteralSupport {
+                            value_set: vec!["refactor.extract".into(), "refactor.inline".into()],
+                        },
+                    }),
+                    resolve_support: Some(lsp_types::CodeActionCapabilityResolveSupport {
+                        properties: vec!["edit".to_owned()],
+                    }),
+                    ..Default::default()
+                }),
                 ..Default::default()
             }),
             ..Default::default()
-        };
+        }
+    }
+
+    pub(crate) fn initialize_with_capabilities(
+        &self,
+        roots: &[&str],
+        capabilities: lsp_types::ClientCapabilities,
+    ) -> InitializeResult {
+        self.initialize_with_options(roots, capabilities, None)
+    }
+
+    pub(crate) fn initialize_with_options(
+        &self,
+        roots: &[&str],
+        capabilities: lsp_types::ClientCapabilities,
+        initialization_options: Option<Value>,
+    ) -> InitializeResult {
         let initialize_params = lsp_types::InitializeParams {
             workspace_folders: Some(
                 roots
@@ -107,6 +151,7 @@ impl Connection {
                     .collect(),
             ),
             capabilities,
+            initialization_options,
             ..Default::default()
         };
         let response = self.request::<lsp_types::request::Initialize>(initialize_params);
@@ -115,6 +160,16 @@ impl Connection {
     }
 
     pub(crate) fn request_with_response<R>(&self, params: R::Params) -> lsp_server::Response
+    where
+        R: lsp_types::request::Request,
+        R::Params: Serialize,
+    {
+        self.send_request::<R>(params);
+        self.expect_response()
+    }
+
+    /// Sends a request without waiting for the response.
+    pub(crate) fn send_request<R>(&self, params: R::Params) -> i32
     where
         R: lsp_types::request::Request,
         R::Params: Serialize,
@@ -124,8 +179,7 @@ impl Connection {
 
         let r = lsp_server::Request::new(id.into(), R::METHOD.to_string(), params);
         self.send(r);
-
-        self.expect_response()
+        id
     }
 
     pub(crate) fn request_with_expected_error<R>(
@@ -181,7 +235,20 @@ impl Connection {
             .expect("Expected to be able to send a message");
     }
 
-    fn expect_response(&self) -> lsp_server::Response {
+    pub(crate) fn expect_request<R>(&self) -> (lsp_server::RequestId, R::Params)
+    where
+        R: lsp_types::request::Request,
+    {
+        match self.recv_timeout() {
+            Ok(Message::Request(req)) => req
+                .extract::<R::Params>(R::METHOD)
+                .unwrap_or_else(|err| panic!("Wanted {}, got {err:?}", R::METHOD)),
+            Ok(msg) => panic!("Unexpected message, expected request: {msg:?}"),
+            Err(err) => panic!("Expected the request {}, but got: {err:?}", R::METHOD),
+        }
+    }
+
+    pub(crate) fn expect_response(&self) -> lsp_server::Response {
         match self.recv_timeout() {
             Ok(Message::Response(response)) => response,
             Ok(msg) => panic!("Unexpected message, expected response: {msg:?}"),
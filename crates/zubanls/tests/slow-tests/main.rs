@@ -7,19 +7,27 @@
 //! specific JSON shapes here -- there's little value in such tests, as we can't
 //! be sure without a real client anyway.
 
-use std::str::FromStr;
+use std::{path::Path, str::FromStr};
 
 use lsp_server::Response;
 use lsp_types::{
-    CompletionItemKind, CompletionParams, DiagnosticServerCapabilities, DocumentDiagnosticParams,
-    DocumentDiagnosticReport, DocumentDiagnosticReportResult, DocumentHighlightKind,
-    DocumentHighlightParams, GotoDefinitionParams, HoverParams, NumberOrString,
-    PartialResultParams, Position, PositionEncodingKind, ReferenceContext, ReferenceParams,
-    RenameParams, TextDocumentIdentifier, TextDocumentPositionParams, Uri, WorkDoneProgressParams,
+    CompletionItemKind, CompletionParams, DiagnosticServerCapabilities,
+    DidChangeConfigurationParams, DidChangeWatchedFilesParams,
+    DidChangeWatchedFilesRegistrationOptions, DidChangeWorkspaceFoldersParams,
+    DidOpenTextDocumentParams, DocumentDiagnosticParams, DocumentDiagnosticReport,
+    DocumentDiagnosticReportResult, DocumentHighlightKind, DocumentHighlightParams,
+    ExecuteCommandParams, FileChangeType, FileEvent, GlobPattern, GotoDefinitionParams,
+    HoverParams, NumberOrString, PartialResultParams, Position, PositionEncodingKind,
+    PreviousResultId, ReferenceContext, ReferenceParams, RenameParams, TextDocumentIdentifier,
+    TextDocumentPositionParams, Uri, WorkDoneProgressParams, WorkspaceDiagnosticParams,
+    WorkspaceDiagnosticReportPartialResult, WorkspaceDiagnosticReportResult,
+    WorkspaceDocumentDiagnosticReport, WorkspaceFolder, WorkspaceFoldersChangeEvent,
+    notification::{DidChangeConfiguration, DidChangeWatchedFiles, DidChangeWorkspaceFolders},
     request::{
-        Completion, DocumentDiagnosticRequest, DocumentHighlightRequest, GotoDeclaration,
-        GotoDefinition, GotoImplementation, GotoTypeDefinition, HoverRequest, PrepareRenameRequest,
-        References, Rename,
+        Completion, DocumentDiagnosticRequest, DocumentHighlightRequest, ExecuteCommand,
+        GotoDeclaration, GotoDefinition, GotoImplementation, GotoTypeDefinition, HoverRequest,
+        PrepareRenameRequest, References, RegisterCapability, Rename, ResolveCompletionItem,
+        WorkspaceConfiguration, WorkspaceDiagnosticRequest,
     },
 };
 
@@ -27,14 +35,14 @@ mod connection;
 mod support;
 
 use connection::Connection;
-use serde_json::json;
+use serde_json::{Value, json};
 // It is very unfortunate, but we need to tag every test in this crate, to avoid having set_hook
 // overwritten by the thread spawning? test setup? I'm not sure what it is exactly, but I have seen
 // cases where the panic hook disappeared from tests and would be reverted to the default one,
 // which is obviously very unfortunate.
 // https://users.rust-lang.org/t/is-there-any-way-to-set-panic-hook-reliably-in-the-test/18202
 use serial_test::{parallel, serial};
-use support::Project;
+use support::{Project, TestWaitForCancellation};
 
 #[test]
 #[parallel]
@@ -57,8 +65,7 @@ fn basic_server_setup() {
             unreachable!()
         };
         assert!(diagnostics.inter_file_dependencies);
-        // For now this is false, but this might change
-        assert!(!diagnostics.workspace_diagnostics);
+        assert!(diagnostics.workspace_diagnostics);
     }
     assert_eq!(response.server_info.expect("server_info").name, "zubanls");
     con.shutdown_and_exit()
@@ -103,6 +110,127 @@ fn exit_without_shutdown() {
     con.notify::<lsp_types::notification::Exit>(());
 }
 
+#[test]
+#[parallel]
+fn cancel_pending_request() {
+    let con = Connection::initialized(&["/foo/bar"], None, true);
+    // The first request blocks the server until it is cancelled, so the second request is still
+    // pending when it is cancelled.
+    let in_flight = con.send_request::<TestWaitForCancellation>(wait_for_cancellation_params());
+    let pending = con.send_request::<lsp_types::request::DocumentDiagnosticRequest>(
+        DocumentDiagnosticParams {
+            text_document: TextDocumentIdentifier::new(
+                Uri::from_str("file:///foo/bar/m.py").unwrap(),
+            ),
+            identifier: None,
+            previous_result_id: None,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        },
+    );
+    for id in [pending, in_flight] {
+        con.notify::<lsp_types::notification::Cancel>(lsp_types::CancelParams {
+            id: lsp_types::NumberOrString::Number(id),
+        });
+        let response = con.expect_response();
+        assert_eq!(response.id, id.into());
+        assert!(response.result.is_none());
+        let error = response.error.expect("Expected an error");
+        assert_eq!(error.code, lsp_server::ErrorCode::RequestCanceled as i32);
+    }
+    con.shutdown_and_exit()
+}
+
+#[test]
+#[parallel]
+fn change_document_while_request_in_flight() {
+    let con = Connection::initialized(&["/foo/bar"], None, true);
+    let id = con.send_request::<TestWaitForCancellation>(wait_for_cancellation_params());
+    con.notify::<lsp_types::notification::DidOpenTextDocument>(DidOpenTextDocumentParams {
+        text_document: lsp_types::TextDocumentItem {
+            uri: Uri::from_str("file:///foo/bar/m.py").unwrap(),
+            language_id: "python".to_owned(),
+            version: 0,
+            text: "x = 1\n".to_owned(),
+        },
+    });
+    let response = con.expect_response();
+    assert_eq!(response.id, id.into());
+    let error = response.error.expect("Expected an error");
+    assert_eq!(error.code, lsp_server::ErrorCode::ContentModified as i32);
+    con.shutdown_and_exit()
+}
+
+#[test]
+#[parallel]
+fn change_other_document_while_request_in_flight() {
+    // Diagnostics are pushed, so it's visible that the change is handled.
+    let con = Connection::initialized(&["/foo/bar"], None, false);
+    let id = con.send_request::<TestWaitForCancellation>(wait_for_cancellation_params());
+    con.notify::<lsp_types::notification::DidOpenTextDocument>(DidOpenTextDocumentParams {
+        text_document: lsp_types::TextDocumentItem {
+            uri: Uri::from_str("file:///foo/bar/n.py").unwrap(),
+            language_id: "python".to_owned(),
+            version: 0,
+            text: "1()\n".to_owned(),
+        },
+    });
+    // The request is interrupted to handle the change and then continues.
+    let diagnostics = con.expect_notification::<lsp_types::notification::PublishDiagnostics>();
+    assert_eq!(diagnostics.uri.as_str(), "file:///foo/bar/n.py");
+    assert_eq!(diagnostics.diagnostics.len(), 1);
+
+    con.notify::<lsp_types::notification::Cancel>(lsp_types::CancelParams {
+        id: lsp_types::NumberOrString::Number(id),
+    });
+    let response = con.expect_response();
+    assert_eq!(response.id, id.into());
+    let error = response.error.expect("Expected an error");
+    assert_eq!(error.code, lsp_server::ErrorCode::RequestCanceled as i32);
+    con.shutdown_and_exit()
+}
+
+fn wait_for_cancellation_params() -> TextDocumentPositionParams {
+    TextDocumentPositionParams::new(
+        TextDocumentIdentifier::new(Uri::from_str("file:///foo/bar/m.py").unwrap()),
+        Position::new(0, 0),
+    )
+}
+
+#[test]
+#[parallel]
+fn progress_while_indexing() {
+    use lsp_types::{
+        ProgressParamsValue, WorkDoneProgress, notification::Progress,
+        request::WorkDoneProgressCreate,
+    };
+    let con = Connection::new();
+    let mut capabilities = Connection::client_capabilities(None, true);
+    capabilities.window = Some(lsp_types::WindowClientCapabilities {
+        work_done_progress: Some(true),
+        ..Default::default()
+    });
+    con.initialize_with_capabilities(&["/foo/bar"], capabilities);
+
+    let (id, create) = con.expect_request::<WorkDoneProgressCreate>();
+    con.send(Response::new_ok(id, ()));
+
+    let begin = con.expect_notification::<Progress>();
+    assert_eq!(begin.token, create.token);
+    let ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(begin)) = begin.value else {
+        panic!("Expected a begin progress, got {:?}", begin.value)
+    };
+    assert_eq!(begin.title, "Indexing");
+
+    let end = con.expect_notification::<Progress>();
+    assert_eq!(end.token, create.token);
+    assert!(matches!(
+        end.value,
+        ProgressParamsValue::WorkDone(WorkDoneProgress::End(_))
+    ));
+    con.shutdown_and_exit()
+}
+
 #[test]
 #[serial]
 fn diagnostics_for_saved_files() {
@@ -212,6 +340,156 @@ fn diagnostics_for_saved_files() {
     }
 }
 
+#[test]
+#[parallel]
+fn workspace_diagnostics() {
+    let server = Project::with_fixture(
+        r#"
+        [file pyproject.toml]
+
+        [file m.py]
+        from n import x
+        x()
+
+        [file n.py]
+        x = 1
+
+        [file ok.py]
+        "#,
+    )
+    .into_server();
+
+    let request = |previous_result_ids| {
+        let WorkspaceDiagnosticReportResult::Report(report) = server
+            .request::<WorkspaceDiagnosticRequest>(WorkspaceDiagnosticParams {
+                identifier: None,
+                previous_result_ids,
+                partial_result_params: PartialResultParams::default(),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+        else {
+            unreachable!()
+        };
+        report.items
+    };
+    let summarize = |items: &[WorkspaceDocumentDiagnosticReport]| {
+        let mut summary: Vec<_> = items
+            .iter()
+            .map(|item| match item {
+                WorkspaceDocumentDiagnosticReport::Full(full) => {
+                    let file = full.uri.as_str().rsplit('/').next().unwrap().to_owned();
+                    let items = &full.full_document_diagnostic_report.items;
+                    (file, items.iter().map(|d| d.message.clone()).collect())
+                }
+                WorkspaceDocumentDiagnosticReport::Unchanged(unchanged) => {
+                    panic!("Unexpected unchanged report {unchanged:?}")
+                }
+            })
+            .collect();
+        summary.sort();
+        summary
+    };
+    let previous_ids = |items: &[WorkspaceDocumentDiagnosticReport]| {
+        items
+            .iter()
+            .map(|item| match item {
+                WorkspaceDocumentDiagnosticReport::Full(full) => PreviousResultId {
+                    uri: full.uri.clone(),
+                    value: full
+                        .full_document_diagnostic_report
+                        .result_id
+                        .clone()
+                        .unwrap(),
+                },
+                WorkspaceDocumentDiagnosticReport::Unchanged(_) => unreachable!(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let items = request(vec![]);
+    assert_eq!(
+        summarize(&items),
+        [
+            ("m.py".to_owned(), vec!["\"int\" not callable".to_owned()]),
+            ("n.py".to_owned(), vec![]),
+            ("ok.py".to_owned(), vec![]),
+        ]
+    );
+
+    // Nothing changed, so the client can keep its diagnostics
+    assert!(request(previous_ids(&items)).is_empty());
+
+    // A change in n.py also changes the diagnostics of m.py, only changed files are reported.
+    server.open_in_memory_file("n.py", "def x() -> None: ...\n1()\n");
+    assert_eq!(
+        summarize(&request(previous_ids(&items))),
+        [
+            ("m.py".to_owned(), vec![]),
+            ("n.py".to_owned(), vec!["\"int\" not callable".to_owned()]),
+        ]
+    );
+
+    // ok.py has to be checked again, but its diagnostics did not change
+    let items = request(vec![]);
+    server.open_in_memory_file("ok.py", "# Still ok\n");
+    assert!(request(previous_ids(&items)).is_empty());
+}
+
+#[test]
+#[parallel]
+fn workspace_diagnostics_with_partial_results() {
+    let mut fixture = "[file pyproject.toml]\n".to_owned();
+    for i in 0..60 {
+        fixture += &format!("[file m{i}.py]\n1()\n");
+    }
+    let server = Project::with_fixture(&fixture).into_server();
+
+    let token = NumberOrString::String("partial".to_owned());
+    server.send_request::<WorkspaceDiagnosticRequest>(WorkspaceDiagnosticParams {
+        identifier: None,
+        previous_result_ids: vec![],
+        partial_result_params: PartialResultParams {
+            partial_result_token: Some(token.clone()),
+        },
+        work_done_progress_params: WorkDoneProgressParams::default(),
+    });
+    let first = server.expect_notification::<WorkspaceDiagnosticsPartialResult>();
+    assert_eq!(first.token, token);
+    assert_eq!(first.value.items.len(), 50);
+    // The remaining items are also sent as a partial result
+    let second = server.expect_notification::<WorkspaceDiagnosticsPartialResult>();
+    assert_eq!(second.token, token);
+    assert_eq!(second.value.items.len(), 10);
+
+    let response = server.expect_response();
+    let WorkspaceDiagnosticReportResult::Report(report) =
+        serde_json::from_value(response.result.unwrap()).unwrap()
+    else {
+        unreachable!()
+    };
+    assert!(report.items.is_empty());
+    for item in first.value.items.iter().chain(&second.value.items) {
+        let WorkspaceDocumentDiagnosticReport::Full(full) = item else {
+            unreachable!()
+        };
+        assert_eq!(full.full_document_diagnostic_report.items.len(), 1);
+    }
+}
+
+/// `$/progress` with the partial results of a workspace diagnostic request
+enum WorkspaceDiagnosticsPartialResult {}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct WorkspaceDiagnosticsPartialResultParams {
+    token: NumberOrString,
+    value: WorkspaceDiagnosticReportPartialResult,
+}
+
+impl lsp_types::notification::Notification for WorkspaceDiagnosticsPartialResult {
+    type Params = WorkspaceDiagnosticsPartialResultParams;
+    const METHOD: &'static str = "$/progress";
+}
+
 #[test]
 #[parallel]
 fn in_memory_file_changes() {
@@ -285,6 +563,88 @@ fn in_memory_file_changes() {
     assert_eq!(error.code, lsp_server::ErrorCode::InvalidParams as i32);
 }
 
+#[test]
+#[parallel]
+fn incremental_in_memory_file_changes() {
+    let server = Project::with_fixture(
+        r#"
+        [file pyproject.toml]
+        "#,
+    )
+    .into_server();
+
+    const PATH: &str = "incremental.py";
+    let range = |start: (u32, u32), end: (u32, u32)| {
+        lsp_types::Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    };
+
+    server.open_in_memory_file(PATH, "s = '😀'; x = 1\n\ndef f() -> int:\n    return ''\n");
+    assert_eq!(
+        server.diagnostics_for_file(PATH),
+        vec!["Incompatible return value type (got \"str\", expected \"int\")".to_string()]
+    );
+
+    // The emoji consists of two UTF-16 code units, so `1` is at column 14.
+    server.change_in_memory_file_incrementally(
+        PATH,
+        vec![
+            (range((0, 14), (0, 15)), "''"),
+            (range((3, 11), (3, 13)), "x"),
+        ],
+    );
+    assert_eq!(
+        server.diagnostics_for_file(PATH),
+        vec!["Incompatible return value type (got \"str\", expected \"int\")".to_string()]
+    );
+
+    server.change_in_memory_file_incrementally(
+        PATH,
+        vec![
+            (range((2, 11), (2, 14)), "str"),
+            (range((4, 0), (4, 0)), "reveal_type(x)\n"),
+        ],
+    );
+    assert_eq!(
+        server.diagnostics_for_file(PATH),
+        vec!["Revealed type is \"builtins.str\"".to_string()]
+    );
+}
+
+#[test]
+#[parallel]
+fn repeated_changes_invalidate_string_annotations() {
+    let server = Project::with_fixture(
+        r#"
+        [file pyproject.toml]
+
+        [file m.py]
+        from n import C
+        x: "list[C]"
+        reveal_type(x)
+        "#,
+    )
+    .into_server();
+
+    let reveal = |t: &str| vec![format!("Revealed type is \"builtins.list[{t}]\"")];
+    server.open_in_memory_file("n.py", "class C: ...\n");
+    assert_eq!(server.diagnostics_for_file("m.py"), reveal("n.C"));
+
+    for i in 0..3 {
+        server.change_in_memory_file("n.py", "C = int\n");
+        assert_eq!(
+            server.diagnostics_for_file("m.py"),
+            reveal("builtins.int"),
+            "iteration {i}"
+        );
+        server.change_in_memory_file("n.py", "C = str\n");
+        assert_eq!(
+            server.diagnostics_for_file("m.py"),
+            reveal("builtins.str"),
+            "iteration {i}"
+        );
+    }
+}
+
 #[test]
 #[serial]
 fn change_config_file() {
@@ -411,6 +771,112 @@ fn check_rename(contains_symlink: bool) {
     );
 }
 
+#[test]
+#[parallel]
+fn register_file_watchers() {
+    let con = Connection::new();
+    let mut capabilities = Connection::client_capabilities(None, true);
+    capabilities
+        .workspace
+        .as_mut()
+        .unwrap()
+        .did_change_watched_files
+        .as_mut()
+        .unwrap()
+        .dynamic_registration = Some(true);
+    con.initialize_with_capabilities(&["/foo/bar"], capabilities);
+
+    let (_, params) = con.expect_request::<RegisterCapability>();
+    assert_eq!(params.registrations.len(), 1);
+    let registration = &params.registrations[0];
+    assert_eq!(registration.method, "workspace/didChangeWatchedFiles");
+    let options: DidChangeWatchedFilesRegistrationOptions =
+        serde_json::from_value(registration.register_options.clone().unwrap()).unwrap();
+    let globs: Vec<_> = options
+        .watchers
+        .into_iter()
+        .map(|watcher| match watcher.glob_pattern {
+            GlobPattern::String(glob) => glob,
+            GlobPattern::Relative(_) => unreachable!(),
+        })
+        .collect();
+    assert_eq!(
+        globs,
+        [
+            "**/*.{py,pyi}",
+            "**/{pyproject.toml,mypy.ini,.mypy.ini,setup.cfg}"
+        ]
+    );
+    con.shutdown_and_exit()
+}
+
+#[test]
+#[serial]
+fn did_change_watched_files() {
+    let server = Project::with_fixture(
+        r#"
+        [file pyproject.toml]
+
+        [file m.py]
+        from n import x
+        x()
+
+        [file n.py]
+        def x() -> None: ...
+        "#,
+    )
+    .into_server();
+
+    assert_eq!(server.diagnostics_for_file("m.py"), Vec::<String>::new());
+
+    // The client might report changes that our own watcher did not see (e.g. in remote setups).
+    server.tmp_dir.write_file("n.py", "x = 1\n");
+    server.notify::<DidChangeWatchedFiles>(DidChangeWatchedFilesParams {
+        changes: vec![FileEvent {
+            uri: server.doc_id("n.py").uri,
+            typ: FileChangeType::CHANGED,
+        }],
+    });
+    assert_eq!(
+        server.diagnostics_for_file("m.py"),
+        ["\"int\" not callable"]
+    );
+}
+
+#[test]
+#[parallel]
+fn did_change_workspace_folders() {
+    let server = Project::with_fixture(
+        r#"
+        [file p1/check.py]
+        import foo
+
+        [file p2/foo.py]
+        "#,
+    )
+    .root("p1")
+    .into_server();
+
+    const NO_FOO: &str = "Cannot find implementation or library stub for module named \"foo\"";
+    let d = || server.diagnostics_for_file("p1/check.py");
+    let folder = |path: &str| WorkspaceFolder {
+        uri: server.doc_id(path).uri,
+        name: path.to_owned(),
+    };
+    let change_folders = |added, removed| {
+        server.notify::<DidChangeWorkspaceFolders>(DidChangeWorkspaceFoldersParams {
+            event: WorkspaceFoldersChangeEvent { added, removed },
+        })
+    };
+    assert_eq!(d(), [NO_FOO]);
+
+    change_folders(vec![folder("p2")], vec![]);
+    assert_eq!(d(), Vec::<String>::new());
+
+    change_folders(vec![], vec![folder("p2")]);
+    assert_eq!(d(), [NO_FOO]);
+}
+
 #[test]
 #[parallel]
 fn multi_roots() {
@@ -446,66 +912,336 @@ fn multi_roots() {
 }
 
 #[test]
-#[serial]
-fn files_outside_of_root() {
+#[parallel]
+fn multi_roots_with_separate_configs() {
     let server = Project::with_fixture(
         r#"
-        [file base/m.py]
-        import outside_workdir
-        import outside_in_mem
-        import foo
-
-        [file base/foo.py]
+        [file p1/pyproject.toml]
+        [tool.mypy]
+        disallow_untyped_defs = true
 
-        [file outside_workdir.py]
-        import foo
+        [file p1/check.py]
+        from bar import g
+        def f(x): ...
+        g(1)
 
-        [file base/with space.py]
-        b''()
+        [file p2/pyproject.toml]
+        [tool.mypy]
+        strict_optional = false
 
+        [file p2/bar.py]
+        def g(x): ...
+        x: int = None
         "#,
     )
-    .root("base")
+    .root("p1")
+    .root("p2")
     .into_server();
 
-    let d = |name| server.diagnostics_for_file(name);
-    const NO_OUTSIDE1: &str =
-        "Cannot find implementation or library stub for module named \"outside_workdir\"";
-    const NO_OUTSIDE2: &str =
-        "Cannot find implementation or library stub for module named \"outside_in_mem\"";
-
-    server.open_in_memory_file("outside_in_mem.py", "import foo");
-    assert!(d("outside_in_mem.py").is_empty());
-    // Since it's not an in-memory file, it's out of scope and diagnostics are not available
-    let response =
-        server.request_with_response::<DocumentDiagnosticRequest>(DocumentDiagnosticParams {
-            text_document: TextDocumentIdentifier {
-                uri: Uri::from_str(&format!(
-                    "file://{}/outside_workdir.py",
-                    server.tmp_dir.path_for_uri()
-                ))
-                .unwrap(),
-            },
-            identifier: None,
-            previous_result_id: None,
-            partial_result_params: PartialResultParams::default(),
-            work_done_progress_params: WorkDoneProgressParams::default(),
-        });
-    let err = response.error.unwrap();
-    assert!(err.message.ends_with("outside_workdir.py does not exist"));
-    assert_eq!(err.code, lsp_server::ErrorCode::InvalidParams as i32);
-
     assert_eq!(
-        d("base/m.py"),
-        vec![NO_OUTSIDE1.to_string(), NO_OUTSIDE2.to_string()]
+        server.diagnostics_for_file("p1/check.py"),
+        ["Function is missing a type annotation"]
+    );
+    assert_eq!(
+        server.diagnostics_for_file("p2/bar.py"),
+        Vec::<String>::new()
     );
+}
 
-    assert_eq!(d("base/with%20space.py"), [r#""bytes" not callable"#]);
+#[test]
+#[parallel]
+fn multi_roots_with_separate_environments() {
+    let (exe, lib39, lib312) = if cfg!(windows) {
+        ("Scripts/python.exe", "Lib", "Lib")
+    } else {
+        ("bin/python", "lib/python3.9", "lib/python3.12")
+    };
+    let check = r#"
+        import sys
+        from typing import NamedTuple
+        from foo import foo
+        reveal_type(foo)
+        if sys.version_info >= (3, 10):
+            x = 1
+        else:
+            x = ''
+        reveal_type(x)
+        class N(NamedTuple):
+            a: int
+        N.__match_args__
+        "#;
+    let fixture = format!(
+        r#"
+        [file p1/venv/{exe}]
 
-    // Check random files that don't really make sense
-    let check_other_uris = [
-        Uri::from_str("file:///bar/foo").unwrap(),
-        Uri::from_str("file://foo").unwrap(),
+        [file p1/venv/pyvenv.cfg]
+        include-system-site-packages = false
+        version = 3.9.0
+
+        [file p1/venv/{lib39}/site-packages/foo/__init__.py]
+        foo = 1
+
+        [file p1/venv/{lib39}/site-packages/foo/py.typed]
+
+        [file p2/venv/{exe}]
+
+        [file p2/venv/pyvenv.cfg]
+        include-system-site-packages = false
+        version = 3.12.0
+
+        [file p2/venv/{lib312}/site-packages/foo/__init__.py]
+        foo = ''
+
+        [file p2/venv/{lib312}/site-packages/foo/py.typed]
+
+        [file p2/nested/pyproject.toml]
+        [tool.mypy]
+        python_version = "3.9"
+
+        [file p1/check.py]
+        {check}
+        [file p2/check.py]
+        {check}
+        [file p2/nested/check.py]
+        {check}
+        "#
+    );
+    let server = Project::with_fixture(&fixture)
+        .root("p1")
+        .root("p2")
+        .into_server();
+
+    assert_eq!(
+        server.diagnostics_for_file("p1/check.py"),
+        [
+            r#"Revealed type is "builtins.int""#,
+            r#"Revealed type is "builtins.str""#,
+            r#""Type[N]" has no attribute "__match_args__""#
+        ]
+    );
+    assert_eq!(
+        server.diagnostics_for_file("p2/check.py"),
+        [
+            r#"Revealed type is "builtins.str""#,
+            r#"Revealed type is "builtins.int""#
+        ]
+    );
+    // Nested configs have their own Python version, but use the environment of the workspace
+    // folder. Typeshed is shared between all roots and is therefore always checked with the
+    // version of the project, but features that are gated in the checked file (like
+    // `__match_args__`) follow the version of the file.
+    assert_eq!(
+        server.diagnostics_for_file("p2/nested/check.py"),
+        [
+            r#"Revealed type is "builtins.str""#,
+            r#"Revealed type is "builtins.str""#,
+            r#""Type[N]" has no attribute "__match_args__""#
+        ]
+    );
+}
+
+#[test]
+#[parallel]
+fn nested_configs_in_ignored_directories() {
+    // Broken configs would make the loading of all nested configs fail, so they must not be found
+    // in hidden directories, virtual environments or excluded directories.
+    let server = Project::with_fixture(
+        r#"
+        [file pyproject.toml]
+        [tool.mypy]
+        exclude = ["excluded/"]
+
+        [file used/pyproject.toml]
+        [tool.mypy]
+        disallow_untyped_defs = true
+
+        [file used/check.py]
+        def f(x): ...
+
+        [file .git/pyproject.toml]
+        [tool.mypy
+
+        [file node_modules/pyproject.toml]
+        [tool.mypy
+
+        [file venv/pyvenv.cfg]
+        [file venv/pyproject.toml]
+        [tool.mypy
+
+        [file excluded/pyproject.toml]
+        [tool.mypy
+        "#,
+    )
+    .into_server();
+
+    assert_eq!(
+        server.diagnostics_for_file("used/check.py"),
+        ["Function is missing a type annotation"]
+    );
+}
+
+#[test]
+#[parallel]
+fn editor_settings() {
+    let server = Project::with_fixture(
+        r#"
+        [file p1/m.py]
+        def f(x): ...
+        x: int = None
+
+        [file p2/pyproject.toml]
+        [tool.mypy]
+        strict_optional = false
+
+        [file p2/n.py]
+        def f(x): ...
+        x: int = None
+        "#,
+    )
+    .root("p1")
+    .root("p2")
+    .with_initialization_options(json!({"disallow_untyped_defs": true}))
+    .into_server();
+
+    const MISSING: &str = "Function is missing a type annotation";
+    const INCOMPATIBLE: &str =
+        r#"Incompatible types in assignment (expression has type "None", variable has type "int")"#;
+    // The editor settings are layered under the project config
+    assert_eq!(
+        server.diagnostics_for_file("p1/m.py"),
+        [MISSING, INCOMPATIBLE]
+    );
+    assert_eq!(server.diagnostics_for_file("p2/n.py"), [MISSING]);
+
+    server.notify::<DidChangeConfiguration>(DidChangeConfigurationParams {
+        settings: json!({"zuban": {"strict_optional": false}}),
+    });
+    assert_eq!(server.diagnostics_for_file("p1/m.py"), Vec::<String>::new());
+
+    server.notify::<DidChangeConfiguration>(DidChangeConfigurationParams {
+        settings: json!({"zuban": {"strict_optional": 1}}),
+    });
+    assert!(
+        server
+            .expect_notification_message()
+            .message
+            .starts_with("Invalid editor setting strict_optional")
+    );
+    // The old settings are still used
+    assert_eq!(server.diagnostics_for_file("p1/m.py"), Vec::<String>::new());
+
+    // Settings of other tools don't change anything and don't cause warnings.
+    server.notify::<DidChangeConfiguration>(DidChangeConfigurationParams {
+        settings: json!({"python": {"analysis": {"typeCheckingMode": "strict"}}}),
+    });
+    assert_eq!(server.diagnostics_for_file("p1/m.py"), Vec::<String>::new());
+}
+
+#[test]
+#[parallel]
+fn editor_settings_from_workspace_configuration() {
+    let server = Project::with_fixture(
+        r#"
+        [file p1/m.py]
+        def f(x): ...
+
+        [file p2/n.py]
+        def f(x): ...
+        "#,
+    )
+    .root("p1")
+    .root("p2")
+    .with_workspace_configuration()
+    .into_server();
+
+    const MISSING: &str = "Function is missing a type annotation";
+    // The settings are requested for every workspace folder.
+    let respond_to_configuration_request = |settings: [Value; 2]| {
+        let (id, params) = server.expect_request::<WorkspaceConfiguration>();
+        assert_eq!(params.items.len(), 2);
+        for (item, folder) in params.items.iter().zip(["p1", "p2"]) {
+            assert_eq!(item.section.as_deref(), Some("zuban"));
+            assert_eq!(item.scope_uri, Some(server.doc_id(folder).uri));
+        }
+        server.send(Response::new_ok(id, json!(settings)));
+    };
+    respond_to_configuration_request([json!({"disallow_untyped_defs": true}), Value::Null]);
+    assert_eq!(server.diagnostics_for_file("p1/m.py"), [MISSING]);
+    assert_eq!(server.diagnostics_for_file("p2/n.py"), Vec::<String>::new());
+
+    // Without settings the client expects the server to request them
+    server.notify::<DidChangeConfiguration>(DidChangeConfigurationParams {
+        settings: Value::Null,
+    });
+    respond_to_configuration_request([
+        json!({"disallow_untyped_defs": false}),
+        json!({"disallow_untyped_defs": true}),
+    ]);
+    assert_eq!(server.diagnostics_for_file("p1/m.py"), Vec::<String>::new());
+    assert_eq!(server.diagnostics_for_file("p2/n.py"), [MISSING]);
+}
+
+#[test]
+#[serial]
+fn files_outside_of_root() {
+    let server = Project::with_fixture(
+        r#"
+        [file base/m.py]
+        import outside_workdir
+        import outside_in_mem
+        import foo
+
+        [file base/foo.py]
+
+        [file outside_workdir.py]
+        import foo
+
+        [file base/with space.py]
+        b''()
+
+        "#,
+    )
+    .root("base")
+    .into_server();
+
+    let d = |name| server.diagnostics_for_file(name);
+    const NO_OUTSIDE1: &str =
+        "Cannot find implementation or library stub for module named \"outside_workdir\"";
+    const NO_OUTSIDE2: &str =
+        "Cannot find implementation or library stub for module named \"outside_in_mem\"";
+
+    server.open_in_memory_file("outside_in_mem.py", "import foo");
+    assert!(d("outside_in_mem.py").is_empty());
+    // Since it's not an in-memory file, it's out of scope and diagnostics are not available
+    let response =
+        server.request_with_response::<DocumentDiagnosticRequest>(DocumentDiagnosticParams {
+            text_document: TextDocumentIdentifier {
+                uri: Uri::from_str(&format!(
+                    "file://{}/outside_workdir.py",
+                    server.tmp_dir.path_for_uri()
+                ))
+                .unwrap(),
+            },
+            identifier: None,
+            previous_result_id: None,
+            partial_result_params: PartialResultParams::default(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        });
+    let err = response.error.unwrap();
+    assert!(err.message.ends_with("outside_workdir.py does not exist"));
+    assert_eq!(err.code, lsp_server::ErrorCode::InvalidParams as i32);
+
+    assert_eq!(
+        d("base/m.py"),
+        vec![NO_OUTSIDE1.to_string(), NO_OUTSIDE2.to_string()]
+    );
+
+    assert_eq!(d("base/with%20space.py"), [r#""bytes" not callable"#]);
+
+    // Check random files that don't really make sense
+    let check_other_uris = [
+        Uri::from_str("file:///bar/foo").unwrap(),
+        Uri::from_str("file://foo").unwrap(),
         Uri::from_str("file://").unwrap(),
         Uri::from_str("https://www.example.com/foo.py").unwrap(),
         Uri::from_str("file:/single_slash").unwrap(),
@@ -1675,113 +2411,634 @@ fn check_goto_likes() {
 
 #[test]
 #[serial]
-fn check_completions() {
+fn reveal_type_command() {
     let server = Project::with_fixture(
         r#"
         [file m.py]
-        class MyClass:
-            """
-            doc 🫶 love 
-            """
+        def f(x: int | str | None) -> None:
+            if isinstance(x, int):
+                x
+            elif x is not None:
+                x
+            x
+        "#,
+    )
+    .into_server();
 
-        def my_func(): ...
+    let reveal = |line, character| {
+        let position =
+            TextDocumentPositionParams::new(server.doc_id("m.py"), Position::new(line, character));
+        ExecuteCommandParams {
+            command: "zuban.revealType".to_owned(),
+            arguments: vec![serde_json::to_value(position).unwrap()],
+            work_done_progress_params: Default::default(),
+        }
+    };
+    // The flow analysis of the file has not run before the first command, because no
+    // diagnostics were requested.
+    server.request_and_expect_json::<ExecuteCommand>(reveal(2, 8), json!("int"));
+    server.request_and_expect_json::<ExecuteCommand>(reveal(4, 8), json!("str"));
+    server.request_and_expect_json::<ExecuteCommand>(reveal(5, 4), json!("int | str | None"));
+    // Checking the file does not change the narrowed types
+    assert!(server.diagnostics_for_file("m.py").is_empty());
+    server.request_and_expect_json::<ExecuteCommand>(reveal(2, 8), json!("int"));
+}
+
+#[test]
+#[serial]
+fn will_rename_files_updates_imports() {
+    let server = Project::with_fixture(
+        r#"
+        [file pkg/__init__.py]
+        from . import a
+        __all__ = ["a", "b"]
+
+        [file pkg/a.py]
+        from .b import x
+        from . import b
+
+        [file pkg/b.py]
+        x = 1
+
+        [file pkg/sub/__init__.py]
+
+        [file main.py]
+        import pkg.a
+        from pkg import a, b
+        from pkg.a import x as y
+        from typing import TYPE_CHECKING
+        if TYPE_CHECKING:
+            z: "pkg.a.X"
+        print(pkg.a.x)
+        log = "pkg.a.x"
+
+        [file README]
+        import pkg.a
         "#,
     )
     .into_server();
 
-    // Open an in memory file that doesn't otherwise exist
-    let path = "n.py";
-    server.open_in_memory_file(path, "import m\nm.my");
+    let files = |items: &[(&str, &str)]| -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(path, code)| (path.to_string(), code.to_string()))
+            .collect()
+    };
 
-    let pos = TextDocumentPositionParams::new(server.doc_id("n.py"), Position::new(1, 4));
-    server.request_and_expect_json::<Completion>(
-        CompletionParams {
-            text_document_position: pos,
-            work_done_progress_params: Default::default(),
-            partial_result_params: Default::default(),
-            context: None,
-        },
-        json!([
-          {
-            "kind": CompletionItemKind::CLASS,
-            "label": "MyClass",
-            "sortText": "00000",
-            "textEdit": {
-              "newText": "MyClass",
-              "range": {
-                "start": {
-                  "line": 1,
-                  "character": 2,
-                },
-                "end": {
-                  "line": 1,
-                  "character": 4,
-                },
-              }
-            },
-          },
-          {
-            "kind": CompletionItemKind::FUNCTION,
-            "label": "my_func",
-            "sortText": "00001",
-            "textEdit": {
-              "newText": "my_func",
-              "range": {
-                "start": {
-                  "line": 1,
-                  "character": 2,
-                },
-                "end": {
-                  "line": 1,
-                  "character": 4,
-                },
-              }
-            },
-          },
-          {
-            "kind": CompletionItemKind::PROPERTY,
-            "label": "__dict__",
-            "sortText": "00002",
-            "textEdit": {
-              "newText": "__dict__",
-              "range": {
-                "start": {
-                  "line": 1,
-                  "character": 2,
-                },
-                "end": {
-                  "line": 1,
-                  "character": 4,
-                },
-              }
-            },
-          },
-          {
-            "kind": CompletionItemKind::FIELD,
-            "label": "__doc__",
-            "sortText": "00003",
-            "textEdit": {
-              "newText": "__doc__",
-              "range": {
-                "start": {
-                  "line": 1,
-                  "character": 2,
-                },
-                "end": {
-                  "line": 1,
-                  "character": 4,
-                },
-              }
-            },
-          },
-          {
-            "kind": CompletionItemKind::FIELD,
-            "label": "__file__",
-            "sortText": "00004",
-            "textEdit": {
-              "newText": "__file__",
-              "range": {
-                "start": {
+    // Move a module to a sub package and rename it
+    assert_eq!(
+        server.will_rename_files(&[("pkg/a.py", "pkg/sub/c.py")]),
+        files(&[
+            (
+                "main.py",
+                "import pkg.sub.c\nfrom pkg.sub import c as a\nfrom pkg import b\n\
+                 from pkg.sub.c import x as y\nfrom typing import TYPE_CHECKING\n\
+                 if TYPE_CHECKING:\n    z: \"pkg.sub.c.X\"\nprint(pkg.sub.c.x)\n\
+                 log = \"pkg.a.x\"\n\n",
+            ),
+            (
+                "pkg/__init__.py",
+                "from .sub import c as a\n__all__ = [\"a\", \"b\"]\n\n",
+            ),
+            ("pkg/a.py", "from ..b import x\nfrom .. import b\n\n"),
+        ])
+    );
+
+    // Rename a module within its package, `__all__` references the submodule
+    assert_eq!(
+        server.will_rename_files(&[("pkg/b.py", "pkg/b2.py")]),
+        files(&[
+            (
+                "main.py",
+                "import pkg.a\nfrom pkg import b2 as b\nfrom pkg import a\n\
+                 from pkg.a import x as y\nfrom typing import TYPE_CHECKING\n\
+                 if TYPE_CHECKING:\n    z: \"pkg.a.X\"\nprint(pkg.a.x)\n\
+                 log = \"pkg.a.x\"\n\n",
+            ),
+            (
+                "pkg/__init__.py",
+                "from . import a\n__all__ = [\"a\", \"b2\"]\n\n",
+            ),
+            ("pkg/a.py", "from .b2 import x\nfrom . import b2 as b\n\n"),
+        ])
+    );
+
+    // Move a whole package
+    assert_eq!(
+        server.will_rename_files(&[("pkg", "lib")]),
+        files(&[(
+            "main.py",
+            "import lib.a\nfrom lib import a, b\nfrom lib.a import x as y\n\
+             from typing import TYPE_CHECKING\nif TYPE_CHECKING:\n    z: \"lib.a.X\"\n\
+             print(lib.a.x)\nlog = \"pkg.a.x\"\n\n",
+        )])
+    );
+
+    // Files that are not Python modules are ignored
+    assert_eq!(server.will_rename_files(&[("README", "README.md")]), vec![]);
+}
+
+#[test]
+#[serial]
+fn rename_keyword_arguments_and_typed_dict_keys() {
+    let server = Project::with_fixture(
+        r#"
+        [file params.py]
+        def f(value: int, *, other: int = 0) -> int:
+            return value + other
+
+        f(value=1)
+        f(1, other=value)
+
+        [file point.py]
+        from dataclasses import dataclass, replace
+
+        @dataclass
+        class Point:
+            x: int
+            y: int
+
+        p = Point(x=1, y=2)
+        q = replace(p, x=3)
+        print(p.x)
+        match p:
+            case Point(x=0):
+                pass
+
+        [file movie.py]
+        from typing import TypedDict
+        from typing_extensions import Unpack
+
+        class Movie(TypedDict):
+            title: str
+            year: int
+
+        def f(**kwargs: Unpack[Movie]) -> None: ...
+
+        m: Movie = {"title": "x", "year": 1}
+        m["title"] = "y"
+        print(m["title"])
+        f(title="z", year=2)
+        other = {"title": 1}
+        "#,
+    )
+    .into_server();
+
+    let files = |items: &[(&str, &str)]| -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(path, code)| (path.to_string(), code.to_string()))
+            .collect()
+    };
+
+    // A param is renamed together with the keyword arguments of the calls
+    assert_eq!(
+        server.rename("params.py", (0, 7), "amount"),
+        files(&[(
+            "params.py",
+            "def f(amount: int, *, other: int = 0) -> int:\n    return amount + other\n\n\
+             f(amount=1)\nf(1, other=value)\n\n",
+        )])
+    );
+
+    // A dataclass field is renamed in `__init__` calls, `replace()` calls and class patterns
+    assert_eq!(
+        server.rename("point.py", (4, 4), "left"),
+        files(&[(
+            "point.py",
+            "from dataclasses import dataclass, replace\n\n@dataclass\nclass Point:\n    \
+             left: int\n    y: int\n\np = Point(left=1, y=2)\nq = replace(p, left=3)\n\
+             print(p.left)\nmatch p:\n    case Point(left=0):\n        pass\n\n",
+        )])
+    );
+
+    // A TypedDict key is renamed in subscripts, dict literals of the TypedDict and unpacked
+    // keyword arguments, but not in unrelated dicts
+    assert_eq!(
+        server.rename("movie.py", (4, 4), "name"),
+        files(&[(
+            "movie.py",
+            "from typing import TypedDict\nfrom typing_extensions import Unpack\n\n\
+             class Movie(TypedDict):\n    name: str\n    year: int\n\n\
+             def f(**kwargs: Unpack[Movie]) -> None: ...\n\n\
+             m: Movie = {\"name\": \"x\", \"year\": 1}\nm[\"name\"] = \"y\"\n\
+             print(m[\"name\"])\nf(name=\"z\", year=2)\nother = {\"title\": 1}\n",
+        )])
+    );
+}
+
+#[test]
+#[serial]
+fn code_actions_extract_and_inline() {
+    let server = Project::with_fixture(
+        r#"
+        [file m.py]
+        def f(a: int, b: str) -> int:
+            c = a + 1
+            d = len(b) * c
+            return d
+
+        x = f(1, "") + 2
+        "#,
+    )
+    .into_server();
+
+    let actions = |start, end| -> Vec<(String, String)> { server.code_actions("m.py", start, end) };
+    let expected = |items: &[(&str, &str)]| -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(title, code)| (title.to_string(), code.to_string()))
+            .collect()
+    };
+
+    // An expression
+    assert_eq!(
+        actions((2, 8), (2, 18)),
+        expected(&[
+            (
+                "Extract variable",
+                "def f(a: int, b: str) -> int:\n    c = a + 1\n    new_var = len(b) * c\n    \
+                 d = new_var\n    return d\n\nx = f(1, \"\") + 2\n",
+            ),
+            (
+                "Extract function",
+                "def new_function(b: str, c: int) -> int:\n    return len(b) * c\n\n\n\
+                 def f(a: int, b: str) -> int:\n    c = a + 1\n    d = new_function(b, c)\n    \
+                 return d\n\nx = f(1, \"\") + 2\n",
+            ),
+        ])
+    );
+
+    // Statements that define a variable that is used later
+    assert_eq!(
+        actions((1, 4), (2, 18)),
+        expected(&[
+            (
+                "Extract function",
+                "def new_function(a: int, b: str) -> int:\n    c = a + 1\n    d = len(b) * c\n    \
+                 return d\n\n\ndef f(a: int, b: str) -> int:\n    d = new_function(a, b)\n    \
+                 return d\n\nx = f(1, \"\") + 2\n",
+            ),
+            (
+                "Inline variable",
+                "def f(a: int, b: str) -> int:\n    d = len(b) * (a + 1)\n    return d\n\n\
+                 x = f(1, \"\") + 2\n",
+            ),
+        ])
+    );
+
+    // Statements with a return at the end of a function
+    assert_eq!(
+        actions((2, 4), (3, 12)),
+        expected(&[
+            (
+                "Extract function",
+                "def new_function(b: str, c: int) -> int:\n    d = len(b) * c\n    return d\n\n\n\
+                 def f(a: int, b: str) -> int:\n    c = a + 1\n    return new_function(b, c)\n\n\
+                 x = f(1, \"\") + 2\n",
+            ),
+            (
+                "Inline variable",
+                "def f(a: int, b: str) -> int:\n    c = a + 1\n    return len(b) * c\n\n\
+                 x = f(1, \"\") + 2\n",
+            ),
+        ])
+    );
+
+    // A module level expression
+    assert_eq!(
+        actions((5, 4), (5, 16)),
+        expected(&[
+            (
+                "Extract variable",
+                "def f(a: int, b: str) -> int:\n    c = a + 1\n    d = len(b) * c\n    \
+                 return d\n\nnew_var = f(1, \"\") + 2\nx = new_var\n",
+            ),
+            (
+                "Extract function",
+                "def f(a: int, b: str) -> int:\n    c = a + 1\n    d = len(b) * c\n    \
+                 return d\n\ndef new_function() -> int:\n    return f(1, \"\") + 2\n\n\n\
+                 x = new_function()\n",
+            ),
+        ])
+    );
+
+    // Functions cannot be inlined and nothing can be extracted from an empty range
+    assert_eq!(actions((0, 4), (0, 4)), vec![]);
+}
+
+#[test]
+#[serial]
+fn code_actions_extract_and_inline_keep_the_evaluation_order() {
+    let server = Project::with_fixture(
+        r#"
+        [file cond.py]
+        def f(a: int, b: int) -> int:
+            x = a and b + 1
+            y = b if a else a + 1
+            try:
+                pass
+            except ValueError:
+                pass
+            return x + y
+
+        [file inline.py]
+        def g() -> int: ...
+
+        def f(a: int) -> int:
+            x = a + 1
+            a = 2
+            y = g()
+            z = a * 2
+            return x + y + y + z + z
+        "#,
+    )
+    .into_server();
+
+    let titles = |path, start, end| {
+        server
+            .code_actions(path, start, end)
+            .into_iter()
+            .map(|(title, _)| title)
+            .collect::<Vec<_>>()
+    };
+    let extract = ["Extract variable", "Extract function"];
+    // Expressions that are only evaluated conditionally cannot be moved before their statement
+    assert_eq!(titles("cond.py", (1, 8), (1, 9)), extract);
+    assert_eq!(titles("cond.py", (1, 14), (1, 19)), ["Extract function"]);
+    assert_eq!(titles("cond.py", (2, 13), (2, 14)), extract);
+    assert_eq!(titles("cond.py", (2, 8), (2, 9)), ["Extract function"]);
+    assert_eq!(titles("cond.py", (2, 20), (2, 25)), ["Extract function"]);
+    assert_eq!(titles("cond.py", (5, 11), (5, 21)), ["Extract function"]);
+
+    assert_eq!(
+        server.code_action_resolve_error("inline.py", (3, 4), "Inline variable"),
+        "Cannot inline \"x\", \"a\" is assigned again before it is used"
+    );
+    assert_eq!(
+        server.code_action_resolve_error("inline.py", (5, 4), "Inline variable"),
+        "Cannot inline \"y\", it is used more than once and its value might have side effects"
+    );
+    assert_eq!(
+        server
+            .code_actions("inline.py", (6, 4), (6, 4))
+            .into_iter()
+            .find(|(title, _)| title == "Inline variable")
+            .unwrap()
+            .1,
+        "def g() -> int: ...\n\ndef f(a: int) -> int:\n    x = a + 1\n    a = 2\n    \
+         y = g()\n    return x + y + y + (a * 2) + (a * 2)\n"
+    );
+}
+
+#[test]
+#[serial]
+fn code_actions_extract_function_from_nested_function() {
+    let server = Project::with_fixture(
+        r#"
+        [file lib.py]
+        class Item: ...
+        def make() -> Item: ...
+
+        [file m.py]
+        from lib import make
+
+        def outer(a: int) -> int:
+            y = a * 2
+            def inner(b: int) -> int:
+                return b + y
+            return inner(1)
+
+        x = make()
+        "#,
+    )
+    .into_server();
+
+    let extract_function = |start, end| {
+        server
+            .code_actions("m.py", start, end)
+            .into_iter()
+            .find(|(title, _)| title == "Extract function")
+            .unwrap()
+            .1
+    };
+    // Variables of the enclosing functions are passed as params
+    assert_eq!(
+        extract_function((5, 15), (5, 20)),
+        "from lib import make\n\ndef new_function(b: int, y: int) -> int:\n    return b + y\n\n\n\
+         def outer(a: int) -> int:\n    y = a * 2\n    def inner(b: int) -> int:\n        \
+         return new_function(b, y)\n    return inner(1)\n\nx = make()\n"
+    );
+    // Classes of other modules are imported for the annotations
+    assert_eq!(
+        extract_function((8, 4), (8, 10)),
+        "from lib import make\nfrom lib import Item\n\ndef outer(a: int) -> int:\n    y = a * 2\n    \
+         def inner(b: int) -> int:\n        return b + y\n    return inner(1)\n\n\
+         def new_function() -> Item:\n    return make()\n\n\nx = new_function()\n"
+    );
+}
+
+#[test]
+#[serial]
+fn selection_and_folding_ranges() {
+    let server = Project::with_fixture(
+        r#"
+        [file m.py]
+        """Module
+        docstring"""
+        import os
+        import sys
+        # region helpers
+        def f(a, b):
+            """Doc
+            string"""
+            if a:
+                x = foo.bar(a, b)
+            else:
+                x = [
+                    1,
+                    2,
+                ]
+            return x
+        # endregion
+        class C:
+            def g(self): return 1
+        "#,
+    )
+    .into_server();
+
+    let code = std::fs::read_to_string(Path::new(server.tmp_dir.path()).join("m.py")).unwrap();
+    let between = |start: &str, end: &str| -> String {
+        let start = code.find(start).unwrap();
+        let end = code.rfind(end).unwrap() + end.len();
+        code[start..end].to_owned()
+    };
+    assert_eq!(
+        server.selection_ranges("m.py", (9, 17)),
+        vec![
+            "bar".to_owned(),
+            "foo.bar".to_owned(),
+            "foo.bar(a, b)".to_owned(),
+            "x = foo.bar(a, b)".to_owned(),
+            between("if a:", "]"),
+            between("\"\"\"Doc", "return x"),
+            between("def f", "return x"),
+            between("\"\"\"Module", "return 1"),
+        ]
+    );
+    assert_eq!(
+        server.selection_ranges("m.py", (9, 24)),
+        vec![
+            "b".to_owned(),
+            "a, b".to_owned(),
+            "foo.bar(a, b)".to_owned(),
+            "x = foo.bar(a, b)".to_owned(),
+            between("if a:", "]"),
+            between("\"\"\"Doc", "return x"),
+            between("def f", "return x"),
+            between("\"\"\"Module", "return 1"),
+        ]
+    );
+
+    use lsp_types::FoldingRangeKind::*;
+    assert_eq!(
+        server.folding_ranges("m.py"),
+        vec![
+            (0, 1, Some(Comment)),
+            (2, 3, Some(Imports)),
+            (4, 16, Some(Region)),
+            (5, 15, None),
+            (6, 7, Some(Comment)),
+            (8, 9, None),
+            (10, 14, None),
+            (11, 13, None),
+            (17, 18, None),
+        ]
+    );
+}
+
+#[test]
+#[serial]
+fn check_completions() {
+    let server = Project::with_fixture(
+        r#"
+        [file m.py]
+        class MyClass:
+            """
+            doc 🫶 love 
+            """
+
+        def my_func(): ...
+        "#,
+    )
+    .into_server();
+
+    // Open an in memory file that doesn't otherwise exist
+    let path = "n.py";
+    server.open_in_memory_file(path, "import m\nm.my");
+
+    let pos = TextDocumentPositionParams::new(server.doc_id("n.py"), Position::new(1, 4));
+    let data = |label: &str, kind, file: &str| {
+        json!({
+            "position": pos,
+            "label": label,
+            "kind": kind,
+            "filePath": format!("[..]/{file}"),
+        })
+    };
+    server.request_and_expect_json::<Completion>(
+        CompletionParams {
+            text_document_position: pos.clone(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        },
+        json!([
+          {
+            "kind": CompletionItemKind::CLASS,
+            "label": "MyClass",
+            "sortText": "00000",
+            "data": data("MyClass", CompletionItemKind::CLASS, "m.py"),
+            "textEdit": {
+              "newText": "MyClass",
+              "range": {
+                "start": {
+                  "line": 1,
+                  "character": 2,
+                },
+                "end": {
+                  "line": 1,
+                  "character": 4,
+                },
+              }
+            },
+          },
+          {
+            "kind": CompletionItemKind::FUNCTION,
+            "label": "my_func",
+            "sortText": "00001",
+            "data": data("my_func", CompletionItemKind::FUNCTION, "m.py"),
+            "textEdit": {
+              "newText": "my_func",
+              "range": {
+                "start": {
+                  "line": 1,
+                  "character": 2,
+                },
+                "end": {
+                  "line": 1,
+                  "character": 4,
+                },
+              }
+            },
+          },
+          {
+            "kind": CompletionItemKind::PROPERTY,
+            "label": "__dict__",
+            "sortText": "00002",
+            "data": data("__dict__", CompletionItemKind::PROPERTY, "types.pyi"),
+            "textEdit": {
+              "newText": "__dict__",
+              "range": {
+                "start": {
+                  "line": 1,
+                  "character": 2,
+                },
+                "end": {
+                  "line": 1,
+                  "character": 4,
+                },
+              }
+            },
+          },
+          {
+            "kind": CompletionItemKind::FIELD,
+            "label": "__doc__",
+            "sortText": "00003",
+            "data": data("__doc__", CompletionItemKind::FIELD, "types.pyi"),
+            "textEdit": {
+              "newText": "__doc__",
+              "range": {
+                "start": {
+                  "line": 1,
+                  "character": 2,
+                },
+                "end": {
+                  "line": 1,
+                  "character": 4,
+                },
+              }
+            },
+          },
+          {
+            "kind": CompletionItemKind::FIELD,
+            "label": "__file__",
+            "sortText": "00004",
+            "data": data("__file__", CompletionItemKind::FIELD, "types.pyi"),
+            "textEdit": {
+              "newText": "__file__",
+              "range": {
+                "start": {
                   "line": 1,
                   "character": 2,
                 },
@@ -1796,6 +3053,7 @@ fn check_completions() {
             "kind": CompletionItemKind::FIELD,
             "label": "__loader__",
             "sortText": "00005",
+            "data": data("__loader__", CompletionItemKind::FIELD, "types.pyi"),
             "textEdit": {
               "newText": "__loader__",
               "range": {
@@ -1814,6 +3072,7 @@ fn check_completions() {
             "kind": CompletionItemKind::FIELD,
             "label": "__name__",
             "sortText": "00006",
+            "data": data("__name__", CompletionItemKind::FIELD, "types.pyi"),
             "textEdit": {
               "newText": "__name__",
               "range": {
@@ -1832,6 +3091,7 @@ fn check_completions() {
             "kind": CompletionItemKind::FIELD,
             "label": "__package__",
             "sortText": "00007",
+            "data": data("__package__", CompletionItemKind::FIELD, "types.pyi"),
             "textEdit": {
               "newText": "__package__",
               "range": {
@@ -1850,6 +3110,7 @@ fn check_completions() {
             "kind": CompletionItemKind::FIELD,
             "label": "__spec__",
             "sortText": "00008",
+            "data": data("__spec__", CompletionItemKind::FIELD, "types.pyi"),
             "textEdit": {
               "newText": "__spec__",
               "range": {
@@ -1867,3 +3128,595 @@ fn check_completions() {
         ]),
     );
 }
+
+#[test]
+#[serial]
+fn completion_resolve() {
+    let server = Project::with_fixture(
+        r#"
+        [file m.py]
+        class MyClass:
+            """
+            doc 🫶 love
+            """
+
+        def my_func(x: int) -> str: ...
+        "#,
+    )
+    .with_completion_label_details()
+    .into_server();
+
+    server.open_in_memory_file("n.py", "import m\nm.my");
+
+    let pos = TextDocumentPositionParams::new(server.doc_id("n.py"), Position::new(1, 4));
+    let items: Vec<_> = server
+        .request_with_expected_response::<Completion>(CompletionParams {
+            text_document_position: pos,
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        })
+        .as_array()
+        .unwrap()
+        .iter()
+        .take(2)
+        .cloned()
+        .collect();
+    for item in &items {
+        assert!(item.get("detail").is_none());
+        assert_eq!(item["data"]["label"], item["label"]);
+        // The module is cheap to find and shown right away if the client supports it
+        assert_eq!(item["labelDetails"], json!({ "description": "m" }));
+    }
+    let labels: Vec<_> = items.iter().map(|item| &item["label"]).collect();
+    assert_eq!(labels, ["MyClass", "my_func"]);
+
+    // The details are only calculated when a completion is resolved
+    let resolve = |item: &Value| -> Value {
+        let resolved = server.request_with_expected_response::<ResolveCompletionItem>(
+            serde_json::from_value(item.clone()).unwrap(),
+        );
+        json!([resolved["detail"], resolved["documentation"]])
+    };
+    assert_eq!(
+        resolve(&items[0]),
+        json!([
+            "MyClass",
+            {
+                "kind": "markdown",
+                "value": "doc 🫶 love",
+            },
+        ])
+    );
+    assert_eq!(
+        resolve(&items[1]),
+        json!(["def my_func(x: int) -> str", null])
+    );
+}
+
+#[test]
+#[serial]
+fn completion_typed_dict_key_in_string() {
+    let server = Project::with_fixture(
+        r#"
+        [file m.py]
+        from typing import TypedDict
+
+        class Movie(TypedDict):
+            title: str
+        "#,
+    )
+    .into_server();
+
+    server.open_in_memory_file("n.py", "import m\nx: m.Movie\nx[\"ti\"]\nx[\"ti");
+
+    let complete = |line| {
+        let items = server.request_with_expected_response::<Completion>(CompletionParams {
+            text_document_position: TextDocumentPositionParams::new(
+                server.doc_id("n.py"),
+                Position::new(line, 5),
+            ),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        });
+        let items = items.as_array().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["label"], "\"title\"");
+        items[0]["textEdit"].clone()
+    };
+    // The closing quote is only added if it is missing
+    let edit = |line, new_text| {
+        json!({
+            "range": {
+                "start": { "line": line, "character": 2 },
+                "end": { "line": line, "character": 5 },
+            },
+            "newText": new_text,
+        })
+    };
+    assert_eq!(complete(2), edit(2, "\"title"));
+    assert_eq!(complete(3), edit(3, "\"title\""));
+}
+
+#[test]
+#[serial]
+fn completion_snippets_and_override_stubs() {
+    let server = Project::with_fixture(
+        r#"
+        [file m.py]
+        from typing import Any
+        from collections import OrderedDict
+
+        def my_func(x: int) -> str: ...
+
+        class Base:
+            def meth(self) -> None: ...
+            def convert(self, x: Any, y: OrderedDict[str, int] = ...) -> OrderedDict: ...
+        "#,
+    )
+    .with_initialization_options(json!({"completion_snippets": true}))
+    .into_server();
+
+    server.open_in_memory_file(
+        "n.py",
+        "from typing import Any\nimport m\nm.my_f\nclass Sub(m.Base):\n    def me\n    def conv",
+    );
+
+    let complete = |line, character, label| {
+        let items = server.request_with_expected_response::<Completion>(CompletionParams {
+            text_document_position: TextDocumentPositionParams::new(
+                server.doc_id("n.py"),
+                Position::new(line, character),
+            ),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        });
+        let mut item = items
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|item| item["label"] == label)
+            .unwrap()
+            .clone();
+        let item_mut = item.as_object_mut().unwrap();
+        item_mut.remove("data");
+        item_mut.remove("sortText");
+        item
+    };
+    assert_eq!(
+        complete(2, 6, "my_func"),
+        json!({
+            "label": "my_func",
+            "kind": 3,
+            "insertTextFormat": 2,
+            "textEdit": {
+                "range": {
+                    "start": { "line": 2, "character": 2 },
+                    "end": { "line": 2, "character": 6 },
+                },
+                "newText": "my_func(${1:x})$0",
+            },
+        })
+    );
+    // The stub replaces `def`, because decorators might be added in front of it.
+    assert_eq!(
+        complete(4, 10, "meth"),
+        json!({
+            "label": "meth",
+            "kind": 2,
+            "filterText": "def meth",
+            "textEdit": {
+                "range": {
+                    "start": { "line": 4, "character": 4 },
+                    "end": { "line": 4, "character": 10 },
+                },
+                "newText": "def meth(self) -> None:\n        ...",
+            },
+        })
+    );
+    // OrderedDict is not imported in n.py, therefore its annotations are left out.
+    assert_eq!(
+        complete(5, 12, "convert")["textEdit"]["newText"],
+        json!("def convert(self, x: Any, y = ...):\n        ..."),
+    );
+}
+
+#[test]
+#[serial]
+fn code_lenses() {
+    let server = Project::with_fixture(
+        r#"
+        [file base.py]
+        class Base:
+            def run(self) -> None: ...
+            def stop(self) -> None: ...
+
+        def create() -> Base:
+            return Base()
+
+        [file sub.py]
+        from base import Base
+
+        class Child(Base):
+            def run(self) -> None: ...
+
+        class GrandChild(Child):
+            def run(self) -> None: ...
+            def stop(self) -> None: ...
+            def __init__(self) -> None: ...
+
+        Child().run()
+        "#,
+    )
+    .into_server();
+
+    let lenses = |path| {
+        server
+            .code_lenses(path)
+            .into_iter()
+            .map(|(line, title)| format!("{line}: {title}"))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        lenses("base.py"),
+        vec![
+            "0: 4 references",
+            "0: 2 implementations",
+            "1: 0 references",
+            "1: 2 implementations",
+            "2: 0 references",
+            "2: 1 implementation",
+            "4: 0 references",
+        ]
+    );
+    // Methods inherited from object like __init__ don't override anything
+    assert_eq!(
+        lenses("sub.py"),
+        vec![
+            "2: 2 references",
+            "2: 1 implementation",
+            "3: 1 reference",
+            "3: 1 implementation",
+            "3: overrides base.Base.run",
+            "5: 0 references",
+            "5: 0 implementations",
+            "6: 0 references",
+            "6: 0 implementations",
+            "6: overrides sub.Child.run",
+            "7: 0 references",
+            "7: 0 implementations",
+            "7: overrides base.Base.stop",
+            "8: 0 references",
+            "8: 0 implementations",
+        ]
+    );
+}
+
+#[test]
+#[serial]
+fn code_action_organize_imports() {
+    let server = Project::with_fixture(
+        r#"
+        [file mypkg/__init__.py]
+        [file mypkg/helpers.py]
+        def helper() -> int: ...
+        def other() -> int: ...
+
+        [file m.py]
+        """Docstring"""
+        from __future__ import annotations
+        import sys
+        from mypkg.helpers import helper
+        import os
+        import requests
+        from typing import List, Any
+        from typing import cast, TYPE_CHECKING
+        from mypkg.helpers import other, helper
+        import json  # noqa
+        from collections import OrderedDict
+        import os.path
+
+        __all__ = ["OrderedDict"]
+
+        if TYPE_CHECKING:
+            import re
+
+        def f(x: "List[int]") -> None:
+            print(os.path, helper, requests, cast)
+
+        [file unused.py]
+        import sys
+        from mypkg.helpers import helper, other
+
+        x = 1
+
+        [file stub.pyi]
+        import sys
+        import os
+
+        [file mypkg/sub/__init__.py]
+        import sys
+        from mypkg.helpers import other, helper
+
+        [file reexports.py]
+        import sys
+        import os as os
+        from mypkg.helpers import other, helper as helper
+        from json import dumps, loads
+
+        __all__ = ["dumps"] + ["loads"]
+
+        [file no_imports.py]
+        print(1)
+
+        [file organized.py]
+        import os
+
+        print(os.sep)
+        "#,
+    )
+    .into_server();
+
+    let organize = |path| {
+        server
+            .code_actions(path, (0, 0), (0, 0))
+            .into_iter()
+            .filter(|(title, _)| title == "Organize imports")
+            .map(|(_, code)| code)
+            .collect::<Vec<_>>()
+    };
+    // Imports with comments stay where they are and separate the blocks that are sorted.
+    assert_eq!(
+        organize("m.py"),
+        vec![
+            "\"\"\"Docstring\"\"\"\n\
+             from __future__ import annotations\n\
+             \n\
+             import os\n\
+             from typing import TYPE_CHECKING, List, cast\n\
+             \n\
+             import requests\n\
+             \n\
+             from mypkg.helpers import helper\n\
+             import json  # noqa\n\
+             import os.path\n\
+             from collections import OrderedDict\n\
+             \n\
+             __all__ = [\"OrderedDict\"]\n\
+             \n\
+             if TYPE_CHECKING:\n    import re\n\
+             \n\
+             def f(x: \"List[int]\") -> None:\n    print(os.path, helper, requests, cast)\n\n"
+        ]
+    );
+    assert_eq!(organize("unused.py"), vec!["\nx = 1\n\n"]);
+    // Imports in stubs are re-exports
+    assert_eq!(organize("stub.pyi"), vec!["import os\nimport sys\n\n"]);
+    // Imports in packages are usually re-exports as well
+    assert_eq!(
+        organize("mypkg/sub/__init__.py"),
+        vec!["import sys\n\nfrom mypkg.helpers import helper, other\n\n"]
+    );
+    // Explicit re-exports and names in `__all__` are kept
+    assert_eq!(
+        organize("reexports.py"),
+        vec![
+            "import os as os\nfrom json import dumps, loads\n\n\
+             from mypkg.helpers import helper as helper\n\n\
+             __all__ = [\"dumps\"] + [\"loads\"]\n\n"
+        ]
+    );
+    assert!(organize("no_imports.py").is_empty());
+    // Resolving the action succeeds without changing anything
+    assert!(organize("organized.py").is_empty());
+}
+
+#[test]
+#[serial]
+fn code_action_add_type_annotations() {
+    let server = Project::with_fixture(
+        r#"
+        [file labels.py]
+        class Label: ...
+
+        [file shapes.py]
+        class Point:
+            def __init__(self, x, y):
+                self.x = x
+                self.y = y
+
+        def render(label):
+            pass
+
+        [file shadowed.py]
+        import re
+        Pattern = "a"
+
+        def pattern(flags):
+            return re.compile("a", flags)
+
+        pattern(0)
+
+        [file m.py]
+        """Docstring"""
+        import os
+        import re
+
+        from labels import Label
+        from shapes import Point, render
+
+        def origin():
+            return Point(0, 0)
+
+        def describe(point, label=None, *, verbose=False):
+            return os.path.join("a", "b")
+
+        def pattern():
+            return re.compile("a")
+
+        def typed(x: int) -> int:
+            return x
+
+        describe(origin(), "origin")
+        describe(Point(1, 2), verbose=True)
+        render(Label())
+        "#,
+    )
+    .into_server();
+
+    let annotate = |path, position| {
+        server
+            .code_actions(path, position, position)
+            .into_iter()
+            .filter(|(title, _)| title == "Add inferred type annotations")
+            .map(|(_, code)| code)
+            .collect::<Vec<_>>()
+    };
+    let with_m_header = |rest: &str| {
+        format!(
+            "\"\"\"Docstring\"\"\"\nimport os\nimport re\n\nfrom labels import Label\n\
+             from shapes import Point, render\n{rest}"
+        )
+    };
+    let m_rest = "\n\
+        def origin():\n    return Point(0, 0)\n\n\
+        def describe(point, label=None, *, verbose=False):\n    return os.path.join(\"a\", \"b\")\n\n\
+        def pattern():\n    return re.compile(\"a\")\n\n\
+        def typed(x: int) -> int:\n    return x\n\n\
+        describe(origin(), \"origin\")\n\
+        describe(Point(1, 2), verbose=True)\n\
+        render(Label())\n";
+
+    // Params are inferred from the call sites, the return type from the body
+    assert_eq!(
+        annotate("m.py", (10, 6)),
+        vec![with_m_header(&m_rest.replace(
+            "def describe(point, label=None, *, verbose=False):",
+            "def describe(point: Point, label: str | None = None, *, verbose: bool = False) -> str:"
+        ))]
+    );
+    // Works from within the body as well
+    assert_eq!(
+        annotate("m.py", (8, 8)),
+        vec![with_m_header(
+            &m_rest.replace("def origin():", "def origin() -> Point:")
+        )]
+    );
+    // Classes that are not visible are imported
+    assert_eq!(
+        annotate("m.py", (13, 6)),
+        vec![with_m_header(&format!(
+            "from re import Pattern\n{}",
+            m_rest.replace("def pattern():", "def pattern() -> Pattern[str]:")
+        ))]
+    );
+    assert!(annotate("m.py", (16, 6)).is_empty());
+    // Names that refer to something else are not imported and the annotation is left out
+    assert_eq!(
+        annotate("shadowed.py", (3, 6)),
+        vec![
+            "import re\nPattern = \"a\"\n\ndef pattern(flags: int):\n    \
+             return re.compile(\"a\", flags)\n\npattern(0)\n\n"
+        ]
+    );
+    assert_eq!(
+        annotate("shapes.py", (5, 6)),
+        vec![
+            "from labels import Label\n\n\
+             class Point:\n    def __init__(self, x, y):\n        self.x = x\n        self.y = y\n\n\
+             def render(label: Label) -> None:\n    pass\n\n"
+        ]
+    );
+    // Instantiations are the call sites of `__init__`
+    assert_eq!(
+        annotate("shapes.py", (1, 10)),
+        vec![
+            "class Point:\n    def __init__(self, x: int, y: int) -> None:\n        self.x = x\n        self.y = y\n\n\
+             def render(label):\n    pass\n\n"
+        ]
+    );
+}
+
+#[test]
+#[serial]
+fn code_action_add_type_annotations_for_old_python_versions() {
+    let server = Project::with_fixture(
+        r#"
+        [file pyproject.toml]
+        [tool.mypy]
+        python_version = "3.8"
+
+        [file plain.py]
+        # A comment
+        def f(x=None):
+            return [1]
+
+        f(1)
+
+        [file imports.py]
+        """Docstring"""
+        import os
+
+        def f():
+            return (os.sep, 1)
+
+        [file future.py]
+        from __future__ import annotations
+
+        def f():
+            return [1]
+
+        [file simple.py]
+        def f():
+            return 1
+
+        [file stub.pyi]
+        def f(x): ...
+
+        [file uses_stub.py]
+        from stub import f
+        f([1])
+        "#,
+    )
+    .into_server();
+
+    let annotate = |path, position| {
+        server
+            .code_actions(path, position, position)
+            .into_iter()
+            .filter(|(title, _)| title == "Add inferred type annotations")
+            .map(|(_, code)| code)
+            .collect::<Vec<_>>()
+    };
+    // `int | None` and `list[int]` cannot be evaluated at runtime before Python 3.10 and 3.9
+    assert_eq!(
+        annotate("plain.py", (1, 4)),
+        vec![
+            "# A comment\nfrom __future__ import annotations\n\n\
+             def f(x: int | None = None) -> list[int]:\n    return [1]\n\nf(1)\n\n"
+        ]
+    );
+    // The future import is added before all other imports
+    assert_eq!(
+        annotate("imports.py", (3, 4)),
+        vec![
+            "\"\"\"Docstring\"\"\"\nfrom __future__ import annotations\n\nimport os\n\n\
+             def f() -> tuple[str, int]:\n    return (os.sep, 1)\n\n"
+        ]
+    );
+    assert_eq!(
+        annotate("future.py", (2, 4)),
+        vec!["from __future__ import annotations\n\ndef f() -> list[int]:\n    return [1]\n\n"]
+    );
+    assert_eq!(
+        annotate("simple.py", (0, 4)),
+        vec!["def f() -> int:\n    return 1\n\n"]
+    );
+    // Annotations in stubs are never evaluated
+    assert_eq!(
+        annotate("stub.pyi", (0, 4)),
+        vec!["def f(x: list[int]) -> None: ...\n\n"]
+    );
+}
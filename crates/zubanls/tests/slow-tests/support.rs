@@ -8,12 +8,20 @@ use std::{
 };
 
 use lsp_types::{
+    CodeActionContext, CodeActionOrCommand, CodeActionParams, CodeLensParams,
     DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
-    DocumentDiagnosticParams, DocumentDiagnosticReport, DocumentDiagnosticReportResult,
-    PartialResultParams, TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem,
-    Uri, VersionedTextDocumentIdentifier, WorkDoneProgressParams,
+    DocumentChangeOperation, DocumentChanges, DocumentDiagnosticParams, DocumentDiagnosticReport,
+    DocumentDiagnosticReportResult, FileRename, FoldingRangeKind, FoldingRangeParams, OneOf,
+    PartialResultParams, Position, Range, RenameFilesParams, RenameParams, SelectionRangeParams,
+    TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem,
+    TextDocumentPositionParams, TextEdit, Uri, VersionedTextDocumentIdentifier,
+    WorkDoneProgressParams, WorkspaceEdit,
     notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument},
-    request::DocumentDiagnosticRequest,
+    request::{
+        CodeActionRequest, CodeActionResolveRequest, CodeLensRequest, CodeLensResolve,
+        DocumentDiagnosticRequest, FoldingRangeRequest, Rename, SelectionRangeRequest,
+        WillRenameFiles,
+    },
 };
 use serde::Serialize;
 use serde_json::{Value, to_string_pretty};
@@ -26,11 +34,23 @@ lazy_static::lazy_static! {
     static ref FILE_SYSTEM_LOCK: Mutex<()> = Mutex::default();
 }
 
+/// A request of the server that only returns once it is cancelled.
+pub(crate) enum TestWaitForCancellation {}
+
+impl lsp_types::request::Request for TestWaitForCancellation {
+    type Params = lsp_types::TextDocumentPositionParams;
+    type Result = ();
+    const METHOD: &'static str = "test-wait-for-cancellation";
+}
+
 pub(crate) struct Project<'a> {
     fixture: &'a str,
     roots: Vec<String>,
     root_dir_contains_symlink: bool,
     push_diagnostics: bool,
+    workspace_configuration: bool,
+    completion_label_details: bool,
+    initialization_options: Option<Value>,
 }
 
 impl<'a> Project<'a> {
@@ -41,6 +61,9 @@ impl<'a> Project<'a> {
             roots: vec![],
             root_dir_contains_symlink: false,
             push_diagnostics: false,
+            workspace_configuration: false,
+            completion_label_details: false,
+            initialization_options: None,
         }
     }
 
@@ -59,6 +82,21 @@ impl<'a> Project<'a> {
         self
     }
 
+    pub(crate) fn with_workspace_configuration(mut self) -> Self {
+        self.workspace_configuration = true;
+        self
+    }
+
+    pub(crate) fn with_completion_label_details(mut self) -> Self {
+        self.completion_label_details = true;
+        self
+    }
+
+    pub(crate) fn with_initialization_options(mut self, options: Value) -> Self {
+        self.initialization_options = Some(options);
+        self
+    }
+
     pub(crate) fn into_server(self) -> Server {
         self.into_server_detailed(None)
     }
@@ -85,13 +123,29 @@ impl<'a> Project<'a> {
         if roots.is_empty() {
             roots.push(tmp_dir_path);
         }
+        let mut capabilities =
+            Connection::client_capabilities(client_encodings, !self.push_diagnostics);
+        if self.workspace_configuration {
+            capabilities.workspace.as_mut().unwrap().configuration = Some(true);
+        }
+        if self.completion_label_details {
+            let text_document = capabilities.text_document.as_mut().unwrap();
+            let completion_item = text_document.completion.as_mut().unwrap();
+            completion_item
+                .completion_item
+                .as_mut()
+                .unwrap()
+                .label_details_support = Some(true);
+        }
+        let connection = Connection::new();
+        connection.initialize_with_options(
+            &roots.iter().map(|root| root.as_str()).collect::<Vec<_>>(),
+            capabilities,
+            self.initialization_options,
+        );
         Server {
             tmp_dir,
-            connection: Connection::initialized(
-                &roots.iter().map(|root| root.as_str()).collect::<Vec<_>>(),
-                client_encodings,
-                !self.push_diagnostics,
-            ),
+            connection,
             version_incrementor: Default::default(),
         }
     }
@@ -170,6 +224,193 @@ impl Server {
         report.full_document_diagnostic_report.items
     }
 
+    /// Sends `workspace/willRenameFiles` and returns the changed files (as relative paths) with
+    /// their new code, after the returned edits were applied.
+    pub(crate) fn will_rename_files(&self, renames: &[(&str, &str)]) -> Vec<(String, String)> {
+        let edit = self.request::<WillRenameFiles>(RenameFilesParams {
+            files: renames
+                .iter()
+                .map(|(from, to)| FileRename {
+                    old_uri: self.doc_id(from).uri.to_string(),
+                    new_uri: self.doc_id(to).uri.to_string(),
+                })
+                .collect(),
+        });
+        let Some(edit) = edit else {
+            return vec![];
+        };
+        self.apply_document_changes(edit)
+    }
+
+    /// Sends `textDocument/rename` and returns the changed files (as relative paths) with their
+    /// new code, after the returned edits were applied.
+    pub(crate) fn rename(
+        &self,
+        path: &str,
+        position: (u32, u32),
+        new_name: &str,
+    ) -> Vec<(String, String)> {
+        let edit = self.request::<Rename>(RenameParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: self.doc_id(path),
+                position: Position::new(position.0, position.1),
+            },
+            new_name: new_name.to_string(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        });
+        let Some(edit) = edit else {
+            return vec![];
+        };
+        self.apply_document_changes(edit)
+    }
+
+    /// Sends `textDocument/codeAction` for a range and returns the titles of the code actions
+    /// together with the code of the file after the edits of the action were applied. Actions
+    /// without edits are resolved with `codeAction/resolve` first.
+    pub(crate) fn code_actions(
+        &self,
+        path: &str,
+        start: (u32, u32),
+        end: (u32, u32),
+    ) -> Vec<(String, String)> {
+        let response = self.request::<CodeActionRequest>(CodeActionParams {
+            text_document: self.doc_id(path),
+            range: Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+            context: CodeActionContext::default(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        response
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|action| {
+                let CodeActionOrCommand::CodeAction(mut action) = action else {
+                    unreachable!()
+                };
+                if action.edit.is_none() {
+                    action = self.request::<CodeActionResolveRequest>(action);
+                }
+                let edit = action.edit.unwrap();
+                // Resolved actions may turn out to change nothing
+                edit.document_changes.as_ref()?;
+                let mut files = self.apply_document_changes(edit);
+                assert_eq!(files.len(), 1);
+                Some((action.title, files.pop().unwrap().1))
+            })
+            .collect()
+    }
+
+    /// Resolves the code action with the title and returns the error message. This is for
+    /// actions that are offered after a cheap check, but turn out to be impossible.
+    pub(crate) fn code_action_resolve_error(
+        &self,
+        path: &str,
+        position: (u32, u32),
+        title: &str,
+    ) -> String {
+        let position = Position::new(position.0, position.1);
+        let response = self.request::<CodeActionRequest>(CodeActionParams {
+            text_document: self.doc_id(path),
+            range: Range::new(position, position),
+            context: CodeActionContext::default(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        let action = response
+            .unwrap_or_default()
+            .into_iter()
+            .find_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) if action.title == title => Some(action),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("Expected the code action {title:?}"));
+        self.request_with_expected_error::<CodeActionResolveRequest>(action)
+            .message
+    }
+
+    /// Sends `textDocument/selectionRange` for a position and returns the code of the ranges,
+    /// from the innermost to the outermost.
+    pub(crate) fn selection_ranges(&self, path: &str, position: (u32, u32)) -> Vec<String> {
+        let response = self.request::<SelectionRangeRequest>(SelectionRangeParams {
+            text_document: self.doc_id(path),
+            positions: vec![Position::new(position.0, position.1)],
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        let code = std::fs::read_to_string(join(self.tmp_dir.path(), path)).unwrap();
+        let [selection_range] = response.unwrap().try_into().unwrap();
+        let mut result = vec![];
+        let mut current = Some(Box::new(selection_range));
+        while let Some(selection_range) = current {
+            let range = selection_range.range;
+            result
+                .push(code[to_offset(&code, range.start)..to_offset(&code, range.end)].to_owned());
+            current = selection_range.parent;
+        }
+        result
+    }
+
+    /// Sends `textDocument/foldingRange` and returns the start and end lines with the kind.
+    pub(crate) fn folding_ranges(&self, path: &str) -> Vec<(u32, u32, Option<FoldingRangeKind>)> {
+        let response = self.request::<FoldingRangeRequest>(FoldingRangeParams {
+            text_document: self.doc_id(path),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        response
+            .unwrap()
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect()
+    }
+
+    /// Returns the line and the title of every code lens after resolving it.
+    pub(crate) fn code_lenses(&self, path: &str) -> Vec<(u32, String)> {
+        let response = self.request::<CodeLensRequest>(CodeLensParams {
+            text_document: self.doc_id(path),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        response
+            .unwrap()
+            .into_iter()
+            .map(|lens| {
+                let resolved = self.request::<CodeLensResolve>(lens);
+                (resolved.range.start.line, resolved.command.unwrap().title)
+            })
+            .collect()
+    }
+
+    fn apply_document_changes(&self, edit: WorkspaceEdit) -> Vec<(String, String)> {
+        let changes = match edit.document_changes {
+            Some(DocumentChanges::Edits(changes)) => changes,
+            Some(DocumentChanges::Operations(operations)) => operations
+                .into_iter()
+                .filter_map(|operation| match operation {
+                    DocumentChangeOperation::Edit(edit) => Some(edit),
+                    DocumentChangeOperation::Op(_) => None,
+                })
+                .collect(),
+            None => unreachable!(),
+        };
+        let root_uri = self.doc_id("").uri.to_string();
+        let mut result: Vec<_> = changes
+            .into_iter()
+            .map(|change| {
+                let uri = change.text_document.uri.to_string();
+                let rel_path = uri.strip_prefix(&root_uri).unwrap().to_string();
+                let code = std::fs::read_to_string(join(self.tmp_dir.path(), &rel_path)).unwrap();
+                let edits = change.edits.into_iter().map(|edit| match edit {
+                    OneOf::Left(edit) => edit,
+                    OneOf::Right(annotated) => annotated.text_edit,
+                });
+                (rel_path, apply_text_edits(&code, edits))
+            })
+            .collect();
+        result.sort();
+        result
+    }
+
     pub fn expect_multiple_diagnostics_pushes_with_uris<'x>(
         &self,
         pushes: impl Into<HashMap<&'x str, Vec<&'x str>>>,
@@ -298,6 +539,29 @@ impl Server {
         });
     }
 
+    pub fn change_in_memory_file_incrementally(
+        &self,
+        path: &str,
+        changes: Vec<(lsp_types::Range, &str)>,
+    ) {
+        self.version_incrementor
+            .set(self.version_incrementor.get() + 1);
+        self.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: self.doc_id(path).uri,
+                version: self.version_incrementor.get(),
+            },
+            content_changes: changes
+                .into_iter()
+                .map(|(range, text)| TextDocumentContentChangeEvent {
+                    text: text.to_string(),
+                    range: Some(range),
+                    range_length: None,
+                })
+                .collect(),
+        });
+    }
+
     pub fn open_in_memory_file(&self, path: &str, code: &str) {
         self.open_in_memory_file_for_uri(self.doc_id(path).uri, code)
     }
@@ -362,6 +626,30 @@ impl Server {
     }
 }
 
+/// Applies edits of a single file. All positions are expected to be in ASCII code.
+fn apply_text_edits(code: &str, edits: impl Iterator<Item = TextEdit>) -> String {
+    let mut edits: Vec<_> = edits.collect();
+    edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+    let mut result = code.to_string();
+    for edit in edits.into_iter().rev() {
+        result.replace_range(
+            to_offset(code, edit.range.start)..to_offset(code, edit.range.end),
+            &edit.new_text,
+        );
+    }
+    result
+}
+
+/// Converts a position to a byte offset. All positions are expected to be in ASCII code.
+fn to_offset(code: &str, pos: Position) -> usize {
+    let line_start: usize = code
+        .split_inclusive('\n')
+        .take(pos.line as usize)
+        .map(|line| line.len())
+        .sum();
+    line_start + pos.character as usize
+}
+
 fn join(path: &str, other: &str) -> String {
     Path::new(path)
         .join(other)